@@ -0,0 +1,14 @@
+-- Create newsletter issues table
+CREATE TABLE newsletter_issues(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    slug TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL,
+    -- Markdown source, rendered into HTML and plain text on demand
+    content TEXT NOT NULL,
+    subscribers_only BOOLEAN NOT NULL DEFAULT FALSE,
+    -- NULL while the issue is still a draft
+    published_at timestamptz NULL,
+    updated_at timestamptz NOT NULL
+);
+CREATE INDEX newsletter_issues_published_at_idx ON newsletter_issues (published_at);
@@ -0,0 +1,22 @@
+-- Full-text search over issue titles and bodies.
+-- Titles weigh more than bodies when ranking results.
+ALTER TABLE newsletter_issues
+ADD COLUMN search_vector tsvector NOT NULL DEFAULT ''::tsvector;
+
+CREATE FUNCTION newsletter_issues_search_vector_update() RETURNS trigger AS $$
+BEGIN
+    NEW.search_vector :=
+        setweight(to_tsvector('english', coalesce(NEW.title, '')), 'A') ||
+        setweight(to_tsvector('english', coalesce(NEW.content, '')), 'B');
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER newsletter_issues_search_vector_trigger
+BEFORE INSERT OR UPDATE OF title, content ON newsletter_issues
+FOR EACH ROW EXECUTE FUNCTION newsletter_issues_search_vector_update();
+
+-- Backfill issues stored before the trigger existed
+UPDATE newsletter_issues SET title = title;
+
+CREATE INDEX newsletter_issues_search_vector_idx ON newsletter_issues USING GIN (search_vector);
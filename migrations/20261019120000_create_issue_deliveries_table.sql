@@ -0,0 +1,15 @@
+-- One row per recipient of a published issue, doubling as the delivery queue
+CREATE TABLE issue_deliveries(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id),
+    -- 'queued', 'sent' or 'failed'
+    status TEXT NOT NULL,
+    n_attempts INT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    last_error TEXT NULL,
+    queued_at timestamptz NOT NULL DEFAULT now(),
+    sent_at timestamptz NULL
+);
+CREATE INDEX issue_deliveries_queued_idx ON issue_deliveries (execute_after)
+WHERE status = 'queued';
@@ -0,0 +1,5 @@
+-- The `X-Message-Id` SendGrid assigned to the request that sent the email,
+-- used to correlate webhook events with the email they are about
+ALTER TABLE issue_deliveries ADD COLUMN message_id TEXT NULL;
+ALTER TABLE subscription_tokens ADD COLUMN message_id TEXT NULL;
+CREATE INDEX issue_deliveries_message_id_idx ON issue_deliveries (message_id);
@@ -0,0 +1,5 @@
+-- Emails sent per UTC day, counted against `email_client.daily_quota`
+CREATE TABLE email_daily_usage (
+    day DATE NOT NULL PRIMARY KEY,
+    sent_emails INTEGER NOT NULL DEFAULT 0
+);
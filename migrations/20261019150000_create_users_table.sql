@@ -0,0 +1,7 @@
+-- Admins of the newsletter, authenticated with HTTP Basic authentication
+CREATE TABLE users (
+    user_id uuid PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    -- Argon2id hash in the PHC string format
+    password_hash TEXT NOT NULL
+);
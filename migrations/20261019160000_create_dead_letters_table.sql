@@ -0,0 +1,13 @@
+-- Deliveries that failed for good, kept until they are replayed
+CREATE TABLE dead_letters (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id),
+    -- e.g. 'rejected', 'server_error' or 'invalid_recipient'
+    error_kind TEXT NOT NULL,
+    error TEXT NOT NULL,
+    n_attempts INT NOT NULL,
+    queued_at timestamptz NOT NULL,
+    failed_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX dead_letters_failed_at_idx ON dead_letters (failed_at);
@@ -0,0 +1,20 @@
+-- Events reported by the SendGrid Event Webhook about the emails we sent
+CREATE TABLE email_events (
+    id uuid PRIMARY KEY,
+    -- `sg_event_id`, to ignore events the webhook delivers more than once
+    provider_event_id TEXT NULL UNIQUE,
+    -- e.g. 'delivered', 'bounce', 'open', 'click' or 'unsubscribe'
+    event TEXT NOT NULL,
+    email TEXT NOT NULL,
+    -- The `X-Message-Id` of the request that sent the email
+    message_id TEXT NULL,
+    -- From the custom arguments of the email, when it was an issue delivery.
+    -- Not foreign keys: events about since deleted rows are still accepted.
+    newsletter_issue_id uuid NULL,
+    subscriber_id uuid NULL,
+    -- The link that was clicked
+    url TEXT NULL,
+    occurred_at timestamptz NOT NULL,
+    received_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX email_events_newsletter_issue_id_idx ON email_events (newsletter_issue_id, event);
@@ -0,0 +1,54 @@
+-- Lists subscribers can belong to, e.g. to segment imported audiences
+CREATE TABLE lists (
+    id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE TABLE subscription_lists (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    list_id uuid NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+    PRIMARY KEY (subscriber_id, list_id),
+    added_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX subscription_lists_list_id_idx ON subscription_lists (list_id);
+
+-- Every status a subscription went through, kept by the triggers below
+CREATE TABLE subscription_status_history (
+    id BIGSERIAL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    changed_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX subscription_status_history_subscriber_id_idx
+    ON subscription_status_history (subscriber_id);
+CREATE INDEX subscription_status_history_status_idx
+    ON subscription_status_history (status, changed_at);
+
+-- Backfill existing subscriptions, which reached their status at an unknown
+-- time: as if they did when they subscribed
+INSERT INTO subscription_status_history (subscriber_id, status, changed_at)
+SELECT id, 'pending_confirmation', subscribed_at FROM subscriptions;
+INSERT INTO subscription_status_history (subscriber_id, status, changed_at)
+SELECT id, status, subscribed_at FROM subscriptions WHERE status <> 'pending_confirmation';
+
+CREATE FUNCTION record_subscription_status() RETURNS trigger AS $$
+BEGIN
+    INSERT INTO subscription_status_history (subscriber_id, status, changed_at)
+    VALUES (
+        NEW.id,
+        NEW.status,
+        CASE WHEN TG_OP = 'INSERT' THEN NEW.subscribed_at ELSE now() END
+    );
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER subscriptions_status_inserted
+AFTER INSERT ON subscriptions
+FOR EACH ROW EXECUTE FUNCTION record_subscription_status();
+
+CREATE TRIGGER subscriptions_status_updated
+AFTER UPDATE OF status ON subscriptions
+FOR EACH ROW WHEN (OLD.status IS DISTINCT FROM NEW.status)
+EXECUTE FUNCTION record_subscription_status();
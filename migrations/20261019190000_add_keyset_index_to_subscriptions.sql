@@ -0,0 +1,2 @@
+-- Keyset pagination of the admin subscriber listing, most recent first
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at DESC, id DESC);
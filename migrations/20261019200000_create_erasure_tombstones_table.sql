@@ -0,0 +1,6 @@
+-- What is left of the subscribers whose data was erased at their request:
+-- the SHA-256 of their email address, so that they are not imported again
+CREATE TABLE erasure_tombstones (
+    email_hash TEXT PRIMARY KEY,
+    erased_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,26 @@
+-- Proof of the consent of subscribers: what they saw and where from when
+-- they signed up, and when they confirmed. Rows are never updated, and only
+-- go along with their subscription when its data is erased.
+CREATE TABLE consent_events (
+    id BIGSERIAL PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    -- 'subscribed' or 'confirmed'
+    event TEXT NOT NULL,
+    ip_address INET NULL,
+    user_agent TEXT NULL,
+    -- The form or page the subscriber signed up from
+    source TEXT NULL,
+    consent_text_version TEXT NULL,
+    occurred_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX consent_events_subscriber_id_idx ON consent_events (subscriber_id);
+
+CREATE FUNCTION forbid_consent_event_updates() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'consent events are append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_events_append_only
+BEFORE UPDATE ON consent_events
+FOR EACH ROW EXECUTE FUNCTION forbid_consent_event_updates();
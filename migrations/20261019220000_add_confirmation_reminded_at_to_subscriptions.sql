@@ -0,0 +1,5 @@
+-- When subscribers who never confirmed were reminded to, once at most before
+-- they are purged
+ALTER TABLE subscriptions ADD COLUMN confirmation_reminded_at timestamptz NULL;
+CREATE INDEX subscriptions_pending_subscribed_at_idx
+    ON subscriptions (subscribed_at) WHERE status = 'pending_confirmation';
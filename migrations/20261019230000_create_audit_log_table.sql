@@ -0,0 +1,29 @@
+-- Every change made through the admin endpoints, written in the same
+-- transaction as the change itself. Entries are never updated nor deleted.
+CREATE TABLE audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    -- The admin who made the change, kept even once they are deleted
+    actor_id uuid NOT NULL,
+    -- e.g. 'subscriber.delete'
+    action TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    target_id TEXT NULL,
+    -- The fields that changed, as {"field": {"before": ..., "after": ...}}
+    changes JSONB NOT NULL,
+    request_id TEXT NULL,
+    ip_address INET NULL,
+    occurred_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX audit_log_actor_id_idx ON audit_log (actor_id);
+CREATE INDEX audit_log_target_idx ON audit_log (target_type, target_id);
+CREATE INDEX audit_log_occurred_at_idx ON audit_log (occurred_at);
+
+CREATE FUNCTION forbid_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'the audit log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+BEFORE UPDATE OR DELETE ON audit_log
+FOR EACH ROW EXECUTE FUNCTION forbid_audit_log_changes();
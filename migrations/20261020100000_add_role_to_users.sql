@@ -0,0 +1,5 @@
+-- What each admin is allowed to do, from least to most: 'viewer', 'editor',
+-- 'publisher' or 'owner'. Existing admins could do everything.
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'owner'
+    CHECK (role IN ('viewer', 'editor', 'publisher', 'owner'));
+ALTER TABLE users ALTER COLUMN role DROP DEFAULT;
@@ -0,0 +1,30 @@
+-- Optional TOTP second factor of admins. The secret is stored as soon as
+-- enrollment starts, and only required at login once confirmed with a code.
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+ALTER TABLE users ADD COLUMN totp_enabled_at timestamptz NULL;
+-- Set by owners, the admin cannot use the admin area until enrolled
+ALTER TABLE users ADD COLUMN totp_required BOOLEAN NOT NULL DEFAULT false;
+-- The time step of the last accepted code, so that none is accepted twice
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT NULL;
+ALTER TABLE users ADD COLUMN totp_failed_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE users ADD COLUMN totp_locked_until timestamptz NULL;
+
+-- Single-use codes to log in without the authenticator, SHA-256 hashed
+CREATE TABLE totp_recovery_codes (
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
+
+-- Sessions of admins logged in with /admin/login, identified by the SHA-256
+-- hash of their bearer token. Sessions of admins with TOTP enabled are only
+-- authenticated once the second factor is verified.
+CREATE TABLE admin_sessions (
+    token_hash TEXT PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL,
+    authenticated_at timestamptz NULL
+);
+CREATE INDEX admin_sessions_user_id ON admin_sessions (user_id);
@@ -0,0 +1,16 @@
+-- Admins signing in with the OpenID Connect provider, identified by its
+-- issuer and their subject there. They have no password.
+ALTER TABLE users ALTER COLUMN password_hash DROP NOT NULL;
+ALTER TABLE users ADD COLUMN oidc_issuer TEXT NULL;
+ALTER TABLE users ADD COLUMN oidc_subject TEXT NULL;
+ALTER TABLE users ADD CONSTRAINT users_oidc_identity UNIQUE (oidc_issuer, oidc_subject);
+
+-- Authorization requests sent to the provider, until its callback. The state
+-- is also kept in a cookie of the browser that started the login.
+CREATE TABLE oidc_logins (
+    state TEXT PRIMARY KEY,
+    -- The PKCE verifier of the code challenge sent to the provider
+    code_verifier TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
@@ -0,0 +1,21 @@
+-- Keys machine clients call the admin endpoints with, as
+-- `Authorization: Bearer <key>`. Only the SHA-256 hash of each key is kept.
+-- A key acts on behalf of the admin who created it, restricted to its scopes.
+CREATE TABLE api_keys (
+    id uuid PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- The start of the key, to tell keys apart without knowing them
+    key_prefix TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    -- e.g. 'subscribers:read', 'subscribers:write', 'issues:publish'
+    scopes TEXT[] NOT NULL,
+    created_by uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    -- Never expires when unset
+    expires_at timestamptz NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL
+);
+
+-- The key the change was made with, if any
+ALTER TABLE audit_log ADD COLUMN api_key_id uuid NULL;
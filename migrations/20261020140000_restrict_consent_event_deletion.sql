@@ -0,0 +1,7 @@
+-- Deleting a subscription no longer takes its proof of consent along: it has
+-- to be erased explicitly, which only the erasure of the data of a subject
+-- and the purge of subscriptions that were never confirmed do.
+ALTER TABLE consent_events
+    DROP CONSTRAINT consent_events_subscriber_id_fkey,
+    ADD CONSTRAINT consent_events_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE RESTRICT;
@@ -0,0 +1,10 @@
+-- Requests for the data links of an address, to limit how many can be made
+-- for one address or from one client. Addresses are only kept hashed.
+CREATE TABLE data_requests (
+    id BIGSERIAL PRIMARY KEY,
+    email_hash TEXT NOT NULL,
+    ip_address INET NULL,
+    requested_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX data_requests_email_hash_idx ON data_requests (email_hash, requested_at);
+CREATE INDEX data_requests_ip_address_idx ON data_requests (ip_address, requested_at);
@@ -0,0 +1,161 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How long a period each point of a time series covers, periods starting on
+/// Mondays for weeks and on the 1st for months.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Granularity {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl Granularity {
+    /// The field as understood by Postgres' `date_trunc`
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+        }
+    }
+}
+
+/// How the subscribers of a list, or all of them, changed over one period.
+/// Days are UTC days.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct GrowthPoint {
+    /// The first day of the period
+    pub date: NaiveDate,
+    pub signups: i64,
+    pub confirmations: i64,
+    /// The share of the period's sign-ups that confirmed since
+    pub confirmation_rate: Option<f64>,
+    /// How long the period's sign-ups that confirmed took to, on average
+    pub average_confirmation_seconds: Option<f64>,
+    pub unsubscribes: i64,
+    /// Subscribers whose emails bounced, per the SendGrid Event Webhook
+    pub bounces: i64,
+}
+
+/// Sign-ups, confirmations, unsubscribes and bounces between `from` and `to`
+/// included, with a point per period even when nothing happened.
+///
+/// Only the subscribers of `list_id` are counted when it is set.
+#[tracing::instrument(name = "Compute subscriber growth", skip(pool))]
+pub async fn subscriber_growth(
+    pool: &PgPool,
+    list_id: Option<Uuid>,
+    from: NaiveDate,
+    to: NaiveDate,
+    granularity: Granularity,
+) -> Result<Vec<GrowthPoint>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        WITH members AS (
+            SELECT id, subscribed_at FROM subscriptions
+            WHERE $4::uuid IS NULL
+                OR id IN (SELECT subscriber_id FROM subscription_lists WHERE list_id = $4)
+        ),
+        buckets AS (
+            SELECT generate_series(
+                date_trunc($3, $1::date::timestamp),
+                date_trunc($3, $2::date::timestamp),
+                ('1 ' || $3)::interval
+            )::date AS bucket
+        ),
+        confirmed AS (
+            SELECT h.subscriber_id, MIN(h.changed_at) AS confirmed_at
+            FROM subscription_status_history h
+            JOIN members m ON m.id = h.subscriber_id
+            WHERE h.status = 'confirmed'
+            GROUP BY h.subscriber_id
+        ),
+        signups AS (
+            SELECT date_trunc($3, m.subscribed_at AT TIME ZONE 'UTC')::date AS bucket,
+                COUNT(*) AS signups,
+                COUNT(c.confirmed_at) AS confirmed_signups,
+                AVG(EXTRACT(EPOCH FROM c.confirmed_at - m.subscribed_at))::float8
+                    AS average_confirmation_seconds
+            FROM members m
+            LEFT JOIN confirmed c ON c.subscriber_id = m.id
+            WHERE (m.subscribed_at AT TIME ZONE 'UTC')::date BETWEEN $1 AND $2
+            GROUP BY 1
+        ),
+        confirmations AS (
+            SELECT date_trunc($3, confirmed_at AT TIME ZONE 'UTC')::date AS bucket,
+                COUNT(*) AS confirmations
+            FROM confirmed
+            WHERE (confirmed_at AT TIME ZONE 'UTC')::date BETWEEN $1 AND $2
+            GROUP BY 1
+        ),
+        unsubscribes AS (
+            SELECT date_trunc($3, h.changed_at AT TIME ZONE 'UTC')::date AS bucket,
+                COUNT(DISTINCT h.subscriber_id) AS unsubscribes
+            FROM subscription_status_history h
+            JOIN members m ON m.id = h.subscriber_id
+            WHERE h.status = 'unsubscribed'
+                AND (h.changed_at AT TIME ZONE 'UTC')::date BETWEEN $1 AND $2
+            GROUP BY 1
+        ),
+        bounces AS (
+            SELECT date_trunc($3, occurred_at AT TIME ZONE 'UTC')::date AS bucket,
+                COUNT(DISTINCT email) AS bounces
+            FROM email_events
+            WHERE event = 'bounce'
+                AND (occurred_at AT TIME ZONE 'UTC')::date BETWEEN $1 AND $2
+                AND ($4::uuid IS NULL OR subscriber_id IN (SELECT id FROM members))
+            GROUP BY 1
+        )
+        SELECT b.bucket AS "date!",
+            COALESCE(s.signups, 0) AS "signups!",
+            s.confirmed_signups,
+            s.average_confirmation_seconds,
+            COALESCE(c.confirmations, 0) AS "confirmations!",
+            COALESCE(u.unsubscribes, 0) AS "unsubscribes!",
+            COALESCE(x.bounces, 0) AS "bounces!"
+        FROM buckets b
+        LEFT JOIN signups s USING (bucket)
+        LEFT JOIN confirmations c USING (bucket)
+        LEFT JOIN unsubscribes u USING (bucket)
+        LEFT JOIN bounces x USING (bucket)
+        ORDER BY b.bucket
+        "#,
+        from,
+        to,
+        granularity.as_str(),
+        list_id,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| GrowthPoint {
+            date: row.date,
+            signups: row.signups,
+            confirmations: row.confirmations,
+            confirmation_rate: row
+                .confirmed_signups
+                .filter(|_| row.signups > 0)
+                .map(|confirmed| ratio(confirmed, row.signups)),
+            average_confirmation_seconds: row.average_confirmation_seconds,
+            unsubscribes: row.unsubscribes,
+            bounces: row.bounces,
+        })
+        .collect())
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn ratio(part: i64, whole: i64) -> f64 {
+    part as f64 / whole as f64
+}
@@ -0,0 +1,94 @@
+//! API keys, for machine clients calling the admin endpoints without a
+//! session. A key acts on behalf of the admin who created it, restricted to
+//! the permissions of its scopes, until it expires or is revoked.
+
+use rand::{thread_rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::AdminUser,
+    authorization::{Permission, Role},
+};
+
+/// Tells API keys apart from session tokens in `Authorization: Bearer`
+pub const API_KEY_PREFIX: &str = "nlk_";
+/// The characters of a key kept in clear, prefix included
+const DISPLAYED_LENGTH: usize = 12;
+
+/// The key an admin is authenticated with, and the permissions it grants
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub permissions: Vec<Permission>,
+}
+
+/// A new key, e.g. `nlk_3f9c…`, only ever shown to the admin creating it
+#[must_use]
+pub fn generate_api_key() -> Secret<String> {
+    let mut key = [0u8; 32];
+    thread_rng().fill_bytes(&mut key);
+    Secret::new(format!("{API_KEY_PREFIX}{}", hex::encode(key)))
+}
+
+/// The start of a key, to tell keys apart in listings
+#[must_use]
+pub fn displayed_prefix(key: &Secret<String>) -> String {
+    key.expose_secret().chars().take(DISPLAYED_LENGTH).collect()
+}
+
+/// The SHA-256 of a key, as stored in `api_keys`. Keys are random enough
+/// not to need a slow password hash.
+#[must_use]
+pub fn api_key_hash(key: &Secret<String>) -> String {
+    hex::encode(Sha256::digest(key.expose_secret().as_bytes()))
+}
+
+/// The admin behind a key that has neither expired nor been revoked,
+/// recording that the key was used
+#[tracing::instrument(name = "Get the user of an API key", skip(pool, key))]
+pub async fn api_key_user(
+    pool: &PgPool,
+    key: &Secret<String>,
+) -> Result<Option<AdminUser>, sqlx::Error> {
+    let Some(row) = sqlx::query!(
+        r#"
+        WITH used AS (
+            UPDATE api_keys SET last_used_at = now()
+            WHERE key_hash = $1
+                AND revoked_at IS NULL
+                AND (expires_at IS NULL OR expires_at > now())
+            RETURNING id, scopes, created_by
+        )
+        SELECT used.id, used.scopes, u.user_id, u.role
+        FROM used
+        JOIN users u ON u.user_id = used.created_by
+        "#,
+        api_key_hash(key),
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let decode = |e: String| sqlx::Error::Decode(e.into());
+    let permissions = row
+        .scopes
+        .iter()
+        .map(|scope| Permission::from_scope(scope))
+        .collect::<Result<_, _>>()
+        .map_err(decode)?;
+    Ok(Some(AdminUser {
+        user_id: row.user_id,
+        role: Role::parse(&row.role).map_err(decode)?,
+        // Keys are for machines, which have no second factor
+        totp_enabled: false,
+        totp_required: false,
+        api_key: Some(ApiKey {
+            id: row.id,
+            permissions,
+        }),
+    }))
+}
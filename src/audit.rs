@@ -0,0 +1,144 @@
+//! Accountability for the changes admins make, kept in the append-only
+//! `audit_log` table.
+//!
+//! Snapshots of targets hold no email address nor name, which would outlive
+//! the erasure of their owner: subscribers are only referred to by id, and
+//! erased data subjects by the hash of their address.
+
+use std::net::IpAddr;
+
+use actix_web::{HttpMessage, HttpRequest};
+use serde_json::{json, Map, Value};
+use sqlx::{Postgres, Transaction};
+use tracing_actix_web::RequestId;
+use uuid::Uuid;
+
+use crate::{authentication::AdminUser, utils::client_ip_address};
+
+/// The admin making a change, and the request they make it with
+#[derive(Debug, Clone)]
+pub struct AuditActor {
+    pub user_id: Uuid,
+    /// The API key the admin made the change with, if any
+    pub api_key_id: Option<Uuid>,
+    pub request_id: Option<String>,
+    pub ip_address: Option<IpAddr>,
+}
+
+impl AuditActor {
+    #[must_use]
+    pub fn new(user: &AdminUser, request: &HttpRequest) -> Self {
+        Self {
+            user_id: user.user_id,
+            api_key_id: user.api_key.as_ref().map(|api_key| api_key.id),
+            request_id: request
+                .extensions()
+                .get::<RequestId>()
+                .map(ToString::to_string),
+            ip_address: client_ip_address(request),
+        }
+    }
+}
+
+/// A change to record, with snapshots of its target as JSON objects
+#[derive(Debug)]
+pub struct AuditEntry<'a> {
+    /// e.g. `subscriber.delete`
+    pub action: &'a str,
+    pub target_type: &'a str,
+    pub target_id: Option<String>,
+    /// Unset when the target is created
+    pub before: Option<Value>,
+    /// Unset when the target is deleted
+    pub after: Option<Value>,
+}
+
+/// Record a change in the transaction making it, so that either both or
+/// neither are kept
+#[tracing::instrument(name = "Record an audit log entry", skip(transaction, actor, entry), fields(action = entry.action))]
+pub async fn record_audit(
+    transaction: &mut Transaction<'_, Postgres>,
+    actor: &AuditActor,
+    entry: AuditEntry<'_>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log
+            (actor_id, action, target_type, target_id, changes, request_id, ip_address,
+                api_key_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7::text::inet, $8)
+        "#,
+        actor.user_id,
+        entry.action,
+        entry.target_type,
+        entry.target_id,
+        diff(entry.before.as_ref(), entry.after.as_ref()),
+        actor.request_id,
+        actor.ip_address.map(|ip_address| ip_address.to_string()),
+        actor.api_key_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// The fields that differ between two snapshots, as
+/// `{"field": {"before": ..., "after": ...}}`, missing fields being null
+#[must_use]
+pub fn diff(before: Option<&Value>, after: Option<&Value>) -> Value {
+    let empty = Map::new();
+    let before = before.and_then(Value::as_object).unwrap_or(&empty);
+    let after = after.and_then(Value::as_object).unwrap_or(&empty);
+    let changes: Map<String, Value> = before
+        .keys()
+        .chain(after.keys().filter(|field| !before.contains_key(*field)))
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| {
+            (
+                field.clone(),
+                json!({
+                    "before": before.get(field).unwrap_or(&Value::Null),
+                    "after": after.get(field).unwrap_or(&Value::Null),
+                }),
+            )
+        })
+        .collect();
+    Value::Object(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::diff;
+
+    #[test]
+    fn only_changed_fields_are_kept() {
+        let before = json!({ "status": "confirmed", "lists": ["weekly"] });
+        let after = json!({ "status": "unsubscribed", "lists": ["weekly"] });
+
+        assert_eq!(
+            diff(Some(&before), Some(&after)),
+            json!({ "status": { "before": "confirmed", "after": "unsubscribed" } })
+        );
+    }
+
+    #[test]
+    fn created_and_deleted_targets_change_every_field_from_or_to_null() {
+        let snapshot = json!({ "status": "confirmed" });
+
+        assert_eq!(
+            diff(None, Some(&snapshot)),
+            json!({ "status": { "before": null, "after": "confirmed" } })
+        );
+        assert_eq!(
+            diff(Some(&snapshot), None),
+            json!({ "status": { "before": "confirmed", "after": null } })
+        );
+        assert_eq!(diff(Some(&snapshot), Some(&snapshot)), json!({}));
+    }
+}
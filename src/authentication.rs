@@ -0,0 +1,327 @@
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    future::Future,
+    pin::Pin,
+};
+
+use actix_web::{
+    dev::Payload,
+    http::header::{self, HeaderMap, HeaderValue},
+    web::Data,
+    FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    api_keys::{api_key_user, ApiKey, API_KEY_PREFIX},
+    authorization::{Permission, Role},
+    session::{bearer_token, session_user},
+    telemetry::spawn_blocking_with_tracing,
+    utils::error_chain_fmt,
+};
+
+/// Verified against when the username is unknown, so that the response takes
+/// as long as for a wrong password and does not reveal which users exist.
+const DUMMY_PASSWORD_HASH: &str = "$argon2id$v=19$m=15000,t=2,p=1$\
+    gZiV/M1gPc22ElAH/Jh1Hw$\
+    CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno";
+
+#[derive(Debug)]
+pub struct Credentials {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+pub enum AuthError {
+    InvalidCredentials(String),
+    /// The user has TOTP enabled, so must log in with their second factor
+    /// rather than use their password on every request
+    SecondFactorRequired,
+    /// The role of the user does not grant the permission
+    Forbidden(Permission),
+    /// An owner requires the user to enable TOTP before anything else
+    EnrollmentRequired,
+    UnexpectedError(Box<dyn Error + Send + Sync>),
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCredentials(reason) => write!(f, "Invalid credentials: {reason}"),
+            Self::SecondFactorRequired => write!(
+                f,
+                "A second factor is required: log in at /admin/login, then /admin/login/totp."
+            ),
+            Self::Forbidden(permission) => write!(
+                f,
+                "The {} permission is required, which your role does not grant.",
+                permission.as_ref()
+            ),
+            Self::EnrollmentRequired => write!(
+                f,
+                "TOTP is required for your account, enable it at /admin/api/totp/enroll."
+            ),
+            Self::UnexpectedError(_) => write!(f, "Failed to authenticate the user."),
+        }
+    }
+}
+
+impl Error for AuthError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::InvalidCredentials(_)
+            | Self::SecondFactorRequired
+            | Self::Forbidden(_)
+            | Self::EnrollmentRequired => None,
+            Self::UnexpectedError(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl Debug for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for AuthError {
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::InvalidCredentials(_) => HttpResponse::Unauthorized()
+                .insert_header((
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Basic realm="admin""#),
+                ))
+                .finish(),
+            Self::SecondFactorRequired => HttpResponse::Unauthorized()
+                .insert_header((
+                    header::WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Bearer realm="admin""#),
+                ))
+                .body(self.to_string()),
+            Self::Forbidden(_) | Self::EnrollmentRequired => {
+                HttpResponse::Forbidden().body(self.to_string())
+            }
+            Self::UnexpectedError(_) => HttpResponse::InternalServerError().finish(),
+        }
+    }
+}
+
+/// Hash a password with Argon2id and a random salt, in the PHC string format
+///
+/// # Panics
+///
+/// If hashing fails, which does not happen with valid parameters.
+#[must_use]
+pub fn compute_password_hash(password: &Secret<String>) -> Secret<String> {
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 2, 1, None).expect("Valid Argon2 parameters"),
+    )
+    .hash_password(password.expose_secret().as_bytes(), &salt)
+    .expect("Failed to hash password")
+    .to_string();
+    Secret::new(password_hash)
+}
+
+/// Parse the credentials of an `Authorization: Basic` header
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, AuthError> {
+    let invalid = |reason: &str| AuthError::InvalidCredentials(reason.to_string());
+    let encoded = headers
+        .get(header::AUTHORIZATION)
+        .ok_or_else(|| invalid("The 'Authorization' header was missing"))?
+        .to_str()
+        .map_err(|_| invalid("The 'Authorization' header was not a valid UTF8 string"))?
+        .strip_prefix("Basic ")
+        .ok_or_else(|| invalid("The authorization scheme was not 'Basic'"))?;
+    let decoded = STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .ok_or_else(|| invalid("The 'Basic' credentials were not valid base64 encoded UTF8"))?;
+    let (username, password) = decoded
+        .split_once(':')
+        .ok_or_else(|| invalid("The 'Basic' credentials had no password"))?;
+    Ok(Credentials {
+        username: username.to_string(),
+        password: Secret::new(password.to_string()),
+    })
+}
+
+/// The user with these credentials
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+pub async fn validate_credentials(
+    credentials: Credentials,
+    pool: &PgPool,
+) -> Result<AdminUser, AuthError> {
+    let stored = sqlx::query!(
+        r#"
+        SELECT user_id, password_hash, role, totp_enabled_at IS NOT NULL AS "totp_enabled!",
+            totp_required
+        FROM users
+        WHERE username = $1
+        "#,
+        credentials.username,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| AuthError::UnexpectedError(Box::new(e)))?;
+    let (user, expected_password_hash) = match stored {
+        Some(row) if row.password_hash.is_some() => (
+            Some(AdminUser {
+                user_id: row.user_id,
+                role: Role::parse(&row.role).map_err(|e| AuthError::UnexpectedError(e.into()))?,
+                totp_enabled: row.totp_enabled,
+                totp_required: row.totp_required,
+                api_key: None,
+            }),
+            Secret::new(row.password_hash.unwrap_or_default()),
+        ),
+        // Unknown, or signing in with the OpenID Connect provider only
+        _ => (None, Secret::new(DUMMY_PASSWORD_HASH.to_string())),
+    };
+
+    spawn_blocking_with_tracing(move || {
+        verify_password_hash(&expected_password_hash, &credentials.password)
+    })
+    .await
+    .map_err(|e| AuthError::UnexpectedError(Box::new(e)))??;
+
+    user.ok_or_else(|| {
+        AuthError::InvalidCredentials("Unknown username, or no password".to_string())
+    })
+}
+
+#[tracing::instrument(name = "Verify password hash", skip_all)]
+fn verify_password_hash(
+    expected_password_hash: &Secret<String>,
+    password_candidate: &Secret<String>,
+) -> Result<(), AuthError> {
+    let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
+        .map_err(|e| AuthError::UnexpectedError(e.to_string().into()))?;
+    Argon2::default()
+        .verify_password(
+            password_candidate.expose_secret().as_bytes(),
+            &expected_password_hash,
+        )
+        .map_err(|_| AuthError::InvalidCredentials("Invalid password".to_string()))
+}
+
+/// A user authenticated with the bearer token of their session or of one of
+/// their API keys, or with HTTP Basic authentication unless they have TOTP
+/// enabled, required by every admin endpoint through an
+/// [`Authorized`](crate::authorization::Authorized) checking their role.
+#[derive(Debug, Clone)]
+pub struct AdminUser {
+    pub user_id: Uuid,
+    pub role: Role,
+    pub totp_enabled: bool,
+    /// Set by an owner, see [`AuthError::EnrollmentRequired`]
+    pub totp_required: bool,
+    /// Set when authenticated with an API key
+    pub api_key: Option<ApiKey>,
+}
+
+impl AdminUser {
+    /// Whether their role, and their API key if they use one, grant the
+    /// permission
+    #[must_use]
+    pub fn can(&self, permission: Permission) -> bool {
+        self.role.can(permission)
+            && self.api_key.as_ref().is_none_or(|api_key| {
+                api_key
+                    .permissions
+                    .iter()
+                    .any(|scoped| scoped.grants(permission))
+            })
+    }
+}
+
+impl FromRequest for AdminUser {
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(request: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let bearer_token = bearer_token(request.headers());
+        let credentials = basic_authentication(request.headers());
+        let pool = request.app_data::<Data<PgPool>>().cloned();
+        Box::pin(async move {
+            let pool = pool.ok_or_else(|| {
+                AuthError::UnexpectedError("The database pool is not configured".into())
+            })?;
+            if let Some(token) = bearer_token {
+                let user = if token.expose_secret().starts_with(API_KEY_PREFIX) {
+                    api_key_user(&pool, &token).await
+                } else {
+                    session_user(&pool, &token).await
+                };
+                return user
+                    .map_err(|e| AuthError::UnexpectedError(Box::new(e)))?
+                    .ok_or_else(|| {
+                        AuthError::InvalidCredentials(
+                            "Unknown, expired or revoked token".to_string(),
+                        )
+                    });
+            }
+            let user = validate_credentials(credentials?, &pool).await?;
+            if user.totp_enabled {
+                return Err(AuthError::SecondFactorRequired);
+            }
+            Ok(user)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use claim::assert_err;
+    use secrecy::{ExposeSecret, Secret};
+
+    use super::{basic_authentication, compute_password_hash, verify_password_hash};
+
+    #[test]
+    fn basic_credentials_are_parsed() {
+        let mut headers = HeaderMap::new();
+        // admin:pass:word
+        headers.insert(
+            AUTHORIZATION,
+            HeaderValue::from_static("Basic YWRtaW46cGFzczp3b3Jk"),
+        );
+
+        let credentials = basic_authentication(&headers).unwrap();
+
+        assert_eq!(credentials.username, "admin");
+        assert_eq!(credentials.password.expose_secret(), "pass:word");
+    }
+
+    #[test]
+    fn other_authorization_schemes_are_rejected() {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer token"));
+
+        assert_err!(basic_authentication(&headers));
+    }
+
+    #[test]
+    fn a_password_only_matches_its_own_hash() {
+        let password = Secret::new("correct horse".to_string());
+        let hash = compute_password_hash(&password);
+
+        assert!(verify_password_hash(&hash, &password).is_ok());
+        assert_err!(verify_password_hash(
+            &hash,
+            &Secret::new("battery staple".to_string())
+        ));
+    }
+}
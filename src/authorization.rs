@@ -0,0 +1,297 @@
+//! What each admin is allowed to do, according to their role.
+//!
+//! Roles are ordered, each one allowed everything the previous ones are:
+//! viewers read analytics, editors also see subscribers and draft issues,
+//! publishers also change subscribers and send issues, and owners also
+//! manage the other admins.
+//!
+//! API keys act on behalf of the admin who created them, and are further
+//! restricted to the scopes they were given, each granting one permission,
+//! and write scopes the matching read ones: `subscribers:write` also grants
+//! `subscribers:read`.
+
+use std::{future::Future, marker::PhantomData, ops::Deref, pin::Pin};
+
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use serde::Deserialize;
+
+use crate::authentication::{AdminUser, AuthError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Publisher,
+    Owner,
+}
+
+impl Role {
+    /// # Errors
+    ///
+    /// If `role` is not the name of a role.
+    pub fn parse(role: &str) -> Result<Self, String> {
+        match role {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "publisher" => Ok(Self::Publisher),
+            "owner" => Ok(Self::Owner),
+            other => Err(format!(
+                "{other} is not a role, use viewer, editor, publisher or owner"
+            )),
+        }
+    }
+
+    #[must_use]
+    pub fn can(self, permission: Permission) -> bool {
+        self >= permission.minimum_role()
+    }
+}
+
+impl AsRef<str> for Role {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Publisher => "publisher",
+            Self::Owner => "owner",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Analytics and delivery reports
+    ViewAnalytics,
+    /// Subscribers, their exports and the data held about them
+    ViewSubscribers,
+    /// Write and change issues until they are published
+    DraftIssues,
+    /// Add, change, import, delete and erase subscribers
+    ManageSubscribers,
+    /// Send issues, including replaying their dead letters
+    PublishIssues,
+    /// Admins and the audit log of their changes
+    ManageUsers,
+}
+
+impl Permission {
+    #[must_use]
+    pub fn minimum_role(self) -> Role {
+        match self {
+            Self::ViewAnalytics => Role::Viewer,
+            Self::ViewSubscribers | Self::DraftIssues => Role::Editor,
+            Self::ManageSubscribers | Self::PublishIssues => Role::Publisher,
+            Self::ManageUsers => Role::Owner,
+        }
+    }
+}
+
+impl Permission {
+    /// The permissions API keys can be given, by scope
+    pub const SCOPED: [Self; 5] = [
+        Self::ViewAnalytics,
+        Self::ViewSubscribers,
+        Self::DraftIssues,
+        Self::ManageSubscribers,
+        Self::PublishIssues,
+    ];
+
+    /// The scope of API keys granting the permission, none granting the
+    /// management of admins
+    #[must_use]
+    pub fn scope(self) -> Option<&'static str> {
+        match self {
+            Self::ViewAnalytics => Some("analytics:read"),
+            Self::ViewSubscribers => Some("subscribers:read"),
+            Self::DraftIssues => Some("issues:draft"),
+            Self::ManageSubscribers => Some("subscribers:write"),
+            Self::PublishIssues => Some("issues:publish"),
+            Self::ManageUsers => None,
+        }
+    }
+
+    /// Whether API keys given the scope of this permission are allowed
+    /// `permission`, writing implying reading
+    #[must_use]
+    pub fn grants(self, permission: Self) -> bool {
+        self == permission
+            || matches!(
+                (self, permission),
+                (Self::ManageSubscribers, Self::ViewSubscribers)
+            )
+    }
+
+    /// # Errors
+    ///
+    /// If `scope` is not the scope of a permission.
+    pub fn from_scope(scope: &str) -> Result<Self, String> {
+        Self::SCOPED
+            .into_iter()
+            .find(|permission| permission.scope() == Some(scope))
+            .ok_or_else(|| {
+                let scopes: Vec<&str> = Self::SCOPED.iter().filter_map(|p| p.scope()).collect();
+                format!("{scope} is not a scope, use {}", scopes.join(", "))
+            })
+    }
+}
+
+impl AsRef<str> for Permission {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::ViewAnalytics => "view_analytics",
+            Self::ViewSubscribers => "view_subscribers",
+            Self::DraftIssues => "draft_issues",
+            Self::ManageSubscribers => "manage_subscribers",
+            Self::PublishIssues => "publish_issues",
+            Self::ManageUsers => "manage_users",
+        }
+    }
+}
+
+/// The permission an [`Authorized`] admin needs, as a type
+pub trait Requirement {
+    const PERMISSION: Permission;
+}
+
+#[derive(Debug)]
+pub struct ViewAnalytics;
+#[derive(Debug)]
+pub struct ViewSubscribers;
+#[derive(Debug)]
+pub struct DraftIssues;
+#[derive(Debug)]
+pub struct ManageSubscribers;
+#[derive(Debug)]
+pub struct PublishIssues;
+#[derive(Debug)]
+pub struct ManageUsers;
+
+impl Requirement for ViewAnalytics {
+    const PERMISSION: Permission = Permission::ViewAnalytics;
+}
+
+impl Requirement for ViewSubscribers {
+    const PERMISSION: Permission = Permission::ViewSubscribers;
+}
+
+impl Requirement for DraftIssues {
+    const PERMISSION: Permission = Permission::DraftIssues;
+}
+
+impl Requirement for ManageSubscribers {
+    const PERMISSION: Permission = Permission::ManageSubscribers;
+}
+
+impl Requirement for PublishIssues {
+    const PERMISSION: Permission = Permission::PublishIssues;
+}
+
+impl Requirement for ManageUsers {
+    const PERMISSION: Permission = Permission::ManageUsers;
+}
+
+/// An admin whose role, and API key if they use one, grant the permission
+/// `R` requires, which endpoints take instead of an [`AdminUser`]. Others are
+/// answered with a 403, as are admins who have yet to enable the TOTP an
+/// owner requires of them.
+#[derive(Debug)]
+pub struct Authorized<R> {
+    user: AdminUser,
+    requirement: PhantomData<R>,
+}
+
+impl<R> Deref for Authorized<R> {
+    type Target = AdminUser;
+
+    fn deref(&self) -> &Self::Target {
+        &self.user
+    }
+}
+
+impl<R: Requirement + 'static> FromRequest for Authorized<R> {
+    type Error = AuthError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(request: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let user = AdminUser::from_request(request, payload);
+        Box::pin(async move {
+            let user = user.await?;
+            if user.api_key.is_none() && user.totp_required && !user.totp_enabled {
+                return Err(AuthError::EnrollmentRequired);
+            }
+            if !user.can(R::PERMISSION) {
+                return Err(AuthError::Forbidden(R::PERMISSION));
+            }
+            Ok(Self {
+                user,
+                requirement: PhantomData,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok_eq};
+
+    use super::{Permission, Role};
+
+    #[test]
+    fn roles_are_parsed_from_their_names() {
+        for role in [Role::Viewer, Role::Editor, Role::Publisher, Role::Owner] {
+            assert_ok_eq!(Role::parse(role.as_ref()), role);
+        }
+        assert_err!(Role::parse("admin"));
+        assert_err!(Role::parse("Owner"));
+    }
+
+    #[test]
+    fn each_role_is_allowed_what_the_previous_ones_are() {
+        let allowed = |role: Role| {
+            [
+                Permission::ViewAnalytics,
+                Permission::ViewSubscribers,
+                Permission::DraftIssues,
+                Permission::ManageSubscribers,
+                Permission::PublishIssues,
+                Permission::ManageUsers,
+            ]
+            .into_iter()
+            .filter(|permission| role.can(*permission))
+            .count()
+        };
+
+        assert_eq!(allowed(Role::Viewer), 1);
+        assert_eq!(allowed(Role::Editor), 3);
+        assert_eq!(allowed(Role::Publisher), 5);
+        assert_eq!(allowed(Role::Owner), 6);
+        assert!(!Role::Viewer.can(Permission::ViewSubscribers));
+        assert!(!Role::Editor.can(Permission::PublishIssues));
+        assert!(!Role::Publisher.can(Permission::ManageUsers));
+    }
+
+    #[test]
+    fn scopes_are_parsed_into_the_permission_they_grant() {
+        for permission in Permission::SCOPED {
+            assert_ok_eq!(
+                Permission::from_scope(permission.scope().unwrap()),
+                permission
+            );
+        }
+        assert_ok_eq!(
+            Permission::from_scope("subscribers:write"),
+            Permission::ManageSubscribers
+        );
+        assert_err!(Permission::from_scope("manage_users"));
+        assert_err!(Permission::from_scope("users:write"));
+    }
+
+    #[test]
+    fn write_scopes_grant_the_matching_read_scope() {
+        assert!(Permission::ManageSubscribers.grants(Permission::ViewSubscribers));
+        assert!(!Permission::ViewSubscribers.grants(Permission::ManageSubscribers));
+        assert!(!Permission::ManageSubscribers.grants(Permission::PublishIssues));
+        assert!(Permission::PublishIssues.grants(Permission::PublishIssues));
+    }
+}
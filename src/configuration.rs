@@ -1,16 +1,39 @@
-use std::time::Duration;
+use std::{collections::HashMap, net::IpAddr, time::Duration};
 
 use config::{Config, ConfigError, File};
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 
-use crate::domain::SubscriberEmail;
+use lettre::{
+    message::dkim::{DkimConfig, DkimSigningAlgorithm, DkimSigningKey},
+    transport::smtp::{
+        authentication::{Credentials, Mechanism},
+        PoolConfig,
+    },
+    AsyncSmtpTransport, Tokio1Executor,
+};
+
+use crate::{
+    authorization::Role,
+    domain::SubscriberEmail,
+    email_client::{
+        Capture, CircuitBreaker, EmailClient, EmailProvider, RateLimiter, Sendgrid, Smtp, Transport,
+    },
+    email_content::MarkdownRenderer,
+};
 
 #[derive(Deserialize)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub newsletter: NewsletterSettings,
+    pub retention: RetentionSettings,
+    /// Single sign-on of admins, disabled when unset
+    pub oidc: Option<OidcSettings>,
+    /// Set from the `ENVIRONMENT` variable rather than the configuration files
+    #[serde(skip)]
+    pub environment: Environment,
 }
 
 #[derive(Deserialize)]
@@ -22,7 +45,9 @@ pub struct DatabaseSettings {
     pub name: String,
 }
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Environment {
+    #[default]
     Development,
     Production,
 }
@@ -73,7 +98,13 @@ pub fn get() -> Result<Settings, ConfigError> {
         .add_source(config::Environment::default())
         .build()?;
 
-    settings.try_deserialize::<Settings>()
+    let mut settings = settings.try_deserialize::<Settings>()?;
+    settings.environment = environment;
+    settings
+        .retention
+        .validate()
+        .map_err(ConfigError::Message)?;
+    Ok(settings)
 }
 
 impl DatabaseSettings {
@@ -109,17 +140,277 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    /// The reverse proxies in front of the application, whose
+    /// `X-Forwarded-For` header gives the address of clients
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 #[derive(Deserialize)]
 pub struct EmailClientSettings {
+    pub backend: EmailBackend,
+    /// Where the `capture` backend writes emails, they are kept in memory when unset
+    pub capture_directory: Option<String>,
+    /// Required by the `smtp` backend
+    pub smtp: Option<SmtpSettings>,
     pub base_url: String,
     sender_email: String,
     pub authorization_token: Secret<String>,
+    /// Expected in the `Authorization` header of the SendGrid Event Webhook,
+    /// as a bearer token or the password of basic credentials
+    pub webhook_token: Secret<String>,
     timeout_milliseconds: u64,
+    stylesheet_path: String,
+    pub circuit_breaker: CircuitBreakerSettings,
+    /// How fast the primary provider lets us send, unlimited when unset
+    pub rate_limit: Option<RateLimitSettings>,
+    /// How many emails can be sent per UTC day, unlimited when unset.
+    /// Issue deliveries wait for the next day once it is reached.
+    pub daily_quota: Option<u32>,
+    /// Providers to try, in order, when SendGrid fails or its circuit breaker is open
+    #[serde(default)]
+    pub fallbacks: Vec<FallbackProviderSettings>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailBackend {
+    Sendgrid,
+    /// Relay emails through an SMTP server
+    Smtp,
+    /// Keep emails for the development mailbox instead of delivering them
+    Capture,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    /// Authenticate when both the username and the password are set
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    /// Mechanisms to authenticate with, the first one offered by the server is used
+    #[serde(default = "default_smtp_authentication")]
+    pub authentication: Vec<SmtpAuthentication>,
+    /// Maximum number of connections kept open to the server
+    pub pool_size: u32,
+    pub dkim: Option<DkimSettings>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    /// Plain text, only for servers on the same host or network
+    None,
+    /// Upgrade the connection with `STARTTLS`, which the server must support
+    Starttls,
+    /// Connect with TLS from the start, usually on port 465
+    Implicit,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpAuthentication {
+    Plain,
+    Login,
+}
+
+fn default_smtp_authentication() -> Vec<SmtpAuthentication> {
+    vec![SmtpAuthentication::Plain, SmtpAuthentication::Login]
+}
+
+/// Sign outgoing messages, the public key being published in the DNS as
+/// `<selector>._domainkey.<domain>`
+#[derive(Deserialize, Clone)]
+pub struct DkimSettings {
+    pub selector: String,
+    pub domain: String,
+    pub algorithm: DkimAlgorithm,
+    /// A PKCS#1 PEM file for RSA, the Base64 encoded key for Ed25519
+    pub private_key_path: String,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DkimAlgorithm {
+    Rsa,
+    Ed25519,
+}
+
+impl SmtpSettings {
+    /// # Panics
+    ///
+    /// If TLS cannot be set up for the host, or the DKIM private key cannot be read.
+    #[must_use]
+    pub fn transport(&self, timeout: Duration) -> Smtp {
+        let builder = match self.tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&self.host),
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)
+                .expect("Failed to set up TLS for the SMTP server"),
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)
+                .expect("Failed to set up TLS for the SMTP server"),
+        };
+        let mut builder = builder
+            .port(self.port)
+            .timeout(Some(timeout))
+            .pool_config(PoolConfig::new().max_size(self.pool_size));
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            let mechanisms = self
+                .authentication
+                .iter()
+                .map(|mechanism| match mechanism {
+                    SmtpAuthentication::Plain => Mechanism::Plain,
+                    SmtpAuthentication::Login => Mechanism::Login,
+                })
+                .collect();
+            builder = builder
+                .credentials(Credentials::new(
+                    username.clone(),
+                    password.expose_secret().clone(),
+                ))
+                .authentication(mechanisms);
+        }
+        let dkim = self.dkim.as_ref().map(DkimSettings::config);
+        Smtp::new(builder.build(), dkim)
+    }
+}
+
+impl DkimSettings {
+    /// # Panics
+    ///
+    /// If the private key cannot be read or parsed.
+    #[must_use]
+    pub fn config(&self) -> DkimConfig {
+        let private_key = std::fs::read_to_string(&self.private_key_path)
+            .expect("Failed to read the DKIM private key");
+        let algorithm = match self.algorithm {
+            DkimAlgorithm::Rsa => DkimSigningAlgorithm::Rsa,
+            DkimAlgorithm::Ed25519 => DkimSigningAlgorithm::Ed25519,
+        };
+        let signing_key =
+            DkimSigningKey::new(private_key.trim(), algorithm).expect("Invalid DKIM private key");
+        DkimConfig::default_config(self.selector.clone(), self.domain.clone(), signing_key)
+    }
+}
+
+/// Thresholds of the circuit breaker guarding each email provider
+#[derive(Deserialize, Clone)]
+pub struct CircuitBreakerSettings {
+    /// Consecutive failures after which requests fail fast
+    pub failure_threshold: u32,
+    /// How long requests fail fast before trial requests are let through
+    pub open_milliseconds: u64,
+    /// Consecutive successful trial requests after which the breaker closes
+    pub success_threshold: u32,
+}
+
+impl CircuitBreakerSettings {
+    #[must_use]
+    pub fn circuit_breaker(&self) -> CircuitBreaker {
+        CircuitBreaker::new(
+            self.failure_threshold,
+            Duration::from_millis(self.open_milliseconds),
+            self.success_threshold,
+        )
+    }
+}
+
+/// The rate limit of an email provider, enforced with a token bucket
+#[derive(Deserialize, Clone)]
+pub struct RateLimitSettings {
+    pub messages_per_second: f64,
+    /// How many messages can be sent at once after a quiet period
+    pub burst: u32,
+}
+
+impl RateLimitSettings {
+    #[must_use]
+    pub fn rate_limiter(&self) -> RateLimiter {
+        RateLimiter::new(self.messages_per_second, self.burst)
+    }
+}
+
+/// A provider compatible with the SendGrid Mail Send API
+#[derive(Deserialize, Clone)]
+pub struct FallbackProviderSettings {
+    pub name: String,
+    pub base_url: String,
+    pub authorization_token: Secret<String>,
+    pub rate_limit: Option<RateLimitSettings>,
 }
 
 impl EmailClientSettings {
+    /// # Panics
+    ///
+    /// If the sender email is invalid or the stylesheet cannot be read.
+    #[must_use]
+    pub fn client(&self) -> EmailClient {
+        let sender = self.sender().expect("Invalid sender email address");
+        let stylesheet = self.stylesheet().expect("Failed to read email stylesheet");
+        let rate_limiter = self
+            .rate_limit
+            .as_ref()
+            .map(RateLimitSettings::rate_limiter);
+        let primary = match self.backend {
+            EmailBackend::Sendgrid => EmailProvider::new(
+                "sendgrid".to_string(),
+                Transport::Sendgrid(Sendgrid::new(
+                    self.base_url.clone(),
+                    self.authorization_token.clone(),
+                    self.timeout(),
+                )),
+                self.circuit_breaker.circuit_breaker(),
+                rate_limiter,
+            ),
+            EmailBackend::Smtp => EmailProvider::new(
+                "smtp".to_string(),
+                Transport::Smtp(
+                    self.smtp
+                        .as_ref()
+                        .expect("The smtp email backend requires `email_client.smtp` settings")
+                        .transport(self.timeout()),
+                ),
+                self.circuit_breaker.circuit_breaker(),
+                rate_limiter,
+            ),
+            EmailBackend::Capture => EmailProvider::new(
+                "capture".to_string(),
+                Transport::Capture(
+                    self.capture_directory
+                        .as_ref()
+                        .map_or_else(Capture::in_memory, |directory| {
+                            Capture::in_directory(directory.into())
+                        }),
+                ),
+                self.circuit_breaker.circuit_breaker(),
+                rate_limiter,
+            ),
+        };
+        let fallbacks = self.fallbacks.iter().map(|fallback| {
+            EmailProvider::new(
+                fallback.name.clone(),
+                Transport::Sendgrid(Sendgrid::new(
+                    fallback.base_url.clone(),
+                    fallback.authorization_token.clone(),
+                    self.timeout(),
+                )),
+                self.circuit_breaker.circuit_breaker(),
+                fallback
+                    .rate_limit
+                    .as_ref()
+                    .map(RateLimitSettings::rate_limiter),
+            )
+        });
+        EmailClient::new(
+            sender,
+            std::iter::once(primary).chain(fallbacks).collect(),
+            MarkdownRenderer::new(stylesheet),
+            self.daily_quota,
+        )
+    }
+
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
@@ -128,4 +419,109 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> Duration {
         Duration::from_millis(self.timeout_milliseconds)
     }
+
+    /// The CSS inlined into the HTML body of emails rendered from Markdown
+    pub fn stylesheet(&self) -> Result<String, std::io::Error> {
+        std::fs::read_to_string(&self.stylesheet_path)
+    }
+}
+
+/// How the newsletter presents itself in the public archive and its feeds
+#[derive(Deserialize, Clone)]
+pub struct NewsletterSettings {
+    pub title: String,
+    pub description: String,
+}
+
+/// How long subscribers who never confirmed are kept
+#[derive(Deserialize, Debug, Clone)]
+pub struct RetentionSettings {
+    /// Subscribers still pending confirmation this many days after signing
+    /// up are deleted, along with their tokens
+    pub pending_subscriber_days: u32,
+    /// Remind them once to confirm this many days after signing up, if set.
+    /// Must be less than `pending_subscriber_days`, those reminded being
+    /// given the days in between to confirm before they are deleted.
+    pub reminder_after_days: Option<u32>,
+    /// Only log who would be reminded and deleted
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl RetentionSettings {
+    fn validate(&self) -> Result<(), String> {
+        match self.reminder_after_days {
+            Some(days) if days >= self.pending_subscriber_days => Err(format!(
+                "retention.reminder_after_days ({days}) must be less than \
+                retention.pending_subscriber_days ({})",
+                self.pending_subscriber_days
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// How long reminded subscribers are given to confirm before they are
+    /// deleted, if they are reminded at all
+    #[must_use]
+    pub fn reminder_grace_period(&self) -> Option<chrono::Duration> {
+        self.reminder_after_days.map(|days| {
+            chrono::Duration::days(self.pending_subscriber_days.saturating_sub(days).into())
+        })
+    }
+}
+
+/// The OpenID Connect provider admins can sign in with, using the
+/// authorization code flow with PKCE
+#[derive(Deserialize, Clone)]
+pub struct OidcSettings {
+    /// Where `/.well-known/openid-configuration` is found, and the `iss` of
+    /// its ID tokens
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: Secret<String>,
+    /// The ID token claim holding the groups or roles of the admin, as a
+    /// string or an array of strings
+    pub role_claim: String,
+    /// The role each value of the role claim grants, the highest one being
+    /// kept. Admins granted none cannot sign in.
+    pub roles: HashMap<String, Role>,
+    /// The ID token claim usernames are taken from
+    #[serde(default = "default_username_claim")]
+    pub username_claim: String,
+}
+
+fn default_username_claim() -> String {
+    "email".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+
+    use super::RetentionSettings;
+
+    fn retention(reminder_after_days: Option<u32>) -> RetentionSettings {
+        RetentionSettings {
+            pending_subscriber_days: 30,
+            reminder_after_days,
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn reminders_must_come_before_the_purge() {
+        assert_ok!(retention(None).validate());
+        assert_ok!(retention(Some(23)).validate());
+        assert_err!(retention(Some(30)).validate());
+        assert_err!(retention(Some(45)).validate());
+    }
+
+    #[test]
+    fn reminded_subscribers_have_until_the_end_of_the_retention_period() {
+        assert_eq!(retention(None).reminder_grace_period(), None);
+        assert_eq!(
+            retention(Some(23)).reminder_grace_period(),
+            Some(chrono::Duration::days(7))
+        );
+    }
 }
@@ -0,0 +1,132 @@
+//! Proof of the consent of subscribers, kept in the append-only
+//! `consent_events` table.
+
+use std::net::IpAddr;
+
+use actix_web::{http::header, HttpRequest};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::utils::client_ip_address;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsentEvent {
+    /// The subscriber filled in the subscription form
+    Subscribed,
+    /// The subscriber followed the link of their confirmation email
+    Confirmed,
+}
+
+impl AsRef<str> for ConsentEvent {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Subscribed => "subscribed",
+            Self::Confirmed => "confirmed",
+        }
+    }
+}
+
+/// Where and how a subscriber gave their consent
+#[derive(Debug, Default)]
+pub struct Consent {
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    /// The form or page the subscriber signed up from
+    pub source: Option<String>,
+    /// The version of the consent text the subscriber was shown
+    pub consent_text_version: Option<String>,
+}
+
+impl Consent {
+    /// The address and user agent of the client behind `request`
+    #[must_use]
+    pub fn from_request(request: &HttpRequest) -> Self {
+        Self {
+            ip_address: client_ip_address(request),
+            user_agent: request
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|user_agent| user_agent.to_str().ok())
+                .map(ToString::to_string),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ConsentRecord {
+    pub event: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub source: Option<String>,
+    pub consent_text_version: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Record consent", skip(executor, consent))]
+pub async fn record_consent(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+    event: ConsentEvent,
+    consent: &Consent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_events
+            (subscriber_id, event, ip_address, user_agent, source, consent_text_version)
+        VALUES ($1, $2, $3::text::inet, $4, $5, $6)
+        "#,
+        subscriber_id,
+        event.as_ref(),
+        consent.ip_address.map(|ip_address| ip_address.to_string()),
+        consent.user_agent,
+        consent.source,
+        consent.consent_text_version,
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// Delete the proof of consent of subscribers about to be deleted, which is
+/// only done when erasing their data or purging them for never confirming
+#[tracing::instrument(skip(executor))]
+pub async fn erase_consent_events(
+    executor: impl PgExecutor<'_>,
+    subscriber_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM consent_events WHERE subscriber_id = ANY($1)",
+        subscriber_ids,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// The consent events of a subscriber, oldest first
+#[tracing::instrument(skip(executor))]
+pub async fn consent_events(
+    executor: impl PgExecutor<'_>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentRecord,
+        r#"
+        SELECT event, host(ip_address) AS ip_address, user_agent, source, consent_text_version,
+            occurred_at
+        FROM consent_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at, id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await
+}
@@ -0,0 +1,393 @@
+//! Answering the access and erasure requests of data subjects, as the GDPR
+//! calls the people whose email addresses we hold.
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::consent::{consent_events, erase_consent_events, ConsentRecord};
+
+/// Everything held about an email address
+#[derive(Serialize, Debug)]
+pub struct SubjectAccessReport {
+    pub email: String,
+    /// One per address matching `email` regardless of case
+    pub subscriptions: Vec<SubscriptionRecord>,
+    pub events: Vec<EventRecord>,
+    /// Whether the data of the address was erased before, only its hash
+    /// being kept since
+    pub erased_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscriptionRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub lists: Vec<String>,
+    pub tokens: Vec<TokenRecord>,
+    pub status_history: Vec<StatusRecord>,
+    pub consent_events: Vec<ConsentRecord>,
+    pub deliveries: Vec<DeliveryRecord>,
+    pub dead_letters: Vec<DeadLetterRecord>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TokenRecord {
+    pub subscription_token: String,
+    pub message_id: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct StatusRecord {
+    pub status: String,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeliveryRecord {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub n_attempts: i32,
+    pub last_error: Option<String>,
+    pub queued_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeadLetterRecord {
+    pub newsletter_issue_id: Uuid,
+    pub error_kind: String,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EventRecord {
+    pub event: String,
+    pub email: String,
+    pub message_id: Option<String>,
+    pub newsletter_issue_id: Option<Uuid>,
+    pub url: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+    pub received_at: DateTime<Utc>,
+}
+
+/// The SHA-256 of an email address, ignoring case, as kept in tombstones
+#[must_use]
+pub fn email_hash(email: &str) -> String {
+    hex::encode(Sha256::digest(email.trim().to_lowercase().as_bytes()))
+}
+
+/// Sign the data links of an email address, valid until `expires_at`
+#[must_use]
+pub fn sign_data_link(secret: &Secret<String>, email: &str, expires_at: i64) -> String {
+    hex::encode(
+        data_link_mac(secret, email, expires_at)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Whether `signature` signs the data links of `email` and has not expired
+#[must_use]
+pub fn verify_data_link(
+    secret: &Secret<String>,
+    email: &str,
+    expires_at: i64,
+    signature: &str,
+) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    expires_at > Utc::now().timestamp()
+        && data_link_mac(secret, email, expires_at)
+            .verify_slice(&signature)
+            .is_ok()
+}
+
+fn data_link_mac(secret: &Secret<String>, email: &str, expires_at: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(format!("data:{email}:{expires_at}").as_bytes());
+    mac
+}
+
+/// When the data of `email` was erased, if it was
+#[tracing::instrument(skip(executor, email))]
+pub async fn erased_at(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let tombstone = sqlx::query!(
+        "SELECT erased_at FROM erasure_tombstones WHERE email_hash = $1",
+        email_hash(email),
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(tombstone.map(|tombstone| tombstone.erased_at))
+}
+
+/// Everything held about `email`, whatever its case
+#[tracing::instrument(name = "Gather the data of a subject", skip(pool, email))]
+pub async fn subject_access_report(
+    pool: &PgPool,
+    email: &str,
+) -> Result<SubjectAccessReport, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT s.id, s.email, s.name, s.status, s.subscribed_at,
+            ARRAY(
+                SELECT l.name FROM subscription_lists sl
+                JOIN lists l ON l.id = sl.list_id
+                WHERE sl.subscriber_id = s.id
+                ORDER BY l.name
+            ) AS "lists!"
+        FROM subscriptions s
+        WHERE lower(s.email) = lower($1)
+        ORDER BY s.subscribed_at, s.id
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut subscriptions = Vec::with_capacity(rows.len());
+    for subscription in rows {
+        let id = subscription.id;
+        subscriptions.push(SubscriptionRecord {
+            id,
+            email: subscription.email,
+            name: subscription.name,
+            status: subscription.status,
+            subscribed_at: subscription.subscribed_at,
+            lists: subscription.lists,
+            tokens: sqlx::query_as!(
+                TokenRecord,
+                "SELECT subscription_token, message_id FROM subscription_tokens WHERE subscriber_id = $1",
+                id,
+            )
+            .fetch_all(pool)
+            .await?,
+            status_history: sqlx::query_as!(
+                StatusRecord,
+                r#"
+                SELECT status, changed_at FROM subscription_status_history
+                WHERE subscriber_id = $1
+                ORDER BY changed_at, id
+                "#,
+                id,
+            )
+            .fetch_all(pool)
+            .await?,
+            consent_events: consent_events(pool, id).await?,
+            deliveries: sqlx::query_as!(
+                DeliveryRecord,
+                r#"
+                SELECT d.newsletter_issue_id, i.title, d.status, d.n_attempts, d.last_error,
+                    d.queued_at, d.sent_at
+                FROM issue_deliveries d
+                JOIN newsletter_issues i ON i.id = d.newsletter_issue_id
+                WHERE d.subscriber_id = $1
+                ORDER BY d.queued_at
+                "#,
+                id,
+            )
+            .fetch_all(pool)
+            .await?,
+            dead_letters: sqlx::query_as!(
+                DeadLetterRecord,
+                r#"
+                SELECT newsletter_issue_id, error_kind, error, failed_at FROM dead_letters
+                WHERE subscriber_id = $1
+                ORDER BY failed_at
+                "#,
+                id,
+            )
+            .fetch_all(pool)
+            .await?,
+        });
+    }
+
+    let subscriber_ids: Vec<Uuid> = subscriptions
+        .iter()
+        .map(|subscription| subscription.id)
+        .collect();
+    let events = sqlx::query_as!(
+        EventRecord,
+        r#"
+        SELECT event, email, message_id, newsletter_issue_id, url, occurred_at, received_at
+        FROM email_events
+        WHERE lower(email) = lower($1) OR subscriber_id = ANY($2)
+        ORDER BY occurred_at
+        "#,
+        email,
+        &subscriber_ids,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(SubjectAccessReport {
+        email: email.to_string(),
+        subscriptions,
+        events,
+        erased_at: erased_at(pool, email).await?,
+    })
+}
+
+/// Delete everything held about `email`, whatever its case, leaving a
+/// tombstone with its hash so that it is not imported again.
+///
+/// Returns whether anything was held about it.
+#[tracing::instrument(name = "Erase the data of a subject", skip(transaction, email))]
+pub async fn erase_subject(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let subscriber_ids: Vec<Uuid> = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1)",
+        email,
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|subscription| subscription.id)
+    .collect();
+    // Tokens and proof of consent are the only rows not deleted along with
+    // their subscription
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)",
+        &subscriber_ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    erase_consent_events(&mut *transaction, &subscriber_ids).await?;
+    let subscriptions = sqlx::query!(
+        "DELETE FROM subscriptions WHERE id = ANY($1)",
+        &subscriber_ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let events = sqlx::query!(
+        "DELETE FROM email_events WHERE lower(email) = lower($1) OR subscriber_id = ANY($2)",
+        email,
+        &subscriber_ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO erasure_tombstones (email_hash) VALUES ($1)
+        ON CONFLICT (email_hash) DO UPDATE SET erased_at = now()
+        "#,
+        email_hash(email),
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let erased = subscriptions.rows_affected() + events.rows_affected() > 0;
+    tracing::info!(erased, "Erased the data of a subject");
+    Ok(erased)
+}
+
+/// Lift the tombstone of `email`, once its owner subscribed again
+#[tracing::instrument(skip(executor, email))]
+pub async fn remove_tombstone(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM erasure_tombstones WHERE email_hash = $1",
+        email_hash(email),
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use secrecy::Secret;
+
+    use super::{email_hash, sign_data_link, verify_data_link};
+
+    #[test]
+    fn email_hashes_ignore_case_and_surrounding_spaces() {
+        assert_eq!(
+            email_hash(" Ursula@Example.com"),
+            email_hash("ursula@example.com")
+        );
+        assert_ne!(
+            email_hash("ursula@example.com"),
+            email_hash("ted@example.com")
+        );
+    }
+
+    #[test]
+    fn data_links_are_only_valid_for_their_email_until_they_expire() {
+        let secret = Secret::new("secret".to_string());
+        let expires_at = Utc::now().timestamp() + 60;
+        let signature = sign_data_link(&secret, "ursula@example.com", expires_at);
+
+        assert!(verify_data_link(
+            &secret,
+            "ursula@example.com",
+            expires_at,
+            &signature
+        ));
+        assert!(!verify_data_link(
+            &secret,
+            "ted@example.com",
+            expires_at,
+            &signature
+        ));
+        assert!(!verify_data_link(
+            &secret,
+            "ursula@example.com",
+            expires_at + 1,
+            &signature
+        ));
+        assert!(!verify_data_link(
+            &Secret::new("other secret".to_string()),
+            "ursula@example.com",
+            expires_at,
+            &signature
+        ));
+        assert!(!verify_data_link(
+            &secret,
+            "ursula@example.com",
+            expires_at,
+            "nope"
+        ));
+    }
+
+    #[test]
+    fn expired_data_links_are_invalid() {
+        let secret = Secret::new("secret".to_string());
+        let expires_at = Utc::now().timestamp() - 1;
+        let signature = sign_data_link(&secret, "ursula@example.com", expires_at);
+
+        assert!(!verify_data_link(
+            &secret,
+            "ursula@example.com",
+            expires_at,
+            &signature
+        ));
+    }
+}
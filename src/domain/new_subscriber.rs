@@ -1,5 +1,6 @@
 use super::{SubscriberEmail, SubscriberName};
 
+#[derive(Debug)]
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
@@ -1,83 +1,452 @@
-use std::time::Duration;
+mod capture;
+mod circuit_breaker;
+mod mime;
+mod rate_limiter;
+mod sendgrid;
+mod smtp;
 
-use reqwest::Client;
-use secrecy::{ExposeSecret, Secret};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    time::Duration,
+};
+
+pub use capture::{Capture, CapturedEmail};
+pub use circuit_breaker::{CircuitBreaker, CircuitState, Permit};
+pub use rate_limiter::RateLimiter;
+pub use sendgrid::Sendgrid;
+pub use smtp::Smtp;
 
 use crate::domain::SubscriberEmail;
-use crate::sendgrid_email_format::{
-    ContentField, FromField, PersonalizationField, SendgridEmailFormat, ToField,
-};
+use crate::email_content::MarkdownRenderer;
+use crate::utils::error_chain_fmt;
+
+/// SendGrid accepts at most this many personalizations in a single request
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// A recipient of a batch email, with the values replacing each substitution
+/// key (e.g. `{{name}}`) in the subject and content sent to them.
+///
+/// `custom_args` are echoed back by SendGrid in the webhook events about the
+/// email, to correlate them with e.g. the subscriber it was sent to.
+#[derive(Debug, Clone)]
+pub struct Recipient {
+    pub email: SubscriberEmail,
+    pub substitutions: BTreeMap<String, String>,
+    pub custom_args: BTreeMap<String, String>,
+}
+
+impl Recipient {
+    /// Replace the substitution keys in `text`, as SendGrid does for providers
+    /// that do not support substitutions themselves.
+    #[must_use]
+    pub fn substitute(&self, text: &str) -> String {
+        self.substitutions
+            .iter()
+            .fold(text.to_string(), |text, (key, value)| {
+                text.replace(key.as_str(), value)
+            })
+    }
+}
+
+/// An email as handed over to a provider
+pub struct OutgoingEmail<'a> {
+    pub sender: &'a SubscriberEmail,
+    pub recipients: &'a [Recipient],
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+}
+
+/// An email the provider accepted for delivery
+#[derive(Debug)]
+pub struct SentEmail {
+    /// The `X-Message-Id` SendGrid assigned to the request, which prefixes
+    /// the `sg_message_id` of the webhook events about the email. Over SMTP,
+    /// the `Message-ID` of the message to each recipient is
+    /// `<{message_id}.{n}@{sender domain}>`.
+    pub message_id: Option<String>,
+    /// Recipients the provider refused while accepting the others
+    pub rejected_recipients: Vec<SubscriberEmail>,
+    /// Set when the provider failed partway through the batch, after
+    /// accepting it for some recipients
+    pub unsent: Option<Unsent>,
+}
+
+/// The recipients of a batch the email was not sent to yet, because the
+/// provider failed with `error`. They can be sent to again without the
+/// recipients that already got the email.
+#[derive(Debug)]
+pub struct Unsent {
+    pub recipients: Vec<SubscriberEmail>,
+    pub error: EmailClientError,
+}
+
+pub enum EmailClientError {
+    /// The provider did not respond in time; the email may still be sent
+    Timeout(Box<dyn Error + Send + Sync>),
+    /// The request could not be sent or its response could not be read
+    Transport(Box<dyn Error + Send + Sync>),
+    /// The provider is throttling us; `retry_after` is how long it asked us
+    /// to wait, when it did.
+    RateLimited {
+        retry_after: Option<Duration>,
+    },
+    /// The provider refused the email, sending it again will not help.
+    /// `status` is the HTTP or SMTP status code of the response.
+    Rejected {
+        status: u16,
+        body: String,
+    },
+    /// The provider refused the request as a whole rather than the email,
+    /// e.g. because our credentials were revoked or the request is too
+    /// large. Another provider may accept it.
+    Refused {
+        status: u16,
+        body: String,
+    },
+    ServerError {
+        status: u16,
+        body: String,
+    },
+    /// The circuit breakers of every provider are open
+    Unavailable,
+}
+
+impl EmailClientError {
+    /// Whether sending the same email again later could succeed
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        !matches!(self, Self::Rejected { .. })
+    }
+
+    /// A stable name for the variant, e.g. to store alongside the message
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Timeout(_) => "timeout",
+            Self::Transport(_) => "transport",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::Rejected { .. } => "rejected",
+            Self::Refused { .. } => "refused",
+            Self::ServerError { .. } => "server_error",
+            Self::Unavailable => "unavailable",
+        }
+    }
+}
+
+impl Debug for EmailClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl Display for EmailClientError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Timeout(_) => write!(f, "The email provider did not respond in time."),
+            Self::Transport(_) => write!(f, "Failed to reach the email provider."),
+            Self::RateLimited {
+                retry_after: Some(retry_after),
+            } => write!(
+                f,
+                "The email provider is rate limiting us, retry after {}s.",
+                retry_after.as_secs()
+            ),
+            Self::RateLimited { retry_after: None } => {
+                write!(f, "The email provider is rate limiting us.")
+            }
+            Self::Rejected { status, body } => {
+                write!(
+                    f,
+                    "The email provider rejected the email ({status}): {body}"
+                )
+            }
+            Self::Refused { status, body } => {
+                write!(
+                    f,
+                    "The email provider refused our request ({status}): {body}"
+                )
+            }
+            Self::ServerError { status, body } => {
+                write!(f, "The email provider failed ({status}): {body}")
+            }
+            Self::Unavailable => write!(f, "No email provider is currently available."),
+        }
+    }
+}
+
+impl Error for EmailClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Timeout(e) | Self::Transport(e) => Some(e.as_ref()),
+            Self::RateLimited { .. }
+            | Self::Rejected { .. }
+            | Self::Refused { .. }
+            | Self::ServerError { .. }
+            | Self::Unavailable => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmailClientError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout(Box::new(e))
+        } else {
+            Self::Transport(Box::new(e))
+        }
+    }
+}
+
+/// How a provider is reached
+#[derive(Clone)]
+pub enum Transport {
+    Sendgrid(Sendgrid),
+    Smtp(Smtp),
+    /// Keep emails for the development mailbox instead of delivering them
+    Capture(Capture),
+}
+
+impl Transport {
+    async fn send(&self, email: &OutgoingEmail<'_>) -> Result<SentEmail, EmailClientError> {
+        match self {
+            Self::Sendgrid(sendgrid) => sendgrid.send(email).await,
+            Self::Smtp(smtp) => smtp.send(email).await,
+            Self::Capture(capture) => capture.send(email).await,
+        }
+    }
+}
+
+/// A provider emails can be sent through, guarded by its own circuit breaker
+/// and, when its plan caps how fast we can send, its own rate limiter.
+#[derive(Clone)]
+pub struct EmailProvider {
+    name: String,
+    transport: Transport,
+    circuit_breaker: CircuitBreaker,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl EmailProvider {
+    #[must_use]
+    pub fn new(
+        name: String,
+        transport: Transport,
+        circuit_breaker: CircuitBreaker,
+        rate_limiter: Option<RateLimiter>,
+    ) -> Self {
+        Self {
+            name,
+            transport,
+            circuit_breaker,
+            rate_limiter,
+        }
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit_breaker.state()
+    }
+
+    /// Give back the rate limiter tokens of recipients it did not send to
+    fn refund(&self, recipients: usize) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.refund(recipients);
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct EmailClient {
-    _sender: SubscriberEmail,
-    http_client: Client,
-    base_url: String,
-    authorization_token: Secret<String>,
+    sender: SubscriberEmail,
+    providers: Vec<EmailProvider>,
+    renderer: MarkdownRenderer,
+    daily_quota: Option<u32>,
 }
 
 impl EmailClient {
+    /// Emails are sent through the first of `providers` whose circuit breaker
+    /// is closed, falling back to the next ones when it fails.
+    ///
+    /// `daily_quota` caps how many emails can be sent per UTC day, it is
+    /// enforced by the issue delivery worker.
+    ///
     /// # Panics
     ///
-    /// This method fails if a TLS backend cannot be initialized,
-    /// or the resolver cannot load the system configuration.
+    /// If there are no providers.
     #[must_use]
     pub fn new(
-        base_url: String,
         sender: SubscriberEmail,
-        authorization_token: Secret<String>,
-        timeout: Duration,
+        providers: Vec<EmailProvider>,
+        renderer: MarkdownRenderer,
+        daily_quota: Option<u32>,
     ) -> Self {
-        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        assert!(
+            !providers.is_empty(),
+            "At least one email provider is needed"
+        );
         Self {
-            _sender: sender,
-            http_client,
-            base_url,
-            authorization_token,
+            sender,
+            providers,
+            renderer,
+            daily_quota,
         }
     }
 
+    /// Send an email authored in Markdown, rendering both its HTML and plain text bodies
+    pub async fn send_markdown_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        markdown: &str,
+    ) -> Result<SentEmail, EmailClientError> {
+        let content = self.renderer.render(markdown);
+        self.send_email(recipient, subject, &content.html, &content.text)
+            .await
+    }
+
     pub async fn send_email(
         &self,
         recipient: SubscriberEmail,
         subject: &str,
-        _html_content: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<SentEmail, EmailClientError> {
+        let recipient = Recipient {
+            email: recipient,
+            substitutions: BTreeMap::new(),
+            custom_args: BTreeMap::new(),
+        };
+        self.send_batch_email(&[recipient], subject, html_content, text_content)
+            .await
+    }
+
+    /// Send the same email to several recipients in a single request.
+    ///
+    /// Each recipient gets their own personalization, so that they neither
+    /// see each other's addresses nor each other's substitutions.
+    ///
+    /// Each recipient takes a token from the rate limiter of the provider,
+    /// waiting for the bucket to refill when it is empty. The tokens of
+    /// recipients a provider failed to send to are given back.
+    ///
+    /// Providers whose circuit breaker is open are skipped. A provider
+    /// failing with a transient error falls back to the next one; the last
+    /// error is returned when none of them succeeds.
+    ///
+    /// # Panics
+    ///
+    /// If there are more than [`MAX_BATCH_SIZE`] recipients.
+    pub async fn send_batch_email(
+        &self,
+        recipients: &[Recipient],
+        subject: &str,
+        html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        let url = format!("{}/email", self.base_url);
-        let request_body = SendgridEmailFormat {
-            personalizations: vec![PersonalizationField {
-                to: vec![ToField {
-                    email: recipient.as_ref(),
-                }],
-            }],
-            from: FromField {
-                email: "satvik@darkmodes.com",
-            },
+    ) -> Result<SentEmail, EmailClientError> {
+        assert!(
+            recipients.len() <= MAX_BATCH_SIZE,
+            "A batch cannot have more than {MAX_BATCH_SIZE} recipients"
+        );
+        let email = OutgoingEmail {
+            sender: &self.sender,
+            recipients,
             subject,
-            content: vec![ContentField {
-                type_field: "text/plain",
-                value: text_content,
-            }],
+            html_content,
+            text_content,
         };
-        let _builder = self
-            .http_client
-            .post(&url)
-            .bearer_auth(self.authorization_token.expose_secret())
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+        let mut last_error = EmailClientError::Unavailable;
+        for provider in &self.providers {
+            let Some(permit) = provider.circuit_breaker.try_acquire() else {
+                continue;
+            };
+            if let Some(rate_limiter) = &provider.rate_limiter {
+                rate_limiter.acquire(recipients.len()).await;
+            }
+            match provider.transport.send(&email).await {
+                Ok(sent_email) => {
+                    match &sent_email.unsent {
+                        Some(unsent) => {
+                            tracing::warn!(
+                                provider = %provider.name,
+                                error.message = %unsent.error,
+                                "Failed to send an email to some of its recipients",
+                            );
+                            provider.refund(unsent.recipients.len());
+                            permit.record_failure();
+                        }
+                        None => permit.record_success(),
+                    }
+                    return Ok(sent_email);
+                }
+                // The provider works, our email does not
+                Err(error) if !error.is_transient() => {
+                    permit.record_success();
+                    return Err(error);
+                }
+                Err(error) => {
+                    tracing::warn!(
+                        provider = %provider.name,
+                        error.message = %error,
+                        "Failed to send an email, falling back to the next provider",
+                    );
+                    provider.refund(recipients.len());
+                    permit.record_failure();
+                    last_error = error;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    #[must_use]
+    pub fn renderer(&self) -> &MarkdownRenderer {
+        &self.renderer
+    }
+
+    #[must_use]
+    pub fn providers(&self) -> &[EmailProvider] {
+        &self.providers
+    }
+
+    #[must_use]
+    pub const fn daily_quota(&self) -> Option<u32> {
+        self.daily_quota
+    }
+
+    /// Where emails are captured, when one of the providers is [`Transport::Capture`]
+    #[must_use]
+    pub fn capture(&self) -> Option<&Capture> {
+        self.providers
+            .iter()
+            .find_map(|provider| match &provider.transport {
+                Transport::Capture(capture) => Some(capture),
+                Transport::Sendgrid(_) | Transport::Smtp(_) => None,
+            })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
+
+    use std::collections::BTreeMap;
 
-    use crate::{domain::SubscriberEmail, email_client::EmailClient};
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{
+            CircuitBreaker, CircuitState, EmailClient, EmailClientError, EmailProvider,
+            RateLimiter, Recipient, Sendgrid, Transport,
+        },
+        email_content::MarkdownRenderer,
+    };
     use claim::{assert_err, assert_ok};
     use fake::{
         faker::{
@@ -101,7 +470,7 @@ mod tests {
             dbg!(&request.body);
             dbg!(&result);
 
-            result.map_or(false, |body| {
+            result.is_ok_and(|body| {
                 // [SendgridEmailFormat]
                 body.get("personalizations").is_some()
                     && body.get("from").is_some()
@@ -123,12 +492,38 @@ mod tests {
         SubscriberEmail::parse(SafeEmail().fake()).unwrap()
     }
 
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(5, Duration::from_secs(60), 1)
+    }
+
+    fn provider(mock_server: &MockServer, circuit_breaker: CircuitBreaker) -> EmailProvider {
+        EmailProvider::new(
+            mock_server.uri(),
+            Transport::Sendgrid(Sendgrid::new(
+                mock_server.uri(),
+                Secret::new(Faker.fake()),
+                Duration::from_millis(200),
+            )),
+            circuit_breaker,
+            None,
+        )
+    }
+
     fn email_client(base_url: String) -> EmailClient {
         EmailClient::new(
-            base_url,
             email(),
-            Secret::new(Faker.fake()),
-            Duration::from_millis(200),
+            vec![EmailProvider::new(
+                "sendgrid".to_string(),
+                Transport::Sendgrid(Sendgrid::new(
+                    base_url,
+                    Secret::new(Faker.fake()),
+                    Duration::from_millis(200),
+                )),
+                breaker(),
+                None,
+            )],
+            MarkdownRenderer::new(String::new()),
+            None,
         )
     }
 
@@ -157,6 +552,82 @@ mod tests {
         // Checked on Drop
     }
 
+    #[tokio::test]
+    async fn send_markdown_email_sends_both_html_and_plain_text_content() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_markdown_email(email(), &subject(), "Hello **world**")
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["content"][0]["type"], "text/plain");
+        assert_eq!(body["content"][0]["value"], "Hello *world*\n");
+        assert_eq!(body["content"][1]["type"], "text/html");
+        assert!(body["content"][1]["value"]
+            .as_str()
+            .unwrap()
+            .contains("<strong>world</strong>"));
+    }
+
+    #[tokio::test]
+    async fn send_batch_email_sends_one_personalization_per_recipient() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients: Vec<Recipient> = ["Ursula", "Octavia"]
+            .into_iter()
+            .map(|name| Recipient {
+                email: email(),
+                substitutions: BTreeMap::from([("{{name}}".to_string(), name.to_string())]),
+                custom_args: BTreeMap::from([("subscriber_id".to_string(), name.to_lowercase())]),
+            })
+            .collect();
+
+        Mock::given(any())
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_batch_email(&recipients, "Hi {{name}}", &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let personalizations = body["personalizations"].as_array().unwrap();
+        assert_eq!(personalizations.len(), 2);
+        for (personalization, recipient) in personalizations.iter().zip(&recipients) {
+            assert_eq!(personalization["to"].as_array().unwrap().len(), 1);
+            assert_eq!(personalization["to"][0]["email"], recipient.email.as_ref());
+            assert_eq!(
+                personalization["substitutions"]["{{name}}"],
+                recipient.substitutions["{{name}}"]
+            );
+            assert_eq!(
+                personalization["custom_args"]["subscriber_id"],
+                recipient.custom_args["subscriber_id"]
+            );
+        }
+    }
+
     #[tokio::test]
     async fn send_email_succeeds_if_server_returns_200() {
         // Arrange
@@ -193,7 +664,10 @@ mod tests {
             .send_email(email(), &subject(), &content(), &content())
             .await;
 
-        assert_err!(outcome);
+        assert!(matches!(
+            assert_err!(outcome),
+            EmailClientError::ServerError { status, .. } if status == 500
+        ));
     }
 
     #[tokio::test]
@@ -216,6 +690,435 @@ mod tests {
             .await;
 
         // Assert
-        assert_err!(outcome);
+        assert!(matches!(assert_err!(outcome), EmailClientError::Timeout(_)));
+    }
+
+    #[tokio::test]
+    async fn send_email_returns_the_message_id_assigned_by_the_provider() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202).insert_header("X-Message-Id", "14c5d75ce93"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_eq!(
+            assert_ok!(outcome).message_id.as_deref(),
+            Some("14c5d75ce93")
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_is_rate_limited_if_server_returns_429() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        let error = assert_err!(outcome);
+        assert!(error.is_transient());
+        assert!(matches!(
+            error,
+            EmailClientError::RateLimited {
+                retry_after: Some(retry_after)
+            } if retry_after == Duration::from_secs(30)
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_is_rejected_if_server_returns_400() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let errors = r#"{"errors":[{"message":"Invalid email","field":"personalizations.0.to"}]}"#;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400).set_body_string(errors))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        let error = assert_err!(outcome);
+        assert!(!error.is_transient());
+        assert!(matches!(
+            error,
+            EmailClientError::Rejected { status, body }
+                if status == 400 && body == errors
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_falls_back_to_the_next_provider_when_one_fails() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client = EmailClient::new(
+            email(),
+            vec![
+                provider(&primary, breaker()),
+                provider(&fallback, breaker()),
+            ],
+            MarkdownRenderer::new(String::new()),
+            None,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202).insert_header("X-Message-Id", "fallback"))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_eq!(assert_ok!(outcome).message_id.as_deref(), Some("fallback"));
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_fall_back_when_the_email_is_rejected() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client = EmailClient::new(
+            email(),
+            vec![
+                provider(&primary, breaker()),
+                provider(&fallback, breaker()),
+            ],
+            MarkdownRenderer::new(String::new()),
+            None,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202))
+            .expect(0)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(
+            assert_err!(outcome),
+            EmailClientError::Rejected { .. }
+        ));
+        assert_eq!(
+            email_client.providers()[0].circuit_state(),
+            CircuitState::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_falls_back_when_the_provider_refuses_our_credentials() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client = EmailClient::new(
+            email(),
+            vec![
+                provider(&primary, CircuitBreaker::new(1, Duration::from_secs(60), 1)),
+                provider(&fallback, breaker()),
+            ],
+            MarkdownRenderer::new(String::new()),
+            None,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202).insert_header("X-Message-Id", "fallback"))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_eq!(assert_ok!(outcome).message_id.as_deref(), Some("fallback"));
+        assert_eq!(
+            email_client.providers()[0].circuit_state(),
+            CircuitState::Open
+        );
+    }
+
+    #[tokio::test]
+    async fn refused_requests_are_transient() {
+        for status in [401, 403, 413] {
+            // Arrange
+            let mock_server = MockServer::start().await;
+            let email_client = email_client(mock_server.uri());
+
+            Mock::given(any())
+                .respond_with(ResponseTemplate::new(status))
+                .expect(1)
+                .mount(&mock_server)
+                .await;
+
+            // Act
+            let outcome = email_client
+                .send_email(email(), &subject(), &content(), &content())
+                .await;
+
+            // Assert
+            let error = assert_err!(outcome);
+            assert!(error.is_transient());
+            assert!(matches!(error, EmailClientError::Refused { .. }));
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_fast_once_the_circuit_breaker_is_open() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            email(),
+            vec![provider(
+                &mock_server,
+                CircuitBreaker::new(2, Duration::from_secs(60), 1),
+            )],
+            MarkdownRenderer::new(String::new()),
+            None,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        for _ in 0..2 {
+            let _outcome = email_client
+                .send_email(email(), &subject(), &content(), &content())
+                .await;
+        }
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(
+            assert_err!(outcome),
+            EmailClientError::Unavailable
+        ));
+        assert_eq!(
+            email_client.providers()[0].circuit_state(),
+            CircuitState::Open
+        );
+    }
+
+    #[tokio::test]
+    async fn providers_with_an_open_circuit_breaker_are_skipped() {
+        // Arrange
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let open_breaker = CircuitBreaker::new(1, Duration::from_secs(60), 1);
+        open_breaker.try_acquire().unwrap().record_failure();
+        let email_client = EmailClient::new(
+            email(),
+            vec![
+                provider(&primary, open_breaker),
+                provider(&fallback, breaker()),
+            ],
+            MarkdownRenderer::new(String::new()),
+            None,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202))
+            .expect(0)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_trial_send_does_not_keep_the_breaker_half_open() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let half_open_breaker = CircuitBreaker::new(1, Duration::ZERO, 1);
+        half_open_breaker.try_acquire().unwrap().record_failure();
+        let email_client = EmailClient::new(
+            email(),
+            vec![provider(&mock_server, half_open_breaker)],
+            MarkdownRenderer::new(String::new()),
+            None,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202).set_delay(Duration::from_millis(100)))
+            .expect(1..=2)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        // As when the client of a request sending an email disconnects
+        let cancelled = tokio::time::timeout(
+            Duration::from_millis(20),
+            email_client.send_email(email(), &subject(), &content(), &content()),
+        )
+        .await;
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_err!(cancelled);
+        assert_ok!(outcome);
+        assert_eq!(
+            email_client.providers()[0].circuit_state(),
+            CircuitState::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_wait_for_the_rate_limiter_of_the_provider() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let provider = EmailProvider::new(
+            "sendgrid".to_string(),
+            Transport::Sendgrid(Sendgrid::new(
+                mock_server.uri(),
+                Secret::new(Faker.fake()),
+                Duration::from_millis(200),
+            )),
+            breaker(),
+            Some(RateLimiter::new(20.0, 2)),
+        );
+        let email_client = EmailClient::new(
+            email(),
+            vec![provider],
+            MarkdownRenderer::new(String::new()),
+            None,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_ok!(
+                email_client
+                    .send_email(email(), &subject(), &content(), &content())
+                    .await
+            );
+        }
+
+        // Assert
+        // The third email waits for a token, refilled at 20 per second
+        assert!(start.elapsed() >= Duration::from_millis(45));
+    }
+
+    #[tokio::test]
+    async fn failed_sends_give_their_rate_limiter_tokens_back() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let provider = EmailProvider::new(
+            "sendgrid".to_string(),
+            Transport::Sendgrid(Sendgrid::new(
+                mock_server.uri(),
+                Secret::new(Faker.fake()),
+                Duration::from_millis(200),
+            )),
+            breaker(),
+            Some(RateLimiter::new(1.0, 1)),
+        );
+        let email_client = EmailClient::new(
+            email(),
+            vec![provider],
+            MarkdownRenderer::new(String::new()),
+            None,
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let start = Instant::now();
+        let failed = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+        let retried = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_err!(failed);
+        assert_ok!(retried);
+        // Without the refund, the retry would wait a second for a token
+        assert!(start.elapsed() < Duration::from_millis(500));
     }
 }
@@ -0,0 +1,239 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::email_client::{mime, EmailClientError, OutgoingEmail, SentEmail};
+
+/// An email kept by the [`Capture`] backend instead of being delivered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedEmail {
+    pub id: Uuid,
+    /// Shared by the emails captured from the same batch
+    pub message_id: Uuid,
+    pub captured_at: DateTime<Utc>,
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub custom_args: BTreeMap<String, String>,
+}
+
+impl CapturedEmail {
+    /// The email as a MIME message, to be opened with a mail client
+    #[must_use]
+    pub fn to_eml(&self) -> String {
+        mime::multipart_alternative(
+            &[
+                ("Message-ID", format!("<{}@capture.localhost>", self.id)),
+                ("Date", self.captured_at.to_rfc2822()),
+                ("From", self.from.clone()),
+                ("To", self.to.clone()),
+                ("Subject", self.subject.clone()),
+            ],
+            &self.id.simple().to_string(),
+            &self.text_content,
+            &self.html_content,
+        )
+    }
+
+    /// The URLs in the plain text body, e.g. confirmation links
+    #[must_use]
+    pub fn links(&self) -> Vec<&str> {
+        self.text_content
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '<' || c == '>')
+            .filter(|word| word.starts_with("http://") || word.starts_with("https://"))
+            .collect()
+    }
+}
+
+/// Keeps outgoing emails, one per recipient and with their substitutions
+/// applied, in memory or as `.eml` and `.json` files in a directory, so that
+/// they can be read in the development mailbox.
+///
+/// Clones share the emails captured in memory.
+#[derive(Clone)]
+pub struct Capture {
+    storage: Storage,
+}
+
+#[derive(Clone)]
+enum Storage {
+    Memory(Arc<Mutex<Vec<CapturedEmail>>>),
+    Directory(PathBuf),
+}
+
+impl Capture {
+    #[must_use]
+    pub fn in_memory() -> Self {
+        Self {
+            storage: Storage::Memory(Arc::default()),
+        }
+    }
+
+    #[must_use]
+    pub fn in_directory(directory: PathBuf) -> Self {
+        Self {
+            storage: Storage::Directory(directory),
+        }
+    }
+
+    pub async fn send(&self, email: &OutgoingEmail<'_>) -> Result<SentEmail, EmailClientError> {
+        let message_id = Uuid::new_v4();
+        let captured_at = Utc::now();
+        for recipient in email.recipients {
+            let captured = CapturedEmail {
+                id: Uuid::new_v4(),
+                message_id,
+                captured_at,
+                from: email.sender.as_ref().to_string(),
+                to: recipient.email.as_ref().to_string(),
+                subject: recipient.substitute(email.subject),
+                text_content: recipient.substitute(email.text_content),
+                html_content: recipient.substitute(email.html_content),
+                custom_args: recipient.custom_args.clone(),
+            };
+            self.store(captured)
+                .map_err(|e| EmailClientError::Transport(Box::new(e)))?;
+        }
+        Ok(SentEmail {
+            message_id: Some(message_id.to_string()),
+            rejected_recipients: Vec::new(),
+            unsent: None,
+        })
+    }
+
+    fn store(&self, email: CapturedEmail) -> Result<(), io::Error> {
+        match &self.storage {
+            Storage::Memory(emails) => {
+                emails.lock().unwrap().push(email);
+            }
+            Storage::Directory(directory) => {
+                std::fs::create_dir_all(directory)?;
+                std::fs::write(directory.join(format!("{}.eml", email.id)), email.to_eml())?;
+                std::fs::write(
+                    directory.join(format!("{}.json", email.id)),
+                    serde_json::to_vec_pretty(&email)?,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every captured email, the most recent first
+    ///
+    /// # Panics
+    ///
+    /// If another thread panicked while capturing an email in memory.
+    pub fn list(&self) -> Result<Vec<CapturedEmail>, io::Error> {
+        let mut emails = match &self.storage {
+            Storage::Memory(emails) => emails.lock().unwrap().clone(),
+            Storage::Directory(directory) => {
+                let entries = match std::fs::read_dir(directory) {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+                    Err(e) => return Err(e),
+                };
+                let mut emails = Vec::new();
+                for entry in entries {
+                    let path = entry?.path();
+                    if path
+                        .extension()
+                        .is_some_and(|extension| extension == "json")
+                    {
+                        emails.push(serde_json::from_slice(&std::fs::read(path)?)?);
+                    }
+                }
+                emails
+            }
+        };
+        emails.sort_by_key(|email| std::cmp::Reverse(email.captured_at));
+        Ok(emails)
+    }
+
+    /// # Panics
+    ///
+    /// If another thread panicked while capturing an email in memory.
+    pub fn get(&self, id: Uuid) -> Result<Option<CapturedEmail>, io::Error> {
+        Ok(self.list()?.into_iter().find(|email| email.id == id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use claim::assert_ok;
+    use uuid::Uuid;
+
+    use super::Capture;
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{OutgoingEmail, Recipient},
+    };
+
+    fn recipient(email: &str, name: &str) -> Recipient {
+        Recipient {
+            email: SubscriberEmail::parse(email.to_string()).unwrap(),
+            substitutions: BTreeMap::from([("{{name}}".to_string(), name.to_string())]),
+            custom_args: BTreeMap::new(),
+        }
+    }
+
+    async fn send(capture: &Capture) {
+        let sender = SubscriberEmail::parse("newsletter@example.com".to_string()).unwrap();
+        let recipients = [
+            recipient("ursula@example.com", "Ursula"),
+            recipient("octavia@example.com", "Octavia"),
+        ];
+        let email = OutgoingEmail {
+            sender: &sender,
+            recipients: &recipients,
+            subject: "Hello {{name}}",
+            html_content: "<p>Visit https://example.com/confirm</p>",
+            text_content: "Dear {{name}}, visit https://example.com/confirm",
+        };
+        assert_ok!(capture.send(&email).await);
+    }
+
+    #[tokio::test]
+    async fn one_email_is_captured_per_recipient_with_substitutions_applied() {
+        let capture = Capture::in_memory();
+
+        send(&capture).await;
+
+        let emails = capture.list().unwrap();
+        assert_eq!(emails.len(), 2);
+        let ursula = emails
+            .iter()
+            .find(|email| email.to == "ursula@example.com")
+            .unwrap();
+        assert_eq!(ursula.subject, "Hello Ursula");
+        assert_eq!(ursula.links(), ["https://example.com/confirm"]);
+        assert_eq!(capture.get(ursula.id).unwrap().as_ref(), Some(ursula));
+    }
+
+    #[tokio::test]
+    async fn emails_can_be_captured_in_a_directory() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let capture = Capture::in_directory(directory.clone());
+
+        send(&capture).await;
+
+        let emails = capture.list().unwrap();
+        let files = std::fs::read_dir(&directory).unwrap().count();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(emails.len(), 2);
+        assert_eq!(files, 4);
+        assert!(emails[0]
+            .to_eml()
+            .contains("From: newsletter@example.com\r\n"));
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests go through
+    Closed,
+    /// The provider failed too many times in a row, requests fail fast
+    Open,
+    /// The provider is given another chance, one request at a time
+    HalfOpen,
+}
+
+/// Stops sending requests to a provider that keeps failing, then lets a few
+/// trial requests through once it had some time to recover.
+///
+/// Clones share their state.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    success_threshold: u32,
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    state: CircuitState,
+    consecutive_failures: u32,
+    consecutive_successes: u32,
+    opened_at: Instant,
+    trial_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    /// The breaker opens after `failure_threshold` consecutive failures, stays
+    /// open for `open_duration`, and closes again after `success_threshold`
+    /// consecutive successful trial requests.
+    #[must_use]
+    pub fn new(failure_threshold: u32, open_duration: Duration, success_threshold: u32) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            success_threshold: success_threshold.max(1),
+            inner: Arc::new(Mutex::new(Inner {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                consecutive_successes: 0,
+                opened_at: Instant::now(),
+                trial_in_flight: false,
+            })),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If another thread panicked while holding the lock.
+    #[must_use]
+    pub fn state(&self) -> CircuitState {
+        let inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::Open if inner.opened_at.elapsed() >= self.open_duration => {
+                CircuitState::HalfOpen
+            }
+            state => state,
+        }
+    }
+
+    /// A permit to send a request now, if the breaker lets one through. The
+    /// outcome of the request is recorded with the permit.
+    ///
+    /// # Panics
+    ///
+    /// If another thread panicked while holding the lock.
+    pub fn try_acquire(&self) -> Option<Permit<'_>> {
+        let mut inner = self.inner.lock().unwrap();
+        let trial = match inner.state {
+            CircuitState::Closed => false,
+            CircuitState::Open if inner.opened_at.elapsed() < self.open_duration => return None,
+            CircuitState::Open | CircuitState::HalfOpen => {
+                if inner.trial_in_flight {
+                    return None;
+                }
+                if inner.state == CircuitState::Open {
+                    inner.state = CircuitState::HalfOpen;
+                    inner.consecutive_successes = 0;
+                }
+                inner.trial_in_flight = true;
+                true
+            }
+        };
+        Some(Permit {
+            circuit_breaker: self,
+            trial,
+            recorded: false,
+        })
+    }
+
+    fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        if inner.state == CircuitState::HalfOpen {
+            inner.trial_in_flight = false;
+            inner.consecutive_successes += 1;
+            if inner.consecutive_successes >= self.success_threshold {
+                tracing::info!("Closing the circuit breaker of an email provider");
+                inner.state = CircuitState::Closed;
+            }
+        }
+    }
+
+    fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        let should_open = match inner.state {
+            CircuitState::Closed => inner.consecutive_failures >= self.failure_threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if should_open {
+            tracing::warn!("Opening the circuit breaker of an email provider");
+            inner.state = CircuitState::Open;
+            inner.opened_at = Instant::now();
+            inner.trial_in_flight = false;
+        }
+    }
+}
+
+/// Permission to send a single request, see [`CircuitBreaker::try_acquire`].
+///
+/// A permit dropped without recording an outcome, e.g. because the request
+/// was cancelled, gives the trial slot of a half-open breaker back.
+#[must_use = "The outcome of the request should be recorded"]
+pub struct Permit<'a> {
+    circuit_breaker: &'a CircuitBreaker,
+    /// Whether this is the trial request of a half-open breaker
+    trial: bool,
+    recorded: bool,
+}
+
+impl Permit<'_> {
+    /// # Panics
+    ///
+    /// If another thread panicked while holding the lock.
+    pub fn record_success(mut self) {
+        self.recorded = true;
+        self.circuit_breaker.record_success();
+    }
+
+    /// # Panics
+    ///
+    /// If another thread panicked while holding the lock.
+    pub fn record_failure(mut self) {
+        self.recorded = true;
+        self.circuit_breaker.record_failure();
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.trial && !self.recorded {
+            if let Ok(mut inner) = self.circuit_breaker.inner.lock() {
+                inner.trial_in_flight = false;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CircuitBreaker, CircuitState};
+
+    #[test]
+    fn the_breaker_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60), 1);
+
+        for _ in 0..2 {
+            breaker.try_acquire().unwrap().record_failure();
+        }
+        breaker.try_acquire().unwrap().record_success();
+        for _ in 0..3 {
+            breaker.try_acquire().unwrap().record_failure();
+        }
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_acquire().is_none());
+    }
+
+    #[test]
+    fn a_half_open_breaker_lets_one_trial_request_through_at_a_time() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO, 2);
+        breaker.try_acquire().unwrap().record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        let trial = breaker.try_acquire().unwrap();
+        assert!(breaker.try_acquire().is_none());
+        trial.record_success();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        breaker.try_acquire().unwrap().record_success();
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn a_failed_trial_request_opens_the_breaker_again() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(20), 1);
+        breaker.try_acquire().unwrap().record_failure();
+        std::thread::sleep(Duration::from_millis(30));
+
+        breaker.try_acquire().unwrap().record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.try_acquire().is_none());
+    }
+
+    #[test]
+    fn a_dropped_trial_request_gives_its_slot_back() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO, 1);
+        breaker.try_acquire().unwrap().record_failure();
+
+        let trial = breaker.try_acquire().unwrap();
+        drop(trial);
+
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        breaker.try_acquire().unwrap().record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn clones_share_their_state() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(60), 1);
+        let clone = breaker.clone();
+
+        breaker.try_acquire().unwrap().record_failure();
+
+        assert_eq!(clone.state(), CircuitState::Open);
+    }
+}
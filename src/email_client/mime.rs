@@ -0,0 +1,79 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Base64 encoded lines may not be longer than this
+const LINE_LENGTH: usize = 76;
+
+/// Format a MIME message with a plain text body and its HTML alternative.
+///
+/// `headers` must not include the MIME headers, which are added here.
+#[must_use]
+pub fn multipart_alternative(
+    headers: &[(&str, String)],
+    boundary: &str,
+    text_content: &str,
+    html_content: &str,
+) -> String {
+    let mut message = String::new();
+    for (name, value) in headers {
+        message.push_str(&format!("{name}: {}\r\n", encode_header_value(value)));
+    }
+    message.push_str("MIME-Version: 1.0\r\n");
+    message.push_str(&format!(
+        "Content-Type: multipart/alternative; boundary=\"{boundary}\"\r\n\r\n"
+    ));
+    for (content_type, content) in [("text/plain", text_content), ("text/html", html_content)] {
+        message.push_str(&format!("--{boundary}\r\n"));
+        message.push_str(&format!("Content-Type: {content_type}; charset=utf-8\r\n"));
+        message.push_str("Content-Transfer-Encoding: base64\r\n\r\n");
+        message.push_str(&encode_body(content));
+    }
+    message.push_str(&format!("--{boundary}--\r\n"));
+    message
+}
+
+/// Header values are ASCII, anything else is encoded as an RFC 2047 word
+fn encode_header_value(value: &str) -> String {
+    if value.chars().all(|c| c.is_ascii() && !c.is_ascii_control()) {
+        value.to_string()
+    } else {
+        format!("=?utf-8?b?{}?=", STANDARD.encode(value))
+    }
+}
+
+fn encode_body(content: &str) -> String {
+    let encoded = STANDARD.encode(content);
+    let mut body = String::with_capacity(encoded.len() + encoded.len() / LINE_LENGTH * 2 + 2);
+    for line in encoded.as_bytes().chunks(LINE_LENGTH) {
+        // Base64 is ASCII
+        body.push_str(std::str::from_utf8(line).unwrap());
+        body.push_str("\r\n");
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_header_value, multipart_alternative};
+
+    #[test]
+    fn non_ascii_header_values_are_encoded() {
+        assert_eq!(encode_header_value("Welcome!"), "Welcome!");
+        assert_eq!(encode_header_value("Café"), "=?utf-8?b?Q2Fmw6k=?=");
+    }
+
+    #[test]
+    fn both_bodies_are_included_with_their_content_type() {
+        let message = multipart_alternative(
+            &[("Subject", "Hello".to_string())],
+            "boundary",
+            "Hello",
+            "<p>Hello</p>",
+        );
+
+        assert!(message.starts_with("Subject: Hello\r\nMIME-Version: 1.0\r\n"));
+        assert!(message.contains("Content-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\nSGVsbG8=\r\n"));
+        assert!(message.contains("Content-Type: text/html; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\nPHA+SGVsbG88L3A+\r\n"));
+        assert!(message.ends_with("--boundary--\r\n"));
+        assert!(message.lines().all(|line| line.len() <= 78));
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+/// A token bucket holding up to `burst` messages, refilled at
+/// `messages_per_second`.
+///
+/// Clones share their bucket.
+#[derive(Clone)]
+pub struct RateLimiter {
+    burst: f64,
+    messages_per_second: f64,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// # Panics
+    ///
+    /// If `messages_per_second` is not positive.
+    #[must_use]
+    pub fn new(messages_per_second: f64, burst: u32) -> Self {
+        assert!(
+            messages_per_second > 0.0,
+            "The rate limit must allow some messages"
+        );
+        let burst = f64::from(burst.max(1));
+        Self {
+            burst,
+            messages_per_second,
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: burst,
+                refilled_at: Instant::now(),
+            })),
+        }
+    }
+
+    /// Wait until `messages` can be sent.
+    ///
+    /// More messages than the bucket holds are let through once it is full,
+    /// leaving it in debt so that the next ones wait for longer.
+    ///
+    /// # Panics
+    ///
+    /// If another thread panicked while holding the lock.
+    pub async fn acquire(&self, messages: usize) {
+        #[allow(clippy::cast_precision_loss)]
+        let messages = messages as f64;
+        loop {
+            // The lock is released while waiting, so that smaller batches
+            // can go through in the meantime
+            let wait = {
+                let mut bucket = self.refilled_bucket();
+                let needed = messages.min(self.burst);
+                if bucket.tokens >= needed {
+                    bucket.tokens -= messages;
+                    return;
+                }
+                (needed - bucket.tokens) / self.messages_per_second
+            };
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+
+    /// Give back the tokens of `messages` that were not sent after all
+    ///
+    /// # Panics
+    ///
+    /// If another thread panicked while holding the lock.
+    pub fn refund(&self, messages: usize) {
+        #[allow(clippy::cast_precision_loss)]
+        let messages = messages as f64;
+        let mut bucket = self.refilled_bucket();
+        bucket.tokens = (bucket.tokens + messages).min(self.burst);
+    }
+
+    fn refilled_bucket(&self) -> MutexGuard<'_, Bucket> {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.messages_per_second).min(self.burst);
+        bucket.refilled_at = now;
+        bucket
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::RateLimiter;
+
+    #[tokio::test]
+    async fn a_burst_goes_through_without_waiting() {
+        let limiter = RateLimiter::new(1.0, 10);
+        let start = Instant::now();
+
+        limiter.acquire(10).await;
+
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn messages_beyond_the_burst_wait_for_the_bucket_to_refill() {
+        let limiter = RateLimiter::new(20.0, 2);
+        let start = Instant::now();
+
+        limiter.acquire(2).await;
+        limiter.acquire(2).await;
+
+        // 2 tokens at 20 per second
+        assert!(start.elapsed() >= Duration::from_millis(95));
+    }
+
+    #[tokio::test]
+    async fn batches_larger_than_the_bucket_leave_it_in_debt() {
+        let limiter = RateLimiter::new(20.0, 2);
+        let start = Instant::now();
+
+        limiter.acquire(4).await;
+        let after_batch = start.elapsed();
+        limiter.acquire(1).await;
+
+        assert!(after_batch < Duration::from_millis(50));
+        // 2 tokens of debt plus 1 token at 20 per second
+        assert!(start.elapsed() >= Duration::from_millis(145));
+    }
+
+    #[tokio::test]
+    async fn refunded_messages_can_be_sent_right_away() {
+        let limiter = RateLimiter::new(1.0, 2);
+        let start = Instant::now();
+
+        limiter.acquire(2).await;
+        limiter.refund(2);
+        limiter.acquire(2).await;
+
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn small_batches_do_not_wait_behind_a_large_one() {
+        let limiter = RateLimiter::new(10.0, 10);
+        limiter.acquire(10).await;
+        let large = tokio::spawn({
+            let limiter = limiter.clone();
+            async move { limiter.acquire(10).await }
+        });
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let start = Instant::now();
+
+        limiter.acquire(1).await;
+
+        assert!(start.elapsed() < Duration::from_millis(50));
+        large.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn clones_share_their_bucket() {
+        let limiter = RateLimiter::new(20.0, 2);
+        let clone = limiter.clone();
+        let start = Instant::now();
+
+        limiter.acquire(2).await;
+        clone.acquire(1).await;
+
+        assert!(start.elapsed() >= Duration::from_millis(45));
+    }
+}
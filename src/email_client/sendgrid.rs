@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::{header::RETRY_AFTER, Client, Response, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::{
+    email_client::{EmailClientError, OutgoingEmail, SentEmail},
+    sendgrid_email_format::{
+        ContentField, FromField, PersonalizationField, SendgridEmailFormat, ToField,
+    },
+};
+
+/// Sends emails through the SendGrid v3 Mail Send API, or any provider
+/// compatible with it.
+#[derive(Clone)]
+pub struct Sendgrid {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+impl Sendgrid {
+    /// # Panics
+    ///
+    /// This method fails if a TLS backend cannot be initialized,
+    /// or the resolver cannot load the system configuration.
+    #[must_use]
+    pub fn new(base_url: String, authorization_token: Secret<String>, timeout: Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+
+    /// Send `email` with one personalization per recipient, so that they
+    /// neither see each other's addresses nor each other's substitutions.
+    pub async fn send(&self, email: &OutgoingEmail<'_>) -> Result<SentEmail, EmailClientError> {
+        let url = format!("{}/email", self.base_url);
+        let request_body = SendgridEmailFormat {
+            personalizations: email
+                .recipients
+                .iter()
+                .map(|recipient| PersonalizationField {
+                    to: vec![ToField {
+                        email: recipient.email.as_ref().into(),
+                        name: None,
+                    }],
+                    substitutions: recipient
+                        .substitutions
+                        .iter()
+                        .map(|(key, value)| (key.into(), value.into()))
+                        .collect(),
+                    custom_args: recipient
+                        .custom_args
+                        .iter()
+                        .map(|(key, value)| (key.into(), value.into()))
+                        .collect(),
+                    ..PersonalizationField::default()
+                })
+                .collect(),
+            from: FromField {
+                email: email.sender.as_ref().into(),
+                name: None,
+            },
+            subject: email.subject.into(),
+            // SendGrid requires `text/plain` to come before `text/html`
+            content: vec![
+                ContentField {
+                    type_field: "text/plain".into(),
+                    value: email.text_content.into(),
+                },
+                ContentField {
+                    type_field: "text/html".into(),
+                    value: email.html_content.into(),
+                },
+            ],
+            ..SendgridEmailFormat::default()
+        };
+        let response = self
+            .http_client
+            .post(&url)
+            .bearer_auth(self.authorization_token.expose_secret())
+            .header("Content-Type", "application/json")
+            .json(&request_body)
+            .send()
+            .await?;
+        sent_email(response).await
+    }
+}
+
+/// Interpret the provider's response to a send request
+async fn sent_email(response: Response) -> Result<SentEmail, EmailClientError> {
+    let status = response.status();
+    if status.is_success() {
+        let message_id = response
+            .headers()
+            .get("X-Message-Id")
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string);
+        return Ok(SentEmail {
+            message_id,
+            rejected_recipients: Vec::new(),
+            unsent: None,
+        });
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, Utc::now()));
+        return Err(EmailClientError::RateLimited { retry_after });
+    }
+    let body = response.text().await?;
+    match status {
+        // Our API key is revoked or lacks the permission to send, or the
+        // batch is too large: none of it is about the email
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::PAYLOAD_TOO_LARGE => {
+            Err(EmailClientError::Refused {
+                status: status.as_u16(),
+                body,
+            })
+        }
+        status if status.is_client_error() => Err(EmailClientError::Rejected {
+            status: status.as_u16(),
+            body,
+        }),
+        status => Err(EmailClientError::ServerError {
+            status: status.as_u16(),
+            body,
+        }),
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - now)
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::parse_retry_after;
+
+    #[test]
+    fn retry_after_can_be_a_number_of_seconds_or_a_date() {
+        let now = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:30:00 GMT", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
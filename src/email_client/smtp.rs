@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use lettre::{
+    message::{dkim::DkimConfig, Mailbox, MultiPart},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use uuid::Uuid;
+
+use crate::email_client::{EmailClientError, OutgoingEmail, Recipient, SentEmail, Unsent};
+
+/// The SMTP code for a mailbox name that is not allowed
+const MAILBOX_NAME_NOT_ALLOWED: u16 = 553;
+/// The SMTP codes for missing or invalid credentials, which are not about
+/// the message
+const AUTHENTICATION_FAILURES: [u16; 3] = [530, 534, 535];
+
+/// Relays emails through an SMTP server, e.g. an internal MTA.
+///
+/// Connections are pooled by the underlying transport and clones share them.
+#[derive(Clone)]
+pub struct Smtp {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    dkim: Option<Arc<DkimConfig>>,
+}
+
+impl Smtp {
+    /// Messages are signed with `dkim` when it is set
+    #[must_use]
+    pub fn new(transport: AsyncSmtpTransport<Tokio1Executor>, dkim: Option<DkimConfig>) -> Self {
+        Self {
+            transport,
+            dkim: dkim.map(Arc::new),
+        }
+    }
+
+    /// Send one message per recipient, with their substitutions applied.
+    ///
+    /// Recipients refused by the server are reported in the [`SentEmail`], the
+    /// email only fails when all of them are. When any other error occurs
+    /// after some recipients got their message, the email is reported as
+    /// [`Unsent`] to the remaining ones, so that only they are sent to again.
+    pub async fn send(&self, email: &OutgoingEmail<'_>) -> Result<SentEmail, EmailClientError> {
+        // Simple, so that it is the first dot-separated part of each Message-ID
+        let message_id = Uuid::new_v4().simple().to_string();
+        let domain = email
+            .sender
+            .as_ref()
+            .rsplit('@')
+            .next()
+            .unwrap_or("localhost");
+        let mut delivered = 0;
+        let mut rejected_recipients = Vec::new();
+        let mut last_rejection = None;
+        for (i, recipient) in email.recipients.iter().enumerate() {
+            let outcome =
+                match self.message(email, recipient, format!("<{message_id}.{i}@{domain}>")) {
+                    Ok(message) => self.transport.send(message).await.map_err(smtp_error),
+                    Err(error) => Err(error),
+                };
+            match outcome {
+                Ok(_) => delivered += 1,
+                Err(error @ EmailClientError::Rejected { .. }) => {
+                    tracing::warn!(
+                        error.message = %error,
+                        "The SMTP server refused a recipient",
+                    );
+                    rejected_recipients.push(recipient.email.clone());
+                    last_rejection = Some(error);
+                }
+                Err(error) if delivered == 0 => return Err(error),
+                Err(error) => {
+                    return Ok(SentEmail {
+                        message_id: Some(message_id),
+                        rejected_recipients,
+                        unsent: Some(Unsent {
+                            recipients: email.recipients[i..]
+                                .iter()
+                                .map(|recipient| recipient.email.clone())
+                                .collect(),
+                            error,
+                        }),
+                    });
+                }
+            }
+        }
+        match last_rejection {
+            Some(error) if delivered == 0 => Err(error),
+            _ => Ok(SentEmail {
+                message_id: Some(message_id),
+                rejected_recipients,
+                unsent: None,
+            }),
+        }
+    }
+
+    fn message(
+        &self,
+        email: &OutgoingEmail<'_>,
+        recipient: &Recipient,
+        message_id: String,
+    ) -> Result<Message, EmailClientError> {
+        let mailbox = |address: &str| {
+            address
+                .parse::<Mailbox>()
+                .map_err(|e| EmailClientError::Rejected {
+                    status: MAILBOX_NAME_NOT_ALLOWED,
+                    body: e.to_string(),
+                })
+        };
+        let mut message = Message::builder()
+            .message_id(Some(message_id))
+            .from(mailbox(email.sender.as_ref())?)
+            .to(mailbox(recipient.email.as_ref())?)
+            .subject(recipient.substitute(email.subject))
+            .multipart(MultiPart::alternative_plain_html(
+                recipient.substitute(email.text_content),
+                recipient.substitute(email.html_content),
+            ))
+            .map_err(|e| EmailClientError::Transport(Box::new(e)))?;
+        if let Some(dkim) = &self.dkim {
+            message.sign(dkim);
+        }
+        Ok(message)
+    }
+}
+
+fn smtp_error(e: lettre::transport::smtp::Error) -> EmailClientError {
+    if e.is_timeout() {
+        return EmailClientError::Timeout(Box::new(e));
+    }
+    let Some(code) = e.status() else {
+        return EmailClientError::Transport(Box::new(e));
+    };
+    let status = code.to_string().parse().unwrap_or_default();
+    let body = e.to_string();
+    if AUTHENTICATION_FAILURES.contains(&status) {
+        EmailClientError::Refused { status, body }
+    } else if e.is_permanent() {
+        EmailClientError::Rejected { status, body }
+    } else {
+        EmailClientError::ServerError { status, body }
+    }
+}
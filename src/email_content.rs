@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+
+use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use textwrap::core::display_width;
+
+/// Plain text lines are wrapped at this width, as recommended by RFC 2646.
+const TEXT_WIDTH: usize = 72;
+
+/// Prepended to the ids kept in the HTML, and to the fragment links to them.
+const ID_PREFIX: &str = "footnote-";
+
+/// The two bodies of an email rendered from the same Markdown source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailContent {
+    pub html: String,
+    pub text: String,
+}
+
+/// Renders Markdown (CommonMark with tables and footnotes) into email bodies.
+///
+/// The HTML body is sanitized and has the configured stylesheet inlined into
+/// `style` attributes, since most mail clients ignore `<style>` elements.
+#[derive(Debug, Clone)]
+pub struct MarkdownRenderer {
+    stylesheet: String,
+}
+
+impl MarkdownRenderer {
+    #[must_use]
+    pub fn new(stylesheet: String) -> Self {
+        Self { stylesheet }
+    }
+
+    #[must_use]
+    pub fn render(&self, markdown: &str) -> EmailContent {
+        let events = parse(markdown);
+
+        EmailContent {
+            html: self.render_html(&events),
+            text: render_text(&events),
+        }
+    }
+
+    /// Render sanitized HTML without a surrounding document or inlined styles,
+    /// to be embedded in web pages that bring their own stylesheet.
+    #[must_use]
+    pub fn render_fragment(&self, markdown: &str) -> String {
+        sanitize(&parse(markdown))
+    }
+
+    fn render_html(&self, events: &[Event]) -> String {
+        let safe_html = sanitize(events);
+        let document = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<style>{}</style>\n</head>\n<body>\n{safe_html}</body>\n</html>\n",
+            self.stylesheet
+        );
+        css_inline::inline(&document).unwrap_or_else(|e| {
+            tracing::warn!("Failed to inline the email stylesheet: {:?}", e);
+            document
+        })
+    }
+}
+
+fn parse(markdown: &str) -> Vec<Event<'_>> {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    Parser::new_ext(markdown, options).collect()
+}
+
+fn sanitize(events: &[Event]) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events.iter().cloned());
+
+    let mut sanitizer = ammonia::Builder::default();
+    // Keep the anchors that link footnote references to their definitions.
+    // Issues are also served on the public archive, so every id is namespaced
+    // to keep authors from clobbering the ids of the surrounding page.
+    sanitizer
+        .add_tag_attributes("div", &["class", "id"])
+        .add_tag_attributes("sup", &["class"])
+        .id_prefix(Some(ID_PREFIX))
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("a", "href") if value.starts_with('#') => {
+                Some(format!("#{ID_PREFIX}{}", &value[1..]).into())
+            }
+            _ => Some(value.into()),
+        });
+    sanitizer.clean(&unsafe_html).to_string()
+}
+
+enum Container {
+    BlockQuote { started: bool },
+    Item { marker: String, pending: bool },
+}
+
+struct List {
+    next_number: Option<u64>,
+    has_items: bool,
+}
+
+#[derive(Default)]
+struct Prefixes {
+    separator: String,
+    first: String,
+    rest: String,
+}
+
+#[derive(Default)]
+struct Table {
+    rows: Vec<Vec<String>>,
+    header_rows: usize,
+}
+
+/// Walks the Markdown events and lays them out as wrapped plain text.
+#[derive(Default)]
+struct TextRenderer {
+    output: String,
+    inline: String,
+    containers: Vec<Container>,
+    lists: Vec<List>,
+    links: Vec<(usize, String)>,
+    table: Option<Table>,
+    footnote_numbers: HashMap<String, usize>,
+    footnotes: Vec<(usize, String)>,
+    footnote_body: Option<(usize, String)>,
+    tight: bool,
+}
+
+fn render_text(events: &[Event]) -> String {
+    let mut renderer = TextRenderer::default();
+    for event in events {
+        renderer.handle(event);
+    }
+    renderer.finish()
+}
+
+impl TextRenderer {
+    fn handle(&mut self, event: &Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(*tag),
+            Event::Text(text) | Event::Code(text) => self.inline.push_str(text),
+            Event::SoftBreak => self.inline.push(' '),
+            Event::HardBreak => self.inline.push('\n'),
+            Event::Rule => {
+                self.flush_paragraph();
+                self.push_block(&["-".repeat(TEXT_WIDTH / 2)]);
+            }
+            Event::FootnoteReference(label) => {
+                let number = self.footnote_number(label);
+                self.inline.push_str(&format!("[{number}]"));
+            }
+            Event::TaskListMarker(checked) => {
+                self.inline.push_str(if *checked { "[x] " } else { "[ ] " });
+            }
+            Event::Html(_)
+            | Event::InlineHtml(_)
+            | Event::InlineMath(_)
+            | Event::DisplayMath(_) => {}
+        }
+    }
+
+    fn start(&mut self, tag: &Tag) {
+        match tag {
+            Tag::BlockQuote(_) => {
+                self.flush_paragraph();
+                self.containers
+                    .push(Container::BlockQuote { started: false });
+            }
+            Tag::List(start) => {
+                self.flush_paragraph();
+                self.lists.push(List {
+                    next_number: *start,
+                    has_items: false,
+                });
+            }
+            Tag::Item => {
+                self.flush_paragraph();
+                let Some(list) = self.lists.last_mut() else {
+                    return;
+                };
+                let marker = list.next_number.map_or_else(
+                    || "- ".to_string(),
+                    |number| {
+                        list.next_number = Some(number + 1);
+                        format!("{number}. ")
+                    },
+                );
+                // Items of the same list are not separated by blank lines
+                self.tight = list.has_items;
+                list.has_items = true;
+                self.containers.push(Container::Item {
+                    marker,
+                    pending: true,
+                });
+            }
+            Tag::FootnoteDefinition(label) => {
+                self.flush_paragraph();
+                let number = self.footnote_number(label);
+                let body = std::mem::take(&mut self.output);
+                self.footnote_body = Some((number, body));
+            }
+            Tag::Table(_) => {
+                self.flush_paragraph();
+                self.table = Some(Table::default());
+            }
+            Tag::TableHead | Tag::TableRow => {
+                if let Some(table) = &mut self.table {
+                    table.rows.push(Vec::new());
+                }
+            }
+            Tag::Emphasis => self.inline.push('_'),
+            Tag::Strong => self.inline.push('*'),
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                self.links.push((self.inline.len(), dest_url.to_string()));
+            }
+            Tag::Paragraph
+            | Tag::Heading { .. }
+            | Tag::CodeBlock(_)
+            | Tag::TableCell
+            | Tag::HtmlBlock
+            | Tag::Strikethrough
+            | Tag::DefinitionList
+            | Tag::DefinitionListTitle
+            | Tag::DefinitionListDefinition
+            | Tag::MetadataBlock(_) => self.flush_paragraph(),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph | TagEnd::DefinitionListTitle | TagEnd::DefinitionListDefinition => {
+                self.flush_paragraph();
+            }
+            TagEnd::Heading(level) => {
+                let heading = std::mem::take(&mut self.inline).trim().to_string();
+                let underline = |c: &str| c.repeat(display_width(&heading));
+                let lines = match level {
+                    HeadingLevel::H1 => vec![heading.clone(), underline("=")],
+                    HeadingLevel::H2 => vec![heading.clone(), underline("-")],
+                    _ => vec![format!("### {heading}")],
+                };
+                self.push_block(&lines);
+            }
+            TagEnd::CodeBlock => {
+                let code = std::mem::take(&mut self.inline);
+                let lines: Vec<String> = code.lines().map(|line| format!("    {line}")).collect();
+                self.push_block(&lines);
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush_paragraph();
+                self.containers.pop();
+            }
+            TagEnd::List(_) => {
+                self.flush_paragraph();
+                self.lists.pop();
+            }
+            TagEnd::Item => {
+                self.flush_paragraph();
+                self.containers.pop();
+            }
+            TagEnd::FootnoteDefinition => {
+                self.flush_paragraph();
+                if let Some((number, body)) = self.footnote_body.take() {
+                    let definition = std::mem::replace(&mut self.output, body);
+                    self.footnotes.push((number, definition.trim().to_string()));
+                }
+            }
+            TagEnd::TableCell => {
+                let cell = std::mem::take(&mut self.inline).trim().to_string();
+                if let Some(row) = self.table.as_mut().and_then(|t| t.rows.last_mut()) {
+                    row.push(cell);
+                }
+            }
+            TagEnd::TableHead => {
+                if let Some(table) = &mut self.table {
+                    table.header_rows = table.rows.len();
+                }
+            }
+            TagEnd::Table => {
+                if let Some(table) = self.table.take() {
+                    self.push_block(&table.lines());
+                }
+            }
+            TagEnd::Emphasis => self.inline.push('_'),
+            TagEnd::Strong => self.inline.push('*'),
+            TagEnd::Link | TagEnd::Image => {
+                if let Some((start, url)) = self.links.pop() {
+                    let label = self.inline[start..].trim().to_string();
+                    let url = url.strip_prefix("mailto:").unwrap_or(&url);
+                    if label.is_empty() {
+                        self.inline.push_str(url);
+                    } else if label != url {
+                        self.inline.push_str(&format!(" ({url})"));
+                    }
+                }
+            }
+            TagEnd::TableRow
+            | TagEnd::HtmlBlock
+            | TagEnd::Strikethrough
+            | TagEnd::DefinitionList
+            | TagEnd::MetadataBlock(_) => {}
+        }
+    }
+
+    fn footnote_number(&mut self, label: &str) -> usize {
+        let next = self.footnote_numbers.len() + 1;
+        *self
+            .footnote_numbers
+            .entry(label.to_string())
+            .or_insert(next)
+    }
+
+    /// The prefixes for the blank line before the next block and for its first
+    /// and following lines, derived from the blockquotes and list items that
+    /// enclose it.
+    fn prefixes(&mut self) -> Prefixes {
+        let mut prefixes = Prefixes::default();
+        for container in &mut self.containers {
+            match container {
+                Container::BlockQuote { started } => {
+                    if *started {
+                        prefixes.separator.push('>');
+                    }
+                    prefixes.first.push_str("> ");
+                    prefixes.rest.push_str("> ");
+                    *started = true;
+                }
+                Container::Item { marker, pending } => {
+                    let indent = " ".repeat(marker.len());
+                    if *pending {
+                        prefixes.first.push_str(marker);
+                        *pending = false;
+                    } else {
+                        prefixes.first.push_str(&indent);
+                    }
+                    prefixes.rest.push_str(&indent);
+                }
+            }
+        }
+        prefixes
+    }
+
+    fn flush_paragraph(&mut self) {
+        let paragraph = std::mem::take(&mut self.inline);
+        let paragraph = paragraph.trim();
+        if paragraph.is_empty() {
+            return;
+        }
+        let prefixes = self.prefixes();
+        let options = textwrap::Options::new(TEXT_WIDTH)
+            .initial_indent(&prefixes.first)
+            .subsequent_indent(&prefixes.rest)
+            .break_words(false);
+        let lines: Vec<String> = textwrap::wrap(paragraph, options)
+            .into_iter()
+            .map(|line| line.trim_end().to_string())
+            .collect();
+        self.write_lines(&lines, &prefixes.separator);
+    }
+
+    fn push_block(&mut self, lines: &[String]) {
+        let prefixes = self.prefixes();
+        let lines: Vec<String> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let prefix = if i == 0 {
+                    &prefixes.first
+                } else {
+                    &prefixes.rest
+                };
+                format!("{prefix}{line}").trim_end().to_string()
+            })
+            .collect();
+        self.write_lines(&lines, &prefixes.separator);
+    }
+
+    fn write_lines(&mut self, lines: &[String], separator: &str) {
+        if !self.output.is_empty() && !self.tight {
+            self.output.push_str(separator);
+            self.output.push('\n');
+        }
+        self.tight = false;
+        for line in lines {
+            self.output.push_str(line);
+            self.output.push('\n');
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.flush_paragraph();
+        if !self.footnotes.is_empty() {
+            self.footnotes.sort_by_key(|(number, _)| *number);
+            let footnotes: Vec<String> = self
+                .footnotes
+                .iter()
+                .map(|(number, text)| format!("[{number}] {text}"))
+                .collect();
+            self.push_block(&["-".repeat(TEXT_WIDTH / 2)]);
+            self.push_block(&footnotes);
+        }
+        self.output
+    }
+}
+
+impl Table {
+    fn lines(&self) -> Vec<String> {
+        let columns = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| display_width(cell))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut lines = Vec::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let cell = row.get(column).map_or("", String::as_str);
+                    let padding = width - display_width(cell);
+                    format!("{cell}{}", " ".repeat(padding))
+                })
+                .collect();
+            lines.push(cells.join(" | ").trim_end().to_string());
+            if i + 1 == self.header_rows {
+                let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+                lines.push(rule.join("-|-"));
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MarkdownRenderer;
+
+    fn renderer() -> MarkdownRenderer {
+        MarkdownRenderer::new("p { color: #333333; }".into())
+    }
+
+    #[test]
+    fn html_and_text_are_rendered_from_the_same_source() {
+        let content = renderer().render("# Issue 1\n\nHello **world**.");
+
+        assert!(content.html.contains("<h1>Issue 1</h1>"));
+        assert!(content.html.contains("<strong>world</strong>"));
+        assert_eq!(content.text, "Issue 1\n=======\n\nHello *world*.\n");
+    }
+
+    #[test]
+    fn stylesheet_is_inlined_into_the_html() {
+        let content = renderer().render("Hello");
+
+        assert!(content
+            .html
+            .contains(r#"<p style="color: #333333;">Hello</p>"#));
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed_from_the_html() {
+        let content = renderer()
+            .render("<script>alert(1)</script>\n\n<img src=\"x.png\" onerror=\"alert(1)\">");
+
+        assert!(!content.html.contains("<script"));
+        assert!(!content.html.contains("onerror"));
+    }
+
+    #[test]
+    fn long_paragraphs_are_wrapped_in_the_text() {
+        let content = renderer().render(&"word ".repeat(40));
+
+        assert!(content.text.lines().count() > 1);
+        assert!(content.text.lines().all(|line| line.len() <= 72));
+    }
+
+    #[test]
+    fn links_keep_their_url_in_the_text() {
+        let content = renderer().render("Read [the docs](https://example.com/docs).");
+
+        assert_eq!(content.text, "Read the docs (https://example.com/docs).\n");
+    }
+
+    #[test]
+    fn lists_and_blockquotes_are_prefixed_in_the_text() {
+        let content = renderer().render("- one\n- two\n\n1. first\n2. second\n\n> quoted");
+
+        assert_eq!(
+            content.text,
+            "- one\n- two\n\n1. first\n2. second\n\n> quoted\n"
+        );
+    }
+
+    #[test]
+    fn tables_are_aligned_in_the_text() {
+        let content = renderer().render("| a | long header |\n|---|---|\n| 1 | 2 |");
+
+        assert!(content.html.contains("<table>"));
+        assert_eq!(content.text, "a | long header\n--|------------\n1 | 2\n");
+    }
+
+    #[test]
+    fn footnotes_are_listed_at_the_end_of_the_text() {
+        let content = renderer().render("Claim.[^source]\n\n[^source]: The source.");
+
+        assert!(content.html.contains("footnote-definition"));
+        assert!(content.text.starts_with("Claim.[1]\n"));
+        assert!(content.text.ends_with("[1] The source.\n"));
+    }
+
+    #[test]
+    fn footnote_links_point_at_their_prefixed_definitions() {
+        let html = renderer().render_fragment("Claim.[^source]\n\n[^source]: The source.");
+
+        assert!(html.contains(r##"href="#footnote-source""##));
+        assert!(html.contains(r#"id="footnote-source""#));
+    }
+
+    #[test]
+    fn ids_written_by_authors_are_namespaced() {
+        let html = renderer().render_fragment(r#"<div id="main">Hi</div>"#);
+
+        assert!(html.contains(r#"id="footnote-main""#));
+        assert!(!html.contains(r#"id="main""#));
+    }
+}
@@ -0,0 +1,577 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::{NaiveDate, Utc};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+    configuration::Settings,
+    domain::SubscriberEmail,
+    email_client::{EmailClient, EmailClientError, Recipient, MAX_BATCH_SIZE},
+    startup::get_connection_pool,
+};
+
+/// Deliveries that keep failing are given up on after this many attempts
+pub const MAX_ATTEMPTS: i32 = 5;
+
+/// The error kind of dead letters for subscribers who could not be sent to,
+/// e.g. because they unsubscribed since the issue was published
+const INVALID_RECIPIENT: &str = "invalid_recipient";
+/// The error kind of dead letters for recipients the email provider refused
+/// while accepting the rest of the batch, as for [`EmailClientError::Rejected`]
+const REFUSED_RECIPIENT: &str = "rejected";
+
+/// How long to wait when rate limited by a provider that did not say how
+/// long, or when no provider is available
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Substitution keys that issue authors can use in the title and Markdown
+/// content, replaced for each recipient when the issue is sent.
+pub const NAME_SUBSTITUTION: &str = "{{name}}";
+pub const EMAIL_SUBSTITUTION: &str = "{{email}}";
+
+/// Custom arguments attached to each delivery, which SendGrid echoes back in
+/// its webhook events about it.
+pub const SUBSCRIBER_ID_ARG: &str = "subscriber_id";
+pub const NEWSLETTER_ISSUE_ID_ARG: &str = "newsletter_issue_id";
+
+pub enum ExecutionOutcome {
+    TasksCompleted,
+    EmptyQueue,
+    /// The daily quota of the email client is used up, deliveries resume
+    /// the next UTC day.
+    QuotaExhausted,
+}
+
+struct Task {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+}
+
+struct Issue {
+    title: String,
+    content: String,
+}
+
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    email_client: EmailClient,
+) -> Result<(), std::io::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    worker_loop(&connection_pool, &email_client).await
+}
+
+async fn worker_loop(pool: &PgPool, email_client: &EmailClient) -> Result<(), std::io::Error> {
+    loop {
+        match try_execute_batch(pool, email_client).await {
+            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+            Ok(ExecutionOutcome::QuotaExhausted) => {
+                tokio::time::sleep(until_next_day()).await;
+            }
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Ok(ExecutionOutcome::TasksCompleted) => {}
+        }
+    }
+}
+
+/// Queue the delivery of an issue to every confirmed subscriber
+#[tracing::instrument(skip(transaction))]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_id, status)
+        SELECT $1, id, 'queued'
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// How long until the next UTC day, when the daily quota is reset
+fn until_next_day() -> Duration {
+    let now = Utc::now();
+    let tomorrow = (now.date_naive() + chrono::Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .expect("Midnight is a valid time")
+        .and_utc();
+    (tomorrow - now).to_std().unwrap_or(DEFAULT_RETRY_AFTER)
+}
+
+/// Count emails sent today against the daily quota
+#[tracing::instrument(skip(executor))]
+pub async fn record_sent_emails(
+    executor: impl PgExecutor<'_>,
+    sent_emails: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_daily_usage (day, sent_emails)
+        VALUES ($1, $2)
+        ON CONFLICT (day) DO UPDATE
+        SET sent_emails = email_daily_usage.sent_emails + EXCLUDED.sent_emails
+        "#,
+        Utc::now().date_naive(),
+        sent_emails,
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// How many emails can still be sent today, locking today's usage until the
+/// transaction ends so that concurrent workers do not exceed the quota.
+async fn remaining_quota(
+    transaction: &mut Transaction<'_, Postgres>,
+    daily_quota: u32,
+    day: NaiveDate,
+) -> Result<usize, sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO email_daily_usage (day) VALUES ($1) ON CONFLICT DO NOTHING",
+        day,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let usage = sqlx::query!(
+        "SELECT sent_emails FROM email_daily_usage WHERE day = $1 FOR UPDATE",
+        day,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+    let sent_emails = u32::try_from(usage.sent_emails).unwrap_or_default();
+    Ok(daily_quota.saturating_sub(sent_emails) as usize)
+}
+
+/// Send one batch of due deliveries, all for the same issue, in a single request.
+///
+/// Once the daily quota of the email client is used up, deliveries are left
+/// queued until the next UTC day.
+#[tracing::instrument(
+    skip_all,
+    fields(newsletter_issue_id = tracing::field::Empty, batch_size = tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_batch(
+    pool: &PgPool,
+    email_client: &EmailClient,
+) -> Result<ExecutionOutcome, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let batch_size = match email_client.daily_quota() {
+        Some(daily_quota) => {
+            let remaining =
+                remaining_quota(&mut transaction, daily_quota, Utc::now().date_naive()).await?;
+            if remaining == 0 {
+                return Ok(ExecutionOutcome::QuotaExhausted);
+            }
+            remaining.min(MAX_BATCH_SIZE)
+        }
+        None => MAX_BATCH_SIZE,
+    };
+    let Some((issue_id, tasks)) = dequeue_batch(&mut transaction, batch_size).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("newsletter_issue_id", display(issue_id))
+        .record("batch_size", display(tasks.len()));
+
+    let issue = get_issue(&mut transaction, issue_id).await?;
+    let content = email_client.renderer().render(&issue.content);
+
+    let mut recipients = Vec::with_capacity(tasks.len());
+    let mut batch = Vec::with_capacity(tasks.len());
+    let mut rejected = Vec::new();
+    for task in tasks {
+        if task.status != "confirmed" {
+            rejected.push(task.subscriber_id);
+            continue;
+        }
+        match SubscriberEmail::parse(task.email.clone()) {
+            Ok(email) => {
+                recipients.push(Recipient {
+                    email,
+                    substitutions: BTreeMap::from([
+                        (NAME_SUBSTITUTION.to_string(), task.name.clone()),
+                        (EMAIL_SUBSTITUTION.to_string(), task.email.clone()),
+                    ]),
+                    custom_args: BTreeMap::from([
+                        (
+                            SUBSCRIBER_ID_ARG.to_string(),
+                            task.subscriber_id.to_string(),
+                        ),
+                        (NEWSLETTER_ISSUE_ID_ARG.to_string(), issue_id.to_string()),
+                    ]),
+                });
+                batch.push(task);
+            }
+            Err(error) => {
+                tracing::error!(
+                    error.message = %error,
+                    "Skipping a subscriber whose stored contact details are invalid",
+                );
+                rejected.push(task.subscriber_id);
+            }
+        }
+    }
+    if !rejected.is_empty() {
+        mark_failed(
+            &mut transaction,
+            issue_id,
+            &rejected,
+            INVALID_RECIPIENT,
+            "The subscriber is no longer confirmed or their email address is invalid",
+        )
+        .await?;
+    }
+
+    if !recipients.is_empty() {
+        let subscriber_ids: Vec<Uuid> = batch.iter().map(|task| task.subscriber_id).collect();
+        match email_client
+            .send_batch_email(&recipients, &issue.title, &content.html, &content.text)
+            .await
+        {
+            Ok(sent_email) => {
+                let is_among = |emails: &[SubscriberEmail], task: &Task| {
+                    emails.iter().any(|email| email.as_ref() == task.email)
+                };
+                let unsent_recipients = sent_email
+                    .unsent
+                    .as_ref()
+                    .map_or(&[][..], |unsent| &unsent.recipients);
+                let (unsent, sent): (Vec<&Task>, Vec<&Task>) = batch
+                    .iter()
+                    .partition(|task| is_among(unsent_recipients, task));
+                let (refused, delivered): (Vec<&Task>, Vec<&Task>) = sent
+                    .into_iter()
+                    .partition(|task| is_among(&sent_email.rejected_recipients, task));
+                let delivered: Vec<Uuid> =
+                    delivered.iter().map(|task| task.subscriber_id).collect();
+                mark_sent(
+                    &mut transaction,
+                    issue_id,
+                    &delivered,
+                    sent_email.message_id.as_deref(),
+                )
+                .await?;
+                record_sent_emails(
+                    &mut *transaction,
+                    i32::try_from(delivered.len()).unwrap_or(i32::MAX),
+                )
+                .await?;
+                if !refused.is_empty() {
+                    let refused: Vec<Uuid> =
+                        refused.iter().map(|task| task.subscriber_id).collect();
+                    mark_failed(
+                        &mut transaction,
+                        issue_id,
+                        &refused,
+                        REFUSED_RECIPIENT,
+                        "The email provider refused the recipient",
+                    )
+                    .await?;
+                }
+                if let Some(error) = sent_email.unsent.map(|unsent| unsent.error) {
+                    tracing::error!(
+                        error.cause_chain = ?error,
+                        error.message = %error,
+                        "Failed to deliver issue to some of a batch of confirmed subscribers",
+                    );
+                    let unsent: Vec<Uuid> = unsent.iter().map(|task| task.subscriber_id).collect();
+                    retry_later(
+                        &mut transaction,
+                        issue_id,
+                        &unsent,
+                        error.kind(),
+                        &error.to_string(),
+                    )
+                    .await?;
+                }
+            }
+            Err(error) => {
+                tracing::error!(
+                    error.cause_chain = ?error,
+                    error.message = %error,
+                    "Failed to deliver issue to a batch of confirmed subscribers",
+                );
+                let message = error.to_string();
+                match error {
+                    // Every provider is throttling us or failing fast, wait
+                    // for one to recover
+                    EmailClientError::RateLimited { .. } | EmailClientError::Unavailable => {
+                        let retry_after = match error {
+                            EmailClientError::RateLimited {
+                                retry_after: Some(retry_after),
+                            } => retry_after,
+                            _ => DEFAULT_RETRY_AFTER,
+                        };
+                        postpone(
+                            &mut transaction,
+                            issue_id,
+                            &subscriber_ids,
+                            retry_after,
+                            &message,
+                        )
+                        .await?;
+                    }
+                    error if !error.is_transient() => {
+                        mark_failed(
+                            &mut transaction,
+                            issue_id,
+                            &subscriber_ids,
+                            error.kind(),
+                            &message,
+                        )
+                        .await?;
+                    }
+                    error => {
+                        retry_later(
+                            &mut transaction,
+                            issue_id,
+                            &subscriber_ids,
+                            error.kind(),
+                            &message,
+                        )
+                        .await?;
+                    }
+                }
+            }
+        }
+    }
+
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TasksCompleted)
+}
+
+/// Lock up to `batch_size` due deliveries of the issue with the oldest due
+/// delivery, skipping those locked by other workers.
+async fn dequeue_batch(
+    transaction: &mut Transaction<'_, Postgres>,
+    batch_size: usize,
+) -> Result<Option<(Uuid, Vec<Task>)>, sqlx::Error> {
+    let next = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM issue_deliveries
+        WHERE status = 'queued' AND execute_after <= now()
+        ORDER BY execute_after
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(next) = next else {
+        return Ok(None);
+    };
+
+    let tasks = sqlx::query_as!(
+        Task,
+        r#"
+        SELECT d.subscriber_id, s.email, s.name, s.status
+        FROM issue_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        WHERE d.newsletter_issue_id = $1
+            AND d.status = 'queued'
+            AND d.execute_after <= now()
+        ORDER BY d.execute_after
+        FOR UPDATE OF d
+        SKIP LOCKED
+        LIMIT $2
+        "#,
+        next.newsletter_issue_id,
+        batch_size as i64,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    Ok(Some((next.newsletter_issue_id, tasks)))
+}
+
+async fn get_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+) -> Result<Issue, sqlx::Error> {
+    sqlx::query_as!(
+        Issue,
+        "SELECT title, content FROM newsletter_issues WHERE id = $1",
+        issue_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+}
+
+async fn mark_sent(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_ids: &[Uuid],
+    message_id: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'sent', n_attempts = n_attempts + 1, sent_at = $3, last_error = NULL,
+            message_id = $4
+        WHERE newsletter_issue_id = $1 AND subscriber_id = ANY($2)
+        "#,
+        issue_id,
+        subscriber_ids,
+        Utc::now(),
+        message_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+async fn mark_failed(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_ids: &[Uuid],
+    error_kind: &str,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET status = 'failed', n_attempts = n_attempts + 1, last_error = $3
+        WHERE newsletter_issue_id = $1 AND subscriber_id = ANY($2)
+        "#,
+        issue_id,
+        subscriber_ids,
+        error,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    dead_letter(transaction, issue_id, subscriber_ids, error_kind, error).await
+}
+
+/// Back off exponentially before the next attempt, or give up on a
+/// delivery once it has been attempted [`MAX_ATTEMPTS`] times.
+async fn retry_later(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_ids: &[Uuid],
+    error_kind: &str,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET n_attempts = n_attempts + 1,
+            status = CASE WHEN n_attempts + 1 >= $3 THEN 'failed' ELSE status END,
+            execute_after = now() + interval '1 minute' * power(2, n_attempts + 1),
+            last_error = $4
+        WHERE newsletter_issue_id = $1 AND subscriber_id = ANY($2)
+        "#,
+        issue_id,
+        subscriber_ids,
+        MAX_ATTEMPTS,
+        error,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    dead_letter(transaction, issue_id, subscriber_ids, error_kind, error).await
+}
+
+/// Record the deliveries among `subscriber_ids` that failed for good in the
+/// dead letters, from where they can be inspected and replayed.
+async fn dead_letter(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_ids: &[Uuid],
+    error_kind: &str,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO dead_letters
+            (newsletter_issue_id, subscriber_id, error_kind, error, n_attempts, queued_at)
+        SELECT newsletter_issue_id, subscriber_id, $3, $4, n_attempts, queued_at
+        FROM issue_deliveries
+        WHERE newsletter_issue_id = $1 AND subscriber_id = ANY($2) AND status = 'failed'
+        ON CONFLICT (newsletter_issue_id, subscriber_id) DO UPDATE
+        SET error_kind = EXCLUDED.error_kind, error = EXCLUDED.error,
+            n_attempts = EXCLUDED.n_attempts, failed_at = now()
+        "#,
+        issue_id,
+        subscriber_ids,
+        error_kind,
+        error,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
+
+/// Queue dead letters of an issue for delivery again, all of them unless
+/// `subscriber_ids` selects some. Returns how many were replayed.
+#[tracing::instrument(skip(transaction))]
+pub async fn replay_dead_letters(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_ids: Option<&[Uuid]>,
+) -> Result<u64, sqlx::Error> {
+    let replayed = sqlx::query!(
+        r#"
+        WITH replayed AS (
+            DELETE FROM dead_letters
+            WHERE newsletter_issue_id = $1
+                AND ($2::uuid[] IS NULL OR subscriber_id = ANY($2))
+            RETURNING subscriber_id
+        )
+        UPDATE issue_deliveries
+        SET status = 'queued', n_attempts = 0, execute_after = now(), last_error = NULL
+        WHERE newsletter_issue_id = $1
+            AND subscriber_id IN (SELECT subscriber_id FROM replayed)
+        "#,
+        issue_id,
+        subscriber_ids as Option<&[Uuid]>,
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(replayed.rows_affected())
+}
+
+/// Wait as long as the email provider asked before trying again, without
+/// counting the throttled request as an attempt.
+async fn postpone(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subscriber_ids: &[Uuid],
+    retry_after: Duration,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    let execute_after = Utc::now()
+        + chrono::Duration::from_std(retry_after).unwrap_or_else(|_| chrono::Duration::days(1));
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET execute_after = $3, last_error = $4
+        WHERE newsletter_issue_id = $1 AND subscriber_id = ANY($2)
+        "#,
+        issue_id,
+        subscriber_ids,
+        execute_after,
+        error,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(())
+}
@@ -1,7 +1,22 @@
+pub mod analytics;
+pub mod api_keys;
+pub mod audit;
+pub mod authentication;
+pub mod authorization;
 pub mod configuration;
+pub mod consent;
+pub mod data_protection;
 pub mod domain;
 pub mod email_client;
+pub mod email_content;
+pub mod issue_delivery_worker;
+pub mod oidc;
+pub mod retention;
 pub mod routes;
 pub mod sendgrid_email_format;
+pub mod session;
 pub mod startup;
+pub mod subscriber_import;
 pub mod telemetry;
+pub mod totp;
+pub mod utils;
@@ -1,8 +1,20 @@
-use std::io::stdout;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{stdin, stdout, Read},
+};
 
+use secrecy::Secret;
+use tokio::task::JoinError;
+use uuid::Uuid;
 use zero2prod::{
+    authentication::compute_password_hash,
+    authorization::Role,
     configuration,
-    startup::Application,
+    issue_delivery_worker::run_worker_until_stopped,
+    retention::run_retention_until_stopped,
+    startup::{get_connection_pool, Application},
+    subscriber_import::{import_subscribers, ImportFormat},
     telemetry::{get_subscriber, init_subscriber},
 };
 
@@ -13,8 +25,109 @@ async fn main() -> std::io::Result<()> {
 
     let configuration = configuration::get().expect("Failed to read configuration");
 
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    if let [command, username, role @ ..] = arguments.as_slice() {
+        if command == "create-user" && role.len() <= 1 {
+            let role = role
+                .first()
+                .map_or(Ok(Role::Owner), |role| Role::parse(role))
+                .map_err(std::io::Error::other)?;
+            return create_user(&configuration, username, role).await;
+        }
+    }
+    if let [command, path, format @ ..] = arguments.as_slice() {
+        if command == "import-subscribers" && format.len() <= 1 {
+            let format = format
+                .first()
+                .map_or(Ok(ImportFormat::Generic), |format| {
+                    ImportFormat::parse(format)
+                })
+                .map_err(std::io::Error::other)?;
+            return import(&configuration, path, format).await;
+        }
+    }
+
     let application = Application::build(&configuration).await?;
-    application.run_until_stopped().await?;
+    let email_client = application.email_client().clone();
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let retention_task = tokio::spawn(run_retention_until_stopped(
+        get_connection_pool(&configuration.database),
+        email_client.clone(),
+        configuration.retention.clone(),
+        configuration.application.base_url.clone(),
+    ));
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration, email_client));
+
+    tokio::select! {
+        outcome = application_task => report_exit("API", outcome),
+        outcome = worker_task => report_exit("Background worker", outcome),
+        outcome = retention_task => report_exit("Retention job", outcome),
+    };
+
+    Ok(())
+}
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name);
+        }
+        Ok(Err(e)) => {
+            tracing::error!(error.message = %e, "{} failed", task_name);
+        }
+        Err(e) => {
+            tracing::error!(error.message = %e, "{} task failed to complete", task_name);
+        }
+    }
+}
+
+/// Add an admin, reading their password from the standard input, as an
+/// owner unless another role is given:
+/// `zero2prod create-user <username> [viewer|editor|publisher|owner]`
+async fn create_user(
+    configuration: &configuration::Settings,
+    username: &str,
+    role: Role,
+) -> std::io::Result<()> {
+    let mut password = String::new();
+    stdin().read_line(&mut password)?;
+    let password = Secret::new(password.trim_end_matches(['\r', '\n']).to_string());
+    let password_hash = compute_password_hash(&password);
+    let pool = get_connection_pool(&configuration.database);
+    sqlx::query!(
+        "INSERT INTO users (user_id, username, password_hash, role) VALUES ($1, $2, $3, $4)",
+        Uuid::new_v4(),
+        username,
+        secrecy::ExposeSecret::expose_secret(&password_hash),
+        role.as_ref(),
+    )
+    .execute(&pool)
+    .await
+    .map_err(std::io::Error::other)?;
+    tracing::info!("Created user {} as {}", username, role.as_ref());
+    Ok(())
+}
 
+/// Import subscribers from a CSV file, or from the standard input given `-`,
+/// printing the report as JSON:
+/// `zero2prod import-subscribers <path> [generic|mailchimp|substack]`
+async fn import(
+    configuration: &configuration::Settings,
+    path: &str,
+    format: ImportFormat,
+) -> std::io::Result<()> {
+    let csv: Box<dyn Read + Send> = if path == "-" {
+        Box::new(stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+    let pool = get_connection_pool(&configuration.database);
+    let report = import_subscribers(&pool, csv, format, None)
+        .await
+        .map_err(std::io::Error::other)?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&report).map_err(std::io::Error::other)?
+    );
     Ok(())
 }
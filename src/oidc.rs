@@ -0,0 +1,334 @@
+//! Single sign-on of admins with an OpenID Connect provider, using the
+//! authorization code flow with PKCE (RFC 7636).
+//!
+//! The provider is discovered from its issuer URL, and its ID tokens are
+//! only trusted once signed by one of its published keys, for this client,
+//! and for the nonce of the login they end.
+
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+};
+
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use rand::{thread_rng, RngCore};
+use reqwest::StatusCode;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{authorization::Role, configuration::OidcSettings, utils::error_chain_fmt};
+
+/// A random URL safe string, for states, nonces and PKCE verifiers
+#[must_use]
+pub fn generate_random_token() -> String {
+    let mut token = [0u8; 32];
+    thread_rng().fill_bytes(&mut token);
+    BASE64URL_NOPAD.encode(&token)
+}
+
+/// The `S256` code challenge of a PKCE verifier
+#[must_use]
+pub fn code_challenge(code_verifier: &Secret<String>) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(code_verifier.expose_secret().as_bytes()))
+}
+
+pub enum OidcError {
+    /// The provider could not be reached, or answered with an error
+    Provider(reqwest::Error),
+    /// The discovery document of the provider is unusable
+    InvalidMetadata(String),
+    /// The provider refused the code, or its PKCE verifier
+    InvalidGrant(String),
+    /// The ID token is missing, malformed, or cannot be trusted
+    InvalidIdToken(String),
+}
+
+impl Display for OidcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Provider(_) => write!(f, "Failed to reach the OpenID Connect provider"),
+            Self::InvalidMetadata(reason) => write!(f, "Invalid provider metadata: {reason}"),
+            Self::InvalidGrant(error) => write!(f, "The provider refused the code: {error}"),
+            Self::InvalidIdToken(reason) => write!(f, "Invalid ID token: {reason}"),
+        }
+    }
+}
+
+impl Error for OidcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Provider(e) => Some(e),
+            Self::InvalidMetadata(_) | Self::InvalidGrant(_) | Self::InvalidIdToken(_) => None,
+        }
+    }
+}
+
+impl Debug for OidcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<reqwest::Error> for OidcError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Provider(e)
+    }
+}
+
+/// The endpoints of the provider, from its discovery document
+#[derive(Deserialize, Debug)]
+struct ProviderMetadata {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// The identity of an admin, according to the provider
+#[derive(Debug)]
+pub struct OidcIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub username: String,
+    /// The highest role granted by the role claim, if any
+    pub role: Option<Role>,
+}
+
+#[derive(Clone)]
+pub struct OidcClient {
+    http_client: reqwest::Client,
+    settings: OidcSettings,
+    /// Where the provider sends admins back to, `/admin/oidc/callback`
+    redirect_url: String,
+}
+
+impl OidcClient {
+    #[must_use]
+    pub fn new(settings: OidcSettings, base_url: &str) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            settings,
+            redirect_url: format!("{base_url}/admin/oidc/callback"),
+        }
+    }
+
+    async fn provider_metadata(&self) -> Result<ProviderMetadata, OidcError> {
+        let metadata = self
+            .http_client
+            .get(format!(
+                "{}/.well-known/openid-configuration",
+                self.settings.issuer_url.trim_end_matches('/')
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(metadata)
+    }
+
+    /// Where to send admins to sign in with the provider
+    #[tracing::instrument(name = "Build the OIDC authorization URL", skip_all)]
+    pub async fn authorization_url(
+        &self,
+        state: &str,
+        nonce: &str,
+        code_verifier: &Secret<String>,
+    ) -> Result<String, OidcError> {
+        let metadata = self.provider_metadata().await?;
+        let mut url = reqwest::Url::parse(&metadata.authorization_endpoint)
+            .map_err(|e| OidcError::InvalidMetadata(format!("The authorization endpoint: {e}")))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.settings.client_id)
+            .append_pair("redirect_uri", &self.redirect_url)
+            .append_pair("scope", "openid profile email")
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &code_challenge(code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.into())
+    }
+
+    /// Exchange the code of the callback for the identity of the admin
+    #[tracing::instrument(name = "Exchange an OIDC authorization code", skip_all)]
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        code_verifier: &Secret<String>,
+        nonce: &str,
+    ) -> Result<OidcIdentity, OidcError> {
+        let metadata = self.provider_metadata().await?;
+        let response = self
+            .http_client
+            .post(&metadata.token_endpoint)
+            .basic_auth(
+                &self.settings.client_id,
+                Some(self.settings.client_secret.expose_secret()),
+            )
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_url),
+                ("code_verifier", code_verifier.expose_secret()),
+            ])
+            .send()
+            .await?;
+        if response.status() == StatusCode::BAD_REQUEST {
+            return Err(OidcError::InvalidGrant(response.text().await?));
+        }
+        let tokens: TokenResponse = response.error_for_status()?.json().await?;
+        let id_token = tokens
+            .id_token
+            .ok_or_else(|| OidcError::InvalidIdToken("The provider sent none".into()))?;
+        let claims = self.verify_id_token(&metadata, &id_token).await?;
+        if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+            return Err(OidcError::InvalidIdToken(
+                "The nonce is not the one of the login".into(),
+            ));
+        }
+        self.identity(&claims)
+    }
+
+    /// The claims of an ID token signed by the provider for this client
+    async fn verify_id_token(
+        &self,
+        metadata: &ProviderMetadata,
+        id_token: &str,
+    ) -> Result<Map<String, Value>, OidcError> {
+        let invalid = |e: jsonwebtoken::errors::Error| OidcError::InvalidIdToken(e.to_string());
+        let header = decode_header(id_token).map_err(invalid)?;
+        // Symmetric algorithms would let anyone knowing the client secret sign
+        if !matches!(
+            header.alg,
+            Algorithm::RS256
+                | Algorithm::RS384
+                | Algorithm::RS512
+                | Algorithm::PS256
+                | Algorithm::PS384
+                | Algorithm::PS512
+                | Algorithm::ES256
+                | Algorithm::ES384
+                | Algorithm::EdDSA
+        ) {
+            return Err(OidcError::InvalidIdToken(format!(
+                "{:?} is not an accepted algorithm",
+                header.alg
+            )));
+        }
+        let keys: JwkSet = self
+            .http_client
+            .get(&metadata.jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let key = keys
+            .keys
+            .iter()
+            .filter(|key| !matches!(key.algorithm, AlgorithmParameters::OctetKey(_)))
+            .find(|key| header.kid.is_none() || key.common.key_id == header.kid)
+            .ok_or_else(|| OidcError::InvalidIdToken("Signed with an unknown key".into()))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.settings.client_id]);
+        validation.set_issuer(&[&self.settings.issuer_url]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let token = decode::<Map<String, Value>>(
+            id_token,
+            &DecodingKey::from_jwk(key).map_err(invalid)?,
+            &validation,
+        )
+        .map_err(invalid)?;
+        Ok(token.claims)
+    }
+
+    fn identity(&self, claims: &Map<String, Value>) -> Result<OidcIdentity, OidcError> {
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or_else(|| OidcError::InvalidIdToken("The subject is not a string".into()))?;
+        let username = claims
+            .get(&self.settings.username_claim)
+            .and_then(Value::as_str)
+            .unwrap_or(subject);
+        Ok(OidcIdentity {
+            issuer: self.settings.issuer_url.clone(),
+            subject: subject.to_string(),
+            username: username.to_string(),
+            role: self.role(claims.get(&self.settings.role_claim)),
+        })
+    }
+
+    /// The highest role granted by the values of the role claim
+    fn role(&self, role_claim: Option<&Value>) -> Option<Role> {
+        let values: Vec<&str> = match role_claim {
+            Some(Value::String(value)) => vec![value],
+            Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        values
+            .into_iter()
+            .filter_map(|value| self.settings.roles.get(value).copied())
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use secrecy::Secret;
+    use serde_json::json;
+
+    use super::{code_challenge, OidcClient};
+    use crate::{authorization::Role, configuration::OidcSettings};
+
+    #[test]
+    fn code_challenges_match_the_rfc_7636_example() {
+        let code_verifier = Secret::new("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+
+        assert_eq!(
+            code_challenge(&code_verifier),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn the_highest_role_granted_by_the_role_claim_is_kept() {
+        let client = OidcClient::new(
+            OidcSettings {
+                issuer_url: "https://login.example.com".to_string(),
+                client_id: "newsletter".to_string(),
+                client_secret: Secret::new("secret".to_string()),
+                role_claim: "groups".to_string(),
+                roles: HashMap::from([
+                    ("editors".to_string(), Role::Editor),
+                    ("admins".to_string(), Role::Owner),
+                ]),
+                username_claim: "email".to_string(),
+            },
+            "https://newsletter.example.com",
+        );
+
+        assert_eq!(
+            client.role(Some(&json!(["staff", "editors", "admins"]))),
+            Some(Role::Owner)
+        );
+        assert_eq!(client.role(Some(&json!("editors"))), Some(Role::Editor));
+        assert_eq!(client.role(Some(&json!(["staff"]))), None);
+        assert_eq!(client.role(None), None);
+    }
+}
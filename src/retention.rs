@@ -0,0 +1,231 @@
+//! Purging subscribers who never confirmed, so that their addresses are not
+//! kept forever.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    configuration::RetentionSettings, consent::erase_consent_events, domain::SubscriberEmail,
+    email_client::EmailClient, issue_delivery_worker::record_sent_emails,
+};
+
+/// How often pending subscribers are looked for
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The subscribers a retention run reminded and purged, or would have in a
+/// dry run
+#[derive(Debug, Default)]
+pub struct RetentionReport {
+    pub reminded: Vec<Uuid>,
+    pub purged: Vec<Uuid>,
+}
+
+pub async fn run_retention_until_stopped(
+    pool: PgPool,
+    email_client: EmailClient,
+    settings: RetentionSettings,
+    base_url: String,
+) -> Result<(), std::io::Error> {
+    loop {
+        // Failures are logged, the next run tries again
+        let _ = purge_pending_subscribers(&pool, &email_client, &settings, &base_url).await;
+        tokio::time::sleep(RETENTION_INTERVAL).await;
+    }
+}
+
+/// Remind subscribers still pending confirmation to confirm, once, then
+/// delete them and their tokens when they still have not after the
+/// retention period.
+///
+/// When reminders are sent, subscribers are only deleted once the time
+/// between the reminder and the end of the retention period went by since
+/// they were reminded, or as long again past that period if they could not
+/// be reminded.
+#[tracing::instrument(skip_all, fields(dry_run = settings.dry_run))]
+pub async fn purge_pending_subscribers(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    settings: &RetentionSettings,
+    base_url: &str,
+) -> Result<RetentionReport, sqlx::Error> {
+    let now = Utc::now();
+    let purge_before = now - chrono::Duration::days(settings.pending_subscriber_days.into());
+    let grace_period = settings.reminder_grace_period();
+    // Those who could not be reminded by then are purged all the same
+    let forget_before = purge_before - grace_period.unwrap_or_else(chrono::Duration::zero);
+    let reminded = match settings.reminder_after_days {
+        Some(days) => {
+            let remind_before = now - chrono::Duration::days(days.into());
+            remind_pending_subscribers(
+                pool,
+                email_client,
+                remind_before,
+                forget_before,
+                settings.dry_run,
+                base_url,
+            )
+            .await?
+        }
+        None => Vec::new(),
+    };
+
+    let mut transaction = pool.begin().await?;
+    let purged: Vec<Uuid> = sqlx::query!(
+        r#"
+        SELECT id FROM subscriptions
+        WHERE status = 'pending_confirmation' AND subscribed_at < $1
+            AND (
+                $2::timestamptz IS NULL
+                OR confirmation_reminded_at < $2
+                OR subscribed_at < $3
+            )
+        FOR UPDATE
+        "#,
+        purge_before,
+        grace_period.map(|period| now - period),
+        forget_before,
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .into_iter()
+    .map(|subscriber| subscriber.id)
+    .collect();
+    for subscriber_id in &purged {
+        tracing::info!(
+            %subscriber_id,
+            dry_run = settings.dry_run,
+            "Purging a subscriber who never confirmed"
+        );
+    }
+    if !settings.dry_run && !purged.is_empty() {
+        // Tokens and proof of consent are the only rows not deleted along
+        // with their subscription, the latter proving a consent never given
+        sqlx::query!(
+            "DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)",
+            &purged,
+        )
+        .execute(&mut transaction)
+        .await?;
+        erase_consent_events(&mut transaction, &purged).await?;
+        sqlx::query!("DELETE FROM subscriptions WHERE id = ANY($1)", &purged)
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+    }
+
+    tracing::info!(
+        reminded = reminded.len(),
+        purged = purged.len(),
+        "Finished purging subscribers who never confirmed"
+    );
+    Ok(RetentionReport { reminded, purged })
+}
+
+/// Remind the subscribers who signed up between `forget_before` and
+/// `remind_before` and were not reminded yet, returning their ids
+async fn remind_pending_subscribers(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    remind_before: DateTime<Utc>,
+    forget_before: DateTime<Utc>,
+    dry_run: bool,
+    base_url: &str,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let pending = sqlx::query!(
+        r#"
+        SELECT s.id, s.email, t.subscription_token
+        FROM subscriptions s
+        JOIN LATERAL (
+            SELECT subscription_token FROM subscription_tokens
+            WHERE subscriber_id = s.id
+            LIMIT 1
+        ) t ON true
+        WHERE s.status = 'pending_confirmation'
+            AND s.confirmation_reminded_at IS NULL
+            AND s.subscribed_at < $1
+            AND s.subscribed_at >= $2
+        "#,
+        remind_before,
+        forget_before,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+
+    let mut reminded = Vec::with_capacity(pending.len());
+    for subscriber in pending {
+        tracing::info!(
+            subscriber_id = %subscriber.id,
+            dry_run,
+            "Reminding a subscriber to confirm"
+        );
+        if dry_run {
+            reminded.push(subscriber.id);
+            continue;
+        }
+        let email = match SubscriberEmail::parse(subscriber.email) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::warn!(
+                    subscriber_id = %subscriber.id,
+                    error.message = %e,
+                    "Skipping a pending subscriber whose stored email address is invalid"
+                );
+                continue;
+            }
+        };
+        let confirmation_link = format!(
+            "{base_url}/subscriptions/confirm?subscription_token={}",
+            subscriber.subscription_token
+        );
+        let plain_body = format!(
+            "You have not confirmed your subscription to our newsletter yet.\n\
+            Visit {confirmation_link} to confirm it, or ignore this email to be forgotten."
+        );
+        let html_body = format!(
+            "You have not confirmed your subscription to our newsletter yet.<br />\
+            Click <a href=\"{confirmation_link}\">here</a> to confirm it, or ignore this email to be forgotten."
+        );
+        match email_client
+            .send_email(
+                email,
+                "Please confirm your subscription",
+                &html_body,
+                &plain_body,
+            )
+            .await
+        {
+            Ok(_) => reminded.push(subscriber.id),
+            Err(e) => {
+                // Not marked as reminded, so tried again by the next run
+                tracing::error!(
+                    subscriber_id = %subscriber.id,
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to remind a subscriber to confirm"
+                );
+            }
+        }
+    }
+
+    if !dry_run && !reminded.is_empty() {
+        let _ = record_sent_emails(pool, reminded.len().try_into().unwrap_or(i32::MAX)).await;
+        sqlx::query!(
+            "UPDATE subscriptions SET confirmation_reminded_at = now() WHERE id = ANY($1)",
+            &reminded,
+        )
+        .execute(pool)
+        .await?;
+    }
+    Ok(reminded)
+}
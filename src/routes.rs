@@ -1,3 +1,11 @@
+pub mod admin;
+pub mod archive;
+pub mod archive_search;
+mod caching;
+pub mod data_requests;
+pub mod dev_mailbox;
+pub mod feeds;
 pub mod health;
 pub mod subscriptions;
 pub mod subscriptions_confirm;
+pub mod webhooks;
@@ -0,0 +1,16 @@
+//! Endpoints for the admins of the newsletter, all of them requiring an
+//! [`Authorized`](crate::authorization::Authorized) admin.
+
+pub mod analytics;
+pub mod api_keys;
+pub mod audit_log;
+pub mod data_subjects;
+pub mod dead_letters;
+pub mod delivery_reports;
+pub mod issues;
+pub mod login;
+pub mod oidc;
+pub mod subscriber_export;
+pub mod subscribers;
+pub mod totp;
+pub mod users;
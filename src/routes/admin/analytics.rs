@@ -0,0 +1,88 @@
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse,
+};
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    analytics::{subscriber_growth, Granularity, GrowthPoint},
+    authorization::{Authorized, ViewAnalytics},
+    utils::e500,
+};
+
+/// The range covered when `from` is not set, in days up to `to`
+const DEFAULT_RANGE_DAYS: i64 = 30;
+/// Ten years of daily points at most
+const MAX_RANGE_DAYS: i64 = 3660;
+
+#[derive(Deserialize, Debug)]
+pub struct AnalyticsQuery {
+    /// Both included, `to` defaults to today
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    #[serde(default)]
+    granularity: Granularity,
+    /// The name of a list, all subscribers are counted when unset
+    list: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Analytics {
+    list: Option<String>,
+    from: NaiveDate,
+    to: NaiveDate,
+    granularity: Granularity,
+    series: Vec<GrowthPoint>,
+}
+
+/// Subscriber growth and churn over time, as a JSON time series
+#[tracing::instrument(name = "Show subscriber analytics", skip(pool), fields(user_id = %user.user_id))]
+pub async fn analytics(
+    user: Authorized<ViewAnalytics>,
+    query: Query<AnalyticsQuery>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let query = query.into_inner();
+    let to = query.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = match query.from {
+        Some(from) => from,
+        None => match to.checked_sub_signed(Duration::days(DEFAULT_RANGE_DAYS - 1)) {
+            Some(from) => from,
+            None => return Ok(HttpResponse::BadRequest().body("`to` is out of range")),
+        },
+    };
+    if from > to {
+        return Ok(HttpResponse::BadRequest().body("`from` must not be after `to`"));
+    }
+    if (to - from).num_days() >= MAX_RANGE_DAYS {
+        return Ok(HttpResponse::BadRequest()
+            .body(format!("The range cannot exceed {MAX_RANGE_DAYS} days")));
+    }
+
+    let list_id = match &query.list {
+        Some(name) => {
+            let list = sqlx::query!("SELECT id FROM lists WHERE name = $1", name)
+                .fetch_optional(pool.get_ref())
+                .await
+                .map_err(e500)?;
+            let Some(list) = list else {
+                return Ok(HttpResponse::NotFound().body(format!("There is no list named {name}")));
+            };
+            Some(list.id)
+        }
+        None => None,
+    };
+
+    let series = subscriber_growth(&pool, list_id, from, to, query.granularity)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(Analytics {
+        list: query.list,
+        from,
+        to,
+        granularity: query.granularity,
+        series,
+    }))
+}
@@ -0,0 +1,192 @@
+use actix_web::{
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    api_keys::{api_key_hash, displayed_prefix, generate_api_key},
+    audit::{record_audit, AuditActor, AuditEntry},
+    authorization::{Authorized, ManageUsers, Permission},
+    utils::e500,
+};
+
+#[derive(Serialize)]
+struct ApiKeySummary {
+    id: Uuid,
+    name: String,
+    /// The start of the key, which is not kept
+    key_prefix: String,
+    scopes: Vec<String>,
+    /// The admin the key acts on behalf of
+    created_by: Uuid,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    last_used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+/// The API keys, without the keys themselves
+#[tracing::instrument(name = "List API keys", skip(pool), fields(user_id = %user.user_id))]
+pub async fn list_api_keys(
+    user: Authorized<ManageUsers>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let api_keys = sqlx::query_as!(
+        ApiKeySummary,
+        r#"
+        SELECT id, name, key_prefix, scopes, created_by, created_at, expires_at, last_used_at,
+            revoked_at
+        FROM api_keys
+        ORDER BY created_at DESC
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(api_keys))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewApiKeyRequest {
+    /// What the key is for, e.g. `crm`
+    name: String,
+    /// e.g. `subscribers:read`
+    scopes: Vec<String>,
+    /// Never expires when unset
+    expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct CreatedApiKey {
+    #[serde(flatten)]
+    summary: ApiKeySummary,
+    /// Shown this once, only its hash is kept
+    key: String,
+}
+
+/// Create an API key acting on behalf of the admin creating it, restricted to
+/// its scopes
+#[tracing::instrument(
+    name = "Create an API key",
+    skip(http_request, pool),
+    fields(user_id = %user.user_id)
+)]
+pub async fn create_api_key(
+    user: Authorized<ManageUsers>,
+    http_request: HttpRequest,
+    request: Json<NewApiKeyRequest>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let NewApiKeyRequest {
+        name,
+        scopes,
+        expires_at,
+    } = request.into_inner();
+    if name.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().body("The name is empty"));
+    }
+    if scopes.is_empty() {
+        return Ok(HttpResponse::BadRequest().body("An API key needs at least one scope"));
+    }
+    if let Err(e) = scopes
+        .iter()
+        .map(|scope| Permission::from_scope(scope))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        return Ok(HttpResponse::BadRequest().body(e));
+    }
+    if expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Ok(HttpResponse::BadRequest().body("The expiry is in the past"));
+    }
+
+    let key = generate_api_key();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let summary = sqlx::query_as!(
+        ApiKeySummary,
+        r#"
+        INSERT INTO api_keys (id, name, key_prefix, key_hash, scopes, created_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, name, key_prefix, scopes, created_by, created_at, expires_at,
+            last_used_at, revoked_at
+        "#,
+        Uuid::new_v4(),
+        name,
+        displayed_prefix(&key),
+        api_key_hash(&key),
+        &scopes,
+        user.user_id,
+        expires_at,
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .map_err(e500)?;
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "api_key.create",
+            target_type: "api_key",
+            target_id: Some(summary.id.to_string()),
+            before: None,
+            after: Some(json!({
+                "name": summary.name,
+                "scopes": summary.scopes,
+                "expires_at": summary.expires_at,
+            })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    Ok(HttpResponse::Created().json(CreatedApiKey {
+        summary,
+        key: key.expose_secret().clone(),
+    }))
+}
+
+/// Revoke an API key, which is kept for the audit log
+#[tracing::instrument(name = "Revoke an API key", skip(http_request, pool), fields(user_id = %user.user_id))]
+pub async fn revoke_api_key(
+    user: Authorized<ManageUsers>,
+    http_request: HttpRequest,
+    id: Path<Uuid>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(revoked) = sqlx::query!(
+        r#"
+        UPDATE api_keys SET revoked_at = now()
+        WHERE id = $1 AND revoked_at IS NULL
+        RETURNING revoked_at
+        "#,
+        *id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "api_key.revoke",
+            target_type: "api_key",
+            target_id: Some(id.to_string()),
+            before: Some(json!({ "revoked_at": null })),
+            after: Some(json!({ "revoked_at": revoked.revoked_at })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::NoContent().finish())
+}
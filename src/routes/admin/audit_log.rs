@@ -0,0 +1,120 @@
+use actix_web::{
+    web::{Data, Query},
+    HttpResponse,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authorization::{Authorized, ManageUsers},
+    routes::admin::subscribers::{end_of_day, start_of_day},
+    utils::e500,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+#[derive(Deserialize, Debug)]
+pub struct AuditLogFilter {
+    actor_id: Option<Uuid>,
+    /// e.g. `subscriber.delete`
+    action: Option<String>,
+    target_type: Option<String>,
+    target_id: Option<String>,
+    /// On or after this UTC day
+    from: Option<NaiveDate>,
+    /// On or before this UTC day
+    to: Option<NaiveDate>,
+    /// The `next_cursor` of the previous page
+    cursor: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct AuditLogEntry {
+    id: i64,
+    actor_id: Uuid,
+    /// Unset once the admin is deleted
+    actor_username: Option<String>,
+    /// The API key the change was made with, if any
+    api_key_id: Option<Uuid>,
+    action: String,
+    target_type: String,
+    target_id: Option<String>,
+    changes: serde_json::Value,
+    request_id: Option<String>,
+    ip_address: Option<String>,
+    occurred_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct AuditLogPage {
+    entries: Vec<AuditLogEntry>,
+    /// Where the next page starts, unset on the last page
+    next_cursor: Option<i64>,
+}
+
+/// The changes made by admins, the most recent first, a page at a time
+#[tracing::instrument(name = "Browse the audit log", skip(pool), fields(user_id = %user.user_id))]
+pub async fn audit_log(
+    user: Authorized<ManageUsers>,
+    filter: Query<AuditLogFilter>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let limit = filter
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let before = match filter.to {
+        Some(day) => match end_of_day(day) {
+            Some(end) => Some(end),
+            None => return Ok(HttpResponse::BadRequest().body("`to` is out of range")),
+        },
+        None => None,
+    };
+
+    let mut entries = sqlx::query_as!(
+        AuditLogEntry,
+        r#"
+        SELECT a.id, a.actor_id, u.username AS "actor_username?", a.api_key_id, a.action,
+            a.target_type,
+            a.target_id, a.changes, a.request_id, host(a.ip_address) AS ip_address, a.occurred_at
+        FROM audit_log a
+        LEFT JOIN users u ON u.user_id = a.actor_id
+        WHERE ($1::uuid IS NULL OR a.actor_id = $1)
+            AND ($2::text IS NULL OR a.action = $2)
+            AND ($3::text IS NULL OR a.target_type = $3)
+            AND ($4::text IS NULL OR a.target_id = $4)
+            AND ($5::timestamptz IS NULL OR a.occurred_at >= $5)
+            AND ($6::timestamptz IS NULL OR a.occurred_at < $6)
+            AND ($7::bigint IS NULL OR a.id < $7)
+        ORDER BY a.id DESC
+        LIMIT $8
+        "#,
+        filter.actor_id,
+        filter.action,
+        filter.target_type,
+        filter.target_id,
+        filter.from.map(start_of_day),
+        before,
+        filter.cursor,
+        // One more to know whether there is a next page
+        limit + 1,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    let next_cursor = if entries.len() > limit as usize {
+        entries.truncate(limit as usize);
+        entries.last().map(|last| last.id)
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(AuditLogPage {
+        entries,
+        next_cursor,
+    }))
+}
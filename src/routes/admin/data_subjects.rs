@@ -0,0 +1,69 @@
+use actix_web::{
+    web::{Data, Json, Query},
+    HttpRequest, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{record_audit, AuditActor, AuditEntry},
+    authorization::{Authorized, ManageSubscribers, ViewSubscribers},
+    data_protection::{email_hash, erase_subject, subject_access_report},
+    utils::e500,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct DataSubject {
+    email: String,
+}
+
+/// Everything held about an email address, to answer an access request
+#[tracing::instrument(name = "Export the data of a subject", skip(pool, subject), fields(user_id = %user.user_id))]
+pub async fn data_subject(
+    user: Authorized<ViewSubscribers>,
+    subject: Query<DataSubject>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let report = subject_access_report(&pool, &subject.email)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+#[derive(Serialize)]
+struct ErasureResponse {
+    /// Whether anything was held about the address
+    erased: bool,
+}
+
+/// Erase everything held about an email address, to answer an erasure request.
+///
+/// The audit log refers to the address by its hash, as tombstones do.
+#[tracing::instrument(name = "Erase the data of a subject", skip(http_request, pool, subject), fields(user_id = %user.user_id))]
+pub async fn erase_data_subject(
+    user: Authorized<ManageSubscribers>,
+    http_request: HttpRequest,
+    subject: Json<DataSubject>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let erased = erase_subject(&mut transaction, &subject.email)
+        .await
+        .map_err(e500)?;
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "data_subject.erase",
+            target_type: "data_subject",
+            target_id: Some(email_hash(&subject.email)),
+            before: None,
+            after: Some(json!({ "erased": erased })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(ErasureResponse { erased }))
+}
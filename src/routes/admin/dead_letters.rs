@@ -0,0 +1,113 @@
+use actix_web::{
+    web::{Data, Json, Query},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit, AuditActor, AuditEntry},
+    authorization::{Authorized, PublishIssues, ViewSubscribers},
+    issue_delivery_worker::replay_dead_letters,
+    utils::e500,
+};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Deserialize, Debug)]
+pub struct DeadLetterFilter {
+    newsletter_issue_id: Option<Uuid>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct DeadLetter {
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    email: String,
+    /// The kind of the last error, e.g. `rejected` or `server_error`
+    error_kind: String,
+    error: String,
+    n_attempts: i32,
+    queued_at: DateTime<Utc>,
+    failed_at: DateTime<Utc>,
+}
+
+/// The deliveries that failed for good, the most recent first
+#[tracing::instrument(name = "List dead letters", skip(pool), fields(user_id = %user.user_id))]
+pub async fn list_dead_letters(
+    user: Authorized<ViewSubscribers>,
+    filter: Query<DeadLetterFilter>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let dead_letters = sqlx::query_as!(
+        DeadLetter,
+        r#"
+        SELECT d.newsletter_issue_id, d.subscriber_id, s.email, d.error_kind, d.error,
+            d.n_attempts, d.queued_at, d.failed_at
+        FROM dead_letters d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        WHERE $1::uuid IS NULL OR d.newsletter_issue_id = $1
+        ORDER BY d.failed_at DESC
+        LIMIT $2
+        "#,
+        filter.newsletter_issue_id,
+        filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(dead_letters))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReplayRequest {
+    newsletter_issue_id: Uuid,
+    /// The dead letters to replay, all of those of the issue when missing
+    subscriber_ids: Option<Vec<Uuid>>,
+}
+
+#[derive(Serialize)]
+struct ReplayResponse {
+    replayed: u64,
+}
+
+/// Queue dead letters of an issue for delivery again
+#[tracing::instrument(name = "Replay dead letters", skip(http_request, pool), fields(user_id = %user.user_id))]
+pub async fn replay(
+    user: Authorized<PublishIssues>,
+    http_request: HttpRequest,
+    request: Json<ReplayRequest>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let replayed = replay_dead_letters(
+        &mut transaction,
+        request.newsletter_issue_id,
+        request.subscriber_ids.as_deref(),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "dead_letter.replay",
+            target_type: "newsletter_issue",
+            target_id: Some(request.newsletter_issue_id.to_string()),
+            before: None,
+            after: Some(json!({
+                "subscriber_ids": request.subscriber_ids,
+                "replayed": replayed,
+            })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(ReplayResponse { replayed }))
+}
@@ -0,0 +1,256 @@
+use actix_web::{
+    http::header::{self, ContentType},
+    web::{Data, Path},
+    HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authorization::{Authorized, ViewAnalytics},
+    routes::archive::{escape, page},
+    utils::e500,
+};
+
+/// How an issue went out, from its deliveries and the events SendGrid
+/// reported about them
+#[derive(Serialize)]
+pub struct DeliveryReport {
+    newsletter_issue_id: Uuid,
+    title: String,
+    counts: Counts,
+    timings: Timings,
+}
+
+/// Deliveries per status, and recipients per event
+#[derive(Serialize)]
+pub struct Counts {
+    queued: i64,
+    sent: i64,
+    failed: i64,
+    bounced: i64,
+    opened: i64,
+    clicked: i64,
+    unsubscribed: i64,
+}
+
+#[derive(Serialize)]
+pub struct Timings {
+    first_queued_at: Option<DateTime<Utc>>,
+    first_sent_at: Option<DateTime<Utc>>,
+    last_sent_at: Option<DateTime<Utc>>,
+    /// From being queued to being sent, on average
+    average_delivery_seconds: Option<f64>,
+    first_opened_at: Option<DateTime<Utc>>,
+}
+
+/// How one recipient got the issue, a row of the CSV export
+#[derive(Serialize)]
+struct RecipientReport {
+    email: String,
+    status: String,
+    n_attempts: i32,
+    last_error: Option<String>,
+    queued_at: DateTime<Utc>,
+    sent_at: Option<DateTime<Utc>>,
+    bounced_at: Option<DateTime<Utc>>,
+    opened_at: Option<DateTime<Utc>>,
+    clicked_at: Option<DateTime<Utc>>,
+    unsubscribed_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Report on an issue delivery", skip(pool), fields(user_id = %user.user_id))]
+pub async fn delivery_report(
+    user: Authorized<ViewAnalytics>,
+    issue_id: Path<Uuid>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    Ok(
+        match get_delivery_report(*issue_id, &pool).await.map_err(e500)? {
+            Some(report) => HttpResponse::Ok().json(report),
+            None => HttpResponse::NotFound().finish(),
+        },
+    )
+}
+
+#[tracing::instrument(name = "Show the report on an issue delivery", skip(pool), fields(user_id = %user.user_id))]
+pub async fn delivery_report_page(
+    user: Authorized<ViewAnalytics>,
+    issue_id: Path<Uuid>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(report) = get_delivery_report(*issue_id, &pool).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let counts = &report.counts;
+    let timings = &report.timings;
+    let time = |at: Option<DateTime<Utc>>| {
+        at.map_or_else(
+            || "-".to_string(),
+            |at| {
+                format!(
+                    "<time datetime=\"{}\">{}</time>",
+                    at.to_rfc3339(),
+                    at.format("%Y-%m-%d %H:%M:%S")
+                )
+            },
+        )
+    };
+    let title = escape(&report.title);
+    let body = format!(
+        r#"<h1>{title}</h1>
+<table>
+<tbody>
+<tr><th>Queued</th><td>{}</td></tr>
+<tr><th>Sent</th><td>{}</td></tr>
+<tr><th>Failed</th><td>{}</td></tr>
+<tr><th>Bounced</th><td>{}</td></tr>
+<tr><th>Opened</th><td>{}</td></tr>
+<tr><th>Clicked</th><td>{}</td></tr>
+<tr><th>Unsubscribed</th><td>{}</td></tr>
+<tr><th>First queued</th><td>{}</td></tr>
+<tr><th>First sent</th><td>{}</td></tr>
+<tr><th>Last sent</th><td>{}</td></tr>
+<tr><th>Average delivery time</th><td>{}</td></tr>
+<tr><th>First opened</th><td>{}</td></tr>
+</tbody>
+</table>
+<p><a href="/admin/api/issues/{}/report.csv">Export recipients as CSV</a></p>"#,
+        counts.queued,
+        counts.sent,
+        counts.failed,
+        counts.bounced,
+        counts.opened,
+        counts.clicked,
+        counts.unsubscribed,
+        time(timings.first_queued_at),
+        time(timings.first_sent_at),
+        time(timings.last_sent_at),
+        timings
+            .average_delivery_seconds
+            .map_or_else(|| "-".to_string(), |seconds| format!("{seconds:.1}s")),
+        time(timings.first_opened_at),
+        report.newsletter_issue_id,
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page(&format!("Delivery of {title}"), &body)))
+}
+
+/// One row per recipient of the issue
+#[tracing::instrument(name = "Export the report on an issue delivery", skip(pool), fields(user_id = %user.user_id))]
+pub async fn delivery_report_csv(
+    user: Authorized<ViewAnalytics>,
+    issue_id: Path<Uuid>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let recipients = sqlx::query_as!(
+        RecipientReport,
+        r#"
+        SELECT s.email, d.status, d.n_attempts, d.last_error, d.queued_at, d.sent_at,
+            e.bounced_at, e.opened_at, e.clicked_at, e.unsubscribed_at
+        FROM issue_deliveries d
+        JOIN subscriptions s ON s.id = d.subscriber_id
+        LEFT JOIN (
+            SELECT subscriber_id,
+                MIN(occurred_at) FILTER (WHERE event = 'bounce') AS bounced_at,
+                MIN(occurred_at) FILTER (WHERE event = 'open') AS opened_at,
+                MIN(occurred_at) FILTER (WHERE event = 'click') AS clicked_at,
+                MIN(occurred_at) FILTER (
+                    WHERE event IN ('unsubscribe', 'group_unsubscribe')
+                ) AS unsubscribed_at
+            FROM email_events
+            WHERE newsletter_issue_id = $1
+            GROUP BY subscriber_id
+        ) e ON e.subscriber_id = d.subscriber_id
+        WHERE d.newsletter_issue_id = $1
+        ORDER BY s.email
+        "#,
+        *issue_id,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for recipient in recipients {
+        writer.serialize(recipient).map_err(e500)?;
+    }
+    let csv = writer.into_inner().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"issue-{issue_id}-report.csv\""),
+        ))
+        .body(csv))
+}
+
+#[tracing::instrument(name = "Get the report on an issue delivery", skip(pool))]
+async fn get_delivery_report(
+    issue_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<DeliveryReport>, sqlx::Error> {
+    let report = sqlx::query!(
+        r#"
+        SELECT i.title,
+            d.queued AS "queued!", d.sent AS "sent!", d.failed AS "failed!",
+            d.first_queued_at, d.first_sent_at, d.last_sent_at, d.average_delivery_seconds,
+            e.bounced AS "bounced!", e.opened AS "opened!", e.clicked AS "clicked!",
+            e.unsubscribed AS "unsubscribed!", e.first_opened_at
+        FROM newsletter_issues i,
+        LATERAL (
+            SELECT COUNT(*) FILTER (WHERE status = 'queued') AS queued,
+                COUNT(*) FILTER (WHERE status = 'sent') AS sent,
+                COUNT(*) FILTER (WHERE status = 'failed') AS failed,
+                MIN(queued_at) AS first_queued_at,
+                MIN(sent_at) AS first_sent_at,
+                MAX(sent_at) AS last_sent_at,
+                AVG(EXTRACT(EPOCH FROM sent_at - queued_at))::float8 AS average_delivery_seconds
+            FROM issue_deliveries
+            WHERE newsletter_issue_id = i.id
+        ) d,
+        LATERAL (
+            SELECT COUNT(DISTINCT email) FILTER (WHERE event = 'bounce') AS bounced,
+                COUNT(DISTINCT email) FILTER (WHERE event = 'open') AS opened,
+                COUNT(DISTINCT email) FILTER (WHERE event = 'click') AS clicked,
+                COUNT(DISTINCT email) FILTER (
+                    WHERE event IN ('unsubscribe', 'group_unsubscribe')
+                ) AS unsubscribed,
+                MIN(occurred_at) FILTER (WHERE event = 'open') AS first_opened_at
+            FROM email_events
+            WHERE newsletter_issue_id = i.id
+        ) e
+        WHERE i.id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(report.map(|report| DeliveryReport {
+        newsletter_issue_id: issue_id,
+        title: report.title,
+        counts: Counts {
+            queued: report.queued,
+            sent: report.sent,
+            failed: report.failed,
+            bounced: report.bounced,
+            opened: report.opened,
+            clicked: report.clicked,
+            unsubscribed: report.unsubscribed,
+        },
+        timings: Timings {
+            first_queued_at: report.first_queued_at,
+            first_sent_at: report.first_sent_at,
+            last_sent_at: report.last_sent_at,
+            average_delivery_seconds: report.average_delivery_seconds,
+            first_opened_at: report.first_opened_at,
+        },
+    }))
+}
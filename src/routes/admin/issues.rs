@@ -0,0 +1,328 @@
+//! Issues are drafted by editors, and sent to every confirmed subscriber
+//! once a publisher publishes them.
+
+use actix_web::{
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit, AuditActor, AuditEntry},
+    authorization::{Authorized, DraftIssues, PublishIssues},
+    issue_delivery_worker::enqueue_delivery_tasks,
+    utils::{e500, violates_unique_constraint},
+};
+
+/// The longest slug an issue can have
+const MAX_SLUG_LENGTH: usize = 100;
+
+#[derive(Serialize)]
+pub struct Issue {
+    id: Uuid,
+    slug: String,
+    title: String,
+    /// Markdown
+    content: String,
+    subscribers_only: bool,
+    /// Unset while the issue is a draft
+    published_at: Option<DateTime<Utc>>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewIssueRequest {
+    /// Where the issue is found in the archive, e.g. `2026-10-spring`
+    slug: String,
+    title: String,
+    content: String,
+    #[serde(default)]
+    subscribers_only: bool,
+}
+
+/// The fields of a draft to change, those missing being left as they are
+#[derive(Deserialize, Debug)]
+pub struct IssueChanges {
+    slug: Option<String>,
+    title: Option<String>,
+    content: Option<String>,
+    subscribers_only: Option<bool>,
+}
+
+/// Every issue, drafts included, the most recently changed first
+#[tracing::instrument(name = "List issues", skip(pool), fields(user_id = %user.user_id))]
+pub async fn list_issues(
+    user: Authorized<DraftIssues>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issues = sqlx::query_as!(
+        Issue,
+        r#"
+        SELECT id, slug, title, content, subscribers_only, published_at, updated_at
+        FROM newsletter_issues
+        ORDER BY updated_at DESC, id
+        "#,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(issues))
+}
+
+/// Start a draft, which nobody gets until it is published
+#[tracing::instrument(name = "Draft an issue", skip(http_request, request, pool), fields(user_id = %user.user_id))]
+pub async fn draft_issue(
+    user: Authorized<DraftIssues>,
+    http_request: HttpRequest,
+    request: Json<NewIssueRequest>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let request = request.into_inner();
+    if let Err(e) = validate_slug(&request.slug).and(validate_title(&request.title)) {
+        return Ok(HttpResponse::BadRequest().body(e));
+    }
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let id = Uuid::new_v4();
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (id, slug, title, content, subscribers_only, updated_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        id,
+        request.slug,
+        request.title,
+        request.content,
+        request.subscribers_only,
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    if inserted.rows_affected() == 0 {
+        return Ok(HttpResponse::Conflict().body("Another issue has this slug"));
+    }
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "issue.create",
+            target_type: "newsletter_issue",
+            target_id: Some(id.to_string()),
+            before: None,
+            after: Some(json!({
+                "slug": request.slug,
+                "title": request.title,
+                "subscribers_only": request.subscribers_only,
+            })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    let issue = get_issue(&pool, id).await.map_err(e500)?;
+    Ok(HttpResponse::Created().json(issue))
+}
+
+/// Change a draft, published issues having been sent as they are
+#[tracing::instrument(name = "Change a draft", skip(http_request, changes, pool), fields(user_id = %user.user_id))]
+pub async fn change_issue(
+    user: Authorized<DraftIssues>,
+    http_request: HttpRequest,
+    id: Path<Uuid>,
+    changes: Json<IssueChanges>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let changes = changes.into_inner();
+    let valid = changes
+        .slug
+        .as_deref()
+        .map_or(Ok(()), validate_slug)
+        .and(changes.title.as_deref().map_or(Ok(()), validate_title));
+    if let Err(e) = valid {
+        return Ok(HttpResponse::BadRequest().body(e));
+    }
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(before) = lock_issue(&mut transaction, *id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if before.published_at.is_some() {
+        return Ok(HttpResponse::Conflict().body("Published issues cannot be changed"));
+    }
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET slug = COALESCE($2, slug),
+            title = COALESCE($3, title),
+            content = COALESCE($4, content),
+            subscribers_only = COALESCE($5, subscribers_only),
+            updated_at = now()
+        WHERE id = $1
+        "#,
+        *id,
+        changes.slug,
+        changes.title,
+        changes.content,
+        changes.subscribers_only,
+    )
+    .execute(&mut transaction)
+    .await;
+    match updated {
+        Err(e) if violates_unique_constraint(&e, "newsletter_issues_slug_key") => {
+            return Ok(HttpResponse::Conflict().body("Another issue has this slug"));
+        }
+        updated => updated.map_err(e500)?,
+    };
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "issue.update",
+            target_type: "newsletter_issue",
+            target_id: Some(id.to_string()),
+            before: Some(json!({
+                "slug": before.slug,
+                "title": before.title,
+                "subscribers_only": before.subscribers_only,
+            })),
+            after: Some(json!({
+                "slug": changes.slug.unwrap_or(before.slug),
+                "title": changes.title.unwrap_or(before.title),
+                "subscribers_only": changes.subscribers_only.unwrap_or(before.subscribers_only),
+            })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    let issue = get_issue(&pool, *id).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+/// Publish a draft to the archive, and queue its delivery to every confirmed
+/// subscriber in the same transaction
+#[tracing::instrument(name = "Publish an issue", skip(http_request, pool), fields(user_id = %user.user_id))]
+pub async fn publish_issue(
+    user: Authorized<PublishIssues>,
+    http_request: HttpRequest,
+    id: Path<Uuid>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(before) = lock_issue(&mut transaction, *id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if before.published_at.is_some() {
+        return Ok(HttpResponse::Conflict().body("The issue is already published"));
+    }
+    sqlx::query!(
+        "UPDATE newsletter_issues SET published_at = now(), updated_at = now() WHERE id = $1",
+        *id,
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    enqueue_delivery_tasks(&mut transaction, *id)
+        .await
+        .map_err(e500)?;
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "issue.publish",
+            target_type: "newsletter_issue",
+            target_id: Some(id.to_string()),
+            before: Some(json!({ "published": false })),
+            after: Some(json!({ "published": true })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    let issue = get_issue(&pool, *id).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+async fn get_issue(pool: &PgPool, id: Uuid) -> Result<Issue, sqlx::Error> {
+    sqlx::query_as!(
+        Issue,
+        r#"
+        SELECT id, slug, title, content, subscribers_only, published_at, updated_at
+        FROM newsletter_issues
+        WHERE id = $1
+        "#,
+        id,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Lock an issue until the transaction ends, so that it is not published
+/// while being changed
+async fn lock_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Option<Issue>, sqlx::Error> {
+    sqlx::query_as!(
+        Issue,
+        r#"
+        SELECT id, slug, title, content, subscribers_only, published_at, updated_at
+        FROM newsletter_issues
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+}
+
+/// Slugs end up in archive URLs, so only lowercase letters, digits and
+/// dashes are allowed
+fn validate_slug(slug: &str) -> Result<(), String> {
+    if slug.is_empty() || slug.len() > MAX_SLUG_LENGTH {
+        return Err(format!(
+            "The slug must have between 1 and {MAX_SLUG_LENGTH} characters"
+        ));
+    }
+    if !slug
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(format!(
+            "{slug} is not a slug, use lowercase letters, digits and dashes"
+        ));
+    }
+    Ok(())
+}
+
+fn validate_title(title: &str) -> Result<(), String> {
+    if title.trim().is_empty() {
+        return Err("The title cannot be empty".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+
+    use super::{validate_slug, MAX_SLUG_LENGTH};
+
+    #[test]
+    fn slugs_are_lowercase_letters_digits_and_dashes() {
+        assert_ok!(validate_slug("2026-10-spring"));
+        assert_err!(validate_slug(""));
+        assert_err!(validate_slug("Spring"));
+        assert_err!(validate_slug("spring/../admin"));
+        assert_err!(validate_slug(&"a".repeat(MAX_SLUG_LENGTH + 1)));
+    }
+}
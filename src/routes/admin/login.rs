@@ -0,0 +1,137 @@
+use actix_web::{
+    http::header,
+    web::{Data, Json},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{basic_authentication, validate_credentials, AuthError},
+    session::{
+        authenticate_session, bearer_token, end_session, pending_session_user, start_session,
+    },
+    totp::{check_second_factor, SecondFactor, SecondFactorOutcome},
+    utils::e500,
+};
+
+#[derive(Serialize)]
+pub struct LoginResponse {
+    /// Sent back as `Authorization: Bearer <session_token>`
+    pub session_token: String,
+    pub expires_at: DateTime<Utc>,
+    /// Whether the session is pending until the second factor is verified at
+    /// `/admin/login/totp`
+    pub second_factor_required: bool,
+}
+
+/// Start a session with HTTP Basic credentials, only authenticated once the
+/// second factor is verified for admins with TOTP enabled
+#[tracing::instrument(name = "Log in", skip(http_request, pool), fields(user_id = tracing::field::Empty))]
+pub async fn login(
+    http_request: HttpRequest,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let credentials = basic_authentication(http_request.headers())?;
+    let user = validate_credentials(credentials, &pool).await?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user.user_id));
+    let (session_token, expires_at) = start_session(&pool, user.user_id, !user.totp_enabled)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        session_token: session_token.expose_secret().clone(),
+        expires_at,
+        second_factor_required: user.totp_enabled,
+    }))
+}
+
+/// Either a code of the authenticator, or one of the recovery codes
+#[derive(Deserialize)]
+pub struct SecondFactorRequest {
+    code: Option<String>,
+    recovery_code: Option<Secret<String>>,
+}
+
+impl SecondFactorRequest {
+    /// The second factor sent, unless there is none or both
+    pub fn second_factor(self) -> Option<SecondFactor> {
+        match self {
+            Self {
+                code: Some(code),
+                recovery_code: None,
+            } => Some(SecondFactor::Code(code)),
+            Self {
+                code: None,
+                recovery_code: Some(recovery_code),
+            } => Some(SecondFactor::RecoveryCode(
+                recovery_code.expose_secret().clone(),
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Authenticate a pending session with the second factor of its admin
+#[tracing::instrument(name = "Verify a second factor", skip(http_request, request, pool))]
+pub async fn login_totp(
+    http_request: HttpRequest,
+    request: Json<SecondFactorRequest>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invalid_session = || AuthError::InvalidCredentials("Unknown or expired session".into());
+    let session_token = bearer_token(http_request.headers()).ok_or_else(invalid_session)?;
+    let user_id = pending_session_user(&pool, &session_token)
+        .await
+        .map_err(e500)?
+        .ok_or_else(invalid_session)?;
+    let Some(second_factor) = request.into_inner().second_factor() else {
+        return Ok(HttpResponse::BadRequest().body("Send either a code or a recovery code"));
+    };
+
+    match check_second_factor(&pool, user_id, &second_factor)
+        .await
+        .map_err(e500)?
+    {
+        SecondFactorOutcome::Accepted => {}
+        SecondFactorOutcome::Rejected => {
+            return Err(AuthError::InvalidCredentials("Invalid second factor".into()).into())
+        }
+        SecondFactorOutcome::LockedUntil(locked_until) => {
+            return Ok(too_many_attempts(locked_until));
+        }
+    }
+    if matches!(second_factor, SecondFactor::RecoveryCode(_)) {
+        tracing::warn!(%user_id, "A recovery code was used");
+    }
+    let expires_at = authenticate_session(&pool, &session_token)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        session_token: session_token.expose_secret().clone(),
+        expires_at,
+        second_factor_required: false,
+    }))
+}
+
+/// Answer admins locked out after too many failed codes
+pub fn too_many_attempts(locked_until: DateTime<Utc>) -> HttpResponse {
+    let retry_after = (locked_until - Utc::now()).num_seconds().max(1);
+    HttpResponse::TooManyRequests()
+        .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+        .body("Too many invalid codes, try again later")
+}
+
+/// End the session of the bearer token
+#[tracing::instrument(name = "Log out", skip(http_request, pool))]
+pub async fn logout(
+    http_request: HttpRequest,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session_token = bearer_token(http_request.headers()).ok_or_else(|| {
+        AuthError::InvalidCredentials("The authorization scheme was not 'Bearer'".into())
+    })?;
+    end_session(&pool, &session_token).await.map_err(e500)?;
+    Ok(HttpResponse::NoContent().finish())
+}
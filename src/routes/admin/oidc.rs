@@ -0,0 +1,341 @@
+//! Single sign-on with the OpenID Connect provider, only routed when one is
+//! configured. Admins are created at their first sign-in, and their role
+//! follows the one their provider grants them at each sign-in, those it no
+//! longer grants one being cut off.
+
+use actix_web::{
+    cookie::{time, Cookie, SameSite},
+    http::header,
+    web::{Data, Query},
+    HttpRequest, HttpResponse,
+};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit, AuditActor, AuditEntry},
+    authentication::{AdminUser, AuthError},
+    authorization::Role,
+    oidc::{generate_random_token, OidcClient, OidcError, OidcIdentity},
+    routes::admin::login::LoginResponse,
+    session::{end_other_sessions, start_session},
+    startup::ApplicationBaseUrl,
+    utils::e500,
+};
+
+/// Binds the callback to the browser that started the login
+const STATE_COOKIE: &str = "oidc_state";
+/// How long admins have to sign in with the provider
+const LOGIN_MINUTES: i64 = 10;
+
+/// Send the admin to sign in with the provider
+#[tracing::instrument(name = "Start an OIDC login", skip_all)]
+pub async fn oidc_login(
+    oidc: Data<OidcClient>,
+    pool: Data<PgPool>,
+    base_url: Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let state = generate_random_token();
+    let nonce = generate_random_token();
+    let code_verifier = Secret::new(generate_random_token());
+    let authorization_url = oidc
+        .authorization_url(&state, &nonce, &code_verifier)
+        .await
+        .map_err(e500)?;
+
+    sqlx::query!(
+        "DELETE FROM oidc_logins WHERE created_at < now() - make_interval(mins => $1)",
+        LOGIN_MINUTES as i32,
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    sqlx::query!(
+        "INSERT INTO oidc_logins (state, code_verifier, nonce) VALUES ($1, $2, $3)",
+        state,
+        code_verifier.expose_secret(),
+        nonce,
+    )
+    .execute(pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    let cookie = Cookie::build(STATE_COOKIE, state)
+        .path("/admin/oidc")
+        .http_only(true)
+        .secure(base_url.0.starts_with("https://"))
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::minutes(LOGIN_MINUTES))
+        .finish();
+    Ok(HttpResponse::SeeOther()
+        .insert_header((header::LOCATION, authorization_url))
+        .cookie(cookie)
+        .finish())
+}
+
+#[derive(Deserialize)]
+pub struct CallbackParameters {
+    code: String,
+    state: String,
+}
+
+/// Where the provider sends the admin back to, starting their session
+#[tracing::instrument(name = "End an OIDC login", skip_all, fields(user_id = tracing::field::Empty))]
+pub async fn oidc_callback(
+    http_request: HttpRequest,
+    parameters: Query<CallbackParameters>,
+    oidc: Data<OidcClient>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invalid = |reason: &str| AuthError::InvalidCredentials(reason.to_string());
+    if http_request
+        .cookie(STATE_COOKIE)
+        .is_none_or(|cookie| cookie.value() != parameters.state)
+    {
+        return Err(invalid("The login was not started by this browser").into());
+    }
+    let login = sqlx::query!(
+        r#"
+        DELETE FROM oidc_logins
+        WHERE state = $1 AND created_at >= now() - make_interval(mins => $2)
+        RETURNING code_verifier, nonce
+        "#,
+        parameters.state,
+        LOGIN_MINUTES as i32,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(e500)?
+    .ok_or_else(|| invalid("Unknown or expired login"))?;
+
+    let identity = match oidc
+        .exchange_code(
+            &parameters.code,
+            &Secret::new(login.code_verifier),
+            &login.nonce,
+        )
+        .await
+    {
+        Ok(identity) => identity,
+        Err(e @ (OidcError::InvalidGrant(_) | OidcError::InvalidIdToken(_))) => {
+            return Err(invalid(&e.to_string()).into());
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let mut existing = lock_oidc_user(&mut transaction, &identity)
+        .await
+        .map_err(e500)?;
+    let Some(role) = identity.role else {
+        if let Some(existing) = existing {
+            disable_oidc_user(&mut transaction, &http_request, existing)
+                .await
+                .map_err(e500)?;
+            transaction.commit().await.map_err(e500)?;
+        }
+        return Ok(HttpResponse::Forbidden().body("Your identity provider grants you no role"));
+    };
+
+    if existing.is_none() {
+        match create_oidc_user(&mut transaction, &http_request, &identity, role)
+            .await
+            .map_err(e500)?
+        {
+            Some(user) => {
+                transaction.commit().await.map_err(e500)?;
+                return start_oidc_session(&pool, user).await;
+            }
+            // Either the username is taken, or the same admin signed in at
+            // the same time and was created first
+            None => {
+                existing = lock_oidc_user(&mut transaction, &identity)
+                    .await
+                    .map_err(e500)?;
+            }
+        }
+    }
+    let Some(existing) = existing else {
+        return Ok(HttpResponse::Conflict().body(format!(
+            "The username {} is taken by another admin",
+            identity.username
+        )));
+    };
+    let user = AdminUser {
+        user_id: existing.user_id,
+        role,
+        totp_enabled: existing.totp_enabled,
+        totp_required: existing.totp_required,
+        api_key: None,
+    };
+    if existing.role != role.as_ref() {
+        sqlx::query!(
+            "UPDATE users SET role = $2 WHERE user_id = $1",
+            user.user_id,
+            role.as_ref(),
+        )
+        .execute(&mut transaction)
+        .await
+        .map_err(e500)?;
+        // Sessions started with the previous role end with it
+        end_other_sessions(&mut transaction, user.user_id, None)
+            .await
+            .map_err(e500)?;
+        record_audit(
+            &mut transaction,
+            &AuditActor::new(&user, &http_request),
+            AuditEntry {
+                action: "user.change_role",
+                target_type: "user",
+                target_id: Some(user.user_id.to_string()),
+                before: Some(json!({ "role": existing.role })),
+                after: Some(json!({ "role": role.as_ref() })),
+            },
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction.commit().await.map_err(e500)?;
+    start_oidc_session(&pool, user).await
+}
+
+/// Start the session of an admin signed in with the provider
+async fn start_oidc_session(
+    pool: &PgPool,
+    user: AdminUser,
+) -> Result<HttpResponse, actix_web::Error> {
+    tracing::Span::current().record("user_id", tracing::field::display(&user.user_id));
+
+    // Admins who enabled TOTP still verify it, whatever their provider does
+    let (session_token, expires_at) = start_session(pool, user.user_id, !user.totp_enabled)
+        .await
+        .map_err(e500)?;
+    let mut removal = Cookie::build(STATE_COOKIE, "").path("/admin/oidc").finish();
+    removal.make_removal();
+    Ok(HttpResponse::Ok().cookie(removal).json(LoginResponse {
+        session_token: session_token.expose_secret().clone(),
+        expires_at,
+        second_factor_required: user.totp_enabled,
+    }))
+}
+
+struct OidcUser {
+    user_id: Uuid,
+    role: String,
+    totp_enabled: bool,
+    totp_required: bool,
+}
+
+/// The admin with the identity, locked until the transaction ends
+async fn lock_oidc_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    identity: &OidcIdentity,
+) -> Result<Option<OidcUser>, sqlx::Error> {
+    sqlx::query_as!(
+        OidcUser,
+        r#"
+        SELECT user_id, role, totp_enabled_at IS NOT NULL AS "totp_enabled!", totp_required
+        FROM users
+        WHERE oidc_issuer = $1 AND oidc_subject = $2
+        FOR UPDATE
+        "#,
+        identity.issuer,
+        identity.subject,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+}
+
+/// Create the admin signing in for the first time, unless their username is
+/// taken or they were created concurrently
+async fn create_oidc_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    http_request: &HttpRequest,
+    identity: &OidcIdentity,
+    role: Role,
+) -> Result<Option<AdminUser>, sqlx::Error> {
+    // No conflict target, to cover both the username and the identity
+    let Some(created) = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, role, oidc_issuer, oidc_subject)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        identity.username,
+        role.as_ref(),
+        identity.issuer,
+        identity.subject,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let user = AdminUser {
+        user_id: created.user_id,
+        role,
+        totp_enabled: false,
+        totp_required: false,
+        api_key: None,
+    };
+    record_audit(
+        transaction,
+        &AuditActor::new(&user, http_request),
+        AuditEntry {
+            action: "user.create",
+            target_type: "user",
+            target_id: Some(user.user_id.to_string()),
+            before: None,
+            after: Some(json!({
+                "username": identity.username,
+                "role": role.as_ref(),
+                "oidc_issuer": identity.issuer,
+            })),
+        },
+    )
+    .await?;
+    Ok(Some(user))
+}
+
+/// Cut off an admin their provider no longer grants a role: they have no
+/// password, so ending their sessions and revoking their API keys leaves
+/// them no way in until the provider grants them a role again
+async fn disable_oidc_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    http_request: &HttpRequest,
+    existing: OidcUser,
+) -> Result<(), sqlx::Error> {
+    end_other_sessions(&mut *transaction, existing.user_id, None).await?;
+    let revoked = sqlx::query!(
+        "UPDATE api_keys SET revoked_at = now() WHERE created_by = $1 AND revoked_at IS NULL",
+        existing.user_id,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let user = AdminUser {
+        user_id: existing.user_id,
+        role: Role::parse(&existing.role).map_err(|e| sqlx::Error::Decode(e.into()))?,
+        totp_enabled: existing.totp_enabled,
+        totp_required: existing.totp_required,
+        api_key: None,
+    };
+    record_audit(
+        transaction,
+        &AuditActor::new(&user, http_request),
+        AuditEntry {
+            action: "user.disable",
+            target_type: "user",
+            target_id: Some(user.user_id.to_string()),
+            before: Some(json!({ "role_granted": true })),
+            after: Some(json!({
+                "role_granted": false,
+                "api_keys_revoked": revoked.rows_affected(),
+            })),
+        },
+    )
+    .await
+}
@@ -0,0 +1,276 @@
+use std::borrow::Cow;
+
+use actix_web::{
+    http::header,
+    web::{Bytes, Data, Query},
+    HttpResponse,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::{
+    stream::{self, try_unfold},
+    StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    authorization::{Authorized, ViewSubscribers},
+    domain::SubscriptionStatus,
+    routes::admin::subscribers::{end_of_day, escape_like, start_of_day},
+    utils::e500,
+};
+
+/// Rows fetched from the cursor, and written to the response, at a time
+const CHUNK_SIZE: i64 = 1000;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    /// JSON Lines, one subscriber per line
+    Jsonl,
+}
+
+/// The filters of the subscriber listing, without its pagination
+#[derive(Deserialize, Debug)]
+pub struct ExportFilter {
+    #[serde(default)]
+    format: ExportFormat,
+    status: Option<String>,
+    list: Option<String>,
+    subscribed_from: Option<NaiveDate>,
+    subscribed_to: Option<NaiveDate>,
+    search: Option<String>,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+struct ExportedSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    /// When they first confirmed
+    confirmed_at: Option<DateTime<Utc>>,
+    /// When they last unsubscribed, if they still are
+    unsubscribed_at: Option<DateTime<Utc>>,
+    lists: Vec<String>,
+}
+
+/// The columns of [`CsvRow`], written even when no subscriber is exported
+const CSV_HEADER: &str = "id,email,name,status,subscribed_at,confirmed_at,unsubscribed_at,lists\n";
+
+/// CSV having no arrays, lists are separated by `;` as for imports. Text
+/// entered by subscribers or admins is [neutralized](neutralize_formula).
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    id: Uuid,
+    email: Cow<'a, str>,
+    name: Cow<'a, str>,
+    status: &'a str,
+    subscribed_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+    unsubscribed_at: Option<DateTime<Utc>>,
+    lists: String,
+}
+
+/// Where the export stands between two chunks
+struct Export {
+    transaction: Transaction<'static, Postgres>,
+    format: ExportFormat,
+}
+
+/// All subscribers, or those matching the filters, oldest first.
+///
+/// Rows are read through a database cursor and written out a chunk at a time,
+/// so that an export only ever holds [`CHUNK_SIZE`] of them in memory.
+#[tracing::instrument(name = "Export subscribers", skip(pool), fields(user_id = %user.user_id))]
+pub async fn export(
+    user: Authorized<ViewSubscribers>,
+    filter: Query<ExportFilter>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(status) = &filter.status {
+        if let Err(e) = SubscriptionStatus::parse(status) {
+            return Ok(HttpResponse::BadRequest().body(e));
+        }
+    }
+    let subscribed_before = match filter.subscribed_to {
+        Some(day) => match end_of_day(day) {
+            Some(end) => Some(end),
+            None => return Ok(HttpResponse::BadRequest().body("`subscribed_to` is out of range")),
+        },
+        None => None,
+    };
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    // Checked at runtime, query! not supporting cursors
+    sqlx::query(
+        r#"
+        DECLARE subscribers_export NO SCROLL CURSOR FOR
+        SELECT s.id, s.email, s.name, s.status, s.subscribed_at,
+            (
+                SELECT MIN(h.changed_at) FROM subscription_status_history h
+                WHERE h.subscriber_id = s.id AND h.status = 'confirmed'
+            ) AS confirmed_at,
+            CASE WHEN s.status = 'unsubscribed' THEN (
+                SELECT MAX(h.changed_at) FROM subscription_status_history h
+                WHERE h.subscriber_id = s.id AND h.status = 'unsubscribed'
+            ) END AS unsubscribed_at,
+            ARRAY(
+                SELECT l.name FROM subscription_lists sl
+                JOIN lists l ON l.id = sl.list_id
+                WHERE sl.subscriber_id = s.id
+                ORDER BY l.name
+            ) AS lists
+        FROM subscriptions s
+        WHERE ($1::text IS NULL OR s.status = $1)
+            AND ($2::text IS NULL OR EXISTS (
+                SELECT 1 FROM subscription_lists sl
+                JOIN lists l ON l.id = sl.list_id
+                WHERE sl.subscriber_id = s.id AND l.name = $2
+            ))
+            AND ($3::timestamptz IS NULL OR s.subscribed_at >= $3)
+            AND ($4::timestamptz IS NULL OR s.subscribed_at < $4)
+            AND ($5::text IS NULL OR s.email ILIKE $5 OR s.name ILIKE $5)
+        ORDER BY s.subscribed_at, s.id
+        "#,
+    )
+    .bind(&filter.status)
+    .bind(&filter.list)
+    .bind(filter.subscribed_from.map(start_of_day))
+    .bind(subscribed_before)
+    .bind(
+        filter
+            .search
+            .as_deref()
+            .map(|search| format!("%{}%", escape_like(search))),
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+
+    let (content_type, extension, header) = match filter.format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "csv", Some(CSV_HEADER)),
+        ExportFormat::Jsonl => ("application/x-ndjson", "jsonl", None),
+    };
+    let export = Export {
+        transaction,
+        format: filter.format,
+    };
+    let header = stream::iter(header.map(|header| Ok(Bytes::from_static(header.as_bytes()))));
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"subscribers.{extension}\""),
+        ))
+        .streaming(header.chain(try_unfold(export, next_chunk))))
+}
+
+/// The next chunk of the export, or `None` once the cursor is exhausted
+async fn next_chunk(mut export: Export) -> Result<Option<(Bytes, Export)>, std::io::Error> {
+    let subscribers: Vec<ExportedSubscriber> = sqlx::query_as(&format!(
+        "FETCH FORWARD {CHUNK_SIZE} FROM subscribers_export"
+    ))
+    .fetch_all(&mut export.transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to fetch subscribers to export: {:?}", e);
+        std::io::Error::other(e)
+    })?;
+    if subscribers.is_empty() {
+        export
+            .transaction
+            .commit()
+            .await
+            .map_err(std::io::Error::other)?;
+        return Ok(None);
+    }
+
+    let chunk = match export.format {
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(false)
+                .from_writer(Vec::new());
+            for subscriber in &subscribers {
+                writer.serialize(CsvRow {
+                    id: subscriber.id,
+                    email: neutralize_formula(&subscriber.email),
+                    name: neutralize_formula(&subscriber.name),
+                    status: &subscriber.status,
+                    subscribed_at: subscriber.subscribed_at,
+                    confirmed_at: subscriber.confirmed_at,
+                    unsubscribed_at: subscriber.unsubscribed_at,
+                    lists: neutralize_formula(&subscriber.lists.join(";")).into_owned(),
+                })?;
+            }
+            writer.into_inner().map_err(std::io::Error::other)?
+        }
+        ExportFormat::Jsonl => {
+            let mut lines = Vec::new();
+            for subscriber in &subscribers {
+                serde_json::to_writer(&mut lines, subscriber)?;
+                lines.push(b'\n');
+            }
+            lines
+        }
+    };
+    Ok(Some((Bytes::from(chunk), export)))
+}
+
+/// Spreadsheets evaluate cells starting with one of these as formulas
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Prefix a cell that a spreadsheet would evaluate with `'`, so that it is
+/// shown as text instead
+fn neutralize_formula(cell: &str) -> Cow<'_, str> {
+    if cell.starts_with(FORMULA_PREFIXES) {
+        Cow::Owned(format!("'{cell}"))
+    } else {
+        Cow::Borrowed(cell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::{neutralize_formula, CsvRow, CSV_HEADER};
+
+    #[test]
+    fn the_csv_header_names_the_columns_of_rows() {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer
+            .serialize(CsvRow {
+                id: Uuid::new_v4(),
+                email: "ursula@example.com".into(),
+                name: "Ursula".into(),
+                status: "confirmed",
+                subscribed_at: Utc::now(),
+                confirmed_at: None,
+                unsubscribed_at: None,
+                lists: String::new(),
+            })
+            .unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+
+        assert!(csv.starts_with(CSV_HEADER));
+    }
+
+    #[test]
+    fn cells_evaluated_as_formulas_are_prefixed() {
+        for cell in ["=1+1", "+1", "-1", "@SUM(A1)", "\t=1", "\r=1"] {
+            assert_eq!(neutralize_formula(cell), format!("'{cell}"));
+        }
+    }
+
+    #[test]
+    fn other_cells_are_left_alone() {
+        for cell in ["Ursula", "ursula@example.com", "1+1", ""] {
+            assert_eq!(neutralize_formula(cell), cell);
+        }
+    }
+}
@@ -0,0 +1,653 @@
+use actix_web::{
+    web::{Data, Json, Path, Payload, Query},
+    HttpRequest, HttpResponse,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit, AuditActor, AuditEntry},
+    authorization::{Authorized, ManageSubscribers, ViewSubscribers},
+    consent::{consent_events, ConsentRecord},
+    data_protection::erased_at,
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus},
+    email_client::EmailClient,
+    issue_delivery_worker::record_sent_emails,
+    routes::subscriptions::{
+        generate_subscription_token, send_confirmation_email, store_message_id, store_token,
+    },
+    startup::ApplicationBaseUrl,
+    subscriber_import::{import_subscribers, ChunkReader, ImportError, ImportFormat},
+    utils::e500,
+};
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+#[derive(Deserialize, Debug)]
+pub struct SubscriberFilter {
+    status: Option<String>,
+    /// The name of a list the subscribers belong to
+    list: Option<String>,
+    /// Subscribed on or after this UTC day
+    subscribed_from: Option<NaiveDate>,
+    /// Subscribed on or before this UTC day
+    subscribed_to: Option<NaiveDate>,
+    /// Part of the email address or name, case insensitive
+    search: Option<String>,
+    /// The `next_cursor` of the previous page
+    cursor: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    lists: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct SubscriberPage {
+    subscribers: Vec<Subscriber>,
+    /// Where the next page starts, unset on the last page
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberDetail {
+    #[serde(flatten)]
+    subscriber: Subscriber,
+    tokens: Vec<Token>,
+    status_history: Vec<StatusChange>,
+    consent_events: Vec<ConsentRecord>,
+}
+
+#[derive(Serialize)]
+struct Token {
+    subscription_token: String,
+    /// Of the confirmation email the token was sent in
+    message_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct StatusChange {
+    status: String,
+    changed_at: DateTime<Utc>,
+}
+
+/// Subscribers, the most recent first, a page at a time
+#[tracing::instrument(name = "List subscribers", skip(pool), fields(user_id = %user.user_id))]
+pub async fn list_subscribers(
+    user: Authorized<ViewSubscribers>,
+    filter: Query<SubscriberFilter>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(status) = &filter.status {
+        if let Err(e) = SubscriptionStatus::parse(status) {
+            return Ok(HttpResponse::BadRequest().body(e));
+        }
+    }
+    let after = match filter.cursor.as_deref().map(decode_cursor).transpose() {
+        Ok(after) => after,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let limit = filter
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let search = filter
+        .search
+        .as_deref()
+        .map(|search| format!("%{}%", escape_like(search)));
+    let subscribed_before = match filter.subscribed_to {
+        Some(day) => match end_of_day(day) {
+            Some(end) => Some(end),
+            None => return Ok(HttpResponse::BadRequest().body("`subscribed_to` is out of range")),
+        },
+        None => None,
+    };
+
+    let mut subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT s.id, s.email, s.name, s.status, s.subscribed_at,
+            ARRAY(
+                SELECT l.name FROM subscription_lists sl
+                JOIN lists l ON l.id = sl.list_id
+                WHERE sl.subscriber_id = s.id
+                ORDER BY l.name
+            ) AS "lists!"
+        FROM subscriptions s
+        WHERE ($1::text IS NULL OR s.status = $1)
+            AND ($2::text IS NULL OR EXISTS (
+                SELECT 1 FROM subscription_lists sl
+                JOIN lists l ON l.id = sl.list_id
+                WHERE sl.subscriber_id = s.id AND l.name = $2
+            ))
+            AND ($3::timestamptz IS NULL OR s.subscribed_at >= $3)
+            AND ($4::timestamptz IS NULL OR s.subscribed_at < $4)
+            AND ($5::text IS NULL OR s.email ILIKE $5 OR s.name ILIKE $5)
+            AND ($6::timestamptz IS NULL OR (s.subscribed_at, s.id) < ($6, $7))
+        ORDER BY s.subscribed_at DESC, s.id DESC
+        LIMIT $8
+        "#,
+        filter.status,
+        filter.list,
+        filter.subscribed_from.map(start_of_day),
+        subscribed_before,
+        search,
+        after.map(|(subscribed_at, _)| subscribed_at),
+        after.map(|(_, id)| id),
+        // One more to know whether there is a next page
+        limit + 1,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    let next_cursor = if subscribers.len() > limit as usize {
+        subscribers.truncate(limit as usize);
+        subscribers
+            .last()
+            .map(|last| encode_cursor(last.subscribed_at, last.id))
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(SubscriberPage {
+        subscribers,
+        next_cursor,
+    }))
+}
+
+/// A subscriber with their tokens and the statuses they went through
+#[tracing::instrument(name = "Show a subscriber", skip(pool), fields(user_id = %user.user_id))]
+pub async fn get_subscriber(
+    user: Authorized<ViewSubscribers>,
+    id: Path<Uuid>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    Ok(
+        match get_subscriber_detail(*id, &pool).await.map_err(e500)? {
+            Some(subscriber) => HttpResponse::Ok().json(subscriber),
+            None => HttpResponse::NotFound().finish(),
+        },
+    )
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewSubscriberRequest {
+    email: String,
+    name: String,
+    #[serde(default)]
+    lists: Vec<String>,
+    /// Whether the subscriber has to confirm by email, as when subscribing
+    /// through the form, rather than being added as confirmed. Defaults to yes.
+    double_opt_in: Option<bool>,
+}
+
+#[tracing::instrument(
+    name = "Add a subscriber",
+    skip(http_request, pool, email_client, base_url),
+    fields(user_id = %user.user_id)
+)]
+pub async fn add_subscriber(
+    user: Authorized<ManageSubscribers>,
+    http_request: HttpRequest,
+    request: Json<NewSubscriberRequest>,
+    pool: Data<PgPool>,
+    email_client: Data<EmailClient>,
+    base_url: Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let request = request.into_inner();
+    let subscriber = match (
+        SubscriberEmail::parse(request.email),
+        SubscriberName::parse(request.name),
+    ) {
+        (Ok(email), Ok(name)) => NewSubscriber { email, name },
+        (Err(e), _) | (_, Err(e)) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let double_opt_in = request.double_opt_in.unwrap_or(true);
+    let status = if double_opt_in {
+        SubscriptionStatus::PendingConfirmation
+    } else {
+        SubscriptionStatus::Confirmed
+    };
+
+    if erased_at(pool.get_ref(), subscriber.email.as_ref())
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        return Ok(HttpResponse::Conflict()
+            .body("The data of the email address was erased at the request of its owner"));
+    }
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(subscriber_id) = insert_subscriber(&mut transaction, &subscriber, status, Utc::now())
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::Conflict().body("The email address is already subscribed"));
+    };
+    add_to_lists(&mut transaction, subscriber_id, &request.lists)
+        .await
+        .map_err(e500)?;
+    let subscription_token = generate_subscription_token();
+    if double_opt_in {
+        store_token(subscriber_id, &subscription_token, &mut transaction)
+            .await
+            .map_err(e500)?;
+    }
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "subscriber.create",
+            target_type: "subscriber",
+            target_id: Some(subscriber_id.to_string()),
+            before: None,
+            after: Some(json!({
+                "status": status.as_ref(),
+                "lists": request.lists,
+                "double_opt_in": double_opt_in,
+            })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    if double_opt_in {
+        let sent_email = send_confirmation_email(
+            &email_client,
+            subscriber,
+            base_url.0.clone(),
+            &subscription_token,
+        )
+        .await
+        .map_err(e500)?;
+        let _ = record_sent_emails(pool.get_ref(), 1).await;
+        if let Some(message_id) = sent_email.message_id {
+            let _ = store_message_id(&subscription_token, &message_id, &pool).await;
+        }
+    }
+
+    let subscriber = get_subscriber_detail(subscriber_id, &pool)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Created().json(subscriber))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ImportOptions {
+    #[serde(default)]
+    format: ImportFormat,
+}
+
+/// Chunks of the request body read ahead of the CSV reader
+const IMPORT_READ_AHEAD: usize = 16;
+
+/// Import the subscribers of a CSV, by default with `email`, `name` and
+/// optionally `lists` columns, reporting on every row. The CSV is streamed
+/// rather than buffered, however large it is.
+#[tracing::instrument(name = "Import subscribers from a CSV", skip(http_request, payload, pool), fields(user_id = %user.user_id))]
+pub async fn import(
+    user: Authorized<ManageSubscribers>,
+    http_request: HttpRequest,
+    options: Query<ImportOptions>,
+    mut payload: Payload,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let actor = AuditActor::new(&user, &http_request);
+    let (chunks, csv) = ChunkReader::new(IMPORT_READ_AHEAD);
+    let stream = async move {
+        while let Some(chunk) = payload.next().await {
+            let chunk = chunk.map_err(std::io::Error::other);
+            let failed = chunk.is_err();
+            // The import stopped reading, e.g. on a missing column
+            if chunks.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+    };
+    let import = import_subscribers(&pool, csv, options.format, Some(&actor));
+    let ((), imported) = futures_util::join!(stream, import);
+    match imported {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(e @ (ImportError::MissingColumn(_) | ImportError::ReadError(_))) => {
+            Ok(HttpResponse::BadRequest().body(e.to_string()))
+        }
+        Err(e) => Err(e500(e)),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatusChangeRequest {
+    status: String,
+}
+
+#[tracing::instrument(name = "Change the status of a subscriber", skip(http_request, pool), fields(user_id = %user.user_id))]
+pub async fn change_subscriber_status(
+    user: Authorized<ManageSubscribers>,
+    http_request: HttpRequest,
+    id: Path<Uuid>,
+    request: Json<StatusChangeRequest>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let status = match SubscriptionStatus::parse(&request.status) {
+        Ok(status) => status,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(previous_status) = lock_status(&mut transaction, *id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    sqlx::query!(
+        "UPDATE subscriptions SET status = $2 WHERE id = $1",
+        *id,
+        status.as_ref(),
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "subscriber.change_status",
+            target_type: "subscriber",
+            target_id: Some(id.to_string()),
+            before: Some(json!({ "status": previous_status })),
+            after: Some(json!({ "status": status.as_ref() })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    let subscriber = get_subscriber_detail(*id, &pool).await.map_err(e500)?;
+    Ok(HttpResponse::Ok().json(subscriber))
+}
+
+/// Delete a subscriber with their tokens, deliveries and list memberships.
+///
+/// Only subscribers without proof of consent, i.e. added by admins or
+/// imported, can be deleted. Everyone who signed up through the form has
+/// their consent recorded, which is kept for as long as their data is: they
+/// are unsubscribed, or erased through `/admin/api/data_subjects/erase` at
+/// their request, instead.
+#[tracing::instrument(name = "Delete a subscriber", skip(http_request, pool), fields(user_id = %user.user_id))]
+pub async fn delete_subscriber(
+    user: Authorized<ManageSubscribers>,
+    http_request: HttpRequest,
+    id: Path<Uuid>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(status) = lock_status(&mut transaction, *id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if !consent_events(&mut transaction, *id)
+        .await
+        .map_err(e500)?
+        .is_empty()
+    {
+        return Ok(HttpResponse::Conflict().body(
+            "The proof of consent of the subscriber is kept until their data is erased at their \
+            request, through /admin/api/data_subjects/erase",
+        ));
+    }
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        *id
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    sqlx::query!("DELETE FROM subscriptions WHERE id = $1", *id)
+        .execute(&mut transaction)
+        .await
+        .map_err(e500)?;
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "subscriber.delete",
+            target_type: "subscriber",
+            target_id: Some(id.to_string()),
+            before: Some(json!({ "status": status })),
+            after: None,
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// The status of a subscriber, locking them until the transaction ends
+async fn lock_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(subscriber.map(|subscriber| subscriber.status))
+}
+
+/// Store a subscriber with the given status, unless their email address is
+/// already subscribed
+#[tracing::instrument(skip(transaction, subscriber))]
+pub async fn insert_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber: &NewSubscriber,
+    status: SubscriptionStatus,
+    subscribed_at: DateTime<Utc>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
+        "#,
+        Uuid::new_v4(),
+        subscriber.email.as_ref(),
+        subscriber.name.as_ref(),
+        subscribed_at,
+        status.as_ref(),
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(inserted.map(|row| row.id))
+}
+
+/// Add a subscriber to lists, creating those that do not exist yet
+#[tracing::instrument(skip(transaction))]
+pub async fn add_to_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    lists: &[String],
+) -> Result<(), sqlx::Error> {
+    for list in lists {
+        sqlx::query!(
+            r#"
+            WITH list AS (
+                INSERT INTO lists (id, name) VALUES ($2, $3)
+                ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name
+                RETURNING id
+            )
+            INSERT INTO subscription_lists (subscriber_id, list_id)
+            SELECT $1, id FROM list
+            ON CONFLICT DO NOTHING
+            "#,
+            subscriber_id,
+            Uuid::new_v4(),
+            list.trim(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber_detail(
+    id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<SubscriberDetail>, sqlx::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT s.id, s.email, s.name, s.status, s.subscribed_at,
+            ARRAY(
+                SELECT l.name FROM subscription_lists sl
+                JOIN lists l ON l.id = sl.list_id
+                WHERE sl.subscriber_id = s.id
+                ORDER BY l.name
+            ) AS "lists!"
+        FROM subscriptions s
+        WHERE s.id = $1
+        "#,
+        id,
+    )
+    .fetch_optional(pool)
+    .await?;
+    let Some(subscriber) = subscriber else {
+        return Ok(None);
+    };
+    let tokens = sqlx::query_as!(
+        Token,
+        "SELECT subscription_token, message_id FROM subscription_tokens WHERE subscriber_id = $1",
+        id,
+    )
+    .fetch_all(pool)
+    .await?;
+    let status_history = sqlx::query_as!(
+        StatusChange,
+        r#"
+        SELECT status, changed_at FROM subscription_status_history
+        WHERE subscriber_id = $1
+        ORDER BY changed_at, id
+        "#,
+        id,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(Some(SubscriberDetail {
+        subscriber,
+        tokens,
+        status_history,
+        consent_events: consent_events(pool, id).await?,
+    }))
+}
+
+/// Midnight UTC at the start of `day`
+#[must_use]
+pub fn start_of_day(day: NaiveDate) -> DateTime<Utc> {
+    day.and_hms_opt(0, 0, 0)
+        .expect("Midnight is a valid time")
+        .and_utc()
+}
+
+/// Midnight UTC at the end of `day`, i.e. the exclusive end of a range of
+/// days, unless `day` is the last one that can be represented
+#[must_use]
+pub fn end_of_day(day: NaiveDate) -> Option<DateTime<Utc>> {
+    day.succ_opt().map(start_of_day)
+}
+
+/// Escape the wildcards of a `LIKE` pattern
+#[must_use]
+pub fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn encode_cursor(subscribed_at: DateTime<Utc>, id: Uuid) -> String {
+    URL_SAFE_NO_PAD.encode(format!(
+        "{}/{id}",
+        subscribed_at.to_rfc3339_opts(SecondsFormat::Micros, true)
+    ))
+}
+
+fn decode_cursor(cursor: &str) -> Result<(DateTime<Utc>, Uuid), String> {
+    let invalid = || format!("{cursor} is not a valid cursor");
+    let decoded = URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .ok_or_else(invalid)?;
+    let (subscribed_at, id) = decoded.split_once('/').ok_or_else(invalid)?;
+    Ok((
+        DateTime::parse_from_rfc3339(subscribed_at)
+            .map_err(|_| invalid())?
+            .with_timezone(&Utc),
+        id.parse().map_err(|_| invalid())?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use claim::{assert_err, assert_none};
+    use uuid::Uuid;
+
+    use super::{decode_cursor, encode_cursor, end_of_day, escape_like};
+
+    #[test]
+    fn cursors_round_trip_to_the_microsecond() {
+        let subscribed_at = Utc.timestamp_opt(1_700_000_000, 123_456_000).unwrap();
+        let id = Uuid::new_v4();
+
+        let cursor = encode_cursor(subscribed_at, id);
+
+        assert_eq!(decode_cursor(&cursor), Ok((subscribed_at, id)));
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        assert_err!(decode_cursor("not a cursor"));
+        assert_err!(decode_cursor(&base64::Engine::encode(
+            &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+            "2023-11-14T22:13:20Z/not-a-uuid"
+        )));
+    }
+
+    #[test]
+    fn days_end_at_the_next_midnight() {
+        let day = NaiveDate::from_ymd_opt(2023, 12, 31).unwrap();
+
+        assert_eq!(
+            end_of_day(day),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert_none!(end_of_day(NaiveDate::MAX));
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like(r"100%_\"), r"100\%\_\\");
+    }
+}
@@ -0,0 +1,263 @@
+//! Enrollment of admins in TOTP. These take an [`AdminUser`] rather than an
+//! [`Authorized`](crate::authorization::Authorized) one, so that admins an
+//! owner requires TOTP of can enable it, but refuse API keys.
+
+use actix_web::{
+    web::{Data, Json},
+    HttpRequest, HttpResponse,
+};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{record_audit, AuditActor, AuditEntry},
+    authentication::AdminUser,
+    configuration::NewsletterSettings,
+    routes::admin::login::{too_many_attempts, SecondFactorRequest},
+    session::{bearer_token, end_other_sessions},
+    totp::{
+        check_second_factor, generate_recovery_codes, generate_secret, provisioning_uri,
+        recovery_code_hash, SecondFactor, SecondFactorOutcome,
+    },
+    utils::e500,
+};
+
+/// The second factor is the one of a person, not of a machine client
+fn refuse_api_keys(user: &AdminUser) -> Option<HttpResponse> {
+    user.api_key
+        .as_ref()
+        .map(|_| HttpResponse::Forbidden().body("API keys cannot manage TOTP"))
+}
+
+#[derive(Serialize)]
+struct Enrollment {
+    /// Base32, for authenticator apps that cannot scan QR codes
+    secret: String,
+    /// To show as a QR code
+    provisioning_uri: String,
+}
+
+/// Start enrolling in TOTP with a new secret, which is only required at login
+/// once confirmed with a code
+#[tracing::instrument(name = "Enroll in TOTP", skip(pool, newsletter), fields(user_id = %user.user_id))]
+pub async fn enroll(
+    user: AdminUser,
+    pool: Data<PgPool>,
+    newsletter: Data<NewsletterSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(response) = refuse_api_keys(&user) {
+        return Ok(response);
+    }
+    if user.totp_enabled {
+        return Ok(HttpResponse::Conflict().body("TOTP is already enabled"));
+    }
+    let secret = generate_secret();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let enrolled = sqlx::query!(
+        r#"
+        UPDATE users SET totp_secret = $2, totp_last_step = NULL
+        WHERE user_id = $1
+        RETURNING username
+        "#,
+        user.user_id,
+        secret.expose_secret(),
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .map_err(e500)?;
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user.user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    Ok(HttpResponse::Ok().json(Enrollment {
+        provisioning_uri: provisioning_uri(&newsletter.title, &enrolled.username, &secret),
+        secret: secret.expose_secret().clone(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmationRequest {
+    code: String,
+}
+
+#[derive(Serialize)]
+struct RecoveryCodes {
+    /// Shown this once, only their hashes are kept
+    recovery_codes: Vec<String>,
+}
+
+/// Enable TOTP with a first code of the authenticator, returning the recovery
+/// codes
+#[tracing::instrument(
+    name = "Confirm the TOTP enrollment",
+    skip(http_request, request, pool),
+    fields(user_id = %user.user_id)
+)]
+pub async fn confirm_enrollment(
+    user: AdminUser,
+    http_request: HttpRequest,
+    request: Json<ConfirmationRequest>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(response) = refuse_api_keys(&user) {
+        return Ok(response);
+    }
+    if user.totp_enabled {
+        return Ok(HttpResponse::Conflict().body("TOTP is already enabled"));
+    }
+    let second_factor = SecondFactor::Code(request.into_inner().code);
+    match check_second_factor(&pool, user.user_id, &second_factor)
+        .await
+        .map_err(e500)?
+    {
+        SecondFactorOutcome::Accepted => {}
+        SecondFactorOutcome::Rejected => {
+            return Ok(HttpResponse::BadRequest()
+                .body("The code is invalid, or the enrollment was not started"));
+        }
+        SecondFactorOutcome::LockedUntil(locked_until) => {
+            return Ok(too_many_attempts(locked_until));
+        }
+    }
+
+    let recovery_codes = generate_recovery_codes();
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    sqlx::query!(
+        "UPDATE users SET totp_enabled_at = now() WHERE user_id = $1",
+        user.user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    sqlx::query!(
+        r#"
+        INSERT INTO totp_recovery_codes (user_id, code_hash)
+        SELECT $1, * FROM UNNEST($2::text[])
+        "#,
+        user.user_id,
+        &recovery_codes
+            .iter()
+            .map(|code| recovery_code_hash(code.expose_secret()))
+            .collect::<Vec<_>>(),
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    end_other_sessions(
+        &mut transaction,
+        user.user_id,
+        bearer_token(http_request.headers()).as_ref(),
+    )
+    .await
+    .map_err(e500)?;
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "user.enable_totp",
+            target_type: "user",
+            target_id: Some(user.user_id.to_string()),
+            before: Some(json!({ "totp_enabled": false })),
+            after: Some(json!({ "totp_enabled": true })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    Ok(HttpResponse::Ok().json(RecoveryCodes {
+        recovery_codes: recovery_codes
+            .iter()
+            .map(|code| code.expose_secret().clone())
+            .collect(),
+    }))
+}
+
+/// Disable TOTP with a current code or a recovery code, unless an owner
+/// requires it. An enrollment that was not confirmed is cancelled without.
+#[tracing::instrument(
+    name = "Disable TOTP",
+    skip(http_request, request, pool),
+    fields(user_id = %user.user_id)
+)]
+pub async fn disable(
+    user: AdminUser,
+    http_request: HttpRequest,
+    request: Option<Json<SecondFactorRequest>>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(response) = refuse_api_keys(&user) {
+        return Ok(response);
+    }
+    if user.totp_required {
+        return Ok(HttpResponse::Conflict().body("TOTP is required for your account"));
+    }
+    if user.totp_enabled {
+        let Some(second_factor) = request.and_then(|request| request.into_inner().second_factor())
+        else {
+            return Ok(HttpResponse::BadRequest().body("Send either a code or a recovery code"));
+        };
+        match check_second_factor(&pool, user.user_id, &second_factor)
+            .await
+            .map_err(e500)?
+        {
+            SecondFactorOutcome::Accepted => {}
+            SecondFactorOutcome::Rejected => {
+                return Ok(HttpResponse::BadRequest().body("The code is invalid"));
+            }
+            SecondFactorOutcome::LockedUntil(locked_until) => {
+                return Ok(too_many_attempts(locked_until));
+            }
+        }
+    }
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_enabled_at = NULL, totp_last_step = NULL
+        WHERE user_id = $1
+        "#,
+        user.user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    sqlx::query!(
+        "DELETE FROM totp_recovery_codes WHERE user_id = $1",
+        user.user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    if user.totp_enabled {
+        end_other_sessions(
+            &mut transaction,
+            user.user_id,
+            bearer_token(http_request.headers()).as_ref(),
+        )
+        .await
+        .map_err(e500)?;
+        record_audit(
+            &mut transaction,
+            &AuditActor::new(&user, &http_request),
+            AuditEntry {
+                action: "user.disable_totp",
+                target_type: "user",
+                target_id: Some(user.user_id.to_string()),
+                before: Some(json!({ "totp_enabled": true })),
+                after: Some(json!({ "totp_enabled": false })),
+            },
+        )
+        .await
+        .map_err(e500)?;
+    }
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::NoContent().finish())
+}
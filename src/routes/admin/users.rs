@@ -0,0 +1,285 @@
+use actix_web::{
+    web::{Data, Json, Path},
+    HttpRequest, HttpResponse,
+};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit, AuditActor, AuditEntry},
+    authentication::compute_password_hash,
+    authorization::{Authorized, ManageUsers, Role},
+    session::{bearer_token, end_other_sessions},
+    telemetry::spawn_blocking_with_tracing,
+    utils::e500,
+};
+
+const MIN_PASSWORD_LENGTH: usize = 12;
+
+#[derive(Serialize)]
+struct User {
+    user_id: Uuid,
+    username: String,
+    role: String,
+    totp_enabled: bool,
+    totp_required: bool,
+}
+
+/// The admins, with their roles and whether they use TOTP
+#[tracing::instrument(name = "List users", skip(pool), fields(user_id = %user.user_id))]
+pub async fn list_users(
+    user: Authorized<ManageUsers>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT user_id, username, role, totp_enabled_at IS NOT NULL AS "totp_enabled!",
+            totp_required
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(users))
+}
+
+#[derive(Deserialize)]
+pub struct NewUserRequest {
+    username: String,
+    password: Secret<String>,
+    role: String,
+}
+
+#[tracing::instrument(
+    name = "Add a user",
+    skip(http_request, request, pool),
+    fields(user_id = %user.user_id, username = %request.username)
+)]
+pub async fn add_user(
+    user: Authorized<ManageUsers>,
+    http_request: HttpRequest,
+    request: Json<NewUserRequest>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let NewUserRequest {
+        username,
+        password,
+        role,
+    } = request.into_inner();
+    let role = match Role::parse(&role) {
+        Ok(role) => role,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    if username.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().body("The username is empty"));
+    }
+    if password.expose_secret().chars().count() < MIN_PASSWORD_LENGTH {
+        return Ok(HttpResponse::BadRequest().body(format!(
+            "The password must be at least {MIN_PASSWORD_LENGTH} characters long"
+        )));
+    }
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(&password))
+        .await
+        .map_err(e500)?;
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(created) = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash, role)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (username) DO NOTHING
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        username,
+        password_hash.expose_secret(),
+        role.as_ref(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(e500)?
+    else {
+        return Ok(HttpResponse::Conflict().body("The username is taken"));
+    };
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "user.create",
+            target_type: "user",
+            target_id: Some(created.user_id.to_string()),
+            before: None,
+            after: Some(json!({ "username": username, "role": role.as_ref() })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    Ok(HttpResponse::Created().json(User {
+        user_id: created.user_id,
+        username,
+        role: role.as_ref().to_string(),
+        totp_enabled: false,
+        totp_required: false,
+    }))
+}
+
+/// The fields to change, the others being kept
+#[derive(Deserialize, Debug)]
+pub struct UserChangeRequest {
+    role: Option<String>,
+    /// Whether the admin must enable TOTP to use the admin area
+    totp_required: Option<bool>,
+}
+
+/// Change the role of an admin, as long as an owner is left, or whether they
+/// must use TOTP
+#[tracing::instrument(name = "Change a user", skip(http_request, pool), fields(user_id = %user.user_id))]
+pub async fn change_user(
+    user: Authorized<ManageUsers>,
+    http_request: HttpRequest,
+    id: Path<Uuid>,
+    request: Json<UserChangeRequest>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let role = match request.role.as_deref().map(Role::parse).transpose() {
+        Ok(role) => role,
+        Err(e) => return Ok(HttpResponse::BadRequest().body(e)),
+    };
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(target) = lock_user(&mut transaction, *id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if role.is_some_and(|role| role != Role::Owner)
+        && is_last_owner(&mut transaction, &target)
+            .await
+            .map_err(e500)?
+    {
+        return Ok(HttpResponse::Conflict().body("The last owner cannot be demoted"));
+    }
+    let role = role.map_or(target.role.clone(), |role| role.as_ref().to_string());
+    let totp_required = request.totp_required.unwrap_or(target.totp_required);
+    sqlx::query!(
+        "UPDATE users SET role = $2, totp_required = $3 WHERE user_id = $1",
+        *id,
+        role,
+        totp_required,
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(e500)?;
+    if role != target.role || totp_required != target.totp_required {
+        // What they are allowed to do changed, so they log in again, but for
+        // an owner changing themselves in the session they use
+        let current_token = bearer_token(http_request.headers()).filter(|_| *id == user.user_id);
+        end_other_sessions(&mut transaction, *id, current_token.as_ref())
+            .await
+            .map_err(e500)?;
+    }
+    let action = if role == target.role {
+        "user.change_totp_requirement"
+    } else {
+        "user.change_role"
+    };
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action,
+            target_type: "user",
+            target_id: Some(id.to_string()),
+            before: Some(json!({ "role": target.role, "totp_required": target.totp_required })),
+            after: Some(json!({ "role": role, "totp_required": totp_required })),
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+
+    Ok(HttpResponse::Ok().json(User {
+        role,
+        totp_required,
+        ..target
+    }))
+}
+
+/// Delete an admin, as long as an owner is left
+#[tracing::instrument(name = "Delete a user", skip(http_request, pool), fields(user_id = %user.user_id))]
+pub async fn delete_user(
+    user: Authorized<ManageUsers>,
+    http_request: HttpRequest,
+    id: Path<Uuid>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    let Some(target) = lock_user(&mut transaction, *id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if is_last_owner(&mut transaction, &target)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::Conflict().body("The last owner cannot be deleted"));
+    }
+    sqlx::query!("DELETE FROM users WHERE user_id = $1", *id)
+        .execute(&mut transaction)
+        .await
+        .map_err(e500)?;
+    record_audit(
+        &mut transaction,
+        &AuditActor::new(&user, &http_request),
+        AuditEntry {
+            action: "user.delete",
+            target_type: "user",
+            target_id: Some(id.to_string()),
+            before: Some(json!({ "username": target.username, "role": target.role })),
+            after: None,
+        },
+    )
+    .await
+    .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// A user, locked until the transaction ends
+async fn lock_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        r#"
+        SELECT user_id, username, role, totp_enabled_at IS NOT NULL AS "totp_enabled!",
+            totp_required
+        FROM users
+        WHERE user_id = $1
+        FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+}
+
+/// Whether `user` is the only owner, locking the owners so that two of them
+/// cannot demote each other at once
+async fn is_last_owner(
+    transaction: &mut Transaction<'_, Postgres>,
+    user: &User,
+) -> Result<bool, sqlx::Error> {
+    if user.role != Role::Owner.as_ref() {
+        return Ok(false);
+    }
+    let owners = sqlx::query!("SELECT user_id FROM users WHERE role = 'owner' FOR UPDATE")
+        .fetch_all(&mut *transaction)
+        .await?;
+    Ok(owners.len() <= 1)
+}
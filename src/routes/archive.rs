@@ -0,0 +1,216 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{Data, Path},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    configuration::NewsletterSettings,
+    email_content::MarkdownRenderer,
+    routes::caching::{self, Validators},
+    utils::e500,
+};
+
+pub struct PublishedIssue {
+    pub id: Uuid,
+    pub slug: String,
+    pub title: String,
+    pub content: String,
+    pub subscribers_only: bool,
+    pub published_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PublishedIssue {
+    #[must_use]
+    pub fn validators(&self) -> Validators {
+        Validators::new(
+            &format!("{}-{}", self.id, self.updated_at.timestamp_micros()),
+            self.updated_at,
+        )
+    }
+}
+
+/// Validators for a listing of issues, which changes whenever an issue is
+/// added, removed or edited. The entity tag hashes every issue with its
+/// version, so that removing an older issue changes it too.
+#[must_use]
+pub fn listing_validators(issues: &[PublishedIssue]) -> Option<Validators> {
+    let last_modified = issues.iter().map(|issue| issue.updated_at).max()?;
+    let digest = issues
+        .iter()
+        .fold(Sha256::new(), |digest, issue| {
+            digest.chain_update(format!(
+                "{}-{}\n",
+                issue.id,
+                issue.updated_at.timestamp_micros()
+            ))
+        })
+        .finalize();
+    Some(Validators::new(&hex::encode(digest), last_modified))
+}
+
+#[tracing::instrument(name = "List archived issues", skip(request, pool, newsletter))]
+pub async fn archive(
+    request: HttpRequest,
+    pool: Data<PgPool>,
+    newsletter: Data<NewsletterSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issues = get_published_issues(&pool).await.map_err(e500)?;
+
+    Ok(caching::respond(
+        &request,
+        listing_validators(&issues).as_ref(),
+        |response| {
+            let items: String = issues.iter().map(issue_list_item).collect();
+            let body = if items.is_empty() {
+                "<p>No issues have been published yet.</p>".to_string()
+            } else {
+                format!("<ul>{items}</ul>")
+            };
+            response
+                .content_type(ContentType::html())
+                .body(page(&newsletter.title, &body))
+        },
+    ))
+}
+
+#[tracing::instrument(name = "Show an archived issue", skip(request, pool, renderer))]
+pub async fn archive_issue(
+    request: HttpRequest,
+    slug: Path<String>,
+    pool: Data<PgPool>,
+    renderer: Data<MarkdownRenderer>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(issue) = get_published_issue(&slug, &pool).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound()
+            .content_type(ContentType::html())
+            .body(page("Not found", "<p>There is no such issue.</p>")));
+    };
+
+    let title = escape(&issue.title);
+    if issue.subscribers_only {
+        return Ok(HttpResponse::Forbidden()
+            .content_type(ContentType::html())
+            .body(page(
+                &issue.title,
+                &format!("<h1>{title}</h1><p>This issue is only available to subscribers.</p>"),
+            )));
+    }
+
+    Ok(caching::respond(
+        &request,
+        Some(&issue.validators()),
+        |response| {
+            let body = format!(
+                "<h1>{title}</h1><p><time datetime=\"{}\">{}</time></p>{}<p><a href=\"/archive\">All issues</a></p>",
+                issue.published_at.to_rfc3339(),
+                issue.published_at.format("%B %-d, %Y"),
+                renderer.render_fragment(&issue.content)
+            );
+            response
+                .content_type(ContentType::html())
+                .body(page(&issue.title, &body))
+        },
+    ))
+}
+
+fn issue_list_item(issue: &PublishedIssue) -> String {
+    let badge = if issue.subscribers_only {
+        " <small>(subscribers only)</small>"
+    } else {
+        ""
+    };
+    format!(
+        "<li><a href=\"/archive/{}\">{}</a>{badge} <time datetime=\"{}\">{}</time></li>",
+        escape(&issue.slug),
+        escape(&issue.title),
+        issue.published_at.to_rfc3339(),
+        issue.published_at.format("%B %-d, %Y"),
+    )
+}
+
+/// Escape text for use in HTML element content and quoted attribute values
+#[must_use]
+pub fn escape(text: &str) -> String {
+    text.chars()
+        .fold(String::with_capacity(text.len()), |mut escaped, c| {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                '\'' => escaped.push_str("&#x27;"),
+                c => escaped.push(c),
+            }
+            escaped
+        })
+}
+
+/// Wrap `body` in the HTML document shared by the public pages
+#[must_use]
+pub fn page(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>{}</title>
+    <link rel="alternate" type="application/rss+xml" href="/feed.rss">
+    <link rel="alternate" type="application/atom+xml" href="/feed.atom">
+</head>
+<body>
+{body}
+</body>
+</html>"#,
+        escape(title)
+    )
+}
+
+#[tracing::instrument(name = "Get published issues", skip(pool))]
+pub async fn get_published_issues(pool: &PgPool) -> Result<Vec<PublishedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT id, slug, title, content, subscribers_only,
+            published_at AS "published_at!", updated_at
+        FROM newsletter_issues
+        WHERE published_at IS NOT NULL
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get a published issue", skip(pool))]
+pub async fn get_published_issue(
+    slug: &str,
+    pool: &PgPool,
+) -> Result<Option<PublishedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT id, slug, title, content, subscribers_only,
+            published_at AS "published_at!", updated_at
+        FROM newsletter_issues
+        WHERE slug = $1 AND published_at IS NOT NULL
+        "#,
+        slug
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
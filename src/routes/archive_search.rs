@@ -0,0 +1,155 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{Data, Query},
+    HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::{
+    routes::archive::{escape, page},
+    utils::e500,
+};
+
+const PAGE_SIZE: i64 = 10;
+/// Far beyond any archive, and small enough for the offset not to overflow
+const MAX_PAGE: i64 = 10_000;
+
+// Private use characters delimit highlighted words in snippets so that the
+// rest of the snippet can be escaped before they are turned into `<mark>`s.
+const HIGHLIGHT_START: char = '\u{E000}';
+const HIGHLIGHT_STOP: char = '\u{E001}';
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    q: Option<String>,
+    page: Option<i64>,
+}
+
+pub struct SearchResult {
+    pub slug: String,
+    pub title: String,
+    pub snippet: Option<String>,
+    pub published_at: DateTime<Utc>,
+    pub total: i64,
+}
+
+#[tracing::instrument(name = "Search the archive", skip(parameters, pool), fields(q = ?parameters.q))]
+pub async fn archive_search(
+    parameters: Query<Parameters>,
+    pool: Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let q = parameters.q.as_deref().unwrap_or_default().trim();
+    let page_number = parameters.page.unwrap_or(1).clamp(1, MAX_PAGE);
+
+    let form = format!(
+        r#"<form action="/archive/search" method="get"><input type="search" name="q" value="{}"><button type="submit">Search</button></form>"#,
+        escape(q)
+    );
+    if q.is_empty() {
+        return Ok(html(&form));
+    }
+
+    let results = search_issues(q, page_number, &pool).await.map_err(e500)?;
+    let total = results.first().map_or(0, |result| result.total);
+
+    let mut body = form;
+    if results.is_empty() {
+        body.push_str("<p>No issues matched your search.</p>");
+    } else {
+        body.push_str(&format!("<p>{total} matching issues</p><ol>"));
+        for result in &results {
+            body.push_str(&format!(
+                "<li><a href=\"/archive/{}\">{}</a> <time datetime=\"{}\">{}</time>",
+                escape(&result.slug),
+                escape(&result.title),
+                result.published_at.to_rfc3339(),
+                result.published_at.format("%B %-d, %Y"),
+            ));
+            if let Some(snippet) = &result.snippet {
+                body.push_str(&format!("<p>{}</p>", highlight(snippet)));
+            }
+            body.push_str("</li>");
+        }
+        body.push_str("</ol>");
+        body.push_str(&pagination(q, page_number, total));
+    }
+
+    Ok(html(&body))
+}
+
+fn html(body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page("Search the archive", body))
+}
+
+/// Escape a snippet returned by Postgres and mark up its highlighted words
+fn highlight(snippet: &str) -> String {
+    escape(snippet)
+        .replace(HIGHLIGHT_START, "<mark>")
+        .replace(HIGHLIGHT_STOP, "</mark>")
+}
+
+fn pagination(q: &str, page_number: i64, total: i64) -> String {
+    let link = |page_number: i64, label: &str| {
+        format!(
+            "<a href=\"/archive/search?q={}&amp;page={page_number}\">{label}</a>",
+            escape(&urlencoding::encode(q))
+        )
+    };
+    let mut links = Vec::new();
+    if page_number > 1 {
+        links.push(link(page_number - 1, "Newer results"));
+    }
+    if page_number * PAGE_SIZE < total {
+        links.push(link(page_number + 1, "Older results"));
+    }
+    if links.is_empty() {
+        String::new()
+    } else {
+        format!("<nav>{}</nav>", links.join(" "))
+    }
+}
+
+/// Rank published issues matching `q`, written in the web search syntax
+/// (`"quoted phrases"`, `or`, `-excluded`).
+///
+/// Subscribers-only issues are matched on their title alone and come without
+/// a snippet, so that searching does not reveal their content.
+#[tracing::instrument(name = "Search published issues", skip(pool))]
+pub async fn search_issues(
+    q: &str,
+    page_number: i64,
+    pool: &PgPool,
+) -> Result<Vec<SearchResult>, sqlx::Error> {
+    let options = format!(
+        "StartSel={HIGHLIGHT_START}, StopSel={HIGHLIGHT_STOP}, MaxWords=35, MinWords=15, MaxFragments=2"
+    );
+    sqlx::query_as!(
+        SearchResult,
+        r#"
+        SELECT slug, title, published_at AS "published_at!",
+            CASE WHEN subscribers_only THEN NULL
+                ELSE ts_headline('english', content, query, $2)
+            END AS snippet,
+            COUNT(*) OVER () AS "total!"
+        FROM newsletter_issues, websearch_to_tsquery('english', $1) query
+        WHERE published_at IS NOT NULL
+            AND search_vector @@ query
+            AND (NOT subscribers_only OR to_tsvector('english', title) @@ query)
+        ORDER BY ts_rank(search_vector, query) DESC, published_at DESC
+        LIMIT $3 OFFSET $4
+        "#,
+        q,
+        options,
+        PAGE_SIZE,
+        (page_number - 1) * PAGE_SIZE,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
@@ -0,0 +1,89 @@
+use std::time::SystemTime;
+
+use actix_web::{
+    http::header::{
+        CacheControl, CacheDirective, ETag, EntityTag, Header, HttpDate, IfModifiedSince,
+        IfNoneMatch, LastModified,
+    },
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use chrono::{DateTime, Utc};
+
+/// How long shared caches may serve public archive pages without revalidating
+const MAX_AGE_SECONDS: u32 = 300;
+
+/// Validators that let clients and proxies revalidate a cached response
+/// with `If-None-Match` or `If-Modified-Since` instead of downloading it again.
+pub struct Validators {
+    etag: EntityTag,
+    last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// The rendered page also depends on the stylesheet and templates,
+    /// so the entity tag is weak: it only identifies the underlying data.
+    #[must_use]
+    pub fn new(version: &str, last_modified: DateTime<Utc>) -> Self {
+        Self {
+            etag: EntityTag::new_weak(version.to_string()),
+            last_modified,
+        }
+    }
+
+    /// Whether the client already holds the current representation
+    #[must_use]
+    pub fn is_fresh(&self, request: &HttpRequest) -> bool {
+        // `If-None-Match` takes precedence over `If-Modified-Since` (RFC 9110 13.2.2)
+        match IfNoneMatch::parse(request) {
+            Ok(IfNoneMatch::Any) => return true,
+            Ok(IfNoneMatch::Items(tags)) if !tags.is_empty() => {
+                return tags.iter().any(|tag| tag.weak_eq(&self.etag));
+            }
+            _ => {}
+        }
+        IfModifiedSince::parse(request).is_ok_and(|IfModifiedSince(since)| {
+            // HTTP dates have a resolution of one second
+            SystemTime::from(since) >= SystemTime::from(self.http_date())
+        })
+    }
+
+    fn http_date(&self) -> HttpDate {
+        let seconds = DateTime::from_timestamp(self.last_modified.timestamp(), 0)
+            .unwrap_or(self.last_modified);
+        HttpDate::from(SystemTime::from(seconds))
+    }
+}
+
+/// Respond with `304 Not Modified` if the client's copy is still fresh,
+/// otherwise with `response` carrying the validators and caching headers.
+pub fn respond(
+    request: &HttpRequest,
+    validators: Option<&Validators>,
+    response: impl FnOnce(&mut HttpResponseBuilder) -> HttpResponse,
+) -> HttpResponse {
+    let Some(validators) = validators else {
+        return response(HttpResponse::Ok().insert_header(public()));
+    };
+    let is_fresh = validators.is_fresh(request);
+    let mut builder = if is_fresh {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    builder
+        .insert_header(public())
+        .insert_header(ETag(validators.etag.clone()))
+        .insert_header(LastModified(validators.http_date()));
+    if is_fresh {
+        builder.finish()
+    } else {
+        response(&mut builder)
+    }
+}
+
+fn public() -> CacheControl {
+    CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(MAX_AGE_SECONDS),
+    ])
+}
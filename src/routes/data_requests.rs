@@ -0,0 +1,254 @@
+//! Subscribers asking for the data we hold about them, or for its erasure,
+//! through links signed for their email address and sent to it.
+
+use std::net::IpAddr;
+
+use actix_web::{
+    http::header::{self, ContentType},
+    web::{Data, Form, Query},
+    HttpRequest, HttpResponse,
+};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::{
+    data_protection::{
+        email_hash, erase_subject, sign_data_link, subject_access_report, verify_data_link,
+    },
+    domain::SubscriberEmail,
+    email_client::EmailClient,
+    issue_delivery_worker::record_sent_emails,
+    routes::archive::{escape, page},
+    startup::{ApplicationBaseUrl, HmacSecret},
+    utils::{client_ip_address, e500},
+};
+
+/// How long the links sent to a subscriber can be used for
+const DATA_LINK_VALIDITY_HOURS: i64 = 24;
+/// How long data requests count against the limits below
+const DATA_REQUEST_WINDOW_MINUTES: i64 = 60;
+/// Data requests allowed in the window for one address, whatever its case
+const MAX_DATA_REQUESTS_PER_ADDRESS: i64 = 3;
+/// Data requests allowed in the window from one client
+const MAX_DATA_REQUESTS_PER_CLIENT: i64 = 10;
+
+#[derive(Deserialize)]
+pub struct DataRequestForm {
+    email: String,
+}
+
+/// The query string of the signed links, and the erasure form
+#[derive(Deserialize)]
+pub struct SignedLink {
+    email: String,
+    expires_at: i64,
+    signature: String,
+}
+
+/// Email the links to access or erase their data to a subscriber.
+///
+/// Answers the same whether the address is subscribed or not, so as not to
+/// reveal who is, and so counts requests for unknown addresses as well
+/// against the limits per address and per client.
+#[tracing::instrument(
+    name = "Request access to subscriber data",
+    skip(request, form, pool, email_client, base_url, hmac_secret)
+)]
+pub async fn request_data(
+    request: HttpRequest,
+    form: Form<DataRequestForm>,
+    pool: Data<PgPool>,
+    email_client: Data<EmailClient>,
+    base_url: Data<ApplicationBaseUrl>,
+    hmac_secret: Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !record_data_request(&pool, form.email.trim(), client_ip_address(&request))
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::TooManyRequests()
+            .insert_header((
+                header::RETRY_AFTER,
+                (DATA_REQUEST_WINDOW_MINUTES * 60).to_string(),
+            ))
+            .body("Too many data requests, try again later"));
+    }
+
+    let subscriber = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE lower(email) = lower($1)",
+        form.email.trim(),
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .map_err(e500)?;
+
+    if let Some(subscriber) = subscriber {
+        let email = SubscriberEmail::parse(subscriber.email).map_err(e500)?;
+        let expires_at = (Utc::now() + Duration::hours(DATA_LINK_VALIDITY_HOURS)).timestamp();
+        let query = format!(
+            "email={}&expires_at={expires_at}&signature={}",
+            urlencoding::encode(email.as_ref()),
+            sign_data_link(&hmac_secret.0, email.as_ref(), expires_at),
+        );
+        let access_link = format!("{}/subscriptions/data?{query}", base_url.0);
+        let erasure_link = format!("{}/subscriptions/data/erase?{query}", base_url.0);
+
+        let plain_body = format!(
+            "Download the data we hold about you: {access_link}\n\
+            Erase it, unsubscribing you: {erasure_link}\n\
+            The links work for {DATA_LINK_VALIDITY_HOURS} hours."
+        );
+        let html_body = format!(
+            "<a href=\"{access_link}\">Download the data we hold about you</a><br />\
+            <a href=\"{erasure_link}\">Erase it, unsubscribing you</a><br />\
+            The links work for {DATA_LINK_VALIDITY_HOURS} hours."
+        );
+        email_client
+            .send_email(email, "Your data", &html_body, &plain_body)
+            .await
+            .map_err(e500)?;
+        let _ = record_sent_emails(pool.get_ref(), 1).await;
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page(
+            "Your data",
+            "<p>If the address is subscribed, it will get an email with links to your data shortly.</p>",
+        )))
+}
+
+/// Count a data request for `email` from `ip_address`, unless either already
+/// made as many as allowed in the window, returning whether it was counted
+#[tracing::instrument(name = "Limit data requests", skip(pool, email))]
+async fn record_data_request(
+    pool: &PgPool,
+    email: &str,
+    ip_address: Option<IpAddr>,
+) -> Result<bool, sqlx::Error> {
+    let email_hash = email_hash(email);
+    let ip_address = ip_address.map(|ip_address| ip_address.to_string());
+    let window_start = Utc::now() - Duration::minutes(DATA_REQUEST_WINDOW_MINUTES);
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        "DELETE FROM data_requests WHERE requested_at < $1",
+        window_start,
+    )
+    .execute(&mut transaction)
+    .await?;
+    let counts = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE email_hash = $1) AS "for_address!",
+            COUNT(*) FILTER (WHERE ip_address = $2::text::inet) AS "from_client!"
+        FROM data_requests
+        WHERE requested_at >= $3
+        "#,
+        email_hash,
+        ip_address,
+        window_start,
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    if counts.for_address >= MAX_DATA_REQUESTS_PER_ADDRESS
+        || counts.from_client >= MAX_DATA_REQUESTS_PER_CLIENT
+    {
+        tracing::warn!("Too many data requests");
+        return Ok(false);
+    }
+    sqlx::query!(
+        "INSERT INTO data_requests (email_hash, ip_address) VALUES ($1, $2::text::inet)",
+        email_hash,
+        ip_address,
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// Everything held about the subscriber, as JSON
+#[tracing::instrument(name = "Export subscriber data", skip(link, pool, hmac_secret))]
+pub async fn data(
+    link: Query<SignedLink>,
+    pool: Data<PgPool>,
+    hmac_secret: Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !verify_data_link(
+        &hmac_secret.0,
+        &link.email,
+        link.expires_at,
+        &link.signature,
+    ) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let report = subject_access_report(&pool, &link.email)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"your-data.json\"",
+        ))
+        .json(report))
+}
+
+/// Ask the subscriber to confirm the erasure, links being followed by mail
+/// clients and scanners alone
+#[tracing::instrument(
+    name = "Confirm the erasure of subscriber data",
+    skip(link, hmac_secret)
+)]
+pub async fn erasure_form(link: Query<SignedLink>, hmac_secret: Data<HmacSecret>) -> HttpResponse {
+    if !verify_data_link(
+        &hmac_secret.0,
+        &link.email,
+        link.expires_at,
+        &link.signature,
+    ) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let body = format!(
+        r#"<p>Erase everything we hold about {email}? You will be unsubscribed.</p>
+<form action="/subscriptions/data/erase" method="post">
+<input type="hidden" name="email" value="{email}">
+<input type="hidden" name="expires_at" value="{}">
+<input type="hidden" name="signature" value="{}">
+<button type="submit">Erase my data</button>
+</form>"#,
+        link.expires_at,
+        escape(&link.signature),
+        email = escape(&link.email),
+    );
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page("Erase your data", &body))
+}
+
+#[tracing::instrument(name = "Erase subscriber data", skip(form, pool, hmac_secret))]
+pub async fn erase(
+    form: Form<SignedLink>,
+    pool: Data<PgPool>,
+    hmac_secret: Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !verify_data_link(
+        &hmac_secret.0,
+        &form.email,
+        form.expires_at,
+        &form.signature,
+    ) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    erase_subject(&mut transaction, &form.email)
+        .await
+        .map_err(e500)?;
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page(
+            "Your data was erased",
+            "<p>Everything we held about you was erased.</p>",
+        )))
+}
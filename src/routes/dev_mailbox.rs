@@ -0,0 +1,118 @@
+use actix_web::{
+    http::header::ContentType,
+    web::{Data, Path},
+    HttpResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    email_client::{CapturedEmail, EmailClient},
+    routes::archive::{escape, page},
+    utils::e500,
+};
+
+const NOT_CAPTURING: &str =
+    "<p>Emails are being delivered. Set <code>email_client.backend</code> to <code>capture</code> to read them here instead.</p>";
+
+/// List the emails captured by the `capture` backend, only mounted in development
+#[tracing::instrument(name = "List captured emails", skip(email_client))]
+pub async fn mailbox(email_client: Data<EmailClient>) -> Result<HttpResponse, actix_web::Error> {
+    let Some(capture) = email_client.capture() else {
+        return Ok(html("Mailbox", NOT_CAPTURING));
+    };
+    let emails = capture.list().map_err(e500)?;
+
+    let body = if emails.is_empty() {
+        "<h1>Mailbox</h1><p>No emails have been sent yet.</p>".to_string()
+    } else {
+        let rows: String = emails
+            .iter()
+            .map(|email| {
+                format!(
+                    "<tr><td><time datetime=\"{}\">{}</time></td><td>{}</td><td><a href=\"/dev/mailbox/{}\">{}</a></td></tr>",
+                    email.captured_at.to_rfc3339(),
+                    email.captured_at.format("%Y-%m-%d %H:%M:%S"),
+                    escape(&email.to),
+                    email.id,
+                    escape(&email.subject),
+                )
+            })
+            .collect();
+        format!(
+            "<h1>Mailbox</h1><table><thead><tr><th>Sent</th><th>To</th><th>Subject</th></tr></thead><tbody>{rows}</tbody></table>"
+        )
+    };
+    Ok(html("Mailbox", &body))
+}
+
+#[tracing::instrument(name = "Show a captured email", skip(email_client))]
+pub async fn mailbox_email(
+    id: Path<Uuid>,
+    email_client: Data<EmailClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(email) = get_captured_email(*id, &email_client)? else {
+        return Ok(not_found());
+    };
+
+    let links: String = email
+        .links()
+        .into_iter()
+        .map(|link| format!("<li><a href=\"{0}\">{0}</a></li>", escape(link)))
+        .collect();
+    let body = format!(
+        r#"<p><a href="/dev/mailbox">Mailbox</a></p>
+<h1>{subject}</h1>
+<dl><dt>From</dt><dd>{from}</dd><dt>To</dt><dd>{to}</dd><dt>Sent</dt><dd>{captured_at}</dd></dl>
+<h2>Links</h2><ul>{links}</ul>
+<h2>HTML</h2><iframe sandbox="allow-popups allow-top-navigation-by-user-activation" srcdoc="{html_content}" style="width: 100%; height: 30em"></iframe>
+<h2>Plain text</h2><pre>{text_content}</pre>
+<p><a href="/dev/mailbox/{id}/raw">Download as .eml</a></p>"#,
+        subject = escape(&email.subject),
+        from = escape(&email.from),
+        to = escape(&email.to),
+        captured_at = email.captured_at.to_rfc3339(),
+        html_content = escape(&email.html_content),
+        text_content = escape(&email.text_content),
+        id = email.id,
+    );
+    Ok(html(&email.subject, &body))
+}
+
+#[tracing::instrument(name = "Download a captured email", skip(email_client))]
+pub async fn mailbox_email_raw(
+    id: Path<Uuid>,
+    email_client: Data<EmailClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(email) = get_captured_email(*id, &email_client)? else {
+        return Ok(not_found());
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("message/rfc822")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.eml\"", email.id),
+        ))
+        .body(email.to_eml()))
+}
+
+fn get_captured_email(
+    id: Uuid,
+    email_client: &EmailClient,
+) -> Result<Option<CapturedEmail>, actix_web::Error> {
+    match email_client.capture() {
+        Some(capture) => capture.get(id).map_err(e500),
+        None => Ok(None),
+    }
+}
+
+fn html(title: &str, body: &str) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page(title, body))
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound()
+        .content_type(ContentType::html())
+        .body(page("Not found", "<p>There is no such email.</p>"))
+}
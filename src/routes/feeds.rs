@@ -0,0 +1,158 @@
+use actix_web::{web::Data, HttpRequest, HttpResponse};
+use atom_syndication::{Content, Entry, Feed, Link, Text};
+use rss::{Channel, Guid, Item};
+use sqlx::PgPool;
+
+use crate::{
+    configuration::NewsletterSettings,
+    email_content::MarkdownRenderer,
+    routes::{
+        archive::{get_published_issues, listing_validators, PublishedIssue},
+        caching,
+    },
+    startup::ApplicationBaseUrl,
+    utils::e500,
+};
+
+/// Feeds only carry the most recent issues
+const FEED_LENGTH: usize = 20;
+
+const SUBSCRIBERS_ONLY_SUMMARY: &str = "This issue is only available to subscribers.";
+
+#[tracing::instrument(
+    name = "Serve the RSS feed",
+    skip(request, pool, renderer, newsletter, base_url)
+)]
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: Data<PgPool>,
+    renderer: Data<MarkdownRenderer>,
+    newsletter: Data<NewsletterSettings>,
+    base_url: Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut issues = get_published_issues(&pool).await.map_err(e500)?;
+    issues.truncate(FEED_LENGTH);
+
+    Ok(caching::respond(
+        &request,
+        listing_validators(&issues).as_ref(),
+        |response| {
+            let items = issues
+                .iter()
+                .map(|issue| rss_item(issue, &renderer, &base_url.0))
+                .collect();
+            let channel = Channel {
+                title: newsletter.title.clone(),
+                link: format!("{}/archive", base_url.0),
+                description: newsletter.description.clone(),
+                last_build_date: issues
+                    .iter()
+                    .map(|issue| issue.updated_at)
+                    .max()
+                    .map(|updated_at| updated_at.to_rfc2822()),
+                items,
+                ..Channel::default()
+            };
+            response
+                .content_type("application/rss+xml; charset=utf-8")
+                .body(channel.to_string())
+        },
+    ))
+}
+
+#[tracing::instrument(
+    name = "Serve the Atom feed",
+    skip(request, pool, renderer, newsletter, base_url)
+)]
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: Data<PgPool>,
+    renderer: Data<MarkdownRenderer>,
+    newsletter: Data<NewsletterSettings>,
+    base_url: Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut issues = get_published_issues(&pool).await.map_err(e500)?;
+    issues.truncate(FEED_LENGTH);
+
+    Ok(caching::respond(
+        &request,
+        listing_validators(&issues).as_ref(),
+        |response| {
+            let feed = Feed {
+                title: Text::plain(newsletter.title.clone()),
+                id: format!("{}/feed.atom", base_url.0),
+                updated: issues
+                    .iter()
+                    .map(|issue| issue.updated_at)
+                    .max()
+                    .unwrap_or_default()
+                    .into(),
+                subtitle: Some(Text::plain(newsletter.description.clone())),
+                links: vec![
+                    link(format!("{}/archive", base_url.0), "alternate"),
+                    link(format!("{}/feed.atom", base_url.0), "self"),
+                ],
+                entries: issues
+                    .iter()
+                    .map(|issue| atom_entry(issue, &renderer, &base_url.0))
+                    .collect(),
+                ..Feed::default()
+            };
+            response
+                .content_type("application/atom+xml; charset=utf-8")
+                .body(feed.to_string())
+        },
+    ))
+}
+
+fn rss_item(issue: &PublishedIssue, renderer: &MarkdownRenderer, base_url: &str) -> Item {
+    let url = format!("{base_url}/archive/{}", issue.slug);
+    let description = if issue.subscribers_only {
+        SUBSCRIBERS_ONLY_SUMMARY.to_string()
+    } else {
+        renderer.render_fragment(&issue.content)
+    };
+    Item {
+        title: Some(issue.title.clone()),
+        link: Some(url),
+        description: Some(description),
+        guid: Some(Guid {
+            value: format!("urn:uuid:{}", issue.id),
+            permalink: false,
+        }),
+        pub_date: Some(issue.published_at.to_rfc2822()),
+        ..Item::default()
+    }
+}
+
+fn atom_entry(issue: &PublishedIssue, renderer: &MarkdownRenderer, base_url: &str) -> Entry {
+    let url = format!("{base_url}/archive/{}", issue.slug);
+    let (summary, content) = if issue.subscribers_only {
+        (Some(Text::plain(SUBSCRIBERS_ONLY_SUMMARY)), None)
+    } else {
+        let content = Content {
+            value: Some(renderer.render_fragment(&issue.content)),
+            content_type: Some("html".to_string()),
+            ..Content::default()
+        };
+        (None, Some(content))
+    };
+    Entry {
+        title: Text::plain(issue.title.clone()),
+        id: format!("urn:uuid:{}", issue.id),
+        updated: issue.updated_at.into(),
+        published: Some(issue.published_at.into()),
+        links: vec![link(url, "alternate")],
+        summary,
+        content,
+        ..Entry::default()
+    }
+}
+
+fn link(href: String, rel: &str) -> Link {
+    Link {
+        href,
+        rel: rel.to_string(),
+        ..Link::default()
+    }
+}
@@ -1,5 +1,40 @@
-use actix_web::HttpResponse;
+use actix_web::{web::Data, HttpResponse};
+use serde::Serialize;
 
-pub async fn health() -> HttpResponse {
-    HttpResponse::Ok().finish()
+use crate::email_client::{CircuitState, EmailClient};
+
+#[derive(Serialize)]
+struct Health<'a> {
+    /// `degraded` when emails cannot be sent through every provider
+    status: &'static str,
+    email_providers: Vec<EmailProviderHealth<'a>>,
+}
+
+#[derive(Serialize)]
+struct EmailProviderHealth<'a> {
+    name: &'a str,
+    circuit_breaker: CircuitState,
+}
+
+pub async fn health(email_client: Data<EmailClient>) -> HttpResponse {
+    let email_providers: Vec<EmailProviderHealth> = email_client
+        .providers()
+        .iter()
+        .map(|provider| EmailProviderHealth {
+            name: provider.name(),
+            circuit_breaker: provider.circuit_state(),
+        })
+        .collect();
+    let status = if email_providers
+        .iter()
+        .all(|provider| provider.circuit_breaker == CircuitState::Closed)
+    {
+        "ok"
+    } else {
+        "degraded"
+    };
+    HttpResponse::Ok().json(Health {
+        status,
+        email_providers,
+    })
 }
@@ -1,12 +1,12 @@
 use std::{
-    any::Any,
     error::Error,
     fmt::{Debug, Display, Formatter},
 };
 
 use actix_web::{
+    http::header,
     web::{Data, Form},
-    HttpResponse, ResponseError,
+    HttpRequest, HttpResponse, ResponseError,
 };
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
@@ -16,15 +16,23 @@ use sqlx::{query, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
+    consent::{record_consent, Consent, ConsentEvent},
     domain::{NewSubscriber, SubscriberEmail, SubscriberName},
-    email_client::EmailClient,
+    email_client::{EmailClient, EmailClientError, SentEmail},
+    issue_delivery_worker::record_sent_emails,
     startup::ApplicationBaseUrl,
+    utils::error_chain_fmt,
 };
 
 #[derive(Deserialize)]
 pub struct FormData {
     email: String,
     name: String,
+    /// The form or page the subscriber signed up from, the referring page
+    /// when unset
+    source: Option<String>,
+    /// The version of the consent text shown along the form
+    consent_text_version: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -54,16 +62,6 @@ impl Error for StoreTokenError {
     }
 }
 
-fn error_chain_fmt(e: &impl Error, f: &mut Formatter<'_>) -> std::fmt::Result {
-    writeln!(f, "{e}\n")?;
-    let mut current = e.source();
-    while let Some(cause) = current {
-        writeln!(f, "Caused by:\n\t{cause}")?;
-        current = cause.source();
-    }
-    Ok(())
-}
-
 impl Debug for StoreTokenError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -75,7 +73,7 @@ impl Debug for StoreTokenError {
 /// When the database cannot be found or connected to
 #[tracing::instrument(
     name = "Adding new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(form, request, pool, email_client, base_url),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
@@ -83,21 +81,41 @@ impl Debug for StoreTokenError {
 )]
 pub async fn subscribe(
     form: Form<FormData>,
+    request: HttpRequest,
     pool: Data<PgPool>,
     email_client: Data<EmailClient>,
     base_url: Data<ApplicationBaseUrl>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let subscriber = form.0.try_into()?;
+    let mut form = form.0;
+    let consent = Consent {
+        source: form.source.take().or_else(|| {
+            request
+                .headers()
+                .get(header::REFERER)
+                .and_then(|referer| referer.to_str().ok())
+                .map(ToString::to_string)
+        }),
+        consent_text_version: form.consent_text_version.take(),
+        ..Consent::from_request(&request)
+    };
+    let subscriber = form.try_into()?;
     let mut transaction = pool.begin().await?;
 
     let subscriber_id = insert_subscriber(&subscriber, &mut transaction).await?;
+    record_consent(
+        &mut transaction,
+        subscriber_id,
+        ConsentEvent::Subscribed,
+        &consent,
+    )
+    .await?;
 
     let subscription_token = generate_subscription_token();
     store_token(subscriber_id, &subscription_token, &mut transaction).await?;
 
     transaction.commit().await?;
 
-    send_confirmation_email(
+    let sent_email = send_confirmation_email(
         &email_client,
         subscriber,
         base_url.0.to_string(),
@@ -105,6 +123,13 @@ pub async fn subscribe(
     )
     .await?;
 
+    // The email is on its way, failing to keep track of it is not worth
+    // failing the request over.
+    let _ = record_sent_emails(pool.get_ref(), 1).await;
+    if let Some(message_id) = sent_email.message_id {
+        let _ = store_message_id(&subscription_token, &message_id, &pool).await;
+    }
+
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -146,7 +171,7 @@ pub async fn send_confirmation_email(
     subscriber: NewSubscriber,
     base_url: String,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<SentEmail, EmailClientError> {
     let confirmation_link =
         format!("{base_url}/subscriptions/confirm?subscription_token={subscription_token}");
 
@@ -165,7 +190,28 @@ pub async fn send_confirmation_email(
         .await
 }
 
-fn generate_subscription_token() -> String {
+/// Remember the id of the confirmation email sent for `subscription_token`
+#[tracing::instrument(name = "Store the message id of a confirmation email", skip(pool))]
+pub async fn store_message_id(
+    subscription_token: &str,
+    message_id: &str,
+    pool: &PgPool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE subscription_tokens SET message_id = $2 WHERE subscription_token = $1",
+        subscription_token,
+        message_id,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -201,7 +247,7 @@ pub enum SubscribeError {
     ValidationError(String),
     DatabaseError(sqlx::Error),
     StoreTokenError(StoreTokenError),
-    SendEmailError(reqwest::Error),
+    SendEmailError(EmailClientError),
 }
 
 impl Debug for SubscribeError {
@@ -237,8 +283,8 @@ impl Error for SubscribeError {
     }
 }
 
-impl From<reqwest::Error> for SubscribeError {
-    fn from(e: reqwest::Error) -> Self {
+impl From<EmailClientError> for SubscribeError {
+    fn from(e: EmailClientError) -> Self {
         Self::SendEmailError(e)
     }
 }
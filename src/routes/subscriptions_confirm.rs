@@ -1,23 +1,36 @@
 use actix_web::{
     web::{Data, Query},
-    HttpResponse,
+    HttpRequest, HttpResponse,
 };
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::{
+    consent::{record_consent, Consent, ConsentEvent},
+    data_protection::remove_tombstone,
+};
+
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters))]
-pub async fn confirm(parameters: Query<Parameters>, pool: Data<PgPool>) -> HttpResponse {
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, request))]
+pub async fn confirm(
+    parameters: Query<Parameters>,
+    request: HttpRequest,
+    pool: Data<PgPool>,
+) -> HttpResponse {
     let Ok(id) = get_subscriber_id_from_token(&parameters.subscription_token, &pool).await else {
         return HttpResponse::InternalServerError().finish();
     };
 
     if let Some(subscriber_id) = id {
-        if confirm_subscriber(subscriber_id, &pool).await.is_err() {
+        let consent = Consent::from_request(&request);
+        if confirm_subscriber(subscriber_id, &consent, &pool)
+            .await
+            .is_err()
+        {
             return HttpResponse::InternalServerError().finish();
         }
         HttpResponse::Ok().finish()
@@ -26,19 +39,45 @@ pub async fn confirm(parameters: Query<Parameters>, pool: Data<PgPool>) -> HttpR
     }
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(subscriber_id: Uuid, pool: &PgPool) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+/// Mark a pending subscriber as confirmed, recording their consent and lifting
+/// the tombstone left if they had their data erased before, since they opted
+/// in again. Links clicked again change nothing, so that subscribers who
+/// unsubscribed since stay unsubscribed.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, consent, pool)
+)]
+pub async fn confirm_subscriber(
+    subscriber_id: Uuid,
+    consent: &Consent,
+    pool: &PgPool,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let confirmed = sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        RETURNING email
+        "#,
         subscriber_id,
     )
-    .execute(pool)
+    .fetch_optional(&mut transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
         e
     })?;
-    Ok(())
+    if let Some(confirmed) = confirmed {
+        record_consent(
+            &mut transaction,
+            subscriber_id,
+            ConsentEvent::Confirmed,
+            consent,
+        )
+        .await?;
+        remove_tombstone(&mut transaction, &confirmed.email).await?;
+    }
+    transaction.commit().await
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
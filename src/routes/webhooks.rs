@@ -0,0 +1,141 @@
+use actix_web::{
+    http::header::{self, HeaderMap},
+    web::{Data, Json},
+    HttpRequest, HttpResponse,
+};
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{authentication::basic_authentication, startup::WebhookToken, utils::e500};
+
+/// An event of the SendGrid Event Webhook, of which only the fields we keep
+/// are modelled. The custom arguments of the email are top-level fields.
+#[derive(Deserialize, Debug)]
+pub struct SendgridEvent {
+    event: String,
+    email: String,
+    timestamp: i64,
+    sg_event_id: Option<String>,
+    /// The `X-Message-Id` of the request, followed by `.` and an id of the
+    /// message sent to this recipient
+    sg_message_id: Option<String>,
+    url: Option<String>,
+    /// [`SUBSCRIBER_ID_ARG`](crate::issue_delivery_worker::SUBSCRIBER_ID_ARG)
+    subscriber_id: Option<String>,
+    /// [`NEWSLETTER_ISSUE_ID_ARG`](crate::issue_delivery_worker::NEWSLETTER_ISSUE_ID_ARG)
+    newsletter_issue_id: Option<String>,
+}
+
+/// Record the events SendGrid reports about the emails we sent, e.g. bounces,
+/// opens and clicks.
+#[tracing::instrument(
+    name = "Record SendGrid events",
+    skip_all,
+    fields(events = events.len())
+)]
+pub async fn sendgrid_events(
+    request: HttpRequest,
+    events: Json<Vec<SendgridEvent>>,
+    pool: Data<PgPool>,
+    webhook_token: Data<WebhookToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !presented_token(request.headers()).is_some_and(|token| {
+        constant_time_eq(token.as_bytes(), webhook_token.0.expose_secret().as_bytes())
+    }) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let mut transaction = pool.begin().await.map_err(e500)?;
+    for event in events.into_inner() {
+        let occurred_at =
+            DateTime::<Utc>::from_timestamp(event.timestamp, 0).unwrap_or_else(Utc::now);
+        let message_id = event
+            .sg_message_id
+            .as_deref()
+            .and_then(|id| id.split('.').next());
+        let custom_arg = |value: Option<&str>| value.and_then(|id| id.parse::<Uuid>().ok());
+        sqlx::query!(
+            r#"
+            INSERT INTO email_events (id, provider_event_id, event, email, message_id,
+                newsletter_issue_id, subscriber_id, url, occurred_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (provider_event_id) DO NOTHING
+            "#,
+            Uuid::new_v4(),
+            event.sg_event_id,
+            event.event,
+            event.email,
+            message_id,
+            custom_arg(event.newsletter_issue_id.as_deref()),
+            custom_arg(event.subscriber_id.as_deref()),
+            event.url,
+            occurred_at,
+        )
+        .execute(&mut transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e500(e)
+        })?;
+        if event.event == "unsubscribe" || event.event == "group_unsubscribe" {
+            unsubscribe(
+                &mut transaction,
+                custom_arg(event.subscriber_id.as_deref()),
+                &event.email,
+            )
+            .await
+            .map_err(e500)?;
+        }
+    }
+    transaction.commit().await.map_err(e500)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Stop sending to whoever unsubscribed through SendGrid, identified by the
+/// custom arguments of the email or, e.g. for confirmation emails, by address
+async fn unsubscribe(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Option<Uuid>,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed'
+        WHERE (id = $1 OR ($1 IS NULL AND lower(email) = lower($2)))
+            AND status <> 'unsubscribed'
+        "#,
+        subscriber_id,
+        email,
+    )
+    .execute(transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+/// The webhook token of an `Authorization` header, either as a bearer token
+/// or as the password of basic credentials, which SendGrid sends when they
+/// are part of the webhook URL, e.g. `https://sendgrid:<token>@host/...`.
+fn presented_token(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match bearer {
+        Some(token) => Some(token.to_string()),
+        None => basic_authentication(headers)
+            .ok()
+            .map(|credentials| credentials.password.expose_secret().to_string()),
+    }
+}
+
+/// Compare secrets in a time that does not depend on where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
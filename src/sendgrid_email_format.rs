@@ -1,3 +1,6 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
 use serde::{Deserialize, Serialize};
 
 // {
@@ -5,56 +8,408 @@ use serde::{Deserialize, Serialize};
 //     {
 //       "to": [
 //         {
-//           "email": "to@email.com"
+//           "email": "to@email.com",
+//           "name": "Recipient name"
 //         }
-//       ]
+//       ],
+//       "cc": [],
+//       "bcc": [],
+//       "subject": "Per recipient subject line",
+//       "headers": { "X-Header": "value" },
+//       "substitutions": {
+//         "{{name}}": "Recipient name"
+//       },
+//       "custom_args": { "subscriber_id": "..." },
+//       "send_at": 1617260400
 //     }
 //   ],
 //   "from": {
 //     "email": "from@email.com"
 //   },
+//   "reply_to": {
+//     "email": "reply@email.com"
+//   },
 //   "subject": "Subject line",
 //   "content": [
 //     {
 //       "type": "text/plain",
 //       "value": "Content line"
 //     }
-//   ]
+//   ],
+//   "attachments": [
+//     {
+//       "content": "<base64>",
+//       "type": "text/plain",
+//       "filename": "file.txt",
+//       "disposition": "attachment"
+//     }
+//   ],
+//   "headers": { "X-Header": "value" },
+//   "categories": ["newsletter"],
+//   "custom_args": { "newsletter_issue_id": "..." },
+//   "send_at": 1617260400,
+//   "asm": {
+//     "group_id": 12345,
+//     "groups_to_display": [12345]
+//   },
+//   "mail_settings": {
+//     "sandbox_mode": { "enable": true }
+//   }
 // }
+//
+// Optional fields are left out of the payload when they are empty.
+// https://docs.sendgrid.com/api-reference/mail-send/mail-send
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "snake_case")]
 pub struct SendgridEmailFormat<'a> {
+    #[serde(borrow = "'a")]
     pub personalizations: Vec<PersonalizationField<'a>>,
     pub from: FromField<'a>,
-    pub subject: &'a str,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<ReplyToField<'a>>,
+    #[serde(borrow)]
+    pub subject: Cow<'a, str>,
     pub content: Vec<ContentField<'a>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<AttachmentField<'a>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<Cow<'a, str>>,
+    /// Echoed back in webhook events, for every personalization
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom_args: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+    /// Unix timestamp of when the email should be sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asm: Option<AsmField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mail_settings: Option<MailSettingsField>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "snake_case")]
 pub struct PersonalizationField<'a> {
     // https://serde.rs/lifetimes.html#borrowing-data-in-a-derived-impl
     #[serde(borrow = "'a")]
     pub to: Vec<ToField<'a>>,
+    #[serde(borrow = "'a", default, skip_serializing_if = "Vec::is_empty")]
+    pub cc: Vec<ToField<'a>>,
+    #[serde(borrow = "'a", default, skip_serializing_if = "Vec::is_empty")]
+    pub bcc: Vec<ToField<'a>>,
+    /// Overrides the subject of the email for the recipients of this personalization
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<Cow<'a, str>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+    /// Replaced in the subject and content for the recipients of this personalization
+    #[serde(borrow = "'a", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub substitutions: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+    /// Echoed back in webhook events for the recipients of this personalization
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom_args: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_at: Option<i64>,
 }
 
+/// An address in `to`, `cc` or `bcc`
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "snake_case")]
 pub struct ToField<'a> {
-    pub email: &'a str,
+    #[serde(borrow)]
+    pub email: Cow<'a, str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<Cow<'a, str>>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "snake_case")]
 pub struct FromField<'a> {
-    pub email: &'a str,
+    #[serde(borrow)]
+    pub email: Cow<'a, str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<Cow<'a, str>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ReplyToField<'a> {
+    #[serde(borrow)]
+    pub email: Cow<'a, str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<Cow<'a, str>>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "snake_case")]
 pub struct ContentField<'a> {
-    #[serde(rename = "type")]
-    pub type_field: &'a str,
-    pub value: &'a str,
+    #[serde(borrow, rename = "type")]
+    pub type_field: Cow<'a, str>,
+    #[serde(borrow)]
+    pub value: Cow<'a, str>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct AttachmentField<'a> {
+    /// The attached file, Base64 encoded (see [`encode_attachment`])
+    #[serde(borrow)]
+    pub content: Cow<'a, str>,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub type_field: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub filename: Cow<'a, str>,
+    /// Either `attachment` (the default) or `inline`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disposition: Option<Cow<'a, str>>,
+    /// Referenced as `cid:<content_id>` by inline attachments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_id: Option<Cow<'a, str>>,
+}
+
+/// The unsubscribe group the email belongs to
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct AsmField {
+    pub group_id: i64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups_to_display: Vec<i64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct MailSettingsField {
+    /// Validates the request without delivering the email
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox_mode: Option<EnableField>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct EnableField {
+    pub enable: bool,
+}
+
+/// Encode the bytes of a file for [`AttachmentField::content`]
+#[must_use]
+pub fn encode_attachment(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use serde_json::Value;
+
+    use super::{encode_attachment, SendgridEmailFormat};
+
+    /// Parse `payload` and serialize it back, which should give it unchanged
+    /// but for the `unmodeled` fields (as JSON pointers).
+    fn assert_round_trip(payload: &str, unmodeled: &[&str]) {
+        let mut expected: Value = serde_json::from_str(payload).unwrap();
+        for pointer in unmodeled {
+            let (parent, key) = pointer.rsplit_once('/').unwrap();
+            expected
+                .pointer_mut(parent)
+                .and_then(Value::as_object_mut)
+                .and_then(|parent| parent.remove(key))
+                .unwrap_or_else(|| panic!("{pointer} is not in the payload"));
+        }
+        let email: SendgridEmailFormat = serde_json::from_str(payload).unwrap();
+        let actual = serde_json::to_value(&email).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    // The request example of the Mail Send API reference, as published
+    #[test]
+    fn documented_mail_send_example_round_trips() {
+        assert_round_trip(
+            r#"{
+  "personalizations": [
+    {
+      "to": [
+        {
+          "email": "alex@example.com",
+          "name": "Alex"
+        },
+        {
+          "email": "bola@example.com",
+          "name": "Bola"
+        }
+      ],
+      "cc": [
+        {
+          "email": "charlie@example.com",
+          "name": "Charlie"
+        }
+      ],
+      "bcc": [
+        {
+          "email": "dana@example.com",
+          "name": "Dana"
+        }
+      ]
+    },
+    {
+      "from": {
+        "email": "sales@example.com",
+        "name": "Example Sales Team"
+      },
+      "to": [
+        {
+          "email": "ira@example.com",
+          "name": "Ira"
+        }
+      ],
+      "bcc": [
+        {
+          "email": "lee@example.com",
+          "name": "Lee"
+        }
+      ]
+    }
+  ],
+  "from": {
+    "email": "orders@example.com",
+    "name": "Example Order Confirmation"
+  },
+  "reply_to": {
+    "email": "customer_service@example.com",
+    "name": "Example Customer Service Team"
+  },
+  "subject": "Your Example Order Confirmation",
+  "content": [
+    {
+      "type": "text/html",
+      "value": "<p>Hello from Twilio SendGrid!</p><p>Sending with the email service trusted by developers and marketers for <strong>time-savings</strong>, <strong>scalability</strong>, and <strong>delivery expertise</strong>.</p><p>%open-track%</p>"
+    }
+  ],
+  "attachments": [
+    {
+      "content": "PCFET0NUWVBFIGh0bWw+CiAgICA8aHRtbCBsYW5nPSJlbiI+CgogICAgPGhlYWQ+CiAgICAgICAgPG1ldGEgY2hhcnNldD0iVVRGLTgiPgogICAgICAgIDxtZXRhIGh0dHAtZXF1aXY9IlgtVUEtQ29tcGF0aWJsZSIgY29udGVudD0iSUU9ZWRnZSI+CiAgICAgICAgPG1ldGEgbmFtZT0idmlld3BvcnQiIGNvbnRlbnQ9IndpZHRoPWRldmljZS13aWR0aCwgaW5pdGlhbC1zY2FsZT0xLjAiPgogICAgICAgIDx0aXRsZT5Eb2N1bWVudDwvdGl0bGU+CiAgICA8L2hlYWQ+CgogICAgPGJvZHk+CgogICAgPC9ib2R5PgoKICAgIDwvaHRtbD4K",
+      "filename": "index.html",
+      "type": "text/html",
+      "disposition": "attachment"
+    }
+  ],
+  "categories": [
+    "cake",
+    "pie",
+    "baking"
+  ],
+  "send_at": 1617260400,
+  "batch_id": "AsdFgHjklQweRTYuIopzXcVBNm0aSDfGHjklmZcVbNMqWert1znmOP2asDFjkl",
+  "asm": {
+    "group_id": 12345,
+    "groups_to_display": [
+      12345
+    ]
+  },
+  "ip_pool_name": "transactional email",
+  "mail_settings": {
+    "bypass_list_management": {
+      "enable": false
+    },
+    "footer": {
+      "enable": false
+    },
+    "sandbox_mode": {
+      "enable": false
+    }
+  },
+  "tracking_settings": {
+    "click_tracking": {
+      "enable": true,
+      "enable_text": false
+    },
+    "open_tracking": {
+      "enable": true,
+      "substitution_tag": "%open-track%"
+    },
+    "subscription_tracking": {
+      "enable": false
+    }
+  }
+}"#,
+            &[
+                "/personalizations/1/from",
+                "/batch_id",
+                "/ip_pool_name",
+                "/mail_settings/bypass_list_management",
+                "/mail_settings/footer",
+                "/tracking_settings",
+            ],
+        );
+    }
+
+    #[test]
+    fn per_recipient_fields_round_trip() {
+        assert_round_trip(
+            r#"{
+              "personalizations": [
+                {
+                  "to": [{ "email": "ursula@example.com", "name": "Ursula" }],
+                  "subject": "Hello, Ursula!",
+                  "headers": { "X-Accept-Language": "en" },
+                  "substitutions": { "%fname%": "Ursula" },
+                  "custom_args": { "subscriber_id": "343" },
+                  "send_at": 1443636843
+                }
+              ],
+              "from": { "email": "newsletter@example.com" },
+              "subject": "Hello!",
+              "content": [{ "type": "text/plain", "value": "Hello, %fname%!" }],
+              "headers": { "X-Newsletter": "zero2prod" },
+              "custom_args": { "newsletter_issue_id": "0f3c6c0e-5a4f-4c2b-9d55-7d1d0a0b4c2e" }
+            }"#,
+            &[],
+        );
+    }
+
+    #[test]
+    fn escaped_strings_are_deserialized() {
+        let email: SendgridEmailFormat = serde_json::from_str(
+            r#"{
+              "personalizations": [
+                {
+                  "to": [{ "email": "zoe@example.com", "name": "Zoé" }],
+                  "substitutions": { "%quote%": "\"Hi\"" }
+                }
+              ],
+              "from": { "email": "newsletter@example.com" },
+              "subject": "Café \"news\"",
+              "content": [{ "type": "text/plain", "value": "Line one\nLine two" }]
+            }"#,
+        )
+        .unwrap();
+
+        let personalization = &email.personalizations[0];
+        assert_eq!(personalization.to[0].name.as_deref(), Some("Zoé"));
+        assert_eq!(personalization.substitutions["%quote%"], "\"Hi\"");
+        assert_eq!(email.subject, "Café \"news\"");
+        assert_eq!(email.content[0].value, "Line one\nLine two");
+        // Strings without escapes are still borrowed from the payload
+        assert!(matches!(email.content[0].type_field, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn empty_optional_fields_are_left_out_of_the_payload() {
+        let email = SendgridEmailFormat {
+            subject: "Subject".into(),
+            ..SendgridEmailFormat::default()
+        };
+
+        let payload = serde_json::to_value(&email).unwrap();
+
+        let keys: Vec<&String> = payload.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["content", "from", "personalizations", "subject"]);
+    }
+
+    #[test]
+    fn attachments_are_base64_encoded() {
+        assert_eq!(encode_attachment(b"Hello, World!"), "SGVsbG8sIFdvcmxkIQ==");
+    }
 }
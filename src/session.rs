@@ -0,0 +1,176 @@
+//! Sessions of admins logged in with `/admin/login`, who then authenticate
+//! with `Authorization: Bearer <session token>` instead of their password.
+//!
+//! The sessions of admins with TOTP enabled start pending, and are only
+//! authenticated once their second factor is verified.
+
+use actix_web::http::header::{self, HeaderMap};
+use chrono::{DateTime, Duration, Utc};
+use rand::{thread_rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::{authentication::AdminUser, authorization::Role};
+
+const SESSION_HOURS: i64 = 12;
+/// How long the second factor can be verified for after the password
+const PENDING_SESSION_MINUTES: i64 = 5;
+
+/// The token of an `Authorization: Bearer` header, if any
+#[must_use]
+pub fn bearer_token(headers: &HeaderMap) -> Option<Secret<String>> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| Secret::new(token.trim().to_string()))
+}
+
+/// The SHA-256 of a session token, as stored in `admin_sessions`
+fn token_hash(token: &Secret<String>) -> String {
+    hex::encode(Sha256::digest(token.expose_secret().as_bytes()))
+}
+
+/// Start a session, pending until the second factor is verified unless
+/// `authenticated`. The token is only ever known to the admin.
+#[tracing::instrument(name = "Start a session", skip(pool))]
+pub async fn start_session(
+    pool: &PgPool,
+    user_id: Uuid,
+    authenticated: bool,
+) -> Result<(Secret<String>, DateTime<Utc>), sqlx::Error> {
+    let mut token = [0u8; 32];
+    thread_rng().fill_bytes(&mut token);
+    let token = Secret::new(hex::encode(token));
+    let expires_at = Utc::now()
+        + if authenticated {
+            Duration::hours(SESSION_HOURS)
+        } else {
+            Duration::minutes(PENDING_SESSION_MINUTES)
+        };
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_sessions (token_hash, user_id, expires_at, authenticated_at)
+        VALUES ($1, $2, $3, CASE WHEN $4 THEN now() END)
+        "#,
+        token_hash(&token),
+        user_id,
+        expires_at,
+        authenticated,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok((token, expires_at))
+}
+
+/// The admin of an authenticated session that has not expired
+#[tracing::instrument(name = "Get the user of a session", skip(pool, token))]
+pub async fn session_user(
+    pool: &PgPool,
+    token: &Secret<String>,
+) -> Result<Option<AdminUser>, sqlx::Error> {
+    let Some(row) = sqlx::query!(
+        r#"
+        SELECT u.user_id, u.role, u.totp_enabled_at IS NOT NULL AS "totp_enabled!",
+            u.totp_required
+        FROM admin_sessions s
+        JOIN users u ON u.user_id = s.user_id
+        WHERE s.token_hash = $1 AND s.authenticated_at IS NOT NULL AND s.expires_at > now()
+        "#,
+        token_hash(token),
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let role = Role::parse(&row.role).map_err(|e| sqlx::Error::Decode(e.into()))?;
+    Ok(Some(AdminUser {
+        user_id: row.user_id,
+        role,
+        totp_enabled: row.totp_enabled,
+        totp_required: row.totp_required,
+        api_key: None,
+    }))
+}
+
+/// The admin of a session waiting for their second factor
+#[tracing::instrument(name = "Get the user of a pending session", skip(pool, token))]
+pub async fn pending_session_user(
+    pool: &PgPool,
+    token: &Secret<String>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let session = sqlx::query!(
+        r#"
+        SELECT user_id FROM admin_sessions
+        WHERE token_hash = $1 AND authenticated_at IS NULL AND expires_at > now()
+        "#,
+        token_hash(token),
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(session.map(|session| session.user_id))
+}
+
+/// Mark a pending session authenticated, once the second factor is verified
+#[tracing::instrument(name = "Authenticate a session", skip(pool, token))]
+pub async fn authenticate_session(
+    pool: &PgPool,
+    token: &Secret<String>,
+) -> Result<DateTime<Utc>, sqlx::Error> {
+    let expires_at = Utc::now() + Duration::hours(SESSION_HOURS);
+    sqlx::query!(
+        r#"
+        UPDATE admin_sessions SET authenticated_at = now(), expires_at = $2
+        WHERE token_hash = $1
+        "#,
+        token_hash(token),
+        expires_at,
+    )
+    .execute(pool)
+    .await?;
+    Ok(expires_at)
+}
+
+#[tracing::instrument(name = "End a session", skip(pool, token))]
+pub async fn end_session(pool: &PgPool, token: &Secret<String>) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM admin_sessions WHERE token_hash = $1",
+        token_hash(token)
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// End the sessions of an admin whose role or second factor changed, but for
+/// the one of `current_token`, with which they made the change themselves
+#[tracing::instrument(
+    name = "End the other sessions of an admin",
+    skip(executor, current_token)
+)]
+pub async fn end_other_sessions(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    current_token: Option<&Secret<String>>,
+) -> Result<(), sqlx::Error> {
+    let ended = sqlx::query!(
+        "DELETE FROM admin_sessions WHERE user_id = $1 AND token_hash IS DISTINCT FROM $2",
+        user_id,
+        current_token.map(token_hash),
+    )
+    .execute(executor)
+    .await?;
+    tracing::info!(
+        ended = ended.rows_affected(),
+        "Ended the other sessions of an admin"
+    );
+    Ok(())
+}
@@ -1,19 +1,54 @@
-use std::{io::Error, net::TcpListener, time::Duration};
+use std::{
+    io::Error,
+    net::{IpAddr, TcpListener},
+    time::Duration,
+};
 
 use actix_web::{
     dev::Server,
     web::{self, Data},
     App, HttpServer,
 };
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use tracing::info;
 use tracing_actix_web::TracingLogger;
 
 use crate::{
-    configuration::{self, DatabaseSettings, Settings},
+    configuration::{self, DatabaseSettings, Environment, NewsletterSettings, Settings},
     email_client::EmailClient,
-    routes::{health::health, subscriptions::subscribe, subscriptions_confirm::confirm},
+    email_content::MarkdownRenderer,
+    oidc::OidcClient,
+    routes::{
+        admin::{
+            analytics::analytics,
+            api_keys::{create_api_key, list_api_keys, revoke_api_key},
+            audit_log::audit_log,
+            data_subjects::{data_subject, erase_data_subject},
+            dead_letters::{list_dead_letters, replay},
+            delivery_reports::{delivery_report, delivery_report_csv, delivery_report_page},
+            issues::{change_issue, draft_issue, list_issues, publish_issue},
+            login::{login, login_totp, logout},
+            oidc::{oidc_callback, oidc_login},
+            subscriber_export::export,
+            subscribers::{
+                add_subscriber, change_subscriber_status, delete_subscriber, get_subscriber,
+                import, list_subscribers,
+            },
+            totp::{confirm_enrollment, disable, enroll},
+            users::{add_user, change_user, delete_user, list_users},
+        },
+        archive::{archive, archive_issue},
+        archive_search::archive_search,
+        data_requests::{data, erase, erasure_form, request_data},
+        dev_mailbox::{mailbox, mailbox_email, mailbox_email_raw},
+        feeds::{atom_feed, rss_feed},
+        health::health,
+        subscriptions::subscribe,
+        subscriptions_confirm::confirm,
+        webhooks::sendgrid_events,
+    },
+    telemetry::RedactedRootSpanBuilder,
 };
 
 #[must_use]
@@ -27,6 +62,7 @@ pub fn get_connection_pool(database: &DatabaseSettings) -> PgPool {
 pub struct Application {
     port: u16,
     server: Server,
+    email_client: EmailClient,
 }
 
 impl Application {
@@ -41,17 +77,8 @@ impl Application {
             .expect("Failed to run database migrations");
 
         // Email client
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Failed to get email");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url.to_string(),
-            sender_email,
-            configuration.email_client.authorization_token.clone(),
-            timeout,
-        );
+        let email_client = configuration.email_client.client();
+        let renderer = email_client.renderer().clone();
 
         // Application
         let address = format!(
@@ -63,11 +90,25 @@ impl Application {
         let server = run(
             listener,
             connection_pool,
-            email_client,
+            email_client.clone(),
+            renderer,
+            configuration.newsletter.clone(),
             configuration.application.base_url.to_string(),
+            configuration.email_client.webhook_token.clone(),
+            configuration.application.hmac_secret.clone(),
+            configuration.application.trusted_proxies.clone(),
+            configuration
+                .oidc
+                .clone()
+                .map(|oidc| OidcClient::new(oidc, &configuration.application.base_url)),
+            configuration.environment,
         )?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            email_client,
+        })
     }
 
     #[must_use]
@@ -75,6 +116,13 @@ impl Application {
         self.port
     }
 
+    /// The email client of the application, to be shared with the background
+    /// worker so that they share circuit breakers and captured emails.
+    #[must_use]
+    pub fn email_client(&self) -> &EmailClient {
+        &self.email_client
+    }
+
     /// A more expressive name that makes it clear that
     /// this function only returns when the application is stopped.
     pub async fn run_until_stopped(self) -> Result<(), Error> {
@@ -84,23 +132,123 @@ impl Application {
 
 pub struct ApplicationBaseUrl(pub String);
 
+/// The shared secret authenticating calls to the SendGrid Event Webhook
+pub struct WebhookToken(pub Secret<String>);
+
+/// The key signing the links subscribers get to their data
+pub struct HmacSecret(pub Secret<String>);
+
+/// The proxies whose `X-Forwarded-For` header is trusted
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     listener: TcpListener,
     connection_pool: PgPool,
     email_client: EmailClient,
+    renderer: MarkdownRenderer,
+    newsletter: NewsletterSettings,
     base_url: String,
+    webhook_token: Secret<String>,
+    hmac_secret: Secret<String>,
+    trusted_proxies: Vec<IpAddr>,
+    oidc: Option<OidcClient>,
+    environment: Environment,
 ) -> std::io::Result<Server> {
     let pool = Data::new(connection_pool);
+    let email_client = Data::new(email_client);
+    let renderer = Data::new(renderer);
+    let newsletter = Data::new(newsletter);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let webhook_token = Data::new(WebhookToken(webhook_token));
+    let hmac_secret = Data::new(HmacSecret(hmac_secret));
+    let trusted_proxies = Data::new(TrustedProxies(trusted_proxies));
+    let oidc = oidc.map(Data::new);
     let server = HttpServer::new(move || {
         App::new()
-            .wrap(TracingLogger::default())
+            .wrap(TracingLogger::<RedactedRootSpanBuilder>::new())
             .route("/health", web::get().to(health))
             .route("/subscriptions", web::post().to(subscribe))
             .route("subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/data_requests", web::post().to(request_data))
+            .route("/subscriptions/data", web::get().to(data))
+            .route("/subscriptions/data/erase", web::get().to(erasure_form))
+            .route("/subscriptions/data/erase", web::post().to(erase))
+            .route("/archive", web::get().to(archive))
+            .route("/archive/search", web::get().to(archive_search))
+            .route("/archive/{slug}", web::get().to(archive_issue))
+            .route("/feed.rss", web::get().to(rss_feed))
+            .route("/feed.atom", web::get().to(atom_feed))
+            .service(
+                web::scope("/admin")
+                    .route("/login", web::post().to(login))
+                    .route("/login/totp", web::post().to(login_totp))
+                    .route("/logout", web::post().to(logout))
+                    .configure(|config| {
+                        if let Some(oidc) = &oidc {
+                            config
+                                .app_data(oidc.clone())
+                                .route("/oidc/login", web::get().to(oidc_login))
+                                .route("/oidc/callback", web::get().to(oidc_callback));
+                        }
+                    })
+                    .route("/analytics", web::get().to(analytics))
+                    .route("/api/api_keys", web::get().to(list_api_keys))
+                    .route("/api/api_keys", web::post().to(create_api_key))
+                    .route("/api/api_keys/{id}", web::delete().to(revoke_api_key))
+                    .route("/api/audit_log", web::get().to(audit_log))
+                    .route("/api/data_subjects", web::get().to(data_subject))
+                    .route(
+                        "/api/data_subjects/erase",
+                        web::post().to(erase_data_subject),
+                    )
+                    .route("/dead_letters", web::get().to(list_dead_letters))
+                    .route("/dead_letters/replay", web::post().to(replay))
+                    .route("/issues/{id}/report", web::get().to(delivery_report_page))
+                    .route("/api/issues", web::get().to(list_issues))
+                    .route("/api/issues", web::post().to(draft_issue))
+                    .route("/api/issues/{id}", web::patch().to(change_issue))
+                    .route("/api/issues/{id}/publish", web::post().to(publish_issue))
+                    .route("/api/issues/{id}/report", web::get().to(delivery_report))
+                    .route(
+                        "/api/issues/{id}/report.csv",
+                        web::get().to(delivery_report_csv),
+                    )
+                    .route("/api/subscribers", web::get().to(list_subscribers))
+                    .route("/api/subscribers", web::post().to(add_subscriber))
+                    .route("/api/subscribers/export", web::get().to(export))
+                    .route("/api/subscribers/import", web::post().to(import))
+                    .route("/api/subscribers/{id}", web::get().to(get_subscriber))
+                    .route(
+                        "/api/subscribers/{id}",
+                        web::patch().to(change_subscriber_status),
+                    )
+                    .route("/api/subscribers/{id}", web::delete().to(delete_subscriber))
+                    .route("/api/totp", web::delete().to(disable))
+                    .route("/api/totp/confirm", web::post().to(confirm_enrollment))
+                    .route("/api/totp/enroll", web::post().to(enroll))
+                    .route("/api/users", web::get().to(list_users))
+                    .route("/api/users", web::post().to(add_user))
+                    .route("/api/users/{id}", web::patch().to(change_user))
+                    .route("/api/users/{id}", web::delete().to(delete_user)),
+            )
+            .route("/webhooks/sendgrid", web::post().to(sendgrid_events))
+            .configure(|config| {
+                if environment == Environment::Development {
+                    config
+                        .route("/dev/mailbox", web::get().to(mailbox))
+                        .route("/dev/mailbox/{id}", web::get().to(mailbox_email))
+                        .route("/dev/mailbox/{id}/raw", web::get().to(mailbox_email_raw));
+                }
+            })
             .app_data(pool.clone())
             .app_data(email_client.clone())
+            .app_data(renderer.clone())
+            .app_data(newsletter.clone())
             .app_data(base_url.clone())
+            .app_data(webhook_token.clone())
+            .app_data(hmac_secret.clone())
+            .app_data(trusted_proxies.clone())
     })
     .listen(listener)?
     .run();
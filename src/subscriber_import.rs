@@ -0,0 +1,751 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    io::{self, Read},
+};
+
+use actix_web::web::Bytes;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit, AuditActor, AuditEntry},
+    data_protection::erased_at,
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionStatus},
+    routes::admin::subscribers::{add_to_lists, insert_subscriber, start_of_day},
+    telemetry::spawn_blocking_with_tracing,
+    utils::error_chain_fmt,
+};
+
+/// Rows inserted per transaction
+const BATCH_SIZE: usize = 500;
+
+/// What happened to each row of an import, and how many rows had each outcome
+#[derive(Serialize, Debug, Default)]
+pub struct ImportReport {
+    pub accepted: u64,
+    pub duplicates: u64,
+    pub rejected: u64,
+    pub rows: Vec<RowReport>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RowReport {
+    /// The line of the CSV the row starts on, the header being line 1
+    pub line: u64,
+    pub email: Option<String>,
+    pub outcome: RowOutcome,
+    /// Why the row was rejected
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RowOutcome {
+    Accepted,
+    /// The email address is already subscribed, or came up earlier in the file
+    Duplicate,
+    Rejected,
+}
+
+pub enum ImportError {
+    /// The header has no column for a required field
+    MissingColumn(&'static str),
+    ReadError(csv::Error),
+    DatabaseError(sqlx::Error),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingColumn(column) => write!(f, "The CSV has no '{column}' column"),
+            Self::ReadError(_) => write!(f, "Failed to read the CSV"),
+            Self::DatabaseError(_) => write!(f, "Failed to store the imported subscribers"),
+        }
+    }
+}
+
+impl Error for ImportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::MissingColumn(_) => None,
+            Self::ReadError(e) => Some(e),
+            Self::DatabaseError(e) => Some(e),
+        }
+    }
+}
+
+impl Debug for ImportError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<sqlx::Error> for ImportError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+/// Where the CSV comes from, deciding which columns are read and how
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    /// `email`, `name` and optionally `lists` columns, imported as confirmed
+    #[default]
+    Generic,
+    /// An audience export, in which members are subscribed, unsubscribed or
+    /// cleaned, the latter having bounced
+    Mailchimp,
+    /// A subscriber export, in which subscribers who turned emails off are
+    /// unsubscribed
+    Substack,
+}
+
+impl ImportFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "generic" => Ok(Self::Generic),
+            "mailchimp" => Ok(Self::Mailchimp),
+            "substack" => Ok(Self::Substack),
+            other => Err(format!("{other} is not a known import format")),
+        }
+    }
+}
+
+/// The columns of the CSV the importer reads, other columns being ignored
+struct Columns {
+    format: ImportFormat,
+    email: usize,
+    name: Option<usize>,
+    first_name: Option<usize>,
+    last_name: Option<usize>,
+    lists: Option<usize>,
+    status: Option<usize>,
+    /// Tried in order for when the subscriber opted in
+    subscribed_at: Vec<usize>,
+    /// Set for the unsubscribed members of Mailchimp exports
+    unsubscribed_at: Option<usize>,
+    /// Set for the cleaned members of Mailchimp exports
+    cleaned_at: Option<usize>,
+    email_disabled: Option<usize>,
+}
+
+impl Columns {
+    fn from_headers(
+        headers: &csv::StringRecord,
+        format: ImportFormat,
+    ) -> Result<Self, ImportError> {
+        let position = |names: &[&str]| {
+            headers.iter().position(|header| {
+                names
+                    .iter()
+                    .any(|name| header.trim().eq_ignore_ascii_case(name))
+            })
+        };
+        let email =
+            position(&["email", "email address"]).ok_or(ImportError::MissingColumn("email"))?;
+        Ok(match format {
+            ImportFormat::Generic => Self {
+                format,
+                email,
+                name: Some(position(&["name"]).ok_or(ImportError::MissingColumn("name"))?),
+                first_name: None,
+                last_name: None,
+                // Names of lists separated by `;`
+                lists: position(&["lists", "tags"]),
+                status: None,
+                subscribed_at: vec![],
+                unsubscribed_at: None,
+                cleaned_at: None,
+                email_disabled: None,
+            },
+            ImportFormat::Mailchimp => Self {
+                format,
+                email,
+                name: None,
+                first_name: position(&["first name"]),
+                last_name: position(&["last name"]),
+                // Quoted names of tags separated by `,`
+                lists: position(&["tags"]),
+                status: position(&["status", "member_status"]),
+                subscribed_at: [position(&["confirm_time"]), position(&["optin_time"])]
+                    .into_iter()
+                    .flatten()
+                    .collect(),
+                unsubscribed_at: position(&["unsub_time"]),
+                cleaned_at: position(&["clean_time"]),
+                email_disabled: None,
+            },
+            ImportFormat::Substack => Self {
+                format,
+                email,
+                name: position(&["name"]),
+                first_name: None,
+                last_name: None,
+                lists: None,
+                status: None,
+                subscribed_at: position(&["created_at"]).into_iter().collect(),
+                unsubscribed_at: None,
+                cleaned_at: None,
+                email_disabled: position(&["email_disabled"]),
+            },
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ValidRow {
+    line: u64,
+    subscriber: NewSubscriber,
+    status: SubscriptionStatus,
+    /// When they opted in, if the CSV says
+    subscribed_at: Option<DateTime<Utc>>,
+    lists: Vec<String>,
+}
+
+/// Import the subscribers of a CSV with a header. Unless the format says
+/// otherwise they are imported as confirmed, since they opted in wherever
+/// they come from, and as having subscribed now.
+///
+/// Rows are read on a blocking thread and validated as they come, the valid
+/// ones being inserted [`BATCH_SIZE`] at a time, each batch in its own
+/// transaction. Invalid rows are reported as rejected rather than failing the
+/// import. Email addresses are told apart regardless of their case.
+///
+/// The subscribers of each batch are recorded in the audit log under the
+/// admin importing them, if any.
+#[tracing::instrument(name = "Import subscribers", skip(pool, csv, actor))]
+pub async fn import_subscribers(
+    pool: &PgPool,
+    csv: impl Read + Send + 'static,
+    format: ImportFormat,
+    actor: Option<&AuditActor>,
+) -> Result<ImportReport, ImportError> {
+    // The reader stops once the receiver is dropped, e.g. on an error
+    let (sender, mut records) = mpsc::channel(BATCH_SIZE);
+    spawn_blocking_with_tracing(move || read_records(csv, &sender));
+    let headers = match records.recv().await {
+        Some((_, Ok(headers))) => headers,
+        Some((_, Err(e))) => return Err(ImportError::ReadError(e)),
+        None => csv::StringRecord::new(),
+    };
+    let columns = Columns::from_headers(&headers, format)?;
+
+    let mut report = ImportReport::default();
+    let mut seen = HashSet::new();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while let Some((line, read)) = records.recv().await {
+        match read {
+            Ok(record) => match parse_row(line, &record, &columns) {
+                Ok(row) => {
+                    if seen.insert(row.subscriber.email.as_ref().to_lowercase()) {
+                        batch.push(row);
+                    } else {
+                        report.push(
+                            line,
+                            Some(row.subscriber.email.as_ref()),
+                            RowOutcome::Duplicate,
+                            None,
+                        );
+                    }
+                }
+                Err(reason) => {
+                    let email = record.get(columns.email).filter(|email| !email.is_empty());
+                    report.push(line, email, RowOutcome::Rejected, Some(reason));
+                }
+            },
+            Err(e) if e.is_io_error() => return Err(ImportError::ReadError(e)),
+            Err(e) => report.push(line, None, RowOutcome::Rejected, Some(e.to_string())),
+        }
+        if batch.len() == BATCH_SIZE {
+            insert_batch(pool, &mut batch, &mut report, format, actor).await?;
+        }
+    }
+    insert_batch(pool, &mut batch, &mut report, format, actor).await?;
+    report.rows.sort_by_key(|row| row.line);
+
+    tracing::info!(
+        accepted = report.accepted,
+        duplicates = report.duplicates,
+        rejected = report.rejected,
+        "Imported subscribers"
+    );
+    Ok(report)
+}
+
+/// Send the header of a CSV and then its records with the line they start
+/// on, until the CSV ends, it fails to be read or nobody is receiving
+fn read_records(
+    csv: impl Read,
+    records: &mpsc::Sender<(u64, Result<csv::StringRecord, csv::Error>)>,
+) {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv);
+    if records
+        .blocking_send((1, reader.headers().cloned()))
+        .is_err()
+    {
+        return;
+    }
+    loop {
+        let mut record = csv::StringRecord::new();
+        let line = reader.position().line();
+        let read = reader.read_record(&mut record);
+        // The position of a record is where it starts, rather than where the
+        // previous one ended
+        let line = record.position().map_or(line, csv::Position::line);
+        let (done, read) = match read {
+            Ok(false) => return,
+            Ok(true) => (false, Ok(record)),
+            Err(e) => (e.is_io_error(), Err(e)),
+        };
+        if records.blocking_send((line, read)).is_err() || done {
+            return;
+        }
+    }
+}
+
+/// A CSV streamed as the chunks of a request body, read on a blocking thread
+pub struct ChunkReader {
+    chunks: mpsc::Receiver<Result<Bytes, io::Error>>,
+    chunk: Bytes,
+}
+
+impl ChunkReader {
+    /// A reader of the chunks sent to the returned sender, of which at most
+    /// `capacity` are buffered
+    pub fn new(capacity: usize) -> (mpsc::Sender<Result<Bytes, io::Error>>, Self) {
+        let (sender, chunks) = mpsc::channel(capacity);
+        let reader = Self {
+            chunks,
+            chunk: Bytes::new(),
+        };
+        (sender, reader)
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let read = buf.len().min(self.chunk.len());
+        buf[..read].copy_from_slice(&self.chunk.split_to(read));
+        Ok(read)
+    }
+}
+
+fn parse_row(line: u64, record: &csv::StringRecord, columns: &Columns) -> Result<ValidRow, String> {
+    let field = |index: Option<usize>| {
+        index
+            .and_then(|index| record.get(index))
+            .unwrap_or_default()
+    };
+    let email = SubscriberEmail::parse(field(Some(columns.email)).to_string())?;
+    let name = match columns.format {
+        ImportFormat::Generic => field(columns.name).to_string(),
+        // Other platforms do not require names, unlike us
+        ImportFormat::Mailchimp | ImportFormat::Substack => {
+            let name = [
+                field(columns.name),
+                field(columns.first_name),
+                field(columns.last_name),
+            ]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+            if name.is_empty() {
+                email
+                    .as_ref()
+                    .split('@')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string()
+            } else {
+                name
+            }
+        }
+    };
+    let name = SubscriberName::parse(name)?;
+
+    let status = match columns.format {
+        ImportFormat::Generic => SubscriptionStatus::Confirmed,
+        ImportFormat::Mailchimp => match columns.status {
+            Some(_) => match field(columns.status).to_ascii_lowercase().as_str() {
+                "subscribed" => SubscriptionStatus::Confirmed,
+                "unsubscribed" | "cleaned" => SubscriptionStatus::Unsubscribed,
+                other => {
+                    return Err(format!(
+                        "{other} is not a Mailchimp status that can be imported"
+                    ))
+                }
+            },
+            // Audiences are exported as a file per status, told apart by
+            // their columns
+            None if !field(columns.unsubscribed_at).is_empty()
+                || !field(columns.cleaned_at).is_empty() =>
+            {
+                SubscriptionStatus::Unsubscribed
+            }
+            None => SubscriptionStatus::Confirmed,
+        },
+        ImportFormat::Substack => {
+            if field(columns.email_disabled).eq_ignore_ascii_case("true") {
+                SubscriptionStatus::Unsubscribed
+            } else {
+                SubscriptionStatus::Confirmed
+            }
+        }
+    };
+    let subscribed_at = columns
+        .subscribed_at
+        .iter()
+        .map(|&index| field(Some(index)))
+        .find(|at| !at.is_empty())
+        .map(parse_timestamp)
+        .transpose()?;
+
+    let lists = match columns.format {
+        ImportFormat::Mailchimp => field(columns.lists)
+            .split(',')
+            .map(|tag| tag.trim().trim_matches('"').trim())
+            .filter(|tag| !tag.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+        ImportFormat::Generic | ImportFormat::Substack => field(columns.lists)
+            .split(';')
+            .map(str::trim)
+            .filter(|list| !list.is_empty())
+            .map(ToString::to_string)
+            .collect(),
+    };
+    Ok(ValidRow {
+        line,
+        subscriber: NewSubscriber { email, name },
+        status,
+        subscribed_at,
+        lists,
+    })
+}
+
+/// Timestamps as exported by Mailchimp (`2023-01-15 10:22:01`, in UTC) or
+/// Substack (RFC 3339), or bare dates
+fn parse_timestamp(s: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|at| at.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").map(|at| at.and_utc()))
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(start_of_day))
+        .map_err(|_| format!("{s} is not a valid timestamp"))
+}
+
+#[tracing::instrument(name = "Insert a batch of imported subscribers", skip_all, fields(rows = batch.len()))]
+async fn insert_batch(
+    pool: &PgPool,
+    batch: &mut Vec<ValidRow>,
+    report: &mut ImportReport,
+    format: ImportFormat,
+    actor: Option<&AuditActor>,
+) -> Result<(), ImportError> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let mut transaction = pool.begin().await?;
+    let mut outcomes = Vec::with_capacity(batch.len());
+    let mut imported: Vec<Uuid> = Vec::new();
+    for row in batch.iter() {
+        if already_subscribed(&mut transaction, row.subscriber.email.as_ref()).await? {
+            outcomes.push((RowOutcome::Duplicate, None));
+            continue;
+        }
+        if erased_at(&mut transaction, row.subscriber.email.as_ref())
+            .await?
+            .is_some()
+        {
+            outcomes.push((
+                RowOutcome::Rejected,
+                Some("The data of the email address was erased at the request of its owner".into()),
+            ));
+            continue;
+        }
+        let inserted = insert_subscriber(
+            &mut transaction,
+            &row.subscriber,
+            row.status,
+            row.subscribed_at.unwrap_or_else(Utc::now),
+        )
+        .await?;
+        let outcome = match inserted {
+            Some(subscriber_id) => {
+                add_to_lists(&mut transaction, subscriber_id, &row.lists).await?;
+                imported.push(subscriber_id);
+                RowOutcome::Accepted
+            }
+            None => RowOutcome::Duplicate,
+        };
+        outcomes.push((outcome, None));
+    }
+    if let (Some(actor), false) = (actor, imported.is_empty()) {
+        record_audit(
+            &mut transaction,
+            actor,
+            AuditEntry {
+                action: "subscriber.import",
+                target_type: "subscriber",
+                target_id: None,
+                before: None,
+                after: Some(json!({ "format": format, "subscriber_ids": imported })),
+            },
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+
+    // Only reported once committed, the whole batch failing otherwise
+    for (row, (outcome, reason)) in batch.drain(..).zip(outcomes) {
+        report.push(
+            row.line,
+            Some(row.subscriber.email.as_ref()),
+            outcome,
+            reason,
+        );
+    }
+    Ok(())
+}
+
+/// Whether the email address is subscribed, whatever its case
+async fn already_subscribed(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let existing = sqlx::query!(
+        "SELECT id FROM subscriptions WHERE lower(email) = lower($1)",
+        email,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(existing.is_some())
+}
+
+impl ImportReport {
+    fn push(
+        &mut self,
+        line: u64,
+        email: Option<&str>,
+        outcome: RowOutcome,
+        reason: Option<String>,
+    ) {
+        match outcome {
+            RowOutcome::Accepted => self.accepted += 1,
+            RowOutcome::Duplicate => self.duplicates += 1,
+            RowOutcome::Rejected => self.rejected += 1,
+        }
+        self.rows.push(RowReport {
+            line,
+            email: email.map(ToString::to_string),
+            outcome,
+            reason,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use claim::{assert_err, assert_ok};
+
+    use super::{parse_row, parse_timestamp, Columns, ImportError, ImportFormat};
+    use crate::domain::SubscriptionStatus;
+
+    fn record(fields: &[&str]) -> csv::StringRecord {
+        csv::StringRecord::from(fields.to_vec())
+    }
+
+    fn columns(headers: &[&str], format: ImportFormat) -> Columns {
+        Columns::from_headers(&record(headers), format).unwrap()
+    }
+
+    #[test]
+    fn columns_are_found_by_header_whatever_their_order_and_case() {
+        let columns = columns(&["Name", "joined", "EMAIL", "tags"], ImportFormat::Generic);
+
+        assert_eq!(columns.email, 2);
+        assert_eq!(columns.name, Some(0));
+        assert_eq!(columns.lists, Some(3));
+    }
+
+    #[test]
+    fn a_header_without_an_email_column_is_rejected() {
+        assert!(matches!(
+            Columns::from_headers(&record(&["name", "lists"]), ImportFormat::Generic),
+            Err(ImportError::MissingColumn("email"))
+        ));
+    }
+
+    #[test]
+    fn lists_are_split_on_semicolons() {
+        let columns = columns(&["email", "name", "lists"], ImportFormat::Generic);
+
+        let row = parse_row(
+            2,
+            &record(&["ursula@example.com", "Ursula", "fiction; essays;"]),
+            &columns,
+        )
+        .unwrap();
+
+        assert_eq!(row.lists, vec!["fiction", "essays"]);
+    }
+
+    #[test]
+    fn rows_are_validated_like_subscriptions() {
+        let columns = columns(&["email", "name"], ImportFormat::Generic);
+
+        assert_ok!(parse_row(
+            2,
+            &record(&["ursula@example.com", "Ursula"]),
+            &columns
+        ));
+        assert_err!(parse_row(2, &record(&["ursula", "Ursula"]), &columns));
+        assert_err!(parse_row(2, &record(&["ursula@example.com", ""]), &columns));
+        assert_err!(parse_row(2, &record(&["ursula@example.com"]), &columns));
+    }
+
+    #[test]
+    fn mailchimp_statuses_are_mapped_to_ours() {
+        let columns = columns(
+            &["Email Address", "First Name", "Last Name", "Status"],
+            ImportFormat::Mailchimp,
+        );
+        let status = |status: &str| {
+            parse_row(
+                2,
+                &record(&["ursula@example.com", "", "", status]),
+                &columns,
+            )
+            .map(|row| row.status)
+        };
+
+        assert_eq!(status("subscribed"), Ok(SubscriptionStatus::Confirmed));
+        assert_eq!(status("unsubscribed"), Ok(SubscriptionStatus::Unsubscribed));
+        assert_eq!(status("cleaned"), Ok(SubscriptionStatus::Unsubscribed));
+        assert_err!(status("pending"));
+    }
+
+    #[test]
+    fn mailchimp_members_are_named_after_their_email_address_without_a_name() {
+        let columns = columns(
+            &["Email Address", "First Name", "Last Name"],
+            ImportFormat::Mailchimp,
+        );
+
+        let unnamed = parse_row(2, &record(&["ursula@example.com", "", ""]), &columns).unwrap();
+        let named = parse_row(
+            2,
+            &record(&["ursula@example.com", "Ursula", "Le Guin"]),
+            &columns,
+        )
+        .unwrap();
+
+        assert_eq!(unnamed.subscriber.name.as_ref(), "ursula");
+        assert_eq!(named.subscriber.name.as_ref(), "Ursula Le Guin");
+    }
+
+    #[test]
+    fn mailchimp_members_subscribed_when_they_confirmed() {
+        let columns = columns(
+            &["Email Address", "OPTIN_TIME", "CONFIRM_TIME", "TAGS"],
+            ImportFormat::Mailchimp,
+        );
+
+        let confirmed = parse_row(
+            2,
+            &record(&[
+                "ursula@example.com",
+                "2023-01-14 08:00:00",
+                "2023-01-15 10:22:01",
+                r#""fiction","Essays""#,
+            ]),
+            &columns,
+        )
+        .unwrap();
+        let single_opt_in = parse_row(
+            2,
+            &record(&["ted@example.com", "2023-01-14 08:00:00", "", ""]),
+            &columns,
+        )
+        .unwrap();
+
+        assert_eq!(
+            confirmed.subscribed_at,
+            Some(Utc.with_ymd_and_hms(2023, 1, 15, 10, 22, 1).unwrap())
+        );
+        assert_eq!(confirmed.lists, vec!["fiction", "Essays"]);
+        assert_eq!(
+            single_opt_in.subscribed_at,
+            Some(Utc.with_ymd_and_hms(2023, 1, 14, 8, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn substack_subscribers_with_emails_disabled_are_unsubscribed() {
+        let columns = columns(
+            &[
+                "email",
+                "active_subscription",
+                "email_disabled",
+                "created_at",
+            ],
+            ImportFormat::Substack,
+        );
+
+        let disabled = parse_row(
+            2,
+            &record(&[
+                "ursula@example.com",
+                "false",
+                "true",
+                "2021-03-04T15:28:31.123Z",
+            ]),
+            &columns,
+        )
+        .unwrap();
+        let enabled = parse_row(
+            2,
+            &record(&[
+                "ted@example.com",
+                "false",
+                "false",
+                "2021-03-04T15:28:31.123Z",
+            ]),
+            &columns,
+        )
+        .unwrap();
+
+        assert_eq!(disabled.status, SubscriptionStatus::Unsubscribed);
+        assert_eq!(enabled.status, SubscriptionStatus::Confirmed);
+        assert_eq!(
+            enabled.subscribed_at,
+            Some(
+                Utc.with_ymd_and_hms(2021, 3, 4, 15, 28, 31).unwrap()
+                    + chrono::Duration::milliseconds(123)
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_timestamps_are_rejected() {
+        assert_ok!(parse_timestamp("2023-01-15"));
+        assert_err!(parse_timestamp("15/01/2023"));
+    }
+}
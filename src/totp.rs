@@ -0,0 +1,289 @@
+//! The optional TOTP second factor of admins (RFC 6238): six digits from
+//! HMAC-SHA1 over 30 second time steps, as computed by authenticator apps,
+//! and single-use recovery codes for when the authenticator is lost.
+//!
+//! Failed codes are counted per admin, who is locked out for a while after
+//! too many of them, so that the million codes cannot be guessed.
+
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, thread_rng, Rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+/// Steps accepted either side of the current one, for clocks that drift
+const ALLOWED_DRIFT: i64 = 1;
+const SECRET_BYTES: usize = 20;
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Failed codes after which an admin is locked out
+pub const MAX_FAILED_ATTEMPTS: i32 = 5;
+const LOCKOUT_MINUTES: i64 = 15;
+
+/// A new secret, base32 encoded as authenticator apps expect
+#[must_use]
+pub fn generate_secret() -> Secret<String> {
+    let mut secret = [0u8; SECRET_BYTES];
+    thread_rng().fill_bytes(&mut secret);
+    Secret::new(BASE32_NOPAD.encode(&secret))
+}
+
+/// The `otpauth://` URI authenticator apps are provisioned with, usually
+/// shown as a QR code
+#[must_use]
+pub fn provisioning_uri(issuer: &str, account: &str, secret: &Secret<String>) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}",
+        urlencoding::encode(issuer),
+        urlencoding::encode(account),
+        secret.expose_secret(),
+        urlencoding::encode(issuer),
+    )
+}
+
+/// The code of the time step `step`
+fn code_at(key: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    // Dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// The code of `secret` at `unix_time`, as authenticator apps show it
+#[must_use]
+pub fn code(secret: &Secret<String>, unix_time: i64) -> Option<String> {
+    let key = BASE32_NOPAD
+        .decode(secret.expose_secret().as_bytes())
+        .ok()?;
+    Some(code_at(&key, unix_time / STEP_SECONDS))
+}
+
+/// The time step `code` is valid for at `unix_time`, unless it is not, or
+/// its step is not after `last_step`, the one of the last accepted code
+#[must_use]
+pub fn verify_code(
+    secret: &Secret<String>,
+    code: &str,
+    unix_time: i64,
+    last_step: Option<i64>,
+) -> Option<i64> {
+    let key = BASE32_NOPAD
+        .decode(secret.expose_secret().as_bytes())
+        .ok()?;
+    let code = code.trim();
+    let current_step = unix_time / STEP_SECONDS;
+    (current_step - ALLOWED_DRIFT..=current_step + ALLOWED_DRIFT)
+        .filter(|step| last_step.is_none_or(|last_step| *step > last_step))
+        .find(|step| code_at(&key, *step) == code)
+}
+
+/// New recovery codes, e.g. `k3vq8-7hzfm`
+#[must_use]
+pub fn generate_recovery_codes() -> Vec<Secret<String>> {
+    let mut rng = thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .take(10)
+                .collect();
+            Secret::new(format!("{}-{}", &code[..5], &code[5..]))
+        })
+        .collect()
+}
+
+/// The SHA-256 of a recovery code, ignoring case, spaces and dashes, as
+/// stored in `totp_recovery_codes`
+#[must_use]
+pub fn recovery_code_hash(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[derive(Debug)]
+pub enum SecondFactor {
+    Code(String),
+    RecoveryCode(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SecondFactorOutcome {
+    Accepted,
+    Rejected,
+    /// Too many codes failed, none is checked until then
+    LockedUntil(DateTime<Utc>),
+}
+
+/// Check the second factor of an admin, counting the failures and locking
+/// them out after too many of them. Accepted codes and recovery codes
+/// cannot be used again.
+#[tracing::instrument(name = "Check a second factor", skip(pool, second_factor))]
+pub async fn check_second_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    second_factor: &SecondFactor,
+) -> Result<SecondFactorOutcome, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(user) = sqlx::query!(
+        r#"
+        SELECT totp_secret, totp_last_step, totp_failed_attempts, totp_locked_until
+        FROM users
+        WHERE user_id = $1
+        FOR UPDATE
+        "#,
+        user_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    else {
+        return Ok(SecondFactorOutcome::Rejected);
+    };
+    let now = Utc::now();
+    if let Some(locked_until) = user.totp_locked_until.filter(|until| *until > now) {
+        return Ok(SecondFactorOutcome::LockedUntil(locked_until));
+    }
+    let Some(secret) = user.totp_secret.map(Secret::new) else {
+        return Ok(SecondFactorOutcome::Rejected);
+    };
+
+    let accepted = match second_factor {
+        SecondFactor::Code(code) => {
+            match verify_code(&secret, code, now.timestamp(), user.totp_last_step) {
+                Some(step) => {
+                    sqlx::query!(
+                        "UPDATE users SET totp_last_step = $2 WHERE user_id = $1",
+                        user_id,
+                        step,
+                    )
+                    .execute(&mut transaction)
+                    .await?;
+                    true
+                }
+                None => false,
+            }
+        }
+        SecondFactor::RecoveryCode(code) => {
+            sqlx::query!(
+                r#"
+            UPDATE totp_recovery_codes SET used_at = now()
+            WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+                user_id,
+                recovery_code_hash(code),
+            )
+            .execute(&mut transaction)
+            .await?
+            .rows_affected()
+                == 1
+        }
+    };
+
+    let outcome = if accepted {
+        sqlx::query!(
+            r#"
+            UPDATE users SET totp_failed_attempts = 0, totp_locked_until = NULL
+            WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .execute(&mut transaction)
+        .await?;
+        SecondFactorOutcome::Accepted
+    } else if user.totp_failed_attempts + 1 >= MAX_FAILED_ATTEMPTS {
+        let locked_until = now + Duration::minutes(LOCKOUT_MINUTES);
+        tracing::warn!("Too many failed codes, locking the user out");
+        sqlx::query!(
+            r#"
+            UPDATE users SET totp_failed_attempts = 0, totp_locked_until = $2
+            WHERE user_id = $1
+            "#,
+            user_id,
+            locked_until,
+        )
+        .execute(&mut transaction)
+        .await?;
+        SecondFactorOutcome::LockedUntil(locked_until)
+    } else {
+        sqlx::query!(
+            r#"
+            UPDATE users SET totp_failed_attempts = totp_failed_attempts + 1
+            WHERE user_id = $1
+            "#,
+            user_id,
+        )
+        .execute(&mut transaction)
+        .await?;
+        SecondFactorOutcome::Rejected
+    };
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_none, assert_some_eq};
+    use data_encoding::BASE32_NOPAD;
+    use secrecy::Secret;
+
+    use super::{code_at, recovery_code_hash, verify_code};
+
+    /// The SHA1 key of the test vectors of RFC 6238
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn codes_match_the_rfc_6238_test_vectors() {
+        // The last six of the eight digits of the RFC
+        assert_eq!(code_at(RFC_KEY, 59 / 30), "287082");
+        assert_eq!(code_at(RFC_KEY, 1_111_111_109 / 30), "081804");
+        assert_eq!(code_at(RFC_KEY, 1_234_567_890 / 30), "005924");
+        assert_eq!(code_at(RFC_KEY, 2_000_000_000 / 30), "279037");
+    }
+
+    #[test]
+    fn codes_are_accepted_one_step_either_side_and_only_once() {
+        let secret = Secret::new(BASE32_NOPAD.encode(RFC_KEY));
+        let step = 1_111_111_109 / 30;
+
+        assert_some_eq!(verify_code(&secret, "081804", 1_111_111_109, None), step);
+        assert_some_eq!(
+            verify_code(&secret, "081804", 1_111_111_109 + 30, None),
+            step
+        );
+        assert_none!(verify_code(&secret, "081804", 1_111_111_109 + 90, None));
+        assert_none!(verify_code(&secret, "081804", 1_111_111_109, Some(step)));
+        assert_none!(verify_code(&secret, "000000", 1_111_111_109, None));
+    }
+
+    #[test]
+    fn recovery_codes_are_hashed_ignoring_case_and_dashes() {
+        assert_eq!(
+            recovery_code_hash("k3vq8-7hzfm"),
+            recovery_code_hash(" K3VQ87HZFM ")
+        );
+        assert_ne!(
+            recovery_code_hash("k3vq8-7hzfm"),
+            recovery_code_hash("k3vq8-7hzfn")
+        );
+    }
+}
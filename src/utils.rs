@@ -0,0 +1,137 @@
+use std::{
+    error::Error,
+    fmt::{Debug, Display, Formatter},
+    net::{IpAddr, SocketAddr},
+};
+
+use actix_web::{web::Data, HttpRequest};
+
+use crate::startup::TrustedProxies;
+
+/// Return an opaque 500 while preserving the error's root cause for logging
+pub fn e500<T>(e: T) -> actix_web::Error
+where
+    T: Debug + Display + 'static,
+{
+    actix_web::error::ErrorInternalServerError(e)
+}
+
+/// Format an error followed by the chain of errors that caused it
+///
+/// # Errors
+///
+/// If writing to the formatter fails.
+pub fn error_chain_fmt(e: &impl Error, f: &mut Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "{e}\n")?;
+    let mut current = e.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{cause}")?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+/// Whether `e` is the violation of the unique constraint named `constraint`
+#[must_use]
+pub fn violates_unique_constraint(e: &sqlx::Error, constraint: &str) -> bool {
+    match e {
+        sqlx::Error::Database(e) => {
+            e.code().as_deref() == Some("23505") && e.constraint() == Some(constraint)
+        }
+        _ => false,
+    }
+}
+
+/// The address of the client behind `request`: the peer of the connection,
+/// unless it is one of the [`TrustedProxies`] and forwarded the request for
+/// someone else in the `X-Forwarded-For` header
+#[must_use]
+pub fn client_ip_address(request: &HttpRequest) -> Option<IpAddr> {
+    let peer = request.peer_addr()?.ip();
+    let trusted = request
+        .app_data::<Data<TrustedProxies>>()
+        .map_or(&[][..], |proxies| proxies.0.as_slice());
+    let forwarded_for = request
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    forwarding_client(peer, trusted, &forwarded_for)
+}
+
+/// The first address that is not a trusted proxy, going back from the peer
+/// along the addresses each proxy appended to `X-Forwarded-For`. The ones to
+/// the left of it are whatever the client claimed, and are not trusted.
+fn forwarding_client(peer: IpAddr, trusted: &[IpAddr], forwarded_for: &[&str]) -> Option<IpAddr> {
+    let mut client = peer;
+    for address in forwarded_for.iter().rev() {
+        if !trusted.contains(&client) {
+            break;
+        }
+        client = parse_ip_address(address)?;
+    }
+    Some(client)
+}
+
+/// An IP address, possibly followed by a port as proxies may forward it
+fn parse_ip_address(address: &str) -> Option<IpAddr> {
+    address.parse().ok().or_else(|| {
+        address
+            .parse::<SocketAddr>()
+            .ok()
+            .map(|address| address.ip())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::{forwarding_client, parse_ip_address};
+
+    #[test]
+    fn ip_addresses_are_parsed_with_or_without_a_port() {
+        let ipv4 = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let ipv6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+
+        assert_eq!(parse_ip_address("203.0.113.7"), Some(ipv4));
+        assert_eq!(parse_ip_address("203.0.113.7:51234"), Some(ipv4));
+        assert_eq!(parse_ip_address("2001:db8::1"), Some(ipv6));
+        assert_eq!(parse_ip_address("[2001:db8::1]:51234"), Some(ipv6));
+    }
+
+    #[test]
+    fn hostnames_and_obfuscated_identifiers_are_not_ip_addresses() {
+        assert_eq!(parse_ip_address("unknown"), None);
+        assert_eq!(parse_ip_address("_hidden"), None);
+        assert_eq!(parse_ip_address("example.com"), None);
+    }
+
+    #[test]
+    fn forwarded_addresses_are_ignored_unless_the_peer_is_a_trusted_proxy() {
+        let peer = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 23));
+
+        assert_eq!(forwarding_client(peer, &[], &["203.0.113.7"]), Some(peer));
+        assert_eq!(
+            forwarding_client(peer, &[peer], &["203.0.113.7"]),
+            Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)))
+        );
+        assert_eq!(forwarding_client(peer, &[peer], &[]), Some(peer));
+    }
+
+    #[test]
+    fn addresses_claimed_by_the_client_are_skipped() {
+        let proxy = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let load_balancer = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        let client = forwarding_client(
+            load_balancer,
+            &[proxy, load_balancer],
+            &["192.0.2.1", "203.0.113.7", "10.0.0.1"],
+        );
+
+        assert_eq!(client, Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))));
+    }
+}
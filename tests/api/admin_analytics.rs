@@ -0,0 +1,231 @@
+use chrono::{Duration, NaiveDate, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+async fn insert_subscriber(pool: &PgPool, email: &str, days_ago: i64) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'Subscriber', $3, 'pending_confirmation')
+        "#,
+        id,
+        email,
+        Utc::now() - Duration::days(days_ago),
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to insert subscriber.");
+    id
+}
+
+async fn set_status(pool: &PgPool, id: Uuid, status: &str) {
+    sqlx::query!(
+        "UPDATE subscriptions SET status = $2 WHERE id = $1",
+        id,
+        status
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+}
+
+/// Ursula and Octavia signed up two days ago, Ursula confirmed today and
+/// Octavia's emails bounce. Nnedi signed up to the `rust` list yesterday,
+/// confirmed and unsubscribed today.
+async fn grow_the_audience(app: &TestApp) {
+    let pool = &app.database_pool;
+    let ursula = insert_subscriber(pool, "ursula@example.com", 2).await;
+    insert_subscriber(pool, "octavia@example.com", 2).await;
+    let nnedi = insert_subscriber(pool, "nnedi@example.com", 1).await;
+    let list_id = Uuid::new_v4();
+    sqlx::query!("INSERT INTO lists (id, name) VALUES ($1, 'rust')", list_id)
+        .execute(pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        "INSERT INTO subscription_lists (subscriber_id, list_id) VALUES ($1, $2)",
+        nnedi,
+        list_id
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    set_status(pool, ursula, "confirmed").await;
+    set_status(pool, nnedi, "confirmed").await;
+    set_status(pool, nnedi, "unsubscribed").await;
+
+    let response = app
+        .post_sendgrid_events(&serde_json::json!([{
+            "email": "octavia@example.com",
+            "timestamp": Utc::now().timestamp(),
+            "event": "bounce",
+        }]))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+fn days_ago(days: i64) -> NaiveDate {
+    Utc::now().date_naive() - Duration::days(days)
+}
+
+fn counts(series: &serde_json::Value, field: &str) -> Vec<i64> {
+    series
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|point| point[field].as_i64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn analytics_report_daily_signups_confirmations_unsubscribes_and_bounces() {
+    // Arrange
+    let app = spawn_app().await;
+    grow_the_audience(&app).await;
+
+    // Act
+    let response = app
+        .admin_get(&format!(
+            "/admin/analytics?from={}&to={}",
+            days_ago(2),
+            days_ago(0)
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let analytics: serde_json::Value = response.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(analytics["granularity"], "day");
+    let series = &analytics["series"];
+    let dates: Vec<String> = (0..=2)
+        .rev()
+        .map(|days| days_ago(days).to_string())
+        .collect();
+    assert_eq!(
+        series
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|point| point["date"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        dates
+    );
+    assert_eq!(counts(series, "signups"), [2, 1, 0]);
+    assert_eq!(counts(series, "confirmations"), [0, 0, 2]);
+    assert_eq!(counts(series, "unsubscribes"), [0, 0, 1]);
+    assert_eq!(counts(series, "bounces"), [0, 0, 1]);
+    assert_eq!(series[0]["confirmation_rate"], 0.5);
+    assert_eq!(series[1]["confirmation_rate"], 1.0);
+    assert!(series[2]["confirmation_rate"].is_null());
+    // Ursula confirmed two days after signing up
+    let latency = series[0]["average_confirmation_seconds"].as_f64().unwrap();
+    assert!((2.0 * 86400.0 - 60.0..=2.0 * 86400.0 + 60.0).contains(&latency));
+}
+
+#[tokio::test]
+async fn analytics_can_be_restricted_to_a_list() {
+    // Arrange
+    let app = spawn_app().await;
+    grow_the_audience(&app).await;
+
+    // Act
+    let response = app
+        .admin_get(&format!(
+            "/admin/analytics?from={}&to={}&list=rust",
+            days_ago(2),
+            days_ago(0)
+        ))
+        .await;
+
+    // Assert
+    let analytics: serde_json::Value = response.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(analytics["list"], "rust");
+    let series = &analytics["series"];
+    assert_eq!(counts(series, "signups"), [0, 1, 0]);
+    assert_eq!(counts(series, "confirmations"), [0, 0, 1]);
+    assert_eq!(counts(series, "unsubscribes"), [0, 0, 1]);
+    assert_eq!(counts(series, "bounces"), [0, 0, 0]);
+}
+
+#[tokio::test]
+async fn analytics_can_be_grouped_by_month() {
+    // Arrange
+    let app = spawn_app().await;
+    grow_the_audience(&app).await;
+
+    // Act
+    let response = app
+        .admin_get(&format!(
+            "/admin/analytics?from={}&to={}&granularity=month",
+            days_ago(2),
+            days_ago(0)
+        ))
+        .await;
+
+    // Assert
+    let analytics: serde_json::Value = response.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    let series = &analytics["series"];
+    assert!(series[0]["date"].as_str().unwrap().ends_with("-01"));
+    assert_eq!(counts(series, "signups").iter().sum::<i64>(), 3);
+}
+
+#[tokio::test]
+async fn invalid_analytics_queries_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let reversed_range = app
+        .admin_get(&format!(
+            "/admin/analytics?from={}&to={}",
+            days_ago(0),
+            days_ago(1)
+        ))
+        .await;
+    let unknown_granularity = app.admin_get("/admin/analytics?granularity=hour").await;
+    let earliest_day = app
+        .admin_get(&format!("/admin/analytics?to={}", chrono::NaiveDate::MIN))
+        .await;
+    let unknown_list = app.admin_get("/admin/analytics?list=unknown").await;
+    let anonymous = reqwest::Client::new()
+        .get(format!("{}/admin/analytics", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(reversed_range.status().as_u16(), 400);
+    assert_eq!(unknown_granularity.status().as_u16(), 400);
+    assert_eq!(earliest_day.status().as_u16(), 400);
+    assert_eq!(unknown_list.status().as_u16(), 404);
+    assert_eq!(anonymous.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn analytics_default_to_the_last_30_days() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let analytics: serde_json::Value = app
+        .admin_get("/admin/analytics")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(analytics["series"].as_array().unwrap().len(), 30);
+    assert_eq!(analytics["to"], days_ago(0).to_string());
+}
@@ -0,0 +1,255 @@
+use reqwest::Method;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+/// Create a key as the owner, returning the response body
+async fn create_api_key(app: &TestApp, scopes: Value) -> Value {
+    let response = app
+        .admin_post(
+            "/admin/api/api_keys",
+            &json!({ "name": "crm", "scopes": scopes }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn api_keys_are_shown_once_and_only_their_hash_is_stored() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let created = create_api_key(&app, json!(["subscribers:read"])).await;
+    let listed: Value = app
+        .admin_get("/admin/api/api_keys")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    let stored = sqlx::query!("SELECT key_prefix, key_hash, created_by FROM api_keys")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    let key = created["key"].as_str().unwrap();
+    assert!(key.starts_with("nlk_"));
+    assert!(key.starts_with(stored.key_prefix.as_str()));
+    assert_eq!(stored.key_hash, hex::encode(Sha256::digest(key.as_bytes())));
+    assert_eq!(stored.created_by, app.test_user.user_id);
+    assert_eq!(listed.as_array().unwrap().len(), 1);
+    assert_eq!(listed[0]["id"], created["id"]);
+    assert_eq!(listed[0]["scopes"], json!(["subscribers:read"]));
+    assert!(listed[0].get("key").is_none());
+}
+
+#[tokio::test]
+async fn api_keys_only_grant_the_permissions_of_their_scopes() {
+    // Arrange
+    let app = spawn_app().await;
+    let created = create_api_key(&app, json!(["subscribers:read"])).await;
+    let key = created["key"].as_str().unwrap();
+
+    // Act
+    let read = app
+        .request_with_session(key, Method::GET, "/admin/api/subscribers", None)
+        .await;
+    let write = app
+        .request_with_session(
+            key,
+            Method::POST,
+            "/admin/api/subscribers",
+            Some(&json!({ "email": "ursula@example.com", "name": "Ursula" })),
+        )
+        .await;
+    let users = app
+        .request_with_session(key, Method::GET, "/admin/api/users", None)
+        .await;
+    let keys = app
+        .request_with_session(key, Method::GET, "/admin/api/api_keys", None)
+        .await;
+    let totp = app
+        .request_with_session(key, Method::POST, "/admin/api/totp/enroll", None)
+        .await;
+
+    // Assert
+    let last_used_at = sqlx::query!("SELECT last_used_at FROM api_keys")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap()
+        .last_used_at;
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(read.status().as_u16(), 200);
+    assert_eq!(write.status().as_u16(), 403);
+    assert_eq!(users.status().as_u16(), 403);
+    assert_eq!(keys.status().as_u16(), 403);
+    assert_eq!(totp.status().as_u16(), 403);
+    assert!(last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn write_scopes_also_grant_the_matching_read_scope() {
+    // Arrange
+    let app = spawn_app().await;
+    let created = create_api_key(&app, json!(["subscribers:write"])).await;
+    let key = created["key"].as_str().unwrap();
+
+    // Act
+    let read = app
+        .request_with_session(key, Method::GET, "/admin/api/subscribers", None)
+        .await;
+    let write = app
+        .request_with_session(
+            key,
+            Method::POST,
+            "/admin/api/subscribers",
+            Some(
+                &json!({ "email": "ursula@example.com", "name": "Ursula", "double_opt_in": false }),
+            ),
+        )
+        .await;
+    let analytics = app
+        .request_with_session(key, Method::GET, "/admin/analytics", None)
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(read.status().as_u16(), 200);
+    assert_eq!(write.status().as_u16(), 201);
+    assert_eq!(analytics.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn expired_and_revoked_api_keys_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let expired = create_api_key(&app, json!(["subscribers:read"])).await;
+    let revoked = create_api_key(&app, json!(["subscribers:read"])).await;
+    sqlx::query!(
+        "UPDATE api_keys SET expires_at = now() - interval '1 minute' WHERE id = $1",
+        expired["id"].as_str().unwrap().parse::<Uuid>().unwrap(),
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let revocation = app
+        .admin_delete(&format!(
+            "/admin/api/api_keys/{}",
+            revoked["id"].as_str().unwrap()
+        ))
+        .await;
+    let revoked_again = app
+        .admin_delete(&format!(
+            "/admin/api/api_keys/{}",
+            revoked["id"].as_str().unwrap()
+        ))
+        .await;
+    let mut outcomes = Vec::new();
+    for created in [&expired, &revoked] {
+        let response = app
+            .request_with_session(
+                created["key"].as_str().unwrap(),
+                Method::GET,
+                "/admin/api/subscribers",
+                None,
+            )
+            .await;
+        outcomes.push(response.status().as_u16());
+    }
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(revocation.status().as_u16(), 204);
+    assert_eq!(revoked_again.status().as_u16(), 404);
+    assert_eq!(outcomes, [401, 401]);
+}
+
+#[tokio::test]
+async fn api_keys_with_unknown_scopes_or_a_past_expiry_are_not_created() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = [
+        (
+            json!({ "name": "crm", "scopes": ["subscribers:delete"] }),
+            "an unknown scope",
+        ),
+        (json!({ "name": "crm", "scopes": [] }), "no scope"),
+        (
+            json!({ "name": " ", "scopes": ["subscribers:read"] }),
+            "an empty name",
+        ),
+        (
+            json!({
+                "name": "crm",
+                "scopes": ["subscribers:read"],
+                "expires_at": "2020-01-01T00:00:00Z",
+            }),
+            "a past expiry",
+        ),
+    ];
+
+    // Act
+    let mut outcomes = Vec::new();
+    for (body, description) in test_cases {
+        let response = app.admin_post("/admin/api/api_keys", &body).await;
+        outcomes.push((description, response.status().as_u16()));
+    }
+
+    // Assert
+    let stored = sqlx::query!("SELECT id FROM api_keys")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    for (description, status) in outcomes {
+        assert_eq!(status, 400, "A key with {description} was created");
+    }
+    assert!(stored.is_empty());
+}
+
+#[tokio::test]
+async fn changes_made_with_an_api_key_are_audited_with_it() {
+    // Arrange
+    let app = spawn_app().await;
+    let created = create_api_key(&app, json!(["subscribers:write"])).await;
+
+    // Act
+    let response = app
+        .request_with_session(
+            created["key"].as_str().unwrap(),
+            Method::POST,
+            "/admin/api/subscribers",
+            Some(
+                &json!({ "email": "ursula@example.com", "name": "Ursula", "double_opt_in": false }),
+            ),
+        )
+        .await;
+
+    // Assert
+    let page: Value = app
+        .admin_get("/admin/api/audit_log?target_type=subscriber")
+        .await
+        .json()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+    let entry = &page["entries"][0];
+    assert_eq!(entry["action"], "subscriber.create");
+    assert_eq!(
+        entry["actor_id"],
+        app.test_user.user_id.to_string().as_str()
+    );
+    assert_eq!(entry["api_key_id"], created["id"]);
+}
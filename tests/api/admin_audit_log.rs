@@ -0,0 +1,170 @@
+use serde_json::json;
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+async fn add_subscriber(app: &TestApp, email: &str) -> String {
+    let subscriber: serde_json::Value = app
+        .admin_post(
+            "/admin/api/subscribers",
+            &json!({ "email": email, "name": "Ursula", "double_opt_in": false }),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    subscriber["id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn changes_to_subscribers_are_recorded_with_who_made_them() {
+    // Arrange
+    let app = spawn_app().await;
+    let id = add_subscriber(&app, "ursula@example.com").await;
+
+    // Act
+    app.admin_patch(
+        &format!("/admin/api/subscribers/{id}"),
+        &json!({ "status": "unsubscribed" }),
+    )
+    .await;
+    app.admin_delete(&format!("/admin/api/subscribers/{id}"))
+        .await;
+    // Missing subscribers are not changed, so not audited either
+    app.admin_delete(&format!("/admin/api/subscribers/{id}"))
+        .await;
+
+    // Assert
+    let page: serde_json::Value = app
+        .admin_get(&format!(
+            "/admin/api/audit_log?target_type=subscriber&target_id={id}"
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let tampering = sqlx::query!("DELETE FROM audit_log")
+        .execute(&app.database_pool)
+        .await;
+    clean_up_database(app.database_name).await;
+
+    let entries = page["entries"].as_array().unwrap();
+    let actions: Vec<&str> = entries
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        actions,
+        [
+            "subscriber.delete",
+            "subscriber.change_status",
+            "subscriber.create"
+        ]
+    );
+    assert_eq!(
+        entries[1]["changes"],
+        json!({ "status": { "before": "confirmed", "after": "unsubscribed" } })
+    );
+    assert_eq!(
+        entries[0]["changes"],
+        json!({ "status": { "before": "unsubscribed", "after": null } })
+    );
+    assert_eq!(
+        entries[0]["actor_id"],
+        app.test_user.user_id.to_string().as_str()
+    );
+    assert_eq!(
+        entries[0]["actor_username"],
+        app.test_user.username.as_str()
+    );
+    assert_eq!(entries[0]["ip_address"], "127.0.0.1");
+    assert!(entries[0]["request_id"].is_string());
+    assert!(!entries[2].to_string().contains("ursula"));
+    assert!(tampering.is_err());
+}
+
+#[tokio::test]
+async fn the_audit_log_is_filtered_and_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    add_subscriber(&app, "ursula@example.com").await;
+    add_subscriber(&app, "octavia@example.com").await;
+    app.import_subscribers("generic", "email,name\nnnedi@example.com,Nnedi\n")
+        .await;
+    app.admin_post(
+        "/admin/api/data_subjects/erase",
+        &json!({ "email": "ursula@example.com" }),
+    )
+    .await;
+
+    // Act
+    let first_page: serde_json::Value = app
+        .admin_get("/admin/api/audit_log?action=subscriber.create&limit=1")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let second_page: serde_json::Value = app
+        .admin_get(&format!(
+            "/admin/api/audit_log?action=subscriber.create&limit=1&cursor={}",
+            first_page["next_cursor"]
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let imports: serde_json::Value = app
+        .admin_get("/admin/api/audit_log?action=subscriber.import")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let erasures: serde_json::Value = app
+        .admin_get("/admin/api/audit_log?action=data_subject.erase")
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(first_page["entries"].as_array().unwrap().len(), 1);
+    assert_eq!(second_page["entries"].as_array().unwrap().len(), 1);
+    assert_ne!(
+        first_page["entries"][0]["target_id"],
+        second_page["entries"][0]["target_id"]
+    );
+    assert_eq!(second_page["next_cursor"], serde_json::Value::Null);
+    assert_eq!(
+        imports["entries"][0]["changes"]["format"]["after"],
+        "generic"
+    );
+    assert_eq!(
+        imports["entries"][0]["changes"]["subscriber_ids"]["after"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+    let erasure = &erasures["entries"][0];
+    assert_eq!(erasure["changes"]["erased"]["after"], true);
+    assert!(!erasure.to_string().contains("ursula"));
+}
+
+#[tokio::test]
+async fn the_audit_log_rejects_a_range_ending_on_the_last_day() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_get(&format!(
+            "/admin/api/audit_log?to={}",
+            chrono::NaiveDate::MAX
+        ))
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,328 @@
+use reqwest::Method;
+use serde_json::json;
+use uuid::Uuid;
+use zero2prod::authorization::Role;
+
+use crate::helpers::{clean_up_database, spawn_app};
+
+#[tokio::test]
+async fn each_admin_route_requires_its_permission() {
+    // Arrange
+    let app = spawn_app().await;
+    let id = Uuid::new_v4();
+    let routes = [
+        // Viewing analytics
+        (
+            Role::Viewer,
+            Method::GET,
+            "/admin/analytics".to_string(),
+            None,
+        ),
+        (
+            Role::Viewer,
+            Method::GET,
+            format!("/admin/issues/{id}/report"),
+            None,
+        ),
+        (
+            Role::Viewer,
+            Method::GET,
+            format!("/admin/api/issues/{id}/report"),
+            None,
+        ),
+        (
+            Role::Viewer,
+            Method::GET,
+            format!("/admin/api/issues/{id}/report.csv"),
+            None,
+        ),
+        // Viewing subscribers
+        (
+            Role::Editor,
+            Method::GET,
+            "/admin/api/subscribers".to_string(),
+            None,
+        ),
+        (
+            Role::Editor,
+            Method::GET,
+            format!("/admin/api/subscribers/{id}"),
+            None,
+        ),
+        (
+            Role::Editor,
+            Method::GET,
+            "/admin/api/subscribers/export".to_string(),
+            None,
+        ),
+        (
+            Role::Editor,
+            Method::GET,
+            "/admin/api/data_subjects?email=ursula%40example.com".to_string(),
+            None,
+        ),
+        (
+            Role::Editor,
+            Method::GET,
+            "/admin/dead_letters".to_string(),
+            None,
+        ),
+        // Drafting issues
+        (
+            Role::Editor,
+            Method::GET,
+            "/admin/api/issues".to_string(),
+            None,
+        ),
+        (
+            Role::Editor,
+            Method::POST,
+            "/admin/api/issues".to_string(),
+            Some(json!({ "slug": "spring", "title": "Spring", "content": "Hello" })),
+        ),
+        (
+            Role::Editor,
+            Method::PATCH,
+            format!("/admin/api/issues/{id}"),
+            Some(json!({ "title": "Summer" })),
+        ),
+        // Managing subscribers
+        (
+            Role::Publisher,
+            Method::POST,
+            "/admin/api/subscribers".to_string(),
+            Some(json!({ "email": "ursula@example.com", "name": "Ursula" })),
+        ),
+        (
+            Role::Publisher,
+            Method::POST,
+            "/admin/api/subscribers/import".to_string(),
+            None,
+        ),
+        (
+            Role::Publisher,
+            Method::PATCH,
+            format!("/admin/api/subscribers/{id}"),
+            Some(json!({ "status": "confirmed" })),
+        ),
+        (
+            Role::Publisher,
+            Method::DELETE,
+            format!("/admin/api/subscribers/{id}"),
+            None,
+        ),
+        (
+            Role::Publisher,
+            Method::POST,
+            "/admin/api/data_subjects/erase".to_string(),
+            Some(json!({ "email": "ted@example.com" })),
+        ),
+        // Publishing issues
+        (
+            Role::Publisher,
+            Method::POST,
+            format!("/admin/api/issues/{id}/publish"),
+            None,
+        ),
+        (
+            Role::Publisher,
+            Method::POST,
+            "/admin/dead_letters/replay".to_string(),
+            Some(json!({ "newsletter_issue_id": id })),
+        ),
+        // Managing users
+        (
+            Role::Owner,
+            Method::GET,
+            "/admin/api/api_keys".to_string(),
+            None,
+        ),
+        (
+            Role::Owner,
+            Method::POST,
+            "/admin/api/api_keys".to_string(),
+            Some(json!({ "name": "crm", "scopes": ["subscribers:read"] })),
+        ),
+        (
+            Role::Owner,
+            Method::DELETE,
+            format!("/admin/api/api_keys/{id}"),
+            None,
+        ),
+        (
+            Role::Owner,
+            Method::GET,
+            "/admin/api/audit_log".to_string(),
+            None,
+        ),
+        (
+            Role::Owner,
+            Method::GET,
+            "/admin/api/users".to_string(),
+            None,
+        ),
+        (
+            Role::Owner,
+            Method::POST,
+            "/admin/api/users".to_string(),
+            Some(
+                json!({ "username": "octavia", "password": "correct horse battery", "role": "viewer" }),
+            ),
+        ),
+        (
+            Role::Owner,
+            Method::PATCH,
+            format!("/admin/api/users/{id}"),
+            Some(json!({ "role": "viewer" })),
+        ),
+        (
+            Role::Owner,
+            Method::DELETE,
+            format!("/admin/api/users/{id}"),
+            None,
+        ),
+    ];
+    let mut users = Vec::new();
+    for role in [Role::Viewer, Role::Editor, Role::Publisher, Role::Owner] {
+        users.push(app.add_user(role).await);
+    }
+
+    // Act
+    let mut outcomes = Vec::new();
+    for (minimum_role, method, path, body) in &routes {
+        for user in &users {
+            let response = app
+                .request_as(user, method.clone(), path, body.as_ref())
+                .await;
+            outcomes.push((
+                format!("{method} {path} as {}", user.role.as_ref()),
+                user.role >= *minimum_role,
+                response.status().as_u16(),
+            ));
+        }
+    }
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    for (request, allowed, status) in outcomes {
+        if allowed {
+            assert_ne!(status, 403, "{request} was forbidden");
+        } else {
+            assert_eq!(status, 403, "{request} was allowed");
+        }
+    }
+}
+
+#[tokio::test]
+async fn forbidden_requests_change_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = app.add_user(Role::Viewer).await;
+
+    // Act
+    let response = app
+        .request_as(
+            &viewer,
+            Method::POST,
+            "/admin/api/subscribers",
+            Some(&json!({ "email": "ursula@example.com", "name": "Ursula" })),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("manage_subscribers"));
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn owners_add_users_who_get_the_permissions_of_their_role() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_post(
+            "/admin/api/users",
+            &json!({ "username": "octavia", "password": "correct horse battery", "role": "editor" }),
+        )
+        .await;
+    let taken = app
+        .admin_post(
+            "/admin/api/users",
+            &json!({ "username": "octavia", "password": "correct horse battery", "role": "editor" }),
+        )
+        .await;
+    let short_password = app
+        .admin_post(
+            "/admin/api/users",
+            &json!({ "username": "nnedi", "password": "short", "role": "editor" }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let as_editor = |path: &str| {
+        reqwest::Client::new()
+            .get(format!("{}{path}", app.address))
+            .basic_auth("octavia", Some("correct horse battery"))
+            .send()
+    };
+    let subscribers = as_editor("/admin/api/subscribers").await.unwrap();
+    let users = as_editor("/admin/api/users").await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(taken.status().as_u16(), 409);
+    assert_eq!(short_password.status().as_u16(), 400);
+    assert_eq!(subscribers.status().as_u16(), 200);
+    assert_eq!(users.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_last_owner_cannot_be_demoted_nor_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    let owner_id = app.test_user.user_id;
+
+    // Act
+    let demoted = app
+        .admin_patch(
+            &format!("/admin/api/users/{owner_id}"),
+            &json!({ "role": "publisher" }),
+        )
+        .await;
+    let deleted = app
+        .admin_delete(&format!("/admin/api/users/{owner_id}"))
+        .await;
+    let other_owner = app.add_user(Role::Owner).await;
+    let demoted_with_another_owner = app
+        .admin_patch(
+            &format!("/admin/api/users/{owner_id}"),
+            &json!({ "role": "publisher" }),
+        )
+        .await;
+    let other_owner_deleted = app
+        .request_as(
+            &other_owner,
+            Method::DELETE,
+            &format!("/admin/api/users/{}", other_owner.user_id),
+            None,
+        )
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(demoted.status().as_u16(), 409);
+    assert_eq!(deleted.status().as_u16(), 409);
+    assert_eq!(demoted_with_another_owner.status().as_u16(), 200);
+    assert_eq!(other_owner_deleted.status().as_u16(), 409);
+}
@@ -0,0 +1,145 @@
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+async fn add_subscriber(app: &TestApp, email: &str) -> reqwest::Response {
+    app.admin_post(
+        "/admin/api/subscribers",
+        &json!({ "email": email, "name": "Ursula", "double_opt_in": false }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn admins_can_export_the_data_of_a_subject_with_their_events() {
+    // Arrange
+    let app = spawn_app().await;
+    add_subscriber(&app, "ursula@example.com").await;
+    // Addresses differing in case only, e.g. from before imports ignored case
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES (gen_random_uuid(), 'Ursula@Example.com', 'Ursula', now(), 'unsubscribed')
+        "#,
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+    app.post_sendgrid_events(&json!([{
+        "email": "ursula@example.com",
+        "timestamp": 1_700_000_000,
+        "event": "open",
+        "sg_event_id": "open-1",
+    }]))
+    .await;
+
+    // Act
+    let response = app
+        .admin_get("/admin/api/data_subjects?email=URSULA%40example.com")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(data["subscriptions"][0]["email"], "ursula@example.com");
+    assert_eq!(data["subscriptions"][0]["status"], "confirmed");
+    assert_eq!(data["subscriptions"][1]["email"], "Ursula@Example.com");
+    assert_eq!(data["subscriptions"][1]["status"], "unsubscribed");
+    assert_eq!(data["events"][0]["event"], "open");
+}
+
+#[tokio::test]
+async fn erased_subjects_leave_only_a_tombstone_honored_by_imports() {
+    // Arrange
+    let app = spawn_app().await;
+    add_subscriber(&app, "ursula@example.com").await;
+    app.post_sendgrid_events(&json!([{
+        "email": "ursula@example.com",
+        "timestamp": 1_700_000_000,
+        "event": "open",
+        "sg_event_id": "open-1",
+    }]))
+    .await;
+
+    // Act
+    let response = app
+        .admin_post(
+            "/admin/api/data_subjects/erase",
+            &json!({ "email": "ursula@example.com" }),
+        )
+        .await;
+    let unknown = app
+        .admin_post(
+            "/admin/api/data_subjects/erase",
+            &json!({ "email": "nobody@example.com" }),
+        )
+        .await;
+
+    // Assert
+    let erasure: serde_json::Value = response.json().await.unwrap();
+    let unknown: serde_json::Value = unknown.json().await.unwrap();
+    let data: serde_json::Value = app
+        .admin_get("/admin/api/data_subjects?email=ursula%40example.com")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let added_again = add_subscriber(&app, "Ursula@example.com").await;
+    let imported_again: serde_json::Value = app
+        .import_subscribers("generic", "email,name\nursula@example.com,Ursula\n")
+        .await
+        .json()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(erasure["erased"], true);
+    assert_eq!(unknown["erased"], false);
+    assert_eq!(data["subscriptions"], json!([]));
+    assert_eq!(data["events"], json!([]));
+    assert!(data["erased_at"].is_string());
+    assert_eq!(added_again.status().as_u16(), 409);
+    assert_eq!(imported_again["rejected"], 1);
+}
+
+#[tokio::test]
+async fn subscribing_again_lifts_the_tombstone_once_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    add_subscriber(&app, "ursula_le_guin@gmail.com").await;
+    app.admin_post(
+        "/admin/api/data_subjects/erase",
+        &json!({ "email": "ursula_le_guin@gmail.com" }),
+    )
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    let data: serde_json::Value = app
+        .admin_get("/admin/api/data_subjects?email=ursula_le_guin%40gmail.com")
+        .await
+        .json()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(data["subscriptions"][0]["status"], "confirmed");
+    assert_eq!(data["erased_at"], serde_json::Value::Null);
+}
@@ -0,0 +1,229 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::enqueue_delivery_tasks;
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+async fn insert_confirmed_subscriber(pool: &PgPool, email: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'Subscriber', now(), 'confirmed')
+        "#,
+        id,
+        email,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to insert subscriber.");
+    id
+}
+
+/// Publish an issue to two subscribers and have the email provider reject it
+async fn dead_letter_an_issue(app: &TestApp) -> (Uuid, Vec<Uuid>) {
+    let subscriber_ids = vec![
+        insert_confirmed_subscriber(&app.database_pool, "ursula@example.com").await,
+        insert_confirmed_subscriber(&app.database_pool, "octavia@example.com").await,
+    ];
+    let issue_id = app
+        .publish_issue("hello", "Hello", "Content", false, 0)
+        .await;
+    let mut transaction = app.database_pool.begin().await.unwrap();
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    let _guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(400).set_body_string("Invalid payload"))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    (issue_id, subscriber_ids)
+}
+
+async fn delivery_statuses(pool: &PgPool) -> Vec<String> {
+    sqlx::query!("SELECT status FROM issue_deliveries ORDER BY status")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.status)
+        .collect()
+}
+
+#[tokio::test]
+async fn dead_letters_require_an_authenticated_admin() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let anonymous = client
+        .get(format!("{}/admin/dead_letters", app.address))
+        .send()
+        .await
+        .unwrap();
+    let wrong_password = client
+        .post(format!("{}/admin/dead_letters/replay", app.address))
+        .basic_auth(&app.test_user.username, Some("wrong password"))
+        .json(&serde_json::json!({ "newsletter_issue_id": Uuid::new_v4() }))
+        .send()
+        .await
+        .unwrap();
+    let unknown_user = client
+        .get(format!("{}/admin/dead_letters", app.address))
+        .basic_auth(Uuid::new_v4().to_string(), Some(&app.test_user.password))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    for response in [anonymous, wrong_password, unknown_user] {
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Basic realm="admin""#
+        );
+    }
+}
+
+#[tokio::test]
+async fn permanently_failed_deliveries_are_listed_as_dead_letters() {
+    // Arrange
+    let app = spawn_app().await;
+    let (issue_id, _) = dead_letter_an_issue(&app).await;
+
+    // Act
+    let response = app
+        .admin_get(&format!(
+            "/admin/dead_letters?newsletter_issue_id={issue_id}"
+        ))
+        .await;
+    let other_issue = app
+        .admin_get(&format!(
+            "/admin/dead_letters?newsletter_issue_id={}",
+            Uuid::new_v4()
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let dead_letters: serde_json::Value = response.json().await.unwrap();
+    let other_issue: serde_json::Value = other_issue.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    let dead_letters = dead_letters.as_array().unwrap();
+    assert_eq!(dead_letters.len(), 2);
+    for dead_letter in dead_letters {
+        assert_eq!(dead_letter["newsletter_issue_id"], issue_id.to_string());
+        assert_eq!(dead_letter["error_kind"], "rejected");
+        assert!(dead_letter["error"]
+            .as_str()
+            .unwrap()
+            .contains("Invalid payload"));
+        assert_eq!(dead_letter["n_attempts"], 1);
+        assert!(dead_letter["failed_at"].is_string());
+    }
+    assert_eq!(other_issue, serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn the_number_of_dead_letters_listed_is_clamped() {
+    // Arrange
+    let app = spawn_app().await;
+    dead_letter_an_issue(&app).await;
+
+    // Act
+    let mut lengths = Vec::new();
+    for limit in ["-1", "0", "1", "9223372036854775807"] {
+        let dead_letters: Option<serde_json::Value> = app
+            .admin_get(&format!("/admin/dead_letters?limit={limit}"))
+            .await
+            .json()
+            .await
+            .ok();
+        lengths.push(dead_letters.and_then(|dead_letters| dead_letters.as_array().map(Vec::len)));
+    }
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(lengths, [Some(1), Some(1), Some(1), Some(2)]);
+}
+
+#[tokio::test]
+async fn replaying_an_issue_requeues_all_of_its_dead_letters() {
+    // Arrange
+    let app = spawn_app().await;
+    let (issue_id, _) = dead_letter_an_issue(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .admin_post(
+            "/admin/dead_letters/replay",
+            &serde_json::json!({ "newsletter_issue_id": issue_id }),
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let statuses = delivery_statuses(&app.database_pool).await;
+    let remaining: serde_json::Value = app
+        .admin_get("/admin/dead_letters")
+        .await
+        .json()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(body["replayed"], 2);
+    assert_eq!(statuses, vec!["sent", "sent"]);
+    assert_eq!(remaining, serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn selected_dead_letters_can_be_replayed() {
+    // Arrange
+    let app = spawn_app().await;
+    let (issue_id, subscriber_ids) = dead_letter_an_issue(&app).await;
+
+    // Act
+    let response = app
+        .admin_post(
+            "/admin/dead_letters/replay",
+            &serde_json::json!({
+                "newsletter_issue_id": issue_id,
+                "subscriber_ids": [subscriber_ids[0]],
+            }),
+        )
+        .await;
+
+    // Assert
+    let body: serde_json::Value = response.json().await.unwrap();
+    let statuses = delivery_statuses(&app.database_pool).await;
+    let remaining: serde_json::Value = app
+        .admin_get("/admin/dead_letters")
+        .await
+        .json()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(body["replayed"], 1);
+    assert_eq!(statuses, vec!["failed", "queued"]);
+    let remaining = remaining.as_array().unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0]["subscriber_id"], subscriber_ids[1].to_string());
+}
@@ -0,0 +1,204 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::enqueue_delivery_tasks;
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+async fn insert_confirmed_subscriber(pool: &PgPool, email: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'Subscriber', now(), 'confirmed')
+        "#,
+        id,
+        email,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to insert subscriber.");
+    id
+}
+
+fn event(kind: &str, email: &str, subscriber_id: Uuid, issue_id: Uuid) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "timestamp": chrono::Utc::now().timestamp(),
+        "event": kind,
+        "sg_event_id": Uuid::new_v4().to_string(),
+        "subscriber_id": subscriber_id.to_string(),
+        "newsletter_issue_id": issue_id.to_string(),
+    })
+}
+
+/// Send an issue to Ursula and Octavia, who open it and, for Ursula, click
+/// through and unsubscribe, while Nnedi unsubscribed before it went out.
+async fn deliver_an_issue(app: &TestApp) -> Uuid {
+    let ursula = insert_confirmed_subscriber(&app.database_pool, "ursula@example.com").await;
+    let octavia = insert_confirmed_subscriber(&app.database_pool, "octavia@example.com").await;
+    let nnedi = insert_confirmed_subscriber(&app.database_pool, "nnedi@example.com").await;
+    let issue_id = app
+        .publish_issue("hello", "Hello <everyone>", "Content", false, 0)
+        .await;
+    let mut transaction = app.database_pool.begin().await.unwrap();
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'pending_confirmation' WHERE id = $1",
+        nnedi
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    let response = app
+        .post_sendgrid_events(&serde_json::json!([
+            event("delivered", "ursula@example.com", ursula, issue_id),
+            event("open", "ursula@example.com", ursula, issue_id),
+            event("open", "ursula@example.com", ursula, issue_id),
+            event("click", "ursula@example.com", ursula, issue_id),
+            event("unsubscribe", "ursula@example.com", ursula, issue_id),
+            event("open", "octavia@example.com", octavia, issue_id),
+            event("bounce", "nnedi@example.com", nnedi, issue_id),
+        ]))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    issue_id
+}
+
+#[tokio::test]
+async fn the_report_counts_deliveries_per_status_and_recipients_per_event() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = deliver_an_issue(&app).await;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/api/issues/{issue_id}/report"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(
+        report["counts"],
+        serde_json::json!({
+            "queued": 0,
+            "sent": 2,
+            "failed": 1,
+            "bounced": 1,
+            "opened": 2,
+            "clicked": 1,
+            "unsubscribed": 1,
+        })
+    );
+    let timings = &report["timings"];
+    assert!(timings["first_queued_at"].is_string());
+    assert!(timings["first_sent_at"].is_string());
+    assert!(timings["last_sent_at"].is_string());
+    assert!(timings["average_delivery_seconds"].as_f64().unwrap() >= 0.0);
+    assert!(timings["first_opened_at"].is_string());
+}
+
+#[tokio::test]
+async fn the_report_can_be_exported_as_csv_with_a_row_per_recipient() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = deliver_an_issue(&app).await;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/api/issues/{issue_id}/report.csv"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let headers = reader.headers().unwrap().clone();
+    let column = |name: &str| headers.iter().position(|header| header == name).unwrap();
+    let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    let emails: Vec<&str> = rows.iter().map(|row| &row[column("email")]).collect();
+    assert_eq!(
+        emails,
+        [
+            "nnedi@example.com",
+            "octavia@example.com",
+            "ursula@example.com"
+        ]
+    );
+    let nnedi = &rows[0];
+    assert_eq!(&nnedi[column("status")], "failed");
+    assert!(!nnedi[column("bounced_at")].is_empty());
+    let octavia = &rows[1];
+    assert_eq!(&octavia[column("status")], "sent");
+    assert!(!octavia[column("opened_at")].is_empty());
+    assert!(octavia[column("clicked_at")].is_empty());
+    let ursula = &rows[2];
+    assert!(!ursula[column("clicked_at")].is_empty());
+    assert!(!ursula[column("unsubscribed_at")].is_empty());
+}
+
+#[tokio::test]
+async fn the_report_page_shows_the_counts_and_links_to_the_export() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = deliver_an_issue(&app).await;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/issues/{issue_id}/report"))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert!(page.contains("<h1>Hello &lt;everyone&gt;</h1>"));
+    assert!(page.contains("<tr><th>Opened</th><td>2</td></tr>"));
+    assert!(page.contains(&format!("/admin/api/issues/{issue_id}/report.csv")));
+}
+
+#[tokio::test]
+async fn reports_require_an_admin_and_an_existing_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let anonymous = reqwest::Client::new()
+        .get(format!(
+            "{}/admin/api/issues/{}/report",
+            app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .unwrap();
+    let unknown_issue = app
+        .admin_get(&format!("/admin/api/issues/{}/report", Uuid::new_v4()))
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(anonymous.status().as_u16(), 401);
+    assert_eq!(unknown_issue.status().as_u16(), 404);
+}
@@ -0,0 +1,139 @@
+use reqwest::Method;
+use serde_json::json;
+use zero2prod::authorization::Role;
+
+use crate::helpers::{clean_up_database, spawn_app};
+
+#[tokio::test]
+async fn editors_draft_issues_that_publishers_publish() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = app.add_user(Role::Editor).await;
+    let publisher = app.add_user(Role::Publisher).await;
+
+    // Act
+    let drafted: serde_json::Value = app
+        .request_as(
+            &editor,
+            Method::POST,
+            "/admin/api/issues",
+            Some(&json!({ "slug": "spring", "title": "Spring", "content": "Hello" })),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    let id = drafted["id"].as_str().unwrap().to_string();
+    let draft_archive = app.get_archive("/archive/spring").await;
+    let changed: serde_json::Value = app
+        .request_as(
+            &editor,
+            Method::PATCH,
+            &format!("/admin/api/issues/{id}"),
+            Some(&json!({ "content": "Hello *again*" })),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    let published = app
+        .request_as(
+            &publisher,
+            Method::POST,
+            &format!("/admin/api/issues/{id}/publish"),
+            None,
+        )
+        .await;
+    let published_archive = app.get_archive("/archive/spring").await;
+    let changed_after_publishing = app
+        .request_as(
+            &editor,
+            Method::PATCH,
+            &format!("/admin/api/issues/{id}"),
+            Some(&json!({ "title": "Summer" })),
+        )
+        .await;
+    let published_again = app
+        .request_as(
+            &publisher,
+            Method::POST,
+            &format!("/admin/api/issues/{id}/publish"),
+            None,
+        )
+        .await;
+
+    // Assert
+    assert_eq!(published.status().as_u16(), 200);
+    let published: serde_json::Value = published.json().await.unwrap();
+    let archive_page = published_archive.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(drafted["published_at"], serde_json::Value::Null);
+    assert_eq!(draft_archive.status().as_u16(), 404);
+    assert_eq!(changed["content"], "Hello *again*");
+    assert_eq!(changed["title"], "Spring");
+    assert!(published["published_at"].is_string());
+    assert!(archive_page.contains("<em>again</em>"));
+    assert_eq!(changed_after_publishing.status().as_u16(), 409);
+    assert_eq!(published_again.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn invalid_drafts_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.admin_post(
+        "/admin/api/issues",
+        &json!({ "slug": "spring", "title": "Spring", "content": "Hello" }),
+    )
+    .await;
+
+    // Act
+    let taken_slug = app
+        .admin_post(
+            "/admin/api/issues",
+            &json!({ "slug": "spring", "title": "Another spring", "content": "Hello" }),
+        )
+        .await;
+    let invalid_slug = app
+        .admin_post(
+            "/admin/api/issues",
+            &json!({ "slug": "../admin", "title": "Spring", "content": "Hello" }),
+        )
+        .await;
+    let empty_title = app
+        .admin_post(
+            "/admin/api/issues",
+            &json!({ "slug": "summer", "title": " ", "content": "Hello" }),
+        )
+        .await;
+    let summer: serde_json::Value = app
+        .admin_post(
+            "/admin/api/issues",
+            &json!({ "slug": "summer", "title": "Summer", "content": "Hello" }),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    let renamed_to_taken_slug = app
+        .admin_patch(
+            &format!("/admin/api/issues/{}", summer["id"].as_str().unwrap()),
+            &json!({ "slug": "spring" }),
+        )
+        .await;
+    let unknown = app
+        .admin_post(
+            &format!("/admin/api/issues/{}/publish", uuid::Uuid::new_v4()),
+            &json!({}),
+        )
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(taken_slug.status().as_u16(), 409);
+    assert_eq!(invalid_slug.status().as_u16(), 400);
+    assert_eq!(empty_title.status().as_u16(), 400);
+    assert_eq!(renamed_to_taken_slug.status().as_u16(), 409);
+    assert_eq!(unknown.status().as_u16(), 404);
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+
+use reqwest::{redirect::Policy, Method, Response, Url};
+use secrecy::Secret;
+use serde_json::{json, Value};
+use zero2prod::{authorization::Role, configuration::OidcSettings};
+
+use crate::{
+    helpers::{clean_up_database, spawn_app, spawn_app_with, TestApp},
+    oidc_server::{OidcServer, CLIENT_ID, CLIENT_SECRET},
+};
+
+async fn spawn_app_with_oidc(oidc_server: &OidcServer) -> TestApp {
+    let issuer_url = oidc_server.issuer();
+    spawn_app_with(|c| {
+        c.oidc = Some(OidcSettings {
+            issuer_url,
+            client_id: CLIENT_ID.to_string(),
+            client_secret: Secret::new(CLIENT_SECRET.to_string()),
+            role_claim: "groups".to_string(),
+            roles: HashMap::from([
+                ("newsletter-editors".to_string(), Role::Editor),
+                ("newsletter-admins".to_string(), Role::Owner),
+            ]),
+            username_claim: "email".to_string(),
+        });
+    })
+    .await
+}
+
+/// Start a login, sign in with the provider as `claims`, and call back with
+/// the state cookie unless `with_cookie` is false
+async fn sign_in(
+    app: &TestApp,
+    oidc_server: &OidcServer,
+    claims: Value,
+    with_cookie: bool,
+) -> Response {
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap();
+    let login = client
+        .get(format!("{}/admin/oidc/login", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(login.status().as_u16(), 303);
+    let cookie = login
+        .headers()
+        .get("Set-Cookie")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+    let authorization_url = Url::parse(login.headers()["Location"].to_str().unwrap()).unwrap();
+
+    let (code, state) = oidc_server.sign_in(&authorization_url, claims);
+
+    let callback = client
+        .get(format!("{}/admin/oidc/callback", app.address))
+        .query(&[("code", code), ("state", state)]);
+    if with_cookie {
+        callback.header("Cookie", cookie)
+    } else {
+        callback
+    }
+    .send()
+    .await
+    .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn admins_are_created_at_their_first_sign_in_with_the_role_their_groups_grant() {
+    // Arrange
+    let oidc_server = OidcServer::start().await;
+    let app = spawn_app_with_oidc(&oidc_server).await;
+    let claims = |groups: Value| json!({ "sub": "ursula-le-guin", "email": "ursula@example.com", "groups": groups });
+
+    // Act
+    let first = sign_in(
+        &app,
+        &oidc_server,
+        claims(json!(["newsletter-editors"])),
+        true,
+    )
+    .await;
+    let first: Value = first.json().await.unwrap();
+    let session_token = first["session_token"].as_str().unwrap();
+    let subscribers = app
+        .request_with_session(session_token, Method::GET, "/admin/api/subscribers", None)
+        .await;
+    let users = app
+        .request_with_session(session_token, Method::GET, "/admin/api/users", None)
+        .await;
+    let with_password = reqwest::Client::new()
+        .get(format!("{}/admin/api/subscribers", app.address))
+        .basic_auth("ursula@example.com", Some(""))
+        .send()
+        .await
+        .unwrap();
+    let second = sign_in(&app, &oidc_server, claims(json!("newsletter-admins")), true).await;
+
+    // Assert
+    let stored = sqlx::query!(
+        "SELECT username, role, password_hash, oidc_subject FROM users WHERE oidc_issuer = $1",
+        oidc_server.issuer(),
+    )
+    .fetch_all(&app.database_pool)
+    .await
+    .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(first["second_factor_required"], false);
+    assert_eq!(subscribers.status().as_u16(), 200);
+    assert_eq!(users.status().as_u16(), 403);
+    assert_eq!(with_password.status().as_u16(), 401);
+    assert_eq!(second.status().as_u16(), 200);
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].username, "ursula@example.com");
+    assert_eq!(stored[0].role, "owner");
+    assert_eq!(stored[0].password_hash, None);
+    assert_eq!(stored[0].oidc_subject.as_deref(), Some("ursula-le-guin"));
+}
+
+#[tokio::test]
+async fn admins_granted_no_role_cannot_sign_in() {
+    // Arrange
+    let oidc_server = OidcServer::start().await;
+    let app = spawn_app_with_oidc(&oidc_server).await;
+
+    // Act
+    let response = sign_in(
+        &app,
+        &oidc_server,
+        json!({ "sub": "ged", "email": "ged@example.com", "groups": ["staff"] }),
+        true,
+    )
+    .await;
+
+    // Assert
+    let users = sqlx::query!("SELECT user_id FROM users WHERE oidc_subject IS NOT NULL")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(users.is_empty());
+}
+
+#[tokio::test]
+async fn role_changes_at_sign_in_end_the_sessions_started_with_the_previous_role() {
+    // Arrange
+    let oidc_server = OidcServer::start().await;
+    let app = spawn_app_with_oidc(&oidc_server).await;
+    let claims = |groups: Value| json!({ "sub": "ursula-le-guin", "email": "ursula@example.com", "groups": groups });
+    let as_editor: Value = sign_in(
+        &app,
+        &oidc_server,
+        claims(json!("newsletter-editors")),
+        true,
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    let same_role: Value = sign_in(
+        &app,
+        &oidc_server,
+        claims(json!("newsletter-editors")),
+        true,
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+
+    // Act
+    let as_owner: Value = sign_in(&app, &oidc_server, claims(json!("newsletter-admins")), true)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let first_editor_session = app
+        .request_with_session(
+            as_editor["session_token"].as_str().unwrap(),
+            Method::GET,
+            "/admin/api/subscribers",
+            None,
+        )
+        .await;
+    let second_editor_session = app
+        .request_with_session(
+            same_role["session_token"].as_str().unwrap(),
+            Method::GET,
+            "/admin/api/subscribers",
+            None,
+        )
+        .await;
+    let owner_session = app
+        .request_with_session(
+            as_owner["session_token"].as_str().unwrap(),
+            Method::GET,
+            "/admin/api/users",
+            None,
+        )
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(first_editor_session.status().as_u16(), 401);
+    assert_eq!(second_editor_session.status().as_u16(), 401);
+    assert_eq!(owner_session.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn admins_no_longer_granted_a_role_lose_their_sessions_and_api_keys() {
+    // Arrange
+    let oidc_server = OidcServer::start().await;
+    let app = spawn_app_with_oidc(&oidc_server).await;
+    let claims = |groups: Value| json!({ "sub": "ursula-le-guin", "email": "ursula@example.com", "groups": groups });
+    let signed_in: Value = sign_in(&app, &oidc_server, claims(json!("newsletter-admins")), true)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let session_token = signed_in["session_token"].as_str().unwrap();
+    let api_key: Value = app
+        .request_with_session(
+            session_token,
+            Method::POST,
+            "/admin/api/api_keys",
+            Some(&json!({ "name": "crm", "scopes": ["subscribers:read"] })),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    let api_key = api_key["key"].as_str().unwrap();
+
+    // Act
+    let response = sign_in(&app, &oidc_server, claims(json!(["staff"])), true).await;
+    let with_session = app
+        .request_with_session(session_token, Method::GET, "/admin/api/subscribers", None)
+        .await;
+    let with_api_key = app
+        .request_with_session(api_key, Method::GET, "/admin/api/subscribers", None)
+        .await;
+
+    // Assert
+    let disabled =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM audit_log WHERE action = 'user.disable'"#)
+            .fetch_one(&app.database_pool)
+            .await
+            .unwrap()
+            .count;
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(with_session.status().as_u16(), 401);
+    assert_eq!(with_api_key.status().as_u16(), 401);
+    assert_eq!(disabled, 1);
+}
+
+#[tokio::test]
+async fn concurrent_first_sign_ins_create_a_single_admin() {
+    // Arrange
+    let oidc_server = OidcServer::start().await;
+    let app = spawn_app_with_oidc(&oidc_server).await;
+    let claims = json!({ "sub": "ursula-le-guin", "email": "ursula@example.com", "groups": ["newsletter-editors"] });
+
+    // Act
+    let (first, second) = tokio::join!(
+        sign_in(&app, &oidc_server, claims.clone(), true),
+        sign_in(&app, &oidc_server, claims.clone(), true),
+    );
+
+    // Assert
+    let users = sqlx::query!("SELECT user_id FROM users WHERE oidc_subject IS NOT NULL")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    assert_eq!(users.len(), 1);
+}
+
+#[tokio::test]
+async fn callbacks_are_rejected_without_the_state_cookie_or_the_code_verifier() {
+    // Arrange
+    let oidc_server = OidcServer::start().await;
+    let app = spawn_app_with_oidc(&oidc_server).await;
+    let claims =
+        json!({ "sub": "tenar", "email": "tenar@example.com", "groups": "newsletter-admins" });
+
+    // Act
+    let without_cookie = sign_in(&app, &oidc_server, claims.clone(), false).await;
+    let client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap();
+    let login = client
+        .get(format!("{}/admin/oidc/login", app.address))
+        .send()
+        .await
+        .unwrap();
+    let cookie = login.headers()["Set-Cookie"]
+        .to_str()
+        .unwrap()
+        .split(';')
+        .next()
+        .unwrap()
+        .to_string();
+    let (code, state) = oidc_server.sign_in(
+        &Url::parse(login.headers()["Location"].to_str().unwrap()).unwrap(),
+        claims,
+    );
+    // As if the code were intercepted and redeemed with another verifier
+    sqlx::query!(
+        "UPDATE oidc_logins SET code_verifier = 'intercepted' WHERE state = $1",
+        state
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+    let wrong_verifier = client
+        .get(format!("{}/admin/oidc/callback", app.address))
+        .query(&[("code", code), ("state", state)])
+        .header("Cookie", cookie)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(without_cookie.status().as_u16(), 401);
+    assert_eq!(wrong_verifier.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn single_sign_on_is_not_routed_unless_configured() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/oidc/login", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
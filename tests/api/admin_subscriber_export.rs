@@ -0,0 +1,209 @@
+use serde_json::json;
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+async fn add_subscriber(app: &TestApp, email: &str, lists: &[&str]) {
+    let response = app
+        .admin_post(
+            "/admin/api/subscribers",
+            &json!({
+                "email": email,
+                "name": "Reader",
+                "lists": lists,
+                "double_opt_in": false,
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_with_their_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    add_subscriber(&app, "ursula@example.com", &["fiction", "essays"]).await;
+    add_subscriber(&app, "ted@example.com", &[]).await;
+
+    // Act
+    let response = app.admin_get("/admin/api/subscribers/export").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    assert_eq!(
+        reader.headers().unwrap(),
+        vec![
+            "id",
+            "email",
+            "name",
+            "status",
+            "subscribed_at",
+            "confirmed_at",
+            "unsubscribed_at",
+            "lists"
+        ]
+    );
+    let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(&rows[0][1], "ursula@example.com");
+    assert_eq!(&rows[0][3], "confirmed");
+    assert!(!rows[0][5].is_empty());
+    assert_eq!(&rows[0][6], "");
+    assert_eq!(&rows[0][7], "essays;fiction");
+    assert_eq!(&rows[1][1], "ted@example.com");
+}
+
+#[tokio::test]
+async fn filtered_subscribers_are_exported_as_json_lines() {
+    // Arrange
+    let app = spawn_app().await;
+    add_subscriber(&app, "ursula@example.com", &["fiction"]).await;
+    add_subscriber(&app, "octavia@example.com", &["fiction"]).await;
+    add_subscriber(&app, "ted@example.com", &["essays"]).await;
+    let octavia_id =
+        sqlx::query!("SELECT id FROM subscriptions WHERE email = 'octavia@example.com'")
+            .fetch_one(&app.database_pool)
+            .await
+            .unwrap()
+            .id;
+    app.admin_patch(
+        &format!("/admin/api/subscribers/{octavia_id}"),
+        &json!({ "status": "unsubscribed" }),
+    )
+    .await;
+
+    // Act
+    let response = app
+        .admin_get("/admin/api/subscribers/export?format=jsonl&list=fiction&status=unsubscribed")
+        .await;
+    let invalid = app
+        .admin_get("/admin/api/subscribers/export?status=gone")
+        .await;
+    let last_day = app
+        .admin_get(&format!(
+            "/admin/api/subscribers/export?subscribed_to={}",
+            chrono::NaiveDate::MAX
+        ))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    let subscribers: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(subscribers.len(), 1);
+    assert_eq!(subscribers[0]["email"], "octavia@example.com");
+    assert_eq!(subscribers[0]["lists"], json!(["fiction"]));
+    assert!(subscribers[0]["unsubscribed_at"].is_string());
+    assert_eq!(invalid.status().as_u16(), 400);
+    assert_eq!(last_day.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn exports_larger_than_a_chunk_have_a_single_header() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT gen_random_uuid(), 'reader' || i || '@example.com', 'Reader',
+            now() - i * interval '1 second', 'confirmed'
+        FROM generate_series(1, 2500) AS i
+        "#
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.admin_get("/admin/api/subscribers/export").await;
+
+    // Assert
+    let csv = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let emails: Vec<String> = reader
+        .records()
+        .map(|record| record.unwrap()[1].to_string())
+        .collect();
+    assert_eq!(emails.len(), 2500);
+    assert_eq!(emails[0], "reader2500@example.com");
+    assert_eq!(emails[2499], "reader1@example.com");
+}
+
+#[tokio::test]
+async fn exports_without_subscribers_have_a_header() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .admin_get("/admin/api/subscribers/export?status=unsubscribed")
+        .await;
+
+    // Assert
+    let status = response.status().as_u16();
+    let csv = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(
+        csv,
+        "id,email,name,status,subscribed_at,confirmed_at,unsubscribed_at,lists\n"
+    );
+}
+
+#[tokio::test]
+async fn exported_cells_are_not_evaluated_as_formulas() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES (gen_random_uuid(), 'ursula@example.com', '=HYPERLINK("https://evil.example")',
+            now(), 'confirmed')
+        "#
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.admin_get("/admin/api/subscribers/export").await;
+
+    // Assert
+    let csv = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    let row = reader.records().next().unwrap().unwrap();
+    assert_eq!(&row[2], r#"'=HYPERLINK("https://evil.example")"#);
+}
+
+#[tokio::test]
+async fn exporting_requires_an_authenticated_admin() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!("{}/admin/api/subscribers/export", app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
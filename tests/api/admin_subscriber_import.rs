@@ -0,0 +1,258 @@
+use serde_json::json;
+
+use crate::helpers::{clean_up_database, spawn_app};
+
+#[tokio::test]
+async fn imported_rows_are_reported_as_accepted_duplicate_or_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.admin_post(
+        "/admin/api/subscribers",
+        &json!({
+            "email": "ursula@example.com",
+            "name": "Ursula",
+            "double_opt_in": false,
+        }),
+    )
+    .await;
+    let csv = "\
+Name,Email,Tags
+Octavia Butler,octavia@example.com,fiction;classics
+Ursula Le Guin,ursula@example.com,fiction
+Ted Chiang,not-an-email,essays
+Ted Chiang,ted@example.com,
+Octavia E. Butler,octavia@example.com,
+,nameless@example.com,
+";
+
+    // Act
+    let response = app.import_subscribers("generic", csv).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    let octavia: serde_json::Value = app
+        .admin_get("/admin/api/subscribers?search=octavia")
+        .await
+        .json()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(report["accepted"], 2);
+    assert_eq!(report["duplicates"], 2);
+    assert_eq!(report["rejected"], 2);
+    let outcomes: Vec<_> = report["rows"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| {
+            (
+                row["line"].as_u64().unwrap(),
+                row["outcome"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            (2, "accepted"),
+            (3, "duplicate"),
+            (4, "rejected"),
+            (5, "accepted"),
+            (6, "duplicate"),
+            (7, "rejected"),
+        ]
+    );
+    assert!(report["rows"][2]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("not-an-email"));
+    assert_eq!(report["rows"][5]["email"], "nameless@example.com");
+
+    let octavia = &octavia["subscribers"][0];
+    assert_eq!(octavia["name"], "Octavia Butler");
+    assert_eq!(octavia["status"], "confirmed");
+    assert_eq!(octavia["lists"], json!(["classics", "fiction"]));
+}
+
+#[tokio::test]
+async fn email_addresses_differing_only_in_case_are_duplicates() {
+    // Arrange
+    let app = spawn_app().await;
+    app.admin_post(
+        "/admin/api/subscribers",
+        &json!({
+            "email": "ursula@example.com",
+            "name": "Ursula",
+            "double_opt_in": false,
+        }),
+    )
+    .await;
+    let csv = "\
+email,name
+Ursula@Example.com,Ursula Le Guin
+octavia@example.com,Octavia Butler
+OCTAVIA@example.com,Octavia E. Butler
+";
+
+    // Act
+    let response = app.import_subscribers("generic", csv).await;
+
+    // Assert
+    let report: serde_json::Value = response.json().await.unwrap();
+    let stored = sqlx::query!("SELECT email FROM subscriptions ORDER BY email")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(report["accepted"], 1);
+    assert_eq!(report["duplicates"], 2);
+    assert_eq!(
+        stored.into_iter().map(|row| row.email).collect::<Vec<_>>(),
+        vec!["octavia@example.com", "ursula@example.com"]
+    );
+}
+
+#[tokio::test]
+async fn csvs_larger_than_a_batch_and_a_request_buffer_are_imported() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut csv = String::from("email,name,lists\n");
+    for i in 0..1_200 {
+        csv.push_str(&format!(
+            "subscriber-{i}@example.com,Subscriber {i},{}\n",
+            "x".repeat(250)
+        ));
+    }
+
+    // Act
+    let response = app.import_subscribers("generic", &csv).await;
+
+    // Assert
+    let status = response.status().as_u16();
+    let report: serde_json::Value = response.json().await.unwrap();
+    let stored = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert!(csv.len() > 256 * 1024);
+    assert_eq!(status, 200);
+    assert_eq!(report["accepted"], 1_200);
+    assert_eq!(report["rows"][1_199]["line"], 1_201);
+    assert_eq!(stored.count, 1_200);
+}
+
+#[tokio::test]
+async fn a_csv_without_an_email_column_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .import_subscribers("generic", "name,address\nUrsula,ursula@example.com\n")
+        .await;
+
+    // Assert
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(status, 400);
+    assert!(body.contains("email"));
+}
+
+#[tokio::test]
+async fn importing_requires_an_authenticated_admin() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/api/subscribers/import", app.address))
+        .body("email,name\nursula@example.com,Ursula\n")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(subscribers.is_empty());
+}
+
+#[tokio::test]
+async fn mailchimp_audience_exports_are_imported_with_their_statuses() {
+    // Arrange
+    let app = spawn_app().await;
+    let csv = r#"Email Address,First Name,Last Name,OPTIN_TIME,CONFIRM_TIME,CLEAN_TIME,TAGS
+ursula@example.com,Ursula,Le Guin,2023-01-14 08:00:00,2023-01-15 10:22:01,,"""fiction"",""essays"""
+ted@example.com,,,2023-02-01 09:00:00,,2023-06-01 12:00:00,
+"#;
+
+    // Act
+    let response = app.import_subscribers("mailchimp", csv).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report: serde_json::Value = response.json().await.unwrap();
+    let subscribers = sqlx::query!(
+        "SELECT email, name, status, subscribed_at FROM subscriptions ORDER BY email DESC"
+    )
+    .fetch_all(&app.database_pool)
+    .await
+    .unwrap();
+    let unknown_format = app.import_subscribers("revue", csv).await;
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(report["accepted"], 2);
+    assert_eq!(subscribers[0].email, "ursula@example.com");
+    assert_eq!(subscribers[0].name, "Ursula Le Guin");
+    assert_eq!(subscribers[0].status, "confirmed");
+    assert_eq!(
+        subscribers[0].subscribed_at.to_rfc3339(),
+        "2023-01-15T10:22:01+00:00"
+    );
+    assert_eq!(subscribers[1].name, "ted");
+    assert_eq!(subscribers[1].status, "unsubscribed");
+    assert_eq!(unknown_format.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn substack_exports_are_imported_with_their_statuses() {
+    // Arrange
+    let app = spawn_app().await;
+    let csv = "\
+email,active_subscription,expiry,plan,email_disabled,created_at
+ursula@example.com,true,,yearly,false,2021-03-04T15:28:31.123Z
+ted@example.com,false,,,true,2021-05-01T08:00:00.000Z
+";
+
+    // Act
+    let response = app.import_subscribers("substack", csv).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers =
+        sqlx::query!("SELECT email, status, subscribed_at FROM subscriptions ORDER BY email DESC")
+            .fetch_all(&app.database_pool)
+            .await
+            .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(subscribers[0].email, "ursula@example.com");
+    assert_eq!(subscribers[0].status, "confirmed");
+    assert_eq!(
+        subscribers[0].subscribed_at.to_rfc3339(),
+        "2021-03-04T15:28:31.123+00:00"
+    );
+    assert_eq!(subscribers[1].status, "unsubscribed");
+}
@@ -0,0 +1,441 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+/// Add a subscriber as confirmed, without a confirmation email
+async fn add_subscriber(app: &TestApp, email: &str, name: &str, lists: &[&str]) -> Uuid {
+    let response = app
+        .admin_post(
+            "/admin/api/subscribers",
+            &serde_json::json!({
+                "email": email,
+                "name": name,
+                "lists": lists,
+                "double_opt_in": false,
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    subscriber["id"].as_str().unwrap().parse().unwrap()
+}
+
+fn emails(page: &serde_json::Value) -> Vec<&str> {
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|subscriber| subscriber["email"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn subscribers_require_an_authenticated_admin() {
+    // Arrange
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    // Act
+    let listing = client
+        .get(format!("{}/admin/api/subscribers", app.address))
+        .send()
+        .await
+        .unwrap();
+    let deletion = client
+        .delete(format!(
+            "{}/admin/api/subscribers/{}",
+            app.address,
+            Uuid::new_v4()
+        ))
+        .basic_auth(&app.test_user.username, Some("wrong password"))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(listing.status().as_u16(), 401);
+    assert_eq!(deletion.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_a_page_at_a_time_most_recent_first() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..5 {
+        add_subscriber(&app, &format!("reader{i}@example.com"), "Reader", &[]).await;
+    }
+
+    // Act
+    let mut pages = vec![];
+    let mut path = "/admin/api/subscribers?limit=2".to_string();
+    loop {
+        let page: serde_json::Value = app.admin_get(&path).await.json().await.unwrap();
+        let next_cursor = page["next_cursor"].as_str().map(ToString::to_string);
+        pages.push(page);
+        match next_cursor {
+            Some(cursor) => path = format!("/admin/api/subscribers?limit=2&cursor={cursor}"),
+            None => break,
+        }
+    }
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    let pages: Vec<_> = pages.iter().map(emails).collect();
+    assert_eq!(
+        pages,
+        vec![
+            vec!["reader4@example.com", "reader3@example.com"],
+            vec!["reader2@example.com", "reader1@example.com"],
+            vec!["reader0@example.com"],
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_are_filtered_and_searched() {
+    // Arrange
+    let app = spawn_app().await;
+    add_subscriber(&app, "ursula@example.com", "Ursula Le Guin", &["fiction"]).await;
+    add_subscriber(&app, "octavia@example.com", "Octavia Butler", &["fiction"]).await;
+    let ted = add_subscriber(&app, "ted@example.com", "Ted Chiang", &["essays"]).await;
+    app.admin_patch(
+        &format!("/admin/api/subscribers/{ted}"),
+        &serde_json::json!({ "status": "unsubscribed" }),
+    )
+    .await;
+    let today = chrono::Utc::now().date_naive();
+
+    // Act
+    let by_list = app.admin_get("/admin/api/subscribers?list=fiction").await;
+    let by_status = app
+        .admin_get("/admin/api/subscribers?status=unsubscribed")
+        .await;
+    let by_name = app.admin_get("/admin/api/subscribers?search=BUTLER").await;
+    let by_email = app.admin_get("/admin/api/subscribers?search=ted%40").await;
+    let wildcard = app.admin_get("/admin/api/subscribers?search=%25").await;
+    let by_date = app
+        .admin_get(&format!(
+            "/admin/api/subscribers?subscribed_from={today}&subscribed_to={today}"
+        ))
+        .await;
+    let before = app
+        .admin_get(&format!(
+            "/admin/api/subscribers?subscribed_to={}",
+            today.pred_opt().unwrap()
+        ))
+        .await;
+    let invalid_status = app.admin_get("/admin/api/subscribers?status=gone").await;
+    let invalid_cursor = app.admin_get("/admin/api/subscribers?cursor=nope").await;
+    let last_day = app
+        .admin_get(&format!(
+            "/admin/api/subscribers?subscribed_to={}",
+            chrono::NaiveDate::MAX
+        ))
+        .await;
+
+    // Assert
+    let by_list: serde_json::Value = by_list.json().await.unwrap();
+    let by_status: serde_json::Value = by_status.json().await.unwrap();
+    let by_name: serde_json::Value = by_name.json().await.unwrap();
+    let by_email: serde_json::Value = by_email.json().await.unwrap();
+    let wildcard: serde_json::Value = wildcard.json().await.unwrap();
+    let by_date: serde_json::Value = by_date.json().await.unwrap();
+    let before: serde_json::Value = before.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(
+        emails(&by_list),
+        vec!["octavia@example.com", "ursula@example.com"]
+    );
+    assert_eq!(
+        by_list["subscribers"][0]["lists"],
+        serde_json::json!(["fiction"])
+    );
+    assert_eq!(emails(&by_status), vec!["ted@example.com"]);
+    assert_eq!(emails(&by_name), vec!["octavia@example.com"]);
+    assert_eq!(emails(&by_email), vec!["ted@example.com"]);
+    assert!(emails(&wildcard).is_empty());
+    assert_eq!(emails(&by_date).len(), 3);
+    assert!(emails(&before).is_empty());
+    assert_eq!(invalid_status.status().as_u16(), 400);
+    assert_eq!(invalid_cursor.status().as_u16(), 400);
+    assert_eq!(last_day.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_subscriber_is_shown_with_their_tokens_and_status_history() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap();
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap()
+        .id;
+
+    // Act
+    let response = app
+        .admin_get(&format!("/admin/api/subscribers/{subscriber_id}"))
+        .await;
+    let unknown = app
+        .admin_get(&format!("/admin/api/subscribers/{}", Uuid::new_v4()))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(subscriber["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscriber["status"], "confirmed");
+    assert_eq!(subscriber["tokens"].as_array().unwrap().len(), 1);
+    let history: Vec<_> = subscriber["status_history"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| change["status"].as_str().unwrap())
+        .collect();
+    assert_eq!(history, vec!["pending_confirmation", "confirmed"]);
+    assert_eq!(unknown.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn subscribers_added_with_double_opt_in_get_a_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .admin_post(
+            "/admin/api/subscribers",
+            &serde_json::json!({ "email": "ursula@example.com", "name": "Ursula" }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 201);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let token = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(key, _)| key == "subscription_token")
+        .unwrap()
+        .1
+        .to_string();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(subscriber["status"], "pending_confirmation");
+    assert_eq!(
+        subscriber["tokens"][0]["subscription_token"],
+        token.as_str()
+    );
+}
+
+#[tokio::test]
+async fn subscribers_added_without_double_opt_in_are_confirmed_right_away() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let id = add_subscriber(&app, "ursula@example.com", "Ursula", &["fiction"]).await;
+    let duplicate = app
+        .admin_post(
+            "/admin/api/subscribers",
+            &serde_json::json!({
+                "email": "ursula@example.com",
+                "name": "Ursula",
+                "double_opt_in": false,
+            }),
+        )
+        .await;
+    let invalid = app
+        .admin_post(
+            "/admin/api/subscribers",
+            &serde_json::json!({ "email": "not-an-email", "name": "Ursula" }),
+        )
+        .await;
+
+    // Assert
+    let subscriber: serde_json::Value = app
+        .admin_get(&format!("/admin/api/subscribers/{id}"))
+        .await
+        .json()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(subscriber["status"], "confirmed");
+    assert_eq!(subscriber["lists"], serde_json::json!(["fiction"]));
+    assert_eq!(subscriber["tokens"], serde_json::json!([]));
+    assert_eq!(duplicate.status().as_u16(), 409);
+    assert_eq!(invalid.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_status_of_a_subscriber_can_be_changed() {
+    // Arrange
+    let app = spawn_app().await;
+    let id = add_subscriber(&app, "ursula@example.com", "Ursula", &[]).await;
+
+    // Act
+    let response = app
+        .admin_patch(
+            &format!("/admin/api/subscribers/{id}"),
+            &serde_json::json!({ "status": "unsubscribed" }),
+        )
+        .await;
+    let invalid = app
+        .admin_patch(
+            &format!("/admin/api/subscribers/{id}"),
+            &serde_json::json!({ "status": "gone" }),
+        )
+        .await;
+    let unknown = app
+        .admin_patch(
+            &format!("/admin/api/subscribers/{}", Uuid::new_v4()),
+            &serde_json::json!({ "status": "confirmed" }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(subscriber["status"], "unsubscribed");
+    assert_eq!(
+        subscriber["status_history"]
+            .as_array()
+            .unwrap()
+            .last()
+            .unwrap()["status"],
+        "unsubscribed"
+    );
+    assert_eq!(invalid.status().as_u16(), 400);
+    assert_eq!(unknown.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn a_subscriber_can_be_deleted_with_their_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.admin_post(
+        "/admin/api/subscribers",
+        &serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "Ursula" }),
+    )
+    .await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap()
+        .id;
+
+    // Act
+    let response = app
+        .admin_delete(&format!("/admin/api/subscribers/{id}"))
+        .await;
+    let again = app
+        .admin_delete(&format!("/admin/api/subscribers/{id}"))
+        .await;
+
+    // Assert
+    let remaining = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap()
+        .count;
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(again.status().as_u16(), 404);
+    assert_eq!(remaining, 0);
+}
+
+#[tokio::test]
+async fn subscribers_with_proof_of_consent_are_erased_rather_than_deleted() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap()
+        .id;
+
+    // Act
+    let response = app
+        .admin_delete(&format!("/admin/api/subscribers/{id}"))
+        .await;
+    let deleted = sqlx::query!("DELETE FROM subscriptions WHERE id = $1", id)
+        .execute(&app.database_pool)
+        .await;
+    let consent_events_kept = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM consent_events"#)
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap()
+        .count;
+    let erasure = app
+        .admin_post(
+            "/admin/api/data_subjects/erase",
+            &serde_json::json!({ "email": "ursula_le_guin@gmail.com" }),
+        )
+        .await;
+
+    // Assert
+    let consent_events_left = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM consent_events"#)
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap()
+        .count;
+    let subscriber = app.admin_get(&format!("/admin/api/subscribers/{id}")).await;
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 409);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("/admin/api/data_subjects/erase"));
+    assert!(deleted.is_err());
+    assert_eq!(consent_events_kept, 1);
+    assert_eq!(erasure.status().as_u16(), 200);
+    assert_eq!(consent_events_left, 0);
+    assert_eq!(subscriber.status().as_u16(), 404);
+}
@@ -0,0 +1,376 @@
+use chrono::Utc;
+use reqwest::Method;
+use secrecy::Secret;
+use serde_json::json;
+use zero2prod::{authorization::Role, totp};
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp, TestUser};
+
+/// Enable TOTP for `user`, returning their secret and recovery codes
+async fn enable_totp(app: &TestApp, user: &TestUser) -> (Secret<String>, Vec<String>) {
+    let enrollment: serde_json::Value = app
+        .request_as(user, Method::POST, "/admin/api/totp/enroll", None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let secret = Secret::new(enrollment["secret"].as_str().unwrap().to_string());
+    let code = totp::code(&secret, Utc::now().timestamp()).unwrap();
+    let confirmation: serde_json::Value = app
+        .request_as(
+            user,
+            Method::POST,
+            "/admin/api/totp/confirm",
+            Some(&json!({ "code": code })),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    let recovery_codes = confirmation["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|code| code.as_str().unwrap().to_string())
+        .collect();
+    (secret, recovery_codes)
+}
+
+/// Log in with the password of `user`, returning the session response
+async fn login(app: &TestApp, user: &TestUser) -> serde_json::Value {
+    reqwest::Client::new()
+        .post(format!("{}/admin/login", app.address))
+        .basic_auth(&user.username, Some(&user.password))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn verify_second_factor(
+    app: &TestApp,
+    session_token: &str,
+    second_factor: &serde_json::Value,
+) -> reqwest::Response {
+    app.request_with_session(
+        session_token,
+        Method::POST,
+        "/admin/login/totp",
+        Some(second_factor),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn admins_with_totp_are_only_authenticated_once_their_code_is_verified() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = app.add_user(Role::Editor).await;
+    let enrollment: serde_json::Value = app
+        .request_as(&user, Method::POST, "/admin/api/totp/enroll", None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    let secret = Secret::new(enrollment["secret"].as_str().unwrap().to_string());
+    let code = totp::code(&secret, Utc::now().timestamp()).unwrap();
+    app.request_as(
+        &user,
+        Method::POST,
+        "/admin/api/totp/confirm",
+        Some(&json!({ "code": code })),
+    )
+    .await;
+
+    // Act
+    let with_password = app
+        .request_as(&user, Method::GET, "/admin/api/subscribers", None)
+        .await;
+    let session = login(&app, &user).await;
+    let session_token = session["session_token"].as_str().unwrap();
+    let while_pending = app
+        .request_with_session(session_token, Method::GET, "/admin/api/subscribers", None)
+        .await;
+    // The code of the enrollment cannot be replayed
+    let replayed = verify_second_factor(&app, session_token, &json!({ "code": code })).await;
+    let next_code = totp::code(&secret, Utc::now().timestamp() + 30).unwrap();
+    let verified = verify_second_factor(&app, session_token, &json!({ "code": next_code })).await;
+    let authenticated = app
+        .request_with_session(session_token, Method::GET, "/admin/api/subscribers", None)
+        .await;
+    let logout = app
+        .request_with_session(session_token, Method::POST, "/admin/logout", None)
+        .await;
+    let after_logout = app
+        .request_with_session(session_token, Method::GET, "/admin/api/subscribers", None)
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert!(enrollment["provisioning_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/Zero%20To%20Production:"));
+    assert_eq!(with_password.status().as_u16(), 401);
+    assert_eq!(session["second_factor_required"], true);
+    assert_eq!(while_pending.status().as_u16(), 401);
+    assert_eq!(replayed.status().as_u16(), 401);
+    assert_eq!(verified.status().as_u16(), 200);
+    assert_eq!(authenticated.status().as_u16(), 200);
+    assert_eq!(logout.status().as_u16(), 204);
+    assert_eq!(after_logout.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admins_without_totp_are_authenticated_at_login() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let session = login(&app, &app.test_user).await;
+    let response = app
+        .request_with_session(
+            session["session_token"].as_str().unwrap(),
+            Method::GET,
+            "/admin/api/users",
+            None,
+        )
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(session["second_factor_required"], false);
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn recovery_codes_are_stored_hashed_and_only_work_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = app.add_user(Role::Viewer).await;
+    let (_, recovery_codes) = enable_totp(&app, &user).await;
+
+    // Act
+    let first_session = login(&app, &user).await;
+    let first = verify_second_factor(
+        &app,
+        first_session["session_token"].as_str().unwrap(),
+        &json!({ "recovery_code": recovery_codes[0] }),
+    )
+    .await;
+    let second_session = login(&app, &user).await;
+    let second = verify_second_factor(
+        &app,
+        second_session["session_token"].as_str().unwrap(),
+        &json!({ "recovery_code": recovery_codes[0] }),
+    )
+    .await;
+
+    // Assert
+    let stored = sqlx::query!(
+        "SELECT code_hash FROM totp_recovery_codes WHERE user_id = $1",
+        user.user_id
+    )
+    .fetch_all(&app.database_pool)
+    .await
+    .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(recovery_codes.len(), 10);
+    assert_eq!(stored.len(), 10);
+    assert!(stored
+        .iter()
+        .all(|stored| !recovery_codes.contains(&stored.code_hash)));
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn admins_are_locked_out_after_too_many_invalid_codes() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = app.add_user(Role::Viewer).await;
+    let (secret, _) = enable_totp(&app, &user).await;
+    let session = login(&app, &user).await;
+    let session_token = session["session_token"].as_str().unwrap();
+
+    // Act
+    let mut statuses = Vec::new();
+    for _ in 0..totp::MAX_FAILED_ATTEMPTS {
+        let response =
+            verify_second_factor(&app, session_token, &json!({ "code": "not-a-code" })).await;
+        statuses.push(response.status().as_u16());
+    }
+    let next_code = totp::code(&secret, Utc::now().timestamp() + 30).unwrap();
+    let valid = verify_second_factor(&app, session_token, &json!({ "code": next_code })).await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(statuses, [401, 401, 401, 401, 429]);
+    assert_eq!(valid.status().as_u16(), 429);
+    assert!(valid.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn owners_can_require_admins_to_enable_totp() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = app.add_user(Role::Viewer).await;
+
+    // Act
+    let required = app
+        .admin_patch(
+            &format!("/admin/api/users/{}", user.user_id),
+            &json!({ "totp_required": true }),
+        )
+        .await;
+    let before_enrollment = app
+        .request_as(&user, Method::GET, "/admin/analytics", None)
+        .await;
+    let (secret, _) = enable_totp(&app, &user).await;
+    let session = login(&app, &user).await;
+    let session_token = session["session_token"].as_str().unwrap();
+    let next_code = totp::code(&secret, Utc::now().timestamp() + 30).unwrap();
+    verify_second_factor(&app, session_token, &json!({ "code": next_code })).await;
+    let after_enrollment = app
+        .request_with_session(session_token, Method::GET, "/admin/analytics", None)
+        .await;
+    let disabling = app
+        .request_with_session(session_token, Method::DELETE, "/admin/api/totp", None)
+        .await;
+    let users: serde_json::Value = app
+        .admin_get("/admin/api/users")
+        .await
+        .json()
+        .await
+        .unwrap();
+    let audit_log: serde_json::Value = app
+        .admin_get(&format!(
+            "/admin/api/audit_log?target_type=user&target_id={}",
+            user.user_id
+        ))
+        .await
+        .json()
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(required.status().as_u16(), 200);
+    assert_eq!(before_enrollment.status().as_u16(), 403);
+    assert_eq!(after_enrollment.status().as_u16(), 200);
+    assert_eq!(disabling.status().as_u16(), 409);
+    let listed = users
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|listed| listed["user_id"] == user.user_id.to_string())
+        .unwrap();
+    assert_eq!(listed["totp_enabled"], true);
+    assert_eq!(listed["totp_required"], true);
+    let actions: Vec<&str> = audit_log["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        actions,
+        ["user.enable_totp", "user.change_totp_requirement"]
+    );
+}
+
+/// Log in with the password and a recovery code of `user`, returning the
+/// authenticated session token
+async fn login_with_recovery_code(app: &TestApp, user: &TestUser, recovery_code: &str) -> String {
+    let session = login(app, user).await;
+    let session_token = session["session_token"].as_str().unwrap().to_string();
+    let response = verify_second_factor(
+        app,
+        &session_token,
+        &json!({ "recovery_code": recovery_code }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    session_token
+}
+
+#[tokio::test]
+async fn disabling_totp_requires_a_second_factor_and_ends_the_other_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = app.add_user(Role::Viewer).await;
+    let (_, recovery_codes) = enable_totp(&app, &user).await;
+    let current = login_with_recovery_code(&app, &user, &recovery_codes[0]).await;
+    let other = login_with_recovery_code(&app, &user, &recovery_codes[1]).await;
+
+    // Act
+    let without_code = app
+        .request_with_session(&current, Method::DELETE, "/admin/api/totp", None)
+        .await;
+    let wrong_code = app
+        .request_with_session(
+            &current,
+            Method::DELETE,
+            "/admin/api/totp",
+            Some(&json!({ "code": "000000" })),
+        )
+        .await;
+    let other_before = app
+        .request_with_session(&other, Method::GET, "/admin/analytics", None)
+        .await;
+    let disabling = app
+        .request_with_session(
+            &current,
+            Method::DELETE,
+            "/admin/api/totp",
+            Some(&json!({ "recovery_code": recovery_codes[2] })),
+        )
+        .await;
+    let current_after = app
+        .request_with_session(&current, Method::GET, "/admin/analytics", None)
+        .await;
+    let other_after = app
+        .request_with_session(&other, Method::GET, "/admin/analytics", None)
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(without_code.status().as_u16(), 400);
+    assert_eq!(wrong_code.status().as_u16(), 400);
+    assert_eq!(other_before.status().as_u16(), 200);
+    assert_eq!(disabling.status().as_u16(), 204);
+    assert_eq!(current_after.status().as_u16(), 200);
+    assert_eq!(other_after.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn changing_the_role_of_an_admin_ends_their_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = app.add_user(Role::Publisher).await;
+    let session = login(&app, &user).await;
+    let session_token = session["session_token"].as_str().unwrap();
+
+    // Act
+    let before = app
+        .request_with_session(session_token, Method::GET, "/admin/analytics", None)
+        .await;
+    let demotion = app
+        .admin_patch(
+            &format!("/admin/api/users/{}", user.user_id),
+            &json!({ "role": "viewer" }),
+        )
+        .await;
+    let after = app
+        .request_with_session(session_token, Method::GET, "/admin/analytics", None)
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(before.status().as_u16(), 200);
+    assert_eq!(demotion.status().as_u16(), 200);
+    assert_eq!(after.status().as_u16(), 401);
+}
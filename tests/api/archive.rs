@@ -0,0 +1,176 @@
+use reqwest::header::{ETAG, IF_NONE_MATCH, LAST_MODIFIED};
+
+use crate::helpers::{clean_up_database, spawn_app};
+
+#[tokio::test]
+async fn archive_lists_published_issues_newest_first() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("first", "First issue", "Hello", false, 2)
+        .await;
+    app.publish_issue("second", "Second issue", "Hello again", false, 1)
+        .await;
+    sqlx::query!(
+        "INSERT INTO newsletter_issues (id, slug, title, content, updated_at)
+        VALUES (gen_random_uuid(), 'draft', 'Draft issue', 'Not yet', now())"
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app.get_archive("/archive").await;
+    let status = response.status().as_u16();
+    let html = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert_eq!(status, 200);
+    let second = html.find("Second issue").unwrap();
+    let first = html.find("First issue").unwrap();
+    assert!(second < first);
+    assert!(!html.contains("Draft issue"));
+}
+
+#[tokio::test]
+async fn archived_issue_is_rendered_from_markdown() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue(
+        "hello",
+        "Hello",
+        "Some **bold** <script>alert(1)</script>",
+        false,
+        0,
+    )
+    .await;
+
+    // Act
+    let response = app.get_archive("/archive/hello").await;
+    let status = response.status().as_u16();
+    let html = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert!(html.contains("<strong>bold</strong>"));
+    assert!(!html.contains("<script>"));
+}
+
+#[tokio::test]
+async fn unknown_issues_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_archive("/archive/missing").await;
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn subscribers_only_issues_are_listed_but_not_shown() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("secret", "Secret issue", "The secret content", true, 0)
+        .await;
+
+    // Act
+    let listing = app.get_archive("/archive").await.text().await.unwrap();
+    let response = app.get_archive("/archive/secret").await;
+    let status = response.status().as_u16();
+    let html = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert!(listing.contains("Secret issue"));
+    assert_eq!(status, 403);
+    assert!(!html.contains("The secret content"));
+}
+
+#[tokio::test]
+async fn archived_issue_can_be_revalidated_with_its_etag() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("hello", "Hello", "Content", false, 0)
+        .await;
+    let response = app.get_archive("/archive/hello").await;
+    let etag = response.headers()[ETAG].clone();
+    assert!(response.headers().contains_key(LAST_MODIFIED));
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/archive/hello", app.address))
+        .header(IF_NONE_MATCH, etag)
+        .send()
+        .await
+        .unwrap();
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert_eq!(status, 304);
+    assert!(body.is_empty());
+}
+
+#[tokio::test]
+async fn archive_etag_changes_when_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("first", "First", "Content", false, 1)
+        .await;
+    let etag = app.get_archive("/archive").await.headers()[ETAG].clone();
+    app.publish_issue("second", "Second", "Content", false, 0)
+        .await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/archive", app.address))
+        .header(IF_NONE_MATCH, etag.clone())
+        .send()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()[ETAG], etag);
+}
+
+#[tokio::test]
+async fn archive_etag_changes_when_an_older_issue_is_replaced() {
+    // Arrange
+    let app = spawn_app().await;
+    let older = app
+        .publish_issue("first", "First", "Content", false, 1)
+        .await;
+    app.publish_issue("second", "Second", "Content", false, 0)
+        .await;
+    let etag = app.get_archive("/archive").await.headers()[ETAG].clone();
+    // As many issues as before, none of them more recent
+    sqlx::query!(
+        "UPDATE newsletter_issues SET published_at = NULL WHERE id = $1",
+        older
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+    app.publish_issue("third", "Third", "Content", false, 2)
+        .await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .get(format!("{}/archive", app.address))
+        .header(IF_NONE_MATCH, etag.clone())
+        .send()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()[ETAG], etag);
+}
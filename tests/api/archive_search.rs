@@ -0,0 +1,151 @@
+use crate::helpers::{clean_up_database, spawn_app};
+
+#[tokio::test]
+async fn search_returns_ranked_matches_with_highlighted_snippets() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue(
+        "tokio",
+        "Async runtimes",
+        "We compare tokio with other runtimes.",
+        false,
+        2,
+    )
+    .await;
+    app.publish_issue(
+        "tokio-deep-dive",
+        "Tokio deep dive",
+        "Everything about the tokio scheduler.",
+        false,
+        1,
+    )
+    .await;
+    app.publish_issue("sqlx", "Databases", "Working with Postgres.", false, 0)
+        .await;
+
+    // Act
+    let response = app.get_archive("/archive/search?q=tokio").await;
+    let status = response.status().as_u16();
+    let html = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert_eq!(status, 200);
+    // A match in the title ranks above a match in the body only
+    let deep_dive = html.find("Tokio deep dive").unwrap();
+    let runtimes = html.find("Async runtimes").unwrap();
+    assert!(deep_dive < runtimes);
+    assert!(!html.contains("Databases"));
+    assert!(html.contains("<mark>tokio</mark>"));
+}
+
+#[tokio::test]
+async fn search_snippets_are_escaped() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue(
+        "xss",
+        "Escaping",
+        "Escaping a < b & c when it is not a tag",
+        false,
+        0,
+    )
+    .await;
+
+    // Act
+    let html = app
+        .get_archive("/archive/search?q=escaping")
+        .await
+        .text()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert!(html.contains("a &lt; b &amp; c"));
+}
+
+#[tokio::test]
+async fn search_does_not_reveal_subscribers_only_content() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("secret", "Secret issue", "The hidden treasure", true, 0)
+        .await;
+
+    // Act
+    let by_content = app
+        .get_archive("/archive/search?q=treasure")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let by_title = app
+        .get_archive("/archive/search?q=secret")
+        .await
+        .text()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert!(!by_content.contains("Secret issue"));
+    assert!(by_title.contains("Secret issue"));
+    assert!(!by_title.contains("treasure"));
+}
+
+#[tokio::test]
+async fn search_results_are_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..12 {
+        app.publish_issue(
+            &format!("issue-{i}"),
+            &format!("Issue number {i}"),
+            "Newsletter content",
+            false,
+            i,
+        )
+        .await;
+    }
+
+    // Act
+    let first_page = app
+        .get_archive("/archive/search?q=newsletter")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let second_page = app
+        .get_archive("/archive/search?q=newsletter&page=2")
+        .await
+        .text()
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert_eq!(first_page.matches("<li>").count(), 10);
+    assert!(first_page.contains("page=2"));
+    assert_eq!(second_page.matches("<li>").count(), 2);
+    assert!(second_page.contains("page=1"));
+}
+
+#[tokio::test]
+async fn pages_far_beyond_the_results_are_empty() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("issue", "An issue", "Newsletter content", false, 0)
+        .await;
+
+    // Act
+    let response = app
+        .get_archive("/archive/search?q=newsletter&page=9223372036854775807")
+        .await;
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert_eq!(status, 200);
+    assert!(body.contains("No issues matched your search."));
+}
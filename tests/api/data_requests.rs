@@ -0,0 +1,192 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+/// Subscribe and ask for the data links, returning the access and the
+/// erasure links
+async fn request_data_links(app: &TestApp) -> (reqwest::Url, reqwest::Url) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/data_requests", app.address))
+        .form(&[("email", "Ursula_Le_Guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let links: Vec<reqwest::Url> = linkify::LinkFinder::new()
+        .links(body["content"][0]["value"].as_str().unwrap())
+        .filter(|link| *link.kind() == linkify::LinkKind::Url)
+        .map(|link| {
+            let mut link = reqwest::Url::parse(link.as_str()).unwrap();
+            link.set_port(Some(app.port)).unwrap();
+            link
+        })
+        .collect();
+    assert_eq!(links.len(), 2);
+    (links[0].clone(), links[1].clone())
+}
+
+#[tokio::test]
+async fn the_access_link_exports_everything_held_about_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let (access_link, _) = request_data_links(&app).await;
+    let mut tampered_link = access_link.clone();
+    tampered_link.set_query(Some(
+        &access_link
+            .query()
+            .unwrap()
+            .replace("ursula_le_guin%40gmail.com", "ted%40example.com"),
+    ));
+
+    // Act
+    let response = reqwest::get(access_link).await.unwrap();
+    let tampered = reqwest::get(tampered_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let data: serde_json::Value = response.json().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    let subscription = &data["subscriptions"][0];
+    assert_eq!(subscription["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(subscription["name"], "le guin");
+    assert_eq!(subscription["tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(
+        subscription["status_history"][0]["status"],
+        "pending_confirmation"
+    );
+    assert_eq!(data["erased_at"], serde_json::Value::Null);
+    assert_eq!(tampered.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_erasure_link_erases_the_subscriber_once_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, erasure_link) = request_data_links(&app).await;
+    let signed: Vec<(String, String)> = erasure_link.query_pairs().into_owned().collect();
+
+    // Act
+    let form = reqwest::get(erasure_link).await.unwrap();
+    let subscribers_after_form = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/data/erase", app.address))
+        .form(&signed)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(form.status().as_u16(), 200);
+    assert!(form.text().await.unwrap().contains("<form"));
+    assert_eq!(response.status().as_u16(), 200);
+    let subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    let tokens = sqlx::query!("SELECT subscriber_id FROM subscription_tokens")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    let tombstones = sqlx::query!("SELECT email_hash FROM erasure_tombstones")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(subscribers_after_form.len(), 1);
+    assert!(subscribers.is_empty());
+    assert!(tokens.is_empty());
+    assert_eq!(tombstones.len(), 1);
+    assert!(!tombstones[0].email_hash.contains("ursula"));
+}
+
+#[tokio::test]
+async fn data_requests_for_unknown_addresses_send_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions/data_requests", app.address))
+        .form(&[("email", "nobody@example.com")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn data_requests_are_limited_per_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let request = |email: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/data_requests", app.address))
+            .form(&[("email", email)])
+            .send()
+    };
+
+    // Act
+    for _ in 0..3 {
+        let response = request("ursula@example.com").await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let limited = request("URSULA@example.com").await.unwrap();
+    let other_address = request("octavia@example.com").await.unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(limited.status().as_u16(), 429);
+    assert!(limited.headers().contains_key("Retry-After"));
+    assert_eq!(other_address.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn data_requests_are_limited_per_client() {
+    // Arrange
+    let app = spawn_app().await;
+    let request = |email: String| {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/data_requests", app.address))
+            .form(&[("email", email)])
+            .send()
+    };
+
+    // Act
+    for i in 0..10 {
+        let response = request(format!("reader{i}@example.com")).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let limited = request("ursula@example.com".into()).await.unwrap();
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    assert_eq!(limited.status().as_u16(), 429);
+}
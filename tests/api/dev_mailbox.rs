@@ -0,0 +1,103 @@
+use zero2prod::configuration::{EmailBackend, Environment};
+
+use crate::helpers::{clean_up_database, spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn confirmation_emails_can_be_read_and_followed_from_the_mailbox() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_client.backend = EmailBackend::Capture).await;
+    let client = reqwest::Client::new();
+
+    // Act - Part 1 - Subscribe
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Act - Part 2 - Find the email in the mailbox
+    let mailbox = app.get_archive("/dev/mailbox").await.text().await.unwrap();
+    let email_path = mailbox
+        .split("href=\"")
+        .skip(1)
+        .map(|rest| &rest[..rest.find('"').unwrap()])
+        .find(|href| href.starts_with("/dev/mailbox/"))
+        .unwrap()
+        .to_string();
+    let email = app.get_archive(&email_path).await.text().await.unwrap();
+    let raw = app.get_archive(&format!("{email_path}/raw")).await;
+    let raw_content_type = raw.headers()["Content-Type"].to_str().unwrap().to_string();
+
+    // Act - Part 3 - Follow the confirmation link
+    let link = email
+        .split("<li><a href=\"")
+        .nth(1)
+        .map(|rest| rest[..rest.find('"').unwrap()].replace("&amp;", "&"))
+        .unwrap();
+    let mut link = reqwest::Url::parse(&link).unwrap();
+    link.set_port(Some(app.port)).unwrap();
+    let confirmation = client.get(link).send().await.unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert!(mailbox.contains("ursula_le_guin@gmail.com"));
+    assert!(mailbox.contains("Welcome!"));
+    assert!(email.contains("/subscriptions/confirm?subscription_token="));
+    assert_eq!(raw_content_type, "message/rfc822");
+    assert_eq!(confirmation.status().as_u16(), 200);
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn the_mailbox_is_empty_when_nothing_was_sent() {
+    // Arrange
+    let app = spawn_app_with(|c| c.email_client.backend = EmailBackend::Capture).await;
+
+    // Act
+    let response = app.get_archive("/dev/mailbox").await;
+
+    // Assert
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(status, 200);
+    assert!(body.contains("No emails have been sent yet."));
+}
+
+#[tokio::test]
+async fn the_mailbox_explains_how_to_capture_emails_when_they_are_delivered() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_archive("/dev/mailbox").await;
+
+    // Assert
+    let body = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert!(body.contains("<code>capture</code>"));
+}
+
+#[tokio::test]
+async fn the_mailbox_is_not_mounted_in_production() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.email_client.backend = EmailBackend::Capture;
+        c.environment = Environment::Production;
+    })
+    .await;
+
+    // Act
+    let response = app.get_archive("/dev/mailbox").await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
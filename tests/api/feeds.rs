@@ -0,0 +1,109 @@
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+
+use crate::helpers::{clean_up_database, spawn_app};
+
+#[tokio::test]
+async fn rss_feed_contains_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("hello", "Hello readers", "Some **news**", false, 0)
+        .await;
+
+    // Act
+    let response = app.get_archive("/feed.rss").await;
+    let content_type = response.headers()[CONTENT_TYPE].clone();
+    let feed = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert!(content_type
+        .to_str()
+        .unwrap()
+        .starts_with("application/rss+xml"));
+    let channel = rss::Channel::read_from(feed.as_bytes()).unwrap();
+    assert_eq!(channel.items().len(), 1);
+    let item = &channel.items()[0];
+    assert_eq!(item.title(), Some("Hello readers"));
+    assert!(item.link().unwrap().ends_with("/archive/hello"));
+    assert!(item
+        .description()
+        .unwrap()
+        .contains("<strong>news</strong>"));
+}
+
+#[tokio::test]
+async fn atom_feed_contains_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("hello", "Hello readers", "Some **news**", false, 0)
+        .await;
+
+    // Act
+    let response = app.get_archive("/feed.atom").await;
+    let content_type = response.headers()[CONTENT_TYPE].clone();
+    let feed = response.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert!(content_type
+        .to_str()
+        .unwrap()
+        .starts_with("application/atom+xml"));
+    let feed: atom_syndication::Feed = feed.parse().unwrap();
+    assert_eq!(feed.entries().len(), 1);
+    let entry = &feed.entries()[0];
+    assert_eq!(entry.title().as_str(), "Hello readers");
+    assert!(entry
+        .content()
+        .and_then(|c| c.value())
+        .unwrap()
+        .contains("<strong>news</strong>"));
+}
+
+#[tokio::test]
+async fn feeds_do_not_leak_subscribers_only_content() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("secret", "Secret issue", "The secret content", true, 0)
+        .await;
+
+    // Act
+    let rss = app.get_archive("/feed.rss").await.text().await.unwrap();
+    let atom = app.get_archive("/feed.atom").await.text().await.unwrap();
+    clean_up_database(app.database_name).await;
+
+    // Assert
+    assert!(rss.contains("Secret issue"));
+    assert!(atom.contains("Secret issue"));
+    assert!(!rss.contains("The secret content"));
+    assert!(!atom.contains("The secret content"));
+}
+
+#[tokio::test]
+async fn feeds_can_be_revalidated_with_their_etag() {
+    // Arrange
+    let app = spawn_app().await;
+    app.publish_issue("hello", "Hello", "Content", false, 0)
+        .await;
+
+    for path in ["/feed.rss", "/feed.atom"] {
+        let etag = app.get_archive(path).await.headers()[ETAG].clone();
+
+        // Act
+        let response = reqwest::Client::new()
+            .get(format!("{}{path}", app.address))
+            .header(IF_NONE_MATCH, etag)
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            304,
+            "{path} was not revalidated"
+        );
+    }
+
+    clean_up_database(app.database_name).await;
+}
@@ -1,3 +1,5 @@
+use wiremock::{matchers::any, Mock, ResponseTemplate};
+
 use crate::helpers::{clean_up_database, spawn_app, TestApp};
 
 #[tokio::test]
@@ -8,6 +10,9 @@ async fn health() {
         database_name,
         email_server: _,
         port: _,
+        email_client: _,
+        test_user: _,
+        webhook_token: _,
     } = spawn_app().await;
 
     let client = reqwest::Client::new();
@@ -24,5 +29,43 @@ async fn health() {
     clean_up_database(database_name).await;
 
     assert!(response.status().is_success());
-    assert_eq!(Some(0), response.content_length());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["email_providers"][0]["name"], "sendgrid");
+    assert_eq!(body["email_providers"][0]["circuit_breaker"], "closed");
+}
+
+#[tokio::test]
+async fn health_reports_open_circuit_breakers() {
+    let app = spawn_app().await;
+    let failure_threshold = zero2prod::configuration::get()
+        .unwrap()
+        .email_client
+        .circuit_breaker
+        .failure_threshold;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(503))
+        .expect(u64::from(failure_threshold))
+        .mount(&app.email_server)
+        .await;
+
+    for i in 0..failure_threshold {
+        app.post_subscriptions(format!("name=le%20guin&email=ursula{i}%40example.com"))
+            .await;
+    }
+    // Emails fail fast once the breaker is open
+    app.post_subscriptions("name=le%20guin&email=ursula%40example.com".into())
+        .await;
+    let response = reqwest::Client::new()
+        .get(format!("{}/health", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    clean_up_database(app.database_name).await;
+
+    assert!(response.status().is_success());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "degraded");
+    assert_eq!(body["email_providers"][0]["circuit_breaker"], "open");
 }
@@ -1,12 +1,16 @@
 use once_cell::sync::Lazy;
 use reqwest::Response;
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::{Executor, PgPool};
 use std::io::{sink, stdout};
 use uuid::Uuid;
 use wiremock::{MockServer, Request};
 use zero2prod::{
-    configuration::{self, DatabaseSettings},
+    authentication::compute_password_hash,
+    authorization::Role,
+    configuration::{self, DatabaseSettings, EmailBackend, Settings},
+    email_client::EmailClient,
+    issue_delivery_worker::{try_execute_batch, ExecutionOutcome},
     startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
 };
@@ -30,6 +34,43 @@ pub struct TestApp {
     pub database_name: String,
     pub email_server: MockServer,
     pub port: u16,
+    pub email_client: EmailClient,
+    pub test_user: TestUser,
+    pub webhook_token: String,
+}
+
+/// An admin. The owner created with each app is used by the `admin_*`
+/// requests.
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+    pub role: Role,
+}
+
+impl TestUser {
+    fn generate(role: Role) -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+            role,
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let password_hash = compute_password_hash(&Secret::new(self.password.clone()));
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash, role) VALUES ($1, $2, $3, $4)",
+            self.user_id,
+            self.username,
+            password_hash.expose_secret(),
+            self.role.as_ref(),
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
 }
 
 pub struct ConfirmationLinks {
@@ -48,6 +89,167 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn dispatch_all_pending_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue | ExecutionOutcome::QuotaExhausted =
+                try_execute_batch(&self.database_pool, &self.email_client)
+                    .await
+                    .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn get_archive(&self, path: &str) -> Response {
+        reqwest::Client::new()
+            .get(format!("{}{path}", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Store an issue as if it had been published `days_ago`
+    pub async fn publish_issue(
+        &self,
+        slug: &str,
+        title: &str,
+        content: &str,
+        subscribers_only: bool,
+        days_ago: i64,
+    ) -> Uuid {
+        let id = Uuid::new_v4();
+        let published_at = chrono::Utc::now() - chrono::Duration::days(days_ago);
+        sqlx::query!(
+            r#"
+            INSERT INTO newsletter_issues
+                (id, slug, title, content, subscribers_only, published_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $6)
+            "#,
+            id,
+            slug,
+            title,
+            content,
+            subscribers_only,
+            published_at,
+        )
+        .execute(&self.database_pool)
+        .await
+        .expect("Failed to store newsletter issue.");
+        id
+    }
+
+    /// Store another admin, with the given role
+    pub async fn add_user(&self, role: Role) -> TestUser {
+        let user = TestUser::generate(role);
+        user.store(&self.database_pool).await;
+        user
+    }
+
+    /// Send a request as `user`, with a JSON body when given one
+    pub async fn request_as(
+        &self,
+        user: &TestUser,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Response {
+        let request = reqwest::Client::new()
+            .request(method, format!("{}{path}", &self.address))
+            .basic_auth(&user.username, Some(&user.password));
+        match body {
+            Some(body) => request.json(body),
+            None => request,
+        }
+        .send()
+        .await
+        .expect("Failed to execute request.")
+    }
+
+    /// Send a request with the token of a session, with a JSON body when
+    /// given one
+    pub async fn request_with_session(
+        &self,
+        session_token: &str,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<&serde_json::Value>,
+    ) -> Response {
+        let request = reqwest::Client::new()
+            .request(method, format!("{}{path}", &self.address))
+            .bearer_auth(session_token);
+        match body {
+            Some(body) => request.json(body),
+            None => request,
+        }
+        .send()
+        .await
+        .expect("Failed to execute request.")
+    }
+
+    pub async fn admin_get(&self, path: &str) -> Response {
+        reqwest::Client::new()
+            .get(format!("{}{path}", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn admin_post(&self, path: &str, body: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}{path}", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn admin_patch(&self, path: &str, body: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .patch(format!("{}{path}", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn admin_delete(&self, path: &str) -> Response {
+        reqwest::Client::new()
+            .delete(format!("{}{path}", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn import_subscribers(&self, format: &str, csv: &str) -> Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/admin/api/subscribers/import?format={format}",
+                &self.address
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Content-Type", "text/csv")
+            .body(csv.to_string())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// Deliver events to the SendGrid Event Webhook
+    pub async fn post_sendgrid_events(&self, events: &serde_json::Value) -> Response {
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/sendgrid", &self.address))
+            .basic_auth("sendgrid", Some(&self.webhook_token))
+            .json(events)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -68,14 +270,32 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(body["TextBody"].as_str().unwrap());
+        // [SendgridEmailFormat] lists one `content` entry per MIME type
+        let get_content = |mime_type: &str| {
+            body["content"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|c| c["type"] == mime_type)
+                .unwrap()["value"]
+                .as_str()
+                .unwrap()
+                .to_owned()
+        };
+
+        let html = get_link(&get_content("text/html"));
+        let plain_text = get_link(&get_content("text/plain"));
 
         ConfirmationLinks { html, plain_text }
     }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Spawn the application after adjusting its configuration with `configure`
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -86,7 +306,10 @@ pub async fn spawn_app() -> TestApp {
         c.database.name = Uuid::new_v4().to_string();
         // Use a random OS port
         c.application.port = 0;
+        c.email_client.backend = EmailBackend::Sendgrid;
         c.email_client.base_url = email_server.uri();
+        c.email_client.webhook_token = Secret::new(Uuid::new_v4().to_string());
+        configure(&mut c);
         c
     };
 
@@ -97,18 +320,28 @@ pub async fn spawn_app() -> TestApp {
         .expect("Failed to build application");
 
     let port = application.port();
+    let application_email_client = application.email_client().clone();
 
     // Get address before spawning the application
     let address = format!("http://127.0.0.1:{}", application.port());
     tokio::spawn(application.run_until_stopped());
 
-    TestApp {
+    let test_app = TestApp {
         address,
         database_pool: get_connection_pool(&configuration.database),
         database_name: configuration.database.name,
         email_server,
         port,
-    }
+        email_client: application_email_client,
+        test_user: TestUser::generate(Role::Owner),
+        webhook_token: configuration
+            .email_client
+            .webhook_token
+            .expose_secret()
+            .clone(),
+    };
+    test_app.test_user.store(&test_app.database_pool).await;
+    test_app
 }
 
 async fn configure_database(configuration: &DatabaseSettings) -> PgPool {
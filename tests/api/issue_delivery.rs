@@ -0,0 +1,439 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::issue_delivery_worker::{enqueue_delivery_tasks, MAX_ATTEMPTS};
+
+use crate::helpers::{clean_up_database, spawn_app, spawn_app_with};
+
+async fn insert_subscriber(pool: &PgPool, name: &str, email: &str, status: &str) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, now(), $4)
+        "#,
+        Uuid::new_v4(),
+        email,
+        name,
+        status,
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to insert subscriber.");
+}
+
+async fn enqueue(pool: &PgPool, issue_id: Uuid) {
+    let mut transaction = pool.begin().await.unwrap();
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+}
+
+async fn delivery_statuses(pool: &PgPool) -> Vec<(String, i32)> {
+    sqlx::query!("SELECT status, n_attempts FROM issue_deliveries ORDER BY status")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.status, r.n_attempts))
+        .collect()
+}
+
+#[tokio::test]
+async fn issues_are_delivered_to_confirmed_subscribers_in_a_single_batch() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app.database_pool,
+        "Ursula",
+        "ursula@example.com",
+        "confirmed",
+    )
+    .await;
+    insert_subscriber(
+        &app.database_pool,
+        "Octavia",
+        "octavia@example.com",
+        "confirmed",
+    )
+    .await;
+    insert_subscriber(
+        &app.database_pool,
+        "Pending",
+        "pending@example.com",
+        "pending_confirmation",
+    )
+    .await;
+    let issue_id = app
+        .publish_issue("hello", "Hello {{name}}", "Dear {{name}},", false, 0)
+        .await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    enqueue(&app.database_pool, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    let personalizations = body["personalizations"].as_array().unwrap();
+    let statuses = delivery_statuses(&app.database_pool).await;
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(personalizations.len(), 2);
+    let names: Vec<&str> = personalizations
+        .iter()
+        .map(|p| p["substitutions"]["{{name}}"].as_str().unwrap())
+        .collect();
+    assert!(names.contains(&"Ursula"));
+    assert!(names.contains(&"Octavia"));
+    for personalization in personalizations {
+        assert_eq!(
+            personalization["custom_args"]["newsletter_issue_id"],
+            issue_id.to_string()
+        );
+        assert!(personalization["custom_args"]["subscriber_id"].is_string());
+    }
+    assert_eq!(
+        statuses,
+        vec![("sent".to_string(), 1), ("sent".to_string(), 1)]
+    );
+}
+
+#[tokio::test]
+async fn large_lists_are_split_into_batches_of_at_most_1000_recipients() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        SELECT gen_random_uuid(), 'reader' || i || '@example.com', 'Reader', now(), 'confirmed'
+        FROM generate_series(1, 1001) AS i
+        "#
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+    let issue_id = app
+        .publish_issue("hello", "Hello", "Content", false, 0)
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    enqueue(&app.database_pool, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let mut batch_sizes: Vec<usize> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["personalizations"].as_array().unwrap().len()
+        })
+        .collect();
+    batch_sizes.sort_unstable();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(batch_sizes, vec![1, 1000]);
+}
+
+#[tokio::test]
+async fn failed_batches_are_retried_later_and_eventually_marked_as_failed() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app.database_pool,
+        "Ursula",
+        "ursula@example.com",
+        "confirmed",
+    )
+    .await;
+    let issue_id = app
+        .publish_issue("hello", "Hello", "Content", false, 0)
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - First attempt
+    enqueue(&app.database_pool, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1
+    let after_first_attempt = sqlx::query!(
+        "SELECT status, n_attempts, last_error, execute_after > now() AS later FROM issue_deliveries"
+    )
+    .fetch_one(&app.database_pool)
+    .await
+    .unwrap();
+
+    // Act - Part 2 - Keep retrying immediately
+    for _ in 1..MAX_ATTEMPTS {
+        sqlx::query!("UPDATE issue_deliveries SET execute_after = now()")
+            .execute(&app.database_pool)
+            .await
+            .unwrap();
+        app.dispatch_all_pending_emails().await;
+    }
+
+    // Assert - Part 2
+    let statuses = delivery_statuses(&app.database_pool).await;
+    let dead_letter = sqlx::query!("SELECT error_kind, n_attempts FROM dead_letters")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(after_first_attempt.status, "queued");
+    assert_eq!(after_first_attempt.n_attempts, 1);
+    assert!(after_first_attempt.last_error.is_some());
+    assert_eq!(after_first_attempt.later, Some(true));
+    assert_eq!(statuses, vec![("failed".to_string(), MAX_ATTEMPTS)]);
+    assert_eq!(dead_letter.error_kind, "server_error");
+    assert_eq!(dead_letter.n_attempts, MAX_ATTEMPTS);
+}
+
+#[tokio::test]
+async fn the_message_id_of_each_batch_is_stored_with_its_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app.database_pool,
+        "Ursula",
+        "ursula@example.com",
+        "confirmed",
+    )
+    .await;
+    let issue_id = app
+        .publish_issue("hello", "Hello", "Content", false, 0)
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(202).insert_header("X-Message-Id", "14c5d75ce93"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    enqueue(&app.database_pool, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!("SELECT status, message_id FROM issue_deliveries")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.message_id.as_deref(), Some("14c5d75ce93"));
+}
+
+#[tokio::test]
+async fn rejected_batches_are_not_retried() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app.database_pool,
+        "Ursula",
+        "ursula@example.com",
+        "confirmed",
+    )
+    .await;
+    let issue_id = app
+        .publish_issue("hello", "Hello", "Content", false, 0)
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(400).set_body_string("Invalid payload"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    enqueue(&app.database_pool, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!("SELECT status, n_attempts, last_error FROM issue_deliveries")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(delivery.status, "failed");
+    assert_eq!(delivery.n_attempts, 1);
+    assert!(delivery.last_error.unwrap().contains("Invalid payload"));
+}
+
+#[tokio::test]
+async fn rate_limited_batches_are_postponed_without_counting_an_attempt() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app.database_pool,
+        "Ursula",
+        "ursula@example.com",
+        "confirmed",
+    )
+    .await;
+    let issue_id = app
+        .publish_issue("hello", "Hello", "Content", false, 0)
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    enqueue(&app.database_pool, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!(
+        r#"
+        SELECT status, n_attempts,
+            execute_after > now() + interval '59 minutes' AS "postponed!"
+        FROM issue_deliveries
+        "#
+    )
+    .fetch_one(&app.database_pool)
+    .await
+    .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(delivery.status, "queued");
+    assert_eq!(delivery.n_attempts, 0);
+    assert!(delivery.postponed);
+}
+
+#[tokio::test]
+async fn deliveries_pause_once_the_daily_quota_is_used_up_and_resume_the_next_day() {
+    // Arrange
+    let app =
+        spawn_app_with(|configuration| configuration.email_client.daily_quota = Some(2)).await;
+    for name in ["Ursula", "Octavia", "Nnedi"] {
+        let email = format!("{}@example.com", name.to_lowercase());
+        insert_subscriber(&app.database_pool, name, &email, "confirmed").await;
+    }
+    let issue_id = app
+        .publish_issue("hello", "Hello", "Content", false, 0)
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Use up today's quota
+    enqueue(&app.database_pool, issue_id).await;
+    app.dispatch_all_pending_emails().await;
+    let paused_statuses = delivery_statuses(&app.database_pool).await;
+
+    // Act - Part 2 - Tomorrow
+    sqlx::query!("UPDATE email_daily_usage SET day = day - 1")
+        .execute(&app.database_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let statuses = delivery_statuses(&app.database_pool).await;
+    let usage = sqlx::query!("SELECT sent_emails FROM email_daily_usage ORDER BY day")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(
+        paused_statuses,
+        vec![
+            ("queued".to_string(), 0),
+            ("sent".to_string(), 1),
+            ("sent".to_string(), 1)
+        ]
+    );
+    assert!(statuses.iter().all(|(status, _)| status == "sent"));
+    let usage: Vec<i32> = usage.into_iter().map(|r| r.sent_emails).collect();
+    assert_eq!(usage, vec![2, 1]);
+}
+
+#[tokio::test]
+async fn publishing_an_issue_delivers_it_to_confirmed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app.database_pool,
+        "Ursula",
+        "ursula@example.com",
+        "confirmed",
+    )
+    .await;
+    insert_subscriber(
+        &app.database_pool,
+        "Pending",
+        "pending@example.com",
+        "pending_confirmation",
+    )
+    .await;
+    let draft: serde_json::Value = app
+        .admin_post(
+            "/admin/api/issues",
+            &serde_json::json!({ "slug": "hello", "title": "Hello", "content": "Dear {{name}}," }),
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    let issue_id = draft["id"].as_str().unwrap().to_string();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let drafted_deliveries = delivery_statuses(&app.database_pool).await;
+    let response = app
+        .admin_post(
+            &format!("/admin/api/issues/{issue_id}/publish"),
+            &serde_json::json!({}),
+        )
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let deliveries = delivery_statuses(&app.database_pool).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(drafted_deliveries.is_empty());
+    assert_eq!(deliveries, vec![("sent".to_string(), 1)]);
+    assert_eq!(body["personalizations"].as_array().unwrap().len(), 1);
+}
@@ -1,4 +1,28 @@
+mod admin_analytics;
+mod admin_api_keys;
+mod admin_audit_log;
+mod admin_authorization;
+mod admin_data_subjects;
+mod admin_dead_letters;
+mod admin_delivery_reports;
+mod admin_issues;
+mod admin_oidc;
+mod admin_subscriber_export;
+mod admin_subscriber_import;
+mod admin_subscribers;
+mod admin_totp;
+mod archive;
+mod archive_search;
+mod data_requests;
+mod dev_mailbox;
+mod feeds;
 mod health;
 mod helpers;
+mod issue_delivery;
+mod oidc_server;
+mod retention;
+mod smtp;
+mod smtp_server;
 mod subscriptions;
 mod subscriptions_confirm;
+mod webhooks;
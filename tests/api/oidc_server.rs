@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{Duration, Utc};
+use data_encoding::{BASE64, BASE64URL_NOPAD};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use reqwest::Url;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, MockServer, Request, Respond, ResponseTemplate,
+};
+
+pub const CLIENT_ID: &str = "newsletter";
+pub const CLIENT_SECRET: &str = "oidc-client-secret";
+const SIGNING_KEY: &[u8] = include_bytes!("fixtures/oidc_private_key.pem");
+const JWKS: &str = include_str!("fixtures/oidc_jwks.json");
+
+/// An authorization code, and what it was granted for
+#[derive(Debug, Clone)]
+struct Grant {
+    code_challenge: String,
+    nonce: String,
+    claims: Value,
+}
+
+/// An OpenID Connect provider stand-in, serving its discovery document, its
+/// keys and a token endpoint that checks the PKCE verifier of each code.
+pub struct OidcServer {
+    server: MockServer,
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+impl OidcServer {
+    pub async fn start() -> Self {
+        let server = MockServer::start().await;
+        let grants = Arc::new(Mutex::new(HashMap::new()));
+        Mock::given(method("GET"))
+            .and(path("/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "issuer": server.uri(),
+                "authorization_endpoint": format!("{}/authorize", server.uri()),
+                "token_endpoint": format!("{}/token", server.uri()),
+                "jwks_uri": format!("{}/jwks", server.uri()),
+                "response_types_supported": ["code"],
+                "code_challenge_methods_supported": ["S256"],
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/jwks"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(JWKS.as_bytes(), "application/json"),
+            )
+            .mount(&server)
+            .await;
+        // Clients authenticate with `client_secret_basic`
+        let client_authentication = format!(
+            "Basic {}",
+            BASE64.encode(format!("{CLIENT_ID}:{CLIENT_SECRET}").as_bytes())
+        );
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(header("Authorization", client_authentication.as_str()))
+            .respond_with(TokenEndpoint {
+                issuer: server.uri(),
+                grants: grants.clone(),
+            })
+            .mount(&server)
+            .await;
+        Self { server, grants }
+    }
+
+    pub fn issuer(&self) -> String {
+        self.server.uri()
+    }
+
+    /// What the provider does once the admin signs in: grant a code for the
+    /// authorization request at `authorization_url`, with `claims` in the ID
+    /// token it is exchanged for. Returns the code and the state to call back
+    /// with.
+    pub fn sign_in(&self, authorization_url: &Url, claims: Value) -> (String, String) {
+        let parameters: HashMap<String, String> =
+            authorization_url.query_pairs().into_owned().collect();
+        assert_eq!(parameters["client_id"], CLIENT_ID);
+        assert_eq!(parameters["code_challenge_method"], "S256");
+        let code = Uuid::new_v4().to_string();
+        self.grants.lock().unwrap().insert(
+            code.clone(),
+            Grant {
+                code_challenge: parameters["code_challenge"].clone(),
+                nonce: parameters["nonce"].clone(),
+                claims,
+            },
+        );
+        (code, parameters["state"].clone())
+    }
+}
+
+struct TokenEndpoint {
+    issuer: String,
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+impl Respond for TokenEndpoint {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let invalid_grant =
+            ResponseTemplate::new(400).set_body_json(json!({ "error": "invalid_grant" }));
+        let form: HashMap<String, String> = Url::parse(&format!(
+            "http://form/?{}",
+            String::from_utf8_lossy(&request.body)
+        ))
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect();
+        if form.get("grant_type").map(String::as_str) != Some("authorization_code") {
+            return invalid_grant;
+        }
+        // Codes are single use
+        let Some(grant) = form
+            .get("code")
+            .and_then(|code| self.grants.lock().unwrap().remove(code))
+        else {
+            return invalid_grant;
+        };
+        let Some(code_verifier) = form.get("code_verifier") else {
+            return invalid_grant;
+        };
+        if BASE64URL_NOPAD.encode(&Sha256::digest(code_verifier.as_bytes())) != grant.code_challenge
+        {
+            return invalid_grant;
+        }
+
+        let mut claims = json!({
+            "iss": self.issuer,
+            "aud": CLIENT_ID,
+            "iat": Utc::now().timestamp(),
+            "exp": (Utc::now() + Duration::minutes(5)).timestamp(),
+            "nonce": grant.nonce,
+        });
+        claims
+            .as_object_mut()
+            .unwrap()
+            .extend(grant.claims.as_object().unwrap().clone());
+        ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": Uuid::new_v4().to_string(),
+            "token_type": "Bearer",
+            "expires_in": 3600,
+            "id_token": id_token(&claims),
+        }))
+    }
+}
+
+/// An ID token with `claims`, signed with the key the server publishes
+fn id_token(claims: &Value) -> String {
+    let mut header = Header::new(jsonwebtoken::Algorithm::RS256);
+    header.kid = Some("test-key".to_string());
+    encode(
+        &header,
+        claims,
+        &EncodingKey::from_rsa_pem(SIGNING_KEY).unwrap(),
+    )
+    .unwrap()
+}
@@ -0,0 +1,220 @@
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::{configuration::RetentionSettings, retention::purge_pending_subscribers};
+
+use crate::helpers::{clean_up_database, spawn_app, TestApp};
+
+/// Sign up through the form, as if `days_ago`
+async fn sign_up(app: &TestApp, email: &str, days_ago: i32) {
+    app.post_subscriptions(format!(
+        "name=le%20guin&email={}",
+        urlencoding::encode(email)
+    ))
+    .await;
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = now() - make_interval(days => $2) WHERE email = $1",
+        email,
+        days_ago,
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+}
+
+/// Mark a subscriber as reminded to confirm `days_ago`
+async fn remind(app: &TestApp, email: &str, days_ago: i32) {
+    sqlx::query!(
+        "UPDATE subscriptions SET confirmation_reminded_at = now() - make_interval(days => $2) WHERE email = $1",
+        email,
+        days_ago,
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+}
+
+async fn purge(app: &TestApp, reminder_after_days: Option<u32>, dry_run: bool) -> (usize, usize) {
+    let settings = RetentionSettings {
+        pending_subscriber_days: 30,
+        reminder_after_days,
+        dry_run,
+    };
+    let report = purge_pending_subscribers(
+        &app.database_pool,
+        &app.email_client,
+        &settings,
+        &app.address,
+    )
+    .await
+    .unwrap();
+    (report.reminded.len(), report.purged.len())
+}
+
+#[tokio::test]
+async fn subscribers_pending_for_longer_than_the_retention_period_are_purged() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    sign_up(&app, "stale@example.com", 31).await;
+    sign_up(&app, "recent@example.com", 1).await;
+    app.admin_post(
+        "/admin/api/subscribers",
+        &json!({ "email": "confirmed@example.com", "name": "Ursula", "double_opt_in": false }),
+    )
+    .await;
+    sqlx::query!("UPDATE subscriptions SET subscribed_at = now() - interval '1 year' WHERE status = 'confirmed'")
+        .execute(&app.database_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let (reminded, purged) = purge(&app, None, false).await;
+
+    // Assert
+    let mut remaining: Vec<String> = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|subscriber| subscriber.email)
+        .collect();
+    remaining.sort();
+    let tokens = sqlx::query!("SELECT subscriber_id FROM subscription_tokens")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(reminded, 0);
+    assert_eq!(purged, 1);
+    assert_eq!(remaining, ["confirmed@example.com", "recent@example.com"]);
+    assert_eq!(tokens.len(), 1);
+}
+
+#[tokio::test]
+async fn pending_subscribers_are_reminded_once_before_being_purged() {
+    // Arrange
+    let app = spawn_app().await;
+    // Two confirmation emails, then a single reminder
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    sign_up(&app, "reminded@example.com", 25).await;
+    sign_up(&app, "recent@example.com", 1).await;
+
+    // Act
+    let first_run = purge(&app, Some(20), false).await;
+    let second_run = purge(&app, Some(20), false).await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let reminded = sqlx::query!(
+        "SELECT status, confirmation_reminded_at FROM subscriptions WHERE email = 'reminded@example.com'"
+    )
+    .fetch_one(&app.database_pool)
+    .await
+    .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(first_run, (1, 0));
+    assert_eq!(second_run, (0, 0));
+    assert!(reminded.confirmation_reminded_at.is_some());
+    assert_eq!(reminded.status, "confirmed");
+}
+
+#[tokio::test]
+async fn reminded_subscribers_are_given_time_to_confirm_before_being_purged() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    sign_up(&app, "reminded-lately@example.com", 31).await;
+    remind(&app, "reminded-lately@example.com", 5).await;
+    sign_up(&app, "reminded-long-ago@example.com", 31).await;
+    remind(&app, "reminded-long-ago@example.com", 11).await;
+    sign_up(&app, "never-reminded@example.com", 35).await;
+    sign_up(&app, "forgotten@example.com", 41).await;
+
+    // Act
+    let (reminded, purged) = purge(&app, Some(20), false).await;
+
+    // Assert
+    let mut remaining: Vec<String> = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|subscriber| subscriber.email)
+        .collect();
+    remaining.sort();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(reminded, 1);
+    assert_eq!(purged, 2);
+    assert_eq!(
+        remaining,
+        ["never-reminded@example.com", "reminded-lately@example.com"]
+    );
+}
+
+#[tokio::test]
+async fn dry_runs_only_report_who_would_be_reminded_and_purged() {
+    // Arrange
+    let app = spawn_app().await;
+    // The confirmation emails alone
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    sign_up(&app, "stale@example.com", 31).await;
+    remind(&app, "stale@example.com", 11).await;
+    sign_up(&app, "reminded@example.com", 25).await;
+
+    // Act
+    let (reminded, purged) = purge(&app, Some(20), true).await;
+
+    // Assert
+    let subscribers = sqlx::query!("SELECT confirmation_reminded_at FROM subscriptions")
+        .fetch_all(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(reminded, 1);
+    assert_eq!(purged, 1);
+    assert_eq!(subscribers.len(), 2);
+    assert_eq!(
+        subscribers
+            .iter()
+            .filter(|subscriber| subscriber.confirmation_reminded_at.is_some())
+            .count(),
+        1
+    );
+}
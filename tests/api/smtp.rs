@@ -0,0 +1,272 @@
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::configuration::{
+    DkimAlgorithm, DkimSettings, EmailBackend, Settings, SmtpAuthentication, SmtpSettings, SmtpTls,
+};
+
+use crate::{
+    helpers::{clean_up_database, spawn_app_with},
+    smtp_server::SmtpServer,
+};
+
+fn smtp_settings(server: &SmtpServer) -> SmtpSettings {
+    SmtpSettings {
+        host: "127.0.0.1".to_string(),
+        port: server.port,
+        tls: SmtpTls::None,
+        username: Some("newsletter".to_string()),
+        password: Some(Secret::new("hunter2".to_string())),
+        authentication: vec![SmtpAuthentication::Plain, SmtpAuthentication::Login],
+        pool_size: 2,
+        dkim: None,
+    }
+}
+
+fn use_smtp(c: &mut Settings, smtp: SmtpSettings) {
+    c.email_client.backend = EmailBackend::Smtp;
+    c.email_client.smtp = Some(smtp);
+}
+
+#[tokio::test]
+async fn confirmation_emails_are_relayed_through_the_smtp_server() {
+    // Arrange
+    let server = SmtpServer::start().await;
+    let app = spawn_app_with(|c| use_smtp(c, smtp_settings(&server))).await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let messages = server.received_messages();
+    assert_eq!(messages.len(), 1);
+    let message = &messages[0];
+    assert_eq!(
+        message.credentials,
+        Some(("newsletter".to_string(), "hunter2".to_string()))
+    );
+    assert_eq!(message.authentication.as_deref(), Some("PLAIN"));
+    assert_eq!(message.mail_from, "test@email.com");
+    assert_eq!(message.rcpt_to, ["ursula_le_guin@gmail.com"]);
+    assert!(message.data.contains("Subject: Welcome!\r\n"));
+    assert!(message.data.contains("Content-Type: multipart/alternative"));
+}
+
+#[tokio::test]
+async fn the_login_mechanism_can_be_used_to_authenticate() {
+    // Arrange
+    let server = SmtpServer::start().await;
+    let app = spawn_app_with(|c| {
+        use_smtp(
+            c,
+            SmtpSettings {
+                authentication: vec![SmtpAuthentication::Login],
+                ..smtp_settings(&server)
+            },
+        );
+    })
+    .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+
+    let messages = server.received_messages();
+    assert_eq!(messages[0].authentication.as_deref(), Some("LOGIN"));
+    assert_eq!(
+        messages[0].credentials,
+        Some(("newsletter".to_string(), "hunter2".to_string()))
+    );
+}
+
+#[tokio::test]
+async fn messages_are_signed_with_dkim_when_configured() {
+    // Arrange
+    let server = SmtpServer::start().await;
+    let private_key_path = std::env::temp_dir().join(format!("{}.key", Uuid::new_v4()));
+    // Ed25519 private keys are 32 bytes
+    std::fs::write(
+        &private_key_path,
+        "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=",
+    )
+    .unwrap();
+    let dkim = DkimSettings {
+        selector: "newsletter".to_string(),
+        domain: "email.com".to_string(),
+        algorithm: DkimAlgorithm::Ed25519,
+        private_key_path: private_key_path.to_string_lossy().into_owned(),
+    };
+    let app = spawn_app_with(|c| {
+        use_smtp(
+            c,
+            SmtpSettings {
+                dkim: Some(dkim),
+                ..smtp_settings(&server)
+            },
+        );
+    })
+    .await;
+
+    // Act
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+    std::fs::remove_file(private_key_path).unwrap();
+
+    let data = &server.received_messages()[0].data;
+    let signature = data
+        .lines()
+        .find(|line| line.starts_with("DKIM-Signature:"))
+        .expect("The message is not signed");
+    assert!(signature.contains("a=ed25519-sha256"));
+    assert!(signature.contains("d=email.com"));
+    assert!(signature.contains("s=newsletter"));
+}
+
+#[tokio::test]
+async fn issues_are_relayed_as_one_message_per_subscriber_with_substitutions() {
+    // Arrange
+    let server = SmtpServer::start().await;
+    server.reject("octavia@example.com");
+    let app = spawn_app_with(|c| use_smtp(c, smtp_settings(&server))).await;
+    for (name, email) in [
+        ("Ursula", "ursula@example.com"),
+        ("Octavia", "octavia@example.com"),
+    ] {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, $2, $3, now(), 'confirmed')
+            "#,
+            Uuid::new_v4(),
+            email,
+            name,
+        )
+        .execute(&app.database_pool)
+        .await
+        .unwrap();
+    }
+    let issue_id = app
+        .publish_issue("hello", "Hello {{name}}", "Dear {{name}},", false, 0)
+        .await;
+
+    // Act
+    let mut transaction = app.database_pool.begin().await.unwrap();
+    zero2prod::issue_delivery_worker::enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let statuses: Vec<String> = sqlx::query!(
+        r#"
+        SELECT d.status
+        FROM issue_deliveries d JOIN subscriptions s ON s.id = d.subscriber_id
+        ORDER BY s.name
+        "#
+    )
+    .fetch_all(&app.database_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.status)
+    .collect();
+    clean_up_database(app.database_name).await;
+
+    let messages = server.received_messages();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].rcpt_to, ["ursula@example.com"]);
+    assert!(messages[0].data.contains("Subject: Hello Ursula\r\n"));
+    // Octavia, then Ursula
+    assert_eq!(statuses, ["failed", "sent"]);
+}
+
+#[tokio::test]
+async fn only_the_subscribers_left_unsent_are_retried_after_a_transient_failure() {
+    // Arrange
+    let server = SmtpServer::start().await;
+    server.fail_transiently("octavia@example.com");
+    let app = spawn_app_with(|c| use_smtp(c, smtp_settings(&server))).await;
+    // Ursula is sent to first, then the server fails for Octavia
+    for (name, email, minutes_ago) in [
+        ("Ursula", "ursula@example.com", 2.0),
+        ("Octavia", "octavia@example.com", 1.0),
+    ] {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+            VALUES ($1, $2, $3, now() - interval '1 minute' * $4, 'confirmed')
+            "#,
+            Uuid::new_v4(),
+            email,
+            name,
+            minutes_ago,
+        )
+        .execute(&app.database_pool)
+        .await
+        .unwrap();
+    }
+    let issue_id = app
+        .publish_issue("hello", "Hello {{name}}", "Dear {{name}},", false, 0)
+        .await;
+    let mut transaction = app.database_pool.begin().await.unwrap();
+    zero2prod::issue_delivery_worker::enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries d SET execute_after = s.subscribed_at
+        FROM subscriptions s WHERE s.id = d.subscriber_id
+        "#
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+    let after_failure = sqlx::query!(
+        r#"
+        SELECT s.name, d.status, d.message_id
+        FROM issue_deliveries d JOIN subscriptions s ON s.id = d.subscriber_id
+        ORDER BY s.name
+        "#
+    )
+    .fetch_all(&app.database_pool)
+    .await
+    .unwrap();
+    server.recover();
+    sqlx::query!("UPDATE issue_deliveries SET execute_after = now() WHERE status = 'queued'")
+        .execute(&app.database_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    clean_up_database(app.database_name).await;
+
+    // Octavia, then Ursula
+    assert_eq!(after_failure[0].status, "queued");
+    assert_eq!(after_failure[1].status, "sent");
+    let messages = server.received_messages();
+    let recipients: Vec<&str> = messages
+        .iter()
+        .map(|message| message.rcpt_to[0].as_str())
+        .collect();
+    assert_eq!(recipients, ["ursula@example.com", "octavia@example.com"]);
+    let message_id = after_failure[1].message_id.as_deref().unwrap();
+    assert!(messages[0]
+        .data
+        .contains(&format!("Message-ID: <{message_id}.0@email.com>")));
+}
@@ -0,0 +1,176 @@
+use std::sync::{Arc, Mutex};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{tcp::OwnedWriteHalf, TcpListener, TcpStream},
+};
+
+/// A message as received by the [`SmtpServer`]
+#[derive(Debug, Clone, Default)]
+pub struct ReceivedMessage {
+    /// `(username, password)` the client authenticated with
+    pub credentials: Option<(String, String)>,
+    pub authentication: Option<String>,
+    pub mail_from: String,
+    pub rcpt_to: Vec<String>,
+    pub data: String,
+}
+
+/// A plain text SMTP stand-in, speaking just enough of the protocol for
+/// the SMTP email backend, that records the messages it receives.
+pub struct SmtpServer {
+    pub port: u16,
+    messages: Arc<Mutex<Vec<ReceivedMessage>>>,
+    /// Recipients refused with a `550`
+    rejected: Arc<Mutex<Vec<String>>>,
+    /// Recipients refused with a `451`, until the server recovers
+    failing: Arc<Mutex<Vec<String>>>,
+}
+
+impl SmtpServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let rejected = Arc::new(Mutex::new(Vec::new()));
+        let failing = Arc::new(Mutex::new(Vec::new()));
+        let server = Self {
+            port,
+            messages: messages.clone(),
+            rejected: rejected.clone(),
+            failing: failing.clone(),
+        };
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle(
+                    stream,
+                    messages.clone(),
+                    rejected.clone(),
+                    failing.clone(),
+                ));
+            }
+        });
+        server
+    }
+
+    pub fn reject(&self, recipient: &str) {
+        self.rejected.lock().unwrap().push(recipient.to_string());
+    }
+
+    pub fn fail_transiently(&self, recipient: &str) {
+        self.failing.lock().unwrap().push(recipient.to_string());
+    }
+
+    pub fn recover(&self) {
+        self.failing.lock().unwrap().clear();
+    }
+
+    pub fn received_messages(&self) -> Vec<ReceivedMessage> {
+        self.messages.lock().unwrap().clone()
+    }
+}
+
+async fn handle(
+    stream: TcpStream,
+    messages: Arc<Mutex<Vec<ReceivedMessage>>>,
+    rejected: Arc<Mutex<Vec<String>>>,
+    failing: Arc<Mutex<Vec<String>>>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut credentials = None;
+    let mut authentication = None;
+    let mut message = ReceivedMessage::default();
+
+    reply(&mut writer, "220 localhost ESMTP stand-in").await;
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command = line.to_uppercase();
+        if command.starts_with("EHLO") {
+            reply(
+                &mut writer,
+                "250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME",
+            )
+            .await;
+        } else if command.starts_with("AUTH PLAIN") {
+            let response = match line.split_whitespace().nth(2) {
+                Some(response) => response.to_string(),
+                None => {
+                    reply(&mut writer, "334 ").await;
+                    lines.next_line().await.unwrap().unwrap()
+                }
+            };
+            let decoded = String::from_utf8(STANDARD.decode(response).unwrap()).unwrap();
+            let mut parts = decoded.split('\0').skip(1);
+            credentials = Some((
+                parts.next().unwrap().to_string(),
+                parts.next().unwrap().to_string(),
+            ));
+            authentication = Some("PLAIN".to_string());
+            reply(&mut writer, "235 2.7.0 Authentication successful").await;
+        } else if command.starts_with("AUTH LOGIN") {
+            let mut decode_next = async |prompt: &str| {
+                reply(&mut writer, prompt).await;
+                let line = lines.next_line().await.unwrap().unwrap();
+                String::from_utf8(STANDARD.decode(line).unwrap()).unwrap()
+            };
+            let username = decode_next("334 VXNlcm5hbWU6").await;
+            let password = decode_next("334 UGFzc3dvcmQ6").await;
+            credentials = Some((username, password));
+            authentication = Some("LOGIN".to_string());
+            reply(&mut writer, "235 2.7.0 Authentication successful").await;
+        } else if command.starts_with("MAIL FROM:") {
+            message = ReceivedMessage {
+                credentials: credentials.clone(),
+                authentication: authentication.clone(),
+                mail_from: address(&line),
+                ..ReceivedMessage::default()
+            };
+            reply(&mut writer, "250 2.1.0 OK").await;
+        } else if command.starts_with("RCPT TO:") {
+            let recipient = address(&line);
+            if rejected.lock().unwrap().contains(&recipient) {
+                reply(&mut writer, "550 5.1.1 Mailbox unavailable").await;
+            } else if failing.lock().unwrap().contains(&recipient) {
+                reply(&mut writer, "451 4.3.0 Temporary failure").await;
+            } else {
+                message.rcpt_to.push(recipient);
+                reply(&mut writer, "250 2.1.5 OK").await;
+            }
+        } else if command == "DATA" {
+            reply(&mut writer, "354 End data with <CR><LF>.<CR><LF>").await;
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == "." {
+                    break;
+                }
+                // Undo dot-stuffing
+                let line = line.strip_prefix('.').unwrap_or(&line);
+                message.data.push_str(line);
+                message.data.push_str("\r\n");
+            }
+            messages.lock().unwrap().push(std::mem::take(&mut message));
+            reply(&mut writer, "250 2.0.0 OK queued").await;
+        } else if command == "RSET" || command == "NOOP" {
+            reply(&mut writer, "250 2.0.0 OK").await;
+        } else if command == "QUIT" {
+            reply(&mut writer, "221 2.0.0 Bye").await;
+            break;
+        } else {
+            reply(&mut writer, "502 5.5.2 Command not recognized").await;
+        }
+    }
+}
+
+async fn reply(writer: &mut OwnedWriteHalf, reply: &str) {
+    writer
+        .write_all(format!("{reply}\r\n").as_bytes())
+        .await
+        .unwrap();
+}
+
+/// The address in `MAIL FROM:<address>` or `RCPT TO:<address>`
+fn address(line: &str) -> String {
+    let start = line.find('<').unwrap() + 1;
+    let end = line.find('>').unwrap();
+    line[start..end].to_string()
+}
@@ -76,6 +76,12 @@ async fn subscribe_returns_400_when_data_is_missing() {
 async fn subscribe_returns_500_when_email_already_exists() {
     let app = spawn_app().await;
 
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
     // Send first request with form data
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
     let response = app.post_subscriptions(body.into()).await;
@@ -170,3 +176,29 @@ async fn subscribe_fails_if_there_is_fatal_database_error() {
 
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_stores_the_message_id_of_the_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(202).insert_header("X-Message-Id", "14c5d75ce93"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let token = sqlx::query!("SELECT message_id FROM subscription_tokens")
+        .fetch_one(&app.database_pool)
+        .await
+        .expect("Failed to fetch saved token.");
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(token.message_id.as_deref(), Some("14c5d75ce93"));
+}
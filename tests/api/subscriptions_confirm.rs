@@ -3,7 +3,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use crate::helpers::{clean_up_database, spawn_app};
+use crate::helpers::{clean_up_database, spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_400() {
@@ -79,3 +79,144 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn signing_up_and_confirming_record_proof_of_consent() {
+    // Arrange
+    let app = spawn_app_with(|c| c.application.trusted_proxies = vec![[127, 0, 0, 1].into()]).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let client = reqwest::Client::new();
+    client
+        .post(format!("{}/subscriptions", app.address))
+        .header("User-Agent", "Signup browser")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("source", "homepage-footer"),
+            ("consent_text_version", "2026-10"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    // Act
+    client
+        .get(confirmation_links.html)
+        .header("User-Agent", "Mail client")
+        .header("X-Forwarded-For", "198.51.100.23")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    let detail: serde_json::Value = app
+        .admin_get(&format!("/admin/api/subscribers/{}", subscriber.id))
+        .await
+        .json()
+        .await
+        .unwrap();
+    let update = sqlx::query!("UPDATE consent_events SET source = 'forged'")
+        .execute(&app.database_pool)
+        .await;
+    clean_up_database(app.database_name).await;
+
+    let consent_events = &detail["consent_events"];
+    assert_eq!(consent_events[0]["event"], "subscribed");
+    assert_eq!(consent_events[0]["ip_address"], "203.0.113.7");
+    assert_eq!(consent_events[0]["user_agent"], "Signup browser");
+    assert_eq!(consent_events[0]["source"], "homepage-footer");
+    assert_eq!(consent_events[0]["consent_text_version"], "2026-10");
+    assert_eq!(consent_events[1]["event"], "confirmed");
+    assert_eq!(consent_events[1]["ip_address"], "198.51.100.23");
+    assert_eq!(consent_events[1]["user_agent"], "Mail client");
+    assert!(consent_events[1]["occurred_at"].is_string());
+    assert!(update.is_err());
+}
+
+#[tokio::test]
+async fn forwarded_addresses_are_ignored_from_untrusted_peers() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", app.address))
+        .header("X-Forwarded-For", "203.0.113.7")
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let consent = sqlx::query!("SELECT host(ip_address) AS ip_address FROM consent_events")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(consent.ip_address.as_deref(), Some("127.0.0.1"));
+}
+
+#[tokio::test]
+async fn clicking_the_confirmation_link_again_changes_nothing() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.database_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    let confirmations = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM consent_events WHERE event = 'confirmed'"#
+    )
+    .fetch_one(&app.database_pool)
+    .await
+    .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(saved.status, "unsubscribed");
+    assert_eq!(confirmations.count, 1);
+}
@@ -0,0 +1,222 @@
+use uuid::Uuid;
+
+use crate::helpers::{clean_up_database, spawn_app};
+
+#[tokio::test]
+async fn sendgrid_events_are_stored_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = Uuid::new_v4();
+    let issue_id = Uuid::new_v4();
+    let event = serde_json::json!({
+        "email": "ursula@example.com",
+        "timestamp": 1_700_000_000,
+        "event": "click",
+        "url": "https://example.com/article",
+        "sg_event_id": "sZROwMGMagFgnOEmSdvhig",
+        "sg_message_id": "14c5d75ce93.dfd.64b469.filter0001.16648.5515E0B88.0",
+        "subscriber_id": subscriber_id.to_string(),
+        "newsletter_issue_id": issue_id.to_string(),
+    });
+
+    // Act
+    let response = app
+        .post_sendgrid_events(&serde_json::json!([event.clone(), event]))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let events = sqlx::query!(
+        "SELECT event, message_id, newsletter_issue_id, subscriber_id, url, occurred_at FROM email_events"
+    )
+    .fetch_all(&app.database_pool)
+    .await
+    .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.event, "click");
+    assert_eq!(event.message_id.as_deref(), Some("14c5d75ce93"));
+    assert_eq!(event.newsletter_issue_id, Some(issue_id));
+    assert_eq!(event.subscriber_id, Some(subscriber_id));
+    assert_eq!(event.url.as_deref(), Some("https://example.com/article"));
+    assert_eq!(event.occurred_at.timestamp(), 1_700_000_000);
+}
+
+#[tokio::test]
+async fn events_about_emails_other_than_issues_are_stored_too() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_sendgrid_events(&serde_json::json!([{
+            "email": "ursula@example.com",
+            "timestamp": 1_700_000_000,
+            "event": "delivered",
+        }]))
+        .await;
+
+    // Assert
+    let stored = sqlx::query!("SELECT newsletter_issue_id FROM email_events")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(stored.newsletter_issue_id, None);
+}
+
+#[tokio::test]
+async fn sendgrid_events_require_the_webhook_token_in_a_header() {
+    // Arrange
+    let app = spawn_app().await;
+    let url = format!("{}/webhooks/sendgrid", app.address);
+    let client = reqwest::Client::new();
+    let test_cases = [
+        (client.post(&url), "no token"),
+        (
+            client.post(&url).basic_auth("sendgrid", Some("wrong")),
+            "a wrong token",
+        ),
+        (
+            client.post(&url).bearer_auth("wrong"),
+            "a wrong bearer token",
+        ),
+        (
+            client.post(&url).query(&[("token", &app.webhook_token)]),
+            "the token in the query string",
+        ),
+    ];
+
+    // Act
+    let mut outcomes = Vec::new();
+    for (request, description) in test_cases {
+        let response = request
+            .json(&serde_json::json!([{
+                "email": "ursula@example.com",
+                "timestamp": 1_700_000_000,
+                "event": "open",
+            }]))
+            .send()
+            .await
+            .unwrap();
+        outcomes.push((description, response.status().as_u16()));
+    }
+
+    // Assert
+    let stored = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_events"#)
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    for (description, status) in outcomes {
+        assert_eq!(status, 401, "Events with {description} were accepted");
+    }
+    assert_eq!(stored.count, 0);
+}
+
+#[tokio::test]
+async fn sendgrid_events_accept_the_webhook_token_as_a_bearer_token() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/sendgrid", app.address))
+        .bearer_auth(&app.webhook_token)
+        .json(&serde_json::json!([{
+            "email": "ursula@example.com",
+            "timestamp": 1_700_000_000,
+            "event": "open",
+        }]))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let stored = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_events"#)
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(stored.count, 1);
+}
+
+#[tokio::test]
+async fn unsubscribe_events_unsubscribe_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula@example.com', 'Ursula', now(), 'confirmed')
+        "#,
+        subscriber_id,
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .post_sendgrid_events(&serde_json::json!([{
+            "email": "ursula@example.com",
+            "timestamp": 1_700_000_000,
+            "event": "group_unsubscribe",
+            "subscriber_id": subscriber_id.to_string(),
+        }]))
+        .await;
+
+    // Assert
+    let subscription = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscription.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unsubscribe_events_without_a_subscriber_id_match_the_email_regardless_of_case() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'Ursula@Example.com', 'Ursula', now(), 'confirmed')
+        "#,
+        subscriber_id,
+    )
+    .execute(&app.database_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .post_sendgrid_events(&serde_json::json!([{
+            "email": "ursula@example.com",
+            "timestamp": 1_700_000_000,
+            "event": "unsubscribe",
+        }]))
+        .await;
+
+    // Assert
+    let subscription = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.database_pool)
+        .await
+        .unwrap();
+    clean_up_database(app.database_name).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscription.status, "unsubscribed");
+}